@@ -4,6 +4,8 @@
 
 cargo clippy -- -D warnings
 
+cargo run --release -p wgpu-testbed-bake -- -o baked.zip
+
 Push-Location ".\wgpu-testbed-lib"
 $env:RUSTFLAGS = '--cfg=web_sys_unstable_apis --cfg getrandom_backend="wasm_js"'
 wasm-pack build --release
@@ -0,0 +1,49 @@
+// shader.wgsl for devices with texture binding arrays. Every material's textures sit in
+// one bind group, indexed by the material id each vertex carries, so a whole scene draws
+// without switching bind groups.
+
+#include "shader.wgsl"
+
+@group(3) @binding(0)
+var t_diffuse_array: binding_array<texture_2d<f32>>;
+@group(3) @binding(1)
+var t_normal_array: binding_array<texture_2d<f32>>;
+@group(3) @binding(2)
+var s_material: sampler;
+
+@vertex
+fn vertex_bindless(
+    model: VertexInput,
+    instance: InstanceInput,
+    ids: VertexIds,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    var out = transform_vertex(model, instance);
+    out.id = pick_id(ids.mesh, instance_index);
+    out.material = ids.material;
+    return out;
+}
+
+@vertex
+fn vertex_quantized_bindless(
+    model: QuantizedVertexInput,
+    instance: InstanceInput,
+    ids: VertexIds,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    var out = transform_vertex(decode_quantized(model), instance);
+    out.id = pick_id(ids.mesh, instance_index);
+    out.material = ids.material;
+    return out;
+}
+
+@fragment
+fn fragment_bindless(in: VertexOutput) -> FragmentOutput {
+    let object_colour = textureSample(t_diffuse_array[in.material], s_material, in.tex_coords) * in.colour;
+#ifdef NORMAL_MAPPING
+    let object_normal = textureSample(t_normal_array[in.material], s_material, in.tex_coords);
+#else
+    let object_normal = FLAT_NORMAL;
+#endif
+    return shade(in, object_colour, object_normal);
+}
@@ -0,0 +1,9 @@
+// Maps 0 to 1 onto blue, cyan, green, yellow and red, for debug views of counts.
+fn heatmap(t: f32) -> vec3<f32> {
+    let x = clamp(t, 0.0, 1.0) * 4.0;
+    return clamp(
+        vec3<f32>(x - 2.0, min(x, 4.0 - x), 2.0 - x),
+        vec3<f32>(0.0),
+        vec3<f32>(1.0),
+    );
+}
@@ -0,0 +1,5 @@
+// The scene light, as written by `Light` in light.rs.
+struct Light {
+    position: vec3<f32>,
+    colour: vec3<f32>,
+}
@@ -0,0 +1,32 @@
+// `ModelVertex` as loose floats, so storage buffers of vertices need no vec3 padding.
+struct ModelVertex {
+	x: f32, y: f32, z: f32,
+	uv: f32, uw: f32,
+	nx: f32, ny: f32, nz: f32,
+	tx: f32, ty: f32, tz: f32,
+	bx: f32, by: f32, bz: f32,
+	colour: u32, pad0_: u32,
+}
+
+fn getPos(vert: ModelVertex) -> vec3<f32> {
+	return vec3<f32> (
+		vert.x,
+		vert.y,
+		vert.z
+	);
+}
+
+fn getUV(vert: ModelVertex) -> vec2<f32> {
+	return vec2<f32> (
+		vert.uv,
+		vert.uw,
+	);
+}
+
+fn getNormal(vert: ModelVertex) -> vec3<f32> {
+	return vec3<f32> (
+		vert.nx,
+		vert.ny,
+		vert.nz
+	);
+}
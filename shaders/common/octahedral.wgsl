@@ -0,0 +1,9 @@
+// Octahedral unit vectors, as packed by `octahedral_encode` in model.rs.
+fn octahedral_decode(encoded: vec2<f32>) -> vec3<f32> {
+    var v = vec3<f32>(encoded, 1.0 - abs(encoded.x) - abs(encoded.y));
+    if (v.z < 0.0) {
+        let signs = select(vec2<f32>(-1.0), vec2<f32>(1.0), v.xy >= vec2<f32>(0.0));
+        v = vec3<f32>((1.0 - abs(v.yx)) * signs, v.z);
+    }
+    return normalize(v);
+}
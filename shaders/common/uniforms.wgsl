@@ -0,0 +1,5 @@
+// The camera, as written by `Uniforms` in uniform.rs.
+struct Uniforms {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+}
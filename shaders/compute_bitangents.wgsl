@@ -1,16 +1,12 @@
-
-struct ModelVertex {
-	x: f32, y: f32, z: f32,
-	uv: f32, uw: f32,
-	nx: f32, ny: f32, nz: f32,
-	tx: f32, ty: f32, tz: f32,
-	bx: f32, by: f32, bz: f32,
-	pad0_: u32, pad1_: u32,
-}
+#include "common/model_vertex.wgsl"
 
 struct ComputeInfo {
 	num_vertices: u32,
 	num_indicies: u32,
+	// Invocations per row of the dispatch, which is split over y once x hits the
+	// device's workgroup count limit.
+	row_width: u32,
+	pad0_: u32,
 }
 
 struct ModelVetexBuffer {
@@ -21,93 +17,143 @@ struct IndexBuffer {
 	indicies: array<u32>
 }
 
+// Six fixed-point values per vertex: the tangent sum, then the bitangent sum.
+struct TangentSumBuffer {
+	sums: array<atomic<i32>>
+}
+
 @group(0) @binding(0)
-var<storage> src_verts: ModelVetexBuffer;
+var<storage, read_write> vertex_buffer: ModelVetexBuffer;
 
 @group(0) @binding(1)
-var<storage, read_write> dst_verts: ModelVetexBuffer;
+var<storage> idx_buffer: IndexBuffer;
 
 @group(0) @binding(2)
-var<storage> idx_buffer: IndexBuffer;
+var<storage, read_write> tangent_sums: TangentSumBuffer;
 
 @group(0) @binding(3)
 var<uniform> info: ComputeInfo;
 
-fn getPos(vert: ModelVertex) -> vec3<f32> {
-	return vec3<f32> (
-		vert.x,
-		vert.y,
-		vert.z
-	);
+// Every triangle adds unit length vectors, so a vertex can be shared by 32767
+// triangles before its sums overflow.
+const FIXED_POINT_SCALE: f32 = 65536.0;
+
+fn safeNormalize(v: vec3<f32>) -> vec3<f32> {
+	let len = length(v);
+	if(len > 0.0) {
+		return v / len;
+	}
+	return vec3<f32>(0.0);
 }
 
-fn getUV(vert: ModelVertex) -> vec2<f32> {
-	return vec2<f32> (
-		vert.uv,
-		vert.uw,
-	);
+// Meshes without UVs have no tangent to sum, so any frame around the normal will do.
+fn anyTangent(normal: vec3<f32>) -> vec3<f32> {
+	if(abs(normal.x) > 0.9) {
+		return safeNormalize(cross(normal, vec3<f32>(0.0, 1.0, 0.0)));
+	}
+	return safeNormalize(cross(normal, vec3<f32>(1.0, 0.0, 0.0)));
 }
 
-fn calcTangentBitangent(vert_idx: u32) -> ModelVertex {
-	var curr_vert = src_verts.verts[vert_idx];
+fn invocationIndex(global_ix: vec3<u32>) -> u32 {
+	return global_ix.x + global_ix.y * info.row_width;
+}
 
-	var tangent: vec3<f32>;
-	var bitangent: vec3<f32>;
-	var tris_included: f32;
+fn toFixed(value: f32) -> i32 {
+	return i32(round(value * FIXED_POINT_SCALE));
+}
 
-	for(var i: u32 = 0u; i < info.num_indicies; i = i + 3u) {
-		let idx0 = idx_buffer.indicies[i];
-		let idx1 = idx_buffer.indicies[i + 1u];
-		let idx2 = idx_buffer.indicies[i + 2u];
+fn addToVertex(vert_idx: u32, tangent: vec3<f32>, bitangent: vec3<f32>) {
+	let base = vert_idx * 6u;
+	atomicAdd(&tangent_sums.sums[base], toFixed(tangent.x));
+	atomicAdd(&tangent_sums.sums[base + 1u], toFixed(tangent.y));
+	atomicAdd(&tangent_sums.sums[base + 2u], toFixed(tangent.z));
+	atomicAdd(&tangent_sums.sums[base + 3u], toFixed(bitangent.x));
+	atomicAdd(&tangent_sums.sums[base + 4u], toFixed(bitangent.y));
+	atomicAdd(&tangent_sums.sums[base + 5u], toFixed(bitangent.z));
+}
 
-		if(idx0 == vert_idx || idx1 == vert_idx || idx2 == vert_idx) {
-			let vert0 = src_verts.verts[idx0];
-			let vert1 = src_verts.verts[idx1];
-			let vert2 = src_verts.verts[idx2];
+// One invocation per triangle, scattering its tangent and bitangent to its vertices.
+@compute @workgroup_size(64, 1, 1)
+fn accumulate(@builtin(global_invocation_id) global_ix: vec3<u32>) {
+	let i = invocationIndex(global_ix) * 3u;
+	if(i + 2u >= info.num_indicies) {
+		return;
+	}
 
-			let pos0 = getPos(vert0);
-			let pos1 = getPos(vert1);
-			let pos2 = getPos(vert2);
+	let idx0 = idx_buffer.indicies[i];
+	let idx1 = idx_buffer.indicies[i + 1u];
+	let idx2 = idx_buffer.indicies[i + 2u];
 
-			let tex0 = getUV(vert0);
-			let tex1 = getUV(vert1);
-			let tex2 = getUV(vert2);
+	let vert0 = vertex_buffer.verts[idx0];
+	let vert1 = vertex_buffer.verts[idx1];
+	let vert2 = vertex_buffer.verts[idx2];
 
-			let delta_pos1 = pos1 - pos0;
-			let delta_pos2 = pos2 - pos0;
+	let delta_pos1 = getPos(vert1) - getPos(vert0);
+	let delta_pos2 = getPos(vert2) - getPos(vert0);
 
-			let delta_uv1 = tex1 - tex0;
-			let delta_uv2 = tex2 - tex0;
+	let delta_uv1 = getUV(vert1) - getUV(vert0);
+	let delta_uv2 = getUV(vert2) - getUV(vert0);
 
-			let r: f32 = 1.0 / (delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x);
-			tangent = tangent + (delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r;
-            bitangent = bitangent + (delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * r; 
-            tris_included = tris_included + 1.0;
-		}
+	let det = delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x;
+	if(det == 0.0) {
+		return;
+	}
+	let r = 1.0 / det;
+	let tangent = safeNormalize((delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r);
+	let bitangent = safeNormalize((delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * r);
+
+	// A vertex used more than once by a degenerate triangle only counts it once.
+	addToVertex(idx0, tangent, bitangent);
+	if(idx1 != idx0) {
+		addToVertex(idx1, tangent, bitangent);
 	}
+	if(idx2 != idx0 && idx2 != idx1) {
+		addToVertex(idx2, tangent, bitangent);
+	}
+}
 
-	if(tris_included > 0.0) {
-		tangent = tangent / tris_included;
-        bitangent = bitangent / tris_included;
-        tangent = normalize(tangent);
-        bitangent = normalize(bitangent);
+// One invocation per vertex, orthogonalising its summed tangent frame against the normal.
+@compute @workgroup_size(64, 1, 1)
+fn finalize(@builtin(global_invocation_id) global_ix: vec3<u32>) {
+	let vert_idx = invocationIndex(global_ix);
+	if(vert_idx >= info.num_vertices) {
+		return;
 	}
 
-	curr_vert.tx = tangent.x;
-	curr_vert.ty = tangent.y;
-	curr_vert.tz = tangent.z;
+	let base = vert_idx * 6u;
+	let tangent_sum = vec3<f32>(
+		f32(atomicLoad(&tangent_sums.sums[base])),
+		f32(atomicLoad(&tangent_sums.sums[base + 1u])),
+		f32(atomicLoad(&tangent_sums.sums[base + 2u])),
+	);
+	let bitangent_sum = vec3<f32>(
+		f32(atomicLoad(&tangent_sums.sums[base + 3u])),
+		f32(atomicLoad(&tangent_sums.sums[base + 4u])),
+		f32(atomicLoad(&tangent_sums.sums[base + 5u])),
+	);
+
+	var vert = vertex_buffer.verts[vert_idx];
+	let normal = safeNormalize(getNormal(vert));
 
-	curr_vert.bx = bitangent.x;
-    curr_vert.by = bitangent.y;
-    curr_vert.bz = bitangent.z;
+	// Gram-Schmidt: the tangent loses its normal component, then the bitangent
+	// loses both, keeping its side so mirrored UVs stay mirrored.
+	var tangent = safeNormalize(tangent_sum - normal * dot(normal, tangent_sum));
+	if(all(tangent == vec3<f32>(0.0))) {
+		tangent = anyTangent(normal);
+	}
+	var bitangent = bitangent_sum - normal * dot(normal, bitangent_sum);
+	bitangent = safeNormalize(bitangent - tangent * dot(tangent, bitangent));
+	if(all(bitangent == vec3<f32>(0.0))) {
+		bitangent = cross(normal, tangent);
+	}
 
-	return curr_vert;
-}
+	vert.tx = tangent.x;
+	vert.ty = tangent.y;
+	vert.tz = tangent.z;
 
-@compute @workgroup_size(64, 1, 1)
-fn main(@builtin(global_invocation_id) global_ix: vec3<u32>) {
-	let vert_idx = global_ix.x;
-	let result = calcTangentBitangent(vert_idx);
-	dst_verts.verts[vert_idx] = result;
-}
+	vert.bx = bitangent.x;
+	vert.by = bitangent.y;
+	vert.bz = bitangent.z;
 
+	vertex_buffer.verts[vert_idx] = vert;
+}
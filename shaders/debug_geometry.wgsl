@@ -0,0 +1,169 @@
+// Debug overlays drawn from a mesh's vertex and index buffers bound as storage, so they
+// need no line polygon mode and work with either vertex layout. Draws are not indexed:
+// vertex_wireframe is drawn with one vertex per index, and vertex_vectors with six per
+// mesh vertex, for its normal, tangent and bitangent lines.
+
+#include "common/uniforms.wgsl"
+#include "common/octahedral.wgsl"
+
+// `DebugMeshInfo` in debug_view.rs.
+struct DebugMesh {
+    // Words per vertex: 16 for ModelVertex, 8 for QuantizedVertex.
+    stride: u32,
+    quantized: u32,
+    // Whether indices are 16 bit, packed two to a word.
+    short_indices: u32,
+    // The length of vector lines, in world units.
+    vector_length: f32,
+}
+
+@group(0) @binding(0)
+var<storage, read> vertices: array<u32>;
+@group(0) @binding(1)
+var<storage, read> indices: array<u32>;
+@group(0) @binding(2)
+var<uniform> mesh: DebugMesh;
+
+@group(1) @binding(0)
+var<uniform> uniforms: Uniforms;
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+    @location(9) normal_matrix_0: vec3<f32>,
+    @location(10) normal_matrix_1: vec3<f32>,
+    @location(11) normal_matrix_2: vec3<f32>,
+}
+
+struct DebugVertex {
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    tangent: vec3<f32>,
+    bitangent: vec3<f32>,
+}
+
+fn load_index(i: u32) -> u32 {
+    if (mesh.short_indices != 0u) {
+        return (indices[i / 2u] >> ((i % 2u) * 16u)) & 0xffffu;
+    }
+    return indices[i];
+}
+
+fn load_vec3(word: u32) -> vec3<f32> {
+    return bitcast<vec3<f32>>(vec3<u32>(vertices[word], vertices[word + 1u], vertices[word + 2u]));
+}
+
+fn load_vertex(index: u32) -> DebugVertex {
+    let base = index * mesh.stride;
+    var vertex: DebugVertex;
+    vertex.position = load_vec3(base);
+    if (mesh.quantized != 0u) {
+        vertex.normal = octahedral_decode(unpack2x16snorm(vertices[base + 4u]));
+        vertex.tangent = octahedral_decode(unpack2x16snorm(vertices[base + 5u]));
+        let handedness = unpack2x16snorm(vertices[base + 6u]).x;
+        vertex.bitangent = cross(vertex.normal, vertex.tangent) * handedness;
+    } else {
+        vertex.normal = load_vec3(base + 5u);
+        vertex.tangent = load_vec3(base + 8u);
+        vertex.bitangent = load_vec3(base + 11u);
+    }
+    return vertex;
+}
+
+fn model_matrix(instance: InstanceInput) -> mat4x4<f32> {
+    return mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+}
+
+fn normal_matrix(instance: InstanceInput) -> mat3x3<f32> {
+    return mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
+}
+
+// Wireframe
+
+const WIREFRAME_COLOUR: vec3<f32> = vec3<f32>(0.0, 1.0, 0.3);
+
+struct WireframeOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // One at the triangle corner this vertex is, fading to zero at the opposite edge.
+    @location(0) barycentric: vec3<f32>,
+}
+
+@vertex
+fn vertex_wireframe(
+    @builtin(vertex_index) i: u32,
+    instance: InstanceInput,
+) -> WireframeOutput {
+    let vertex = load_vertex(load_index(i));
+    var out: WireframeOutput;
+    out.clip_position = uniforms.view_proj * model_matrix(instance) * vec4<f32>(vertex.position, 1.0);
+    out.barycentric = vec3<f32>(f32(i % 3u == 0u), f32(i % 3u == 1u), f32(i % 3u == 2u));
+    return out;
+}
+
+@fragment
+fn fragment_wireframe(in: WireframeOutput) -> @location(0) vec4<f32> {
+    // About a pixel and a half from the nearest edge, whatever the triangle's size.
+    let edges = smoothstep(vec3<f32>(0.0), fwidth(in.barycentric) * 1.5, in.barycentric);
+    let coverage = 1.0 - min(min(edges.x, edges.y), edges.z);
+    if (coverage <= 0.0) {
+        discard;
+    }
+    return vec4<f32>(WIREFRAME_COLOUR, coverage);
+}
+
+// Normal, tangent and bitangent lines
+
+struct VectorOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) colour: vec3<f32>,
+}
+
+@vertex
+fn vertex_vectors(
+    @builtin(vertex_index) i: u32,
+    instance: InstanceInput,
+) -> VectorOutput {
+    let vertex = load_vertex(i / 6u);
+    let line = (i % 6u) / 2u;
+    let tip = f32(i % 2u);
+
+    // Tangents are red, bitangents green and normals blue, as in most DCC tools.
+    var direction: vec3<f32>;
+    var out: VectorOutput;
+    switch line {
+        case 0u: {
+            direction = vertex.tangent;
+            out.colour = vec3<f32>(1.0, 0.0, 0.0);
+        }
+        case 1u: {
+            direction = vertex.bitangent;
+            out.colour = vec3<f32>(0.0, 1.0, 0.0);
+        }
+        default: {
+            direction = vertex.normal;
+            out.colour = vec3<f32>(0.0, 0.0, 1.0);
+        }
+    }
+
+    let world_position = model_matrix(instance) * vec4<f32>(vertex.position, 1.0);
+    let world_direction = normalize(normal_matrix(instance) * direction);
+    let end = world_position.xyz + world_direction * mesh.vector_length * tip;
+    out.clip_position = uniforms.view_proj * vec4<f32>(end, 1.0);
+    return out;
+}
+
+@fragment
+fn fragment_vectors(in: VectorOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.colour, 1.0);
+}
@@ -0,0 +1,56 @@
+// Debug views that replace the meshes' materials, sharing the vertex shaders and bind
+// groups of shader.wgsl. fragment_uv_checker and fragment_mip_level write into the
+// G-buffer with full lighting, so the output pass shows their colours as they are.
+// fragment_overdraw counts every fragment drawn at a pixel with additive blending, and
+// fragment_wireframe colours the edges drawn with a line polygon mode.
+
+#include "shader.wgsl"
+#include "common/heatmap.wgsl"
+
+// The mip level the heatmap turns red at.
+const MAX_MIP_LEVEL: f32 = 6.0;
+
+const WIREFRAME_COLOUR: vec4<f32> = vec4<f32>(0.0, 1.0, 0.3, 1.0);
+
+fn unlit(in: VertexOutput, colour: vec3<f32>) -> FragmentOutput {
+    var out: FragmentOutput;
+    out.diffuse = vec4<f32>(colour, 1.0);
+    out.normal = vec4<f32>(1.0);
+    out.id = in.id;
+    return out;
+}
+
+@fragment
+fn fragment_uv_checker(in: VertexOutput) -> FragmentOutput {
+    // Eight checks per unit, tinted by the coordinates so flipped or mirrored UVs show.
+    let checks = floor(in.tex_coords * 8.0);
+    let parity = abs(checks.x + checks.y) % 2.0;
+    let tint = vec3<f32>(fract(in.tex_coords), 1.0);
+    return unlit(in, tint * mix(0.35, 1.0, parity));
+}
+
+@fragment
+fn fragment_mip_level(in: VertexOutput) -> FragmentOutput {
+    // The level a trilinear sample of the diffuse texture would read, from how many
+    // texels a pixel covers.
+    let texels = in.tex_coords * vec2<f32>(textureDimensions(t_diffuse));
+    let dx = dpdx(texels);
+    let dy = dpdy(texels);
+    let footprint = max(dot(dx, dx), dot(dy, dy));
+    let level = clamp(
+        0.5 * log2(max(footprint, 1e-8)),
+        0.0,
+        f32(textureNumLevels(t_diffuse) - 1u),
+    );
+    return unlit(in, heatmap(level / MAX_MIP_LEVEL));
+}
+
+@fragment
+fn fragment_overdraw(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(1.0);
+}
+
+@fragment
+fn fragment_wireframe(in: VertexOutput) -> @location(0) vec4<f32> {
+    return WIREFRAME_COLOUR;
+}
@@ -0,0 +1,95 @@
+// Debug views of the G-buffer, depth and stencil, drawn into the scene in place of the
+// output pass.
+
+#include "common/heatmap.wgsl"
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coord: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+}
+
+@vertex
+fn vertex_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(in.position, 1.0, 1.0);
+    return out;
+}
+
+// `ScreenView` in debug_view.rs.
+const VIEW_DEPTH: u32 = 0u;
+const VIEW_STENCIL: u32 = 1u;
+const VIEW_DIFFUSE: u32 = 2u;
+const VIEW_ALPHA: u32 = 3u;
+const VIEW_LIGHTING: u32 = 4u;
+const VIEW_OVERDRAW: u32 = 5u;
+
+// The overdraw count the heatmap turns red at.
+const MAX_OVERDRAW: f32 = 8.0;
+
+// `DebugViewUniform` in debug_view.rs.
+struct DebugView {
+    view: u32,
+    z_near: f32,
+    z_far: f32,
+}
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var t_specular: texture_2d<f32>;
+@group(0) @binding(2)
+var t_depth: texture_depth_2d;
+@group(0) @binding(3)
+var t_stencil: texture_2d<u32>;
+@group(0) @binding(4)
+var t_overdraw: texture_2d<f32>;
+@group(0) @binding(5)
+var<uniform> debug: DebugView;
+
+// A distinct colour for each stencil value, from a hash of it.
+fn id_colour(id: u32) -> vec3<f32> {
+    var h = id * 747796405u + 2891336453u;
+    h = ((h >> ((h >> 28u) + 4u)) ^ h) * 277803737u;
+    h = (h >> 22u) ^ h;
+    return vec3<f32>(vec3<u32>(h, h >> 8u, h >> 16u) & vec3<u32>(0xffu)) / 255.0;
+}
+
+@fragment
+fn fragment_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let coords = vec2<i32>(in.position.xy);
+    var colour: vec3<f32>;
+    switch debug.view {
+        case VIEW_DEPTH: {
+            // Linear depth on a log scale, white at the near plane and black at the far.
+            let depth = textureLoad(t_depth, coords, 0);
+            let z = debug.z_near * debug.z_far / (debug.z_far - depth * (debug.z_far - debug.z_near));
+            colour = vec3<f32>(1.0 - log2(z / debug.z_near) / log2(debug.z_far / debug.z_near));
+        }
+        case VIEW_STENCIL: {
+            let id = textureLoad(t_stencil, coords, 0).r;
+            // 0xff is the clear value, so untouched pixels stay black.
+            colour = select(id_colour(id), vec3<f32>(0.0), id == 0xffu);
+        }
+        case VIEW_DIFFUSE: {
+            colour = textureLoad(t_diffuse, coords, 0).rgb;
+        }
+        case VIEW_ALPHA: {
+            colour = vec3<f32>(textureLoad(t_diffuse, coords, 0).a);
+        }
+        case VIEW_LIGHTING: {
+            colour = textureLoad(t_specular, coords, 0).rgb;
+        }
+        case VIEW_OVERDRAW: {
+            let count = textureLoad(t_overdraw, coords, 0).r;
+            colour = select(heatmap((count - 1.0) / (MAX_OVERDRAW - 1.0)), vec3<f32>(0.0), count < 0.5);
+        }
+        default: {
+            colour = vec3<f32>(1.0, 0.0, 1.0);
+        }
+    }
+    return vec4<f32>(colour, 1.0);
+}
@@ -1,14 +1,9 @@
-struct Uniforms {
-    view_pos: vec4<f32>,
-    view_proj: mat4x4<f32>,
-}
+#include "common/uniforms.wgsl"
+#include "common/light.wgsl"
+
 @group(0) @binding(0)
 var<uniform> uniforms: Uniforms;
 
-struct Light {
-    position: vec3<f32>,
-    colour: vec3<f32>,
-}
 @group(1) @binding(0)
 var<uniform> light: Light;
 
@@ -35,6 +30,8 @@ fn vertex_main(
 struct FragmentOutput {
     @location(0) diffuse: vec4<f32>,
     @location(1) normal: vec4<f32>,
+    // The light can't be picked, so it leaves 0, as if nothing was drawn.
+    @location(2) id: vec2<u32>,
 }
 
 @fragment
@@ -42,5 +39,6 @@ fn fragment_main(in: VertexOutput) -> FragmentOutput {
     var out: FragmentOutput;
     out.diffuse = vec4<f32>(in.colour, 1.0);
     out.normal = vec4<f32>(1.0);
+    out.id = vec2<u32>(0u);
     return out;
 }
\ No newline at end of file
@@ -0,0 +1,36 @@
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coord: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(1) tex_coord: vec2<f32>,
+}
+
+@vertex
+fn vertex_main(in: VertexInput) -> VertexOutput {
+	var out: VertexOutput;
+	out.position = vec4<f32>(in.position.x, in.position.y, 0.0, 1.0);
+	out.tex_coord = in.tex_coord;
+	return out;
+}
+
+struct Loading {
+	progress: f32,
+}
+
+@group(0) @binding(0)
+var<uniform> loading: Loading;
+
+@fragment
+fn fragment_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	let bar_min = vec2<f32>(0.25, 0.49);
+	let bar_max = vec2<f32>(0.75, 0.51);
+	if (any(in.tex_coord < bar_min) || any(in.tex_coord > bar_max)) {
+		discard;
+	}
+
+	let filled = (in.tex_coord.x - bar_min.x) / (bar_max.x - bar_min.x) <= loading.progress;
+	return select(vec4<f32>(0.2, 0.2, 0.2, 1.0), vec4<f32>(1.0, 1.0, 1.0, 1.0), filled);
+}
@@ -0,0 +1,35 @@
+// Resolves the weighted blended transparency accumulated by transparent.wgsl, blending
+// the average transparent colour over the scene by how much of it is covered.
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coord: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+}
+
+@vertex
+fn vertex_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(in.position, 1.0, 1.0);
+    return out;
+}
+
+@group(0) @binding(0)
+var t_accum: texture_2d<f32>;
+@group(0) @binding(1)
+var t_revealage: texture_2d<f32>;
+
+@fragment
+fn fragment_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let coords = vec2<i32>(in.position.xy);
+    let revealage = textureLoad(t_revealage, coords, 0).r;
+    if (revealage >= 1.0) {
+        discard;
+    }
+    let accum = textureLoad(t_accum, coords, 0);
+    let average = accum.rgb / max(accum.a, 1e-5);
+    return vec4<f32>(average, 1.0 - revealage);
+}
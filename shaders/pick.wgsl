@@ -0,0 +1,33 @@
+// Copies the depth under a picked pixel into a one pixel target, since depth-stencil
+// textures can't be copied into a buffer to read back.
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coord: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+}
+
+@vertex
+fn vertex_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(in.position, 1.0, 1.0);
+    return out;
+}
+
+// `PickUniform` in picking.rs.
+struct Pick {
+    pixel: vec2<u32>,
+}
+
+@group(0) @binding(0)
+var t_depth: texture_depth_2d;
+@group(0) @binding(1)
+var<uniform> pick: Pick;
+
+@fragment
+fn fragment_main(in: VertexOutput) -> @location(0) f32 {
+    return textureLoad(t_depth, vec2<i32>(pick.pixel), 0);
+}
@@ -0,0 +1,58 @@
+// Highlights the selected instance over the scene. The G-buffer pass marks its visible
+// pixels in the stencil, which are tinted, and the pixels just outside them outlined.
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coord: vec2<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+}
+
+@vertex
+fn vertex_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(in.position, 1.0, 1.0);
+    return out;
+}
+
+// `SelectionUniform` in picking.rs.
+struct Selection {
+    colour: vec4<f32>,
+    // The outline's width in pixels.
+    width: u32,
+}
+
+@group(0) @binding(0)
+var t_stencil: texture_2d<u32>;
+@group(0) @binding(1)
+var<uniform> selection: Selection;
+
+// A model's stencil value (64) with the selection bit (128) set on top.
+const SELECTED_STENCIL: u32 = 192u;
+// How much of the outline colour tints the instance itself.
+const FILL_ALPHA: f32 = 0.2;
+
+fn is_selected(pixel: vec2<i32>) -> bool {
+    let size = vec2<i32>(textureDimensions(t_stencil));
+    let stencil = textureLoad(t_stencil, clamp(pixel, vec2<i32>(0), size - 1), 0).r;
+    return stencil == SELECTED_STENCIL;
+}
+
+@fragment
+fn fragment_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(floor(in.position.xy));
+    if (is_selected(pixel)) {
+        return vec4<f32>(selection.colour.rgb, selection.colour.a * FILL_ALPHA);
+    }
+    let width = i32(selection.width);
+    for (var y = -1; y <= 1; y += 1) {
+        for (var x = -1; x <= 1; x += 1) {
+            if (is_selected(pixel + vec2<i32>(x, y) * width)) {
+                return selection.colour;
+            }
+        }
+    }
+    return vec4<f32>(0.0);
+}
@@ -1,16 +1,16 @@
+// Feature toggles, which can also be passed in as defines:
+// NORMAL_MAPPING - perturb lighting with the material's normal map.
+// ALPHA_MASK - discard fragments whose alpha is below the material's cutoff.
+
+#include "common/uniforms.wgsl"
+#include "common/light.wgsl"
+#include "common/octahedral.wgsl"
+
 // Vertex shader
 
-struct Uniforms {
-    view_pos: vec4<f32>,
-    view_proj: mat4x4<f32>,
-}
 @group(1) @binding(0)
 var<uniform> uniforms: Uniforms;
 
-struct Light {
-    position: vec3<f32>,
-    colour: vec3<f32>,
-}
 @group(2) @binding(0)
 var<uniform> light: Light;
 
@@ -20,6 +20,22 @@ struct VertexInput {
     @location(2) normal: vec3<f32>,
     @location(3) tangent: vec3<f32>,
     @location(4) bitangent: vec3<f32>,
+    @location(12) colour: vec4<f32>,
+}
+
+// QuantizedVertex: octahedral normal and tangent, with the bitangent's sign in tangent.z.
+struct QuantizedVertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coord: vec2<f32>,
+    @location(2) normal: vec2<f32>,
+    @location(3) tangent: vec4<f32>,
+    @location(12) colour: vec4<f32>,
+}
+
+// `VertexIds` in model.rs.
+struct VertexIds {
+    @location(13) material: u32,
+    @location(14) mesh: u32,
 }
 
 struct InstanceInput {
@@ -38,10 +54,20 @@ struct VertexOutput {
     @location(1) tangent_position: vec3<f32>,
     @location(2) tangent_light_position: vec3<f32>,
     @location(3) tangent_view_position: vec3<f32>,
+    @location(4) colour: vec4<f32>,
+    // Index into the material arrays of bindless.wgsl; left at 0 otherwise.
+    @location(5) @interpolate(flat) material: u32,
+    // The instance and mesh, from pick_id.
+    @location(6) @interpolate(flat) id: vec2<u32>,
 }
 
-@vertex
-fn vertex_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
+// The instance plus one and the mesh, in their own channels so neither limits the
+// other, and a 0 is left wherever nothing was drawn. Read by `decode_id` in picking.rs.
+fn pick_id(mesh: u32, instance: u32) -> vec2<u32> {
+    return vec2<u32>(instance + 1u, mesh);
+}
+
+fn transform_vertex(model: VertexInput, instance: InstanceInput) -> VertexOutput {
     let model_matrix = mat4x4<f32>(
         instance.model_matrix_0,
         instance.model_matrix_1,
@@ -70,12 +96,48 @@ fn vertex_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
 
     out.clip_position = uniforms.view_proj * world_position;
     out.tex_coords = model.tex_coord;
+    out.colour = model.colour;
     out.tangent_position = tangent_matrix * world_position.xyz;
     out.tangent_view_position = tangent_matrix * uniforms.view_pos.xyz;
     out.tangent_light_position = tangent_matrix * light.position;
     return out;
 }
 
+@vertex
+fn vertex_main(
+    model: VertexInput,
+    instance: InstanceInput,
+    ids: VertexIds,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    var out = transform_vertex(model, instance);
+    out.id = pick_id(ids.mesh, instance_index);
+    return out;
+}
+
+fn decode_quantized(model: QuantizedVertexInput) -> VertexInput {
+    var vertex: VertexInput;
+    vertex.position = model.position;
+    vertex.tex_coord = model.tex_coord;
+    vertex.normal = octahedral_decode(model.normal);
+    vertex.tangent = octahedral_decode(model.tangent.xy);
+    vertex.bitangent = cross(vertex.normal, vertex.tangent) * model.tangent.z;
+    vertex.colour = model.colour;
+    return vertex;
+}
+
+@vertex
+fn vertex_quantized(
+    model: QuantizedVertexInput,
+    instance: InstanceInput,
+    ids: VertexIds,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    var out = transform_vertex(decode_quantized(model), instance);
+    out.id = pick_id(ids.mesh, instance_index);
+    return out;
+}
+
 // Fragment shader
 
 @group(0) @binding(0)
@@ -88,17 +150,25 @@ var t_normal: texture_2d<f32>;
 @group(0) @binding(3)
 var s_normal: sampler;
 
+struct MaterialParams {
+    opacity: f32,
+    alpha_cutoff: f32,
+}
+
+@group(0) @binding(4)
+var<uniform> material: MaterialParams;
+
+// A tangent space normal pointing straight out of the surface.
+const FLAT_NORMAL: vec4<f32> = vec4<f32>(0.5, 0.5, 1.0, 1.0);
+
 struct FragmentOutput {
     @location(0) diffuse: vec4<f32>,
     @location(1) normal: vec4<f32>,
+    @location(2) id: vec2<u32>,
 }
 
-@fragment
-fn fragment_main(in: VertexOutput) -> FragmentOutput {
-
+fn shade(in: VertexOutput, object_colour: vec4<f32>, object_normal: vec4<f32>) -> FragmentOutput {
     var out: FragmentOutput;
-    let object_colour: vec4<f32> = textureSample(t_diffuse, s_diffuse, in.tex_coords);
-    let object_normal: vec4<f32> = textureSample(t_normal, s_normal, in.tex_coords);
 
     let ambient_magnitude = 0.1;
     let ambient_colour = light.colour * ambient_magnitude;
@@ -121,5 +191,22 @@ fn fragment_main(in: VertexOutput) -> FragmentOutput {
 
     out.diffuse = vec4<f32>(object_colour);
     out.normal = vec4<f32>(result, 1.0);
+    out.id = in.id;
     return out;
+}
+
+@fragment
+fn fragment_main(in: VertexOutput) -> FragmentOutput {
+    let object_colour: vec4<f32> = textureSample(t_diffuse, s_diffuse, in.tex_coords) * in.colour;
+#ifdef ALPHA_MASK
+    if (object_colour.a < material.alpha_cutoff) {
+        discard;
+    }
+#endif
+#ifdef NORMAL_MAPPING
+    let object_normal: vec4<f32> = textureSample(t_normal, s_normal, in.tex_coords);
+#else
+    let object_normal = FLAT_NORMAL;
+#endif
+    return shade(in, object_colour, object_normal);
 }
\ No newline at end of file
@@ -0,0 +1,55 @@
+// Screen-space glyphs and rectangles for overlays. Each instance stretches the screen quad
+// over a rectangle given in pixels and fills it with one cell of the font atlas, tinted.
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coord: vec2<f32>,
+}
+
+// `TextInstance` in text.rs.
+struct InstanceInput {
+    @location(2) rect: vec4<f32>,
+    @location(3) cell: vec2<f32>,
+    @location(4) colour: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) texel: vec2<f32>,
+    @location(1) colour: vec4<f32>,
+}
+
+// `TextUniform` in text.rs.
+struct Screen {
+    size: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var t_atlas: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> screen: Screen;
+
+// `CELL_WIDTH` and `CELL_HEIGHT` in text.rs.
+const CELL_SIZE: vec2<f32> = vec2<f32>(6.0, 8.0);
+
+@vertex
+fn vertex_main(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
+    // The quad's texture coordinates run from its top left corner, like pixels do.
+    let pixel = instance.rect.xy + vertex.tex_coord * instance.rect.zw;
+    let ndc = pixel / screen.size * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(ndc.x, -ndc.y, 0.0, 1.0);
+    out.texel = instance.cell + vertex.tex_coord * CELL_SIZE;
+    out.colour = instance.colour;
+    return out;
+}
+
+@fragment
+fn fragment_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let coverage = textureLoad(t_atlas, vec2<i32>(floor(in.texel)), 0).r;
+    if (coverage == 0.0) {
+        discard;
+    }
+    return vec4<f32>(in.colour.rgb, in.colour.a * coverage);
+}
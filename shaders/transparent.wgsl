@@ -0,0 +1,48 @@
+// Forward shading for blended materials, drawn over the lit scene after the deferred
+// passes. fragment_blend blends each fragment straight over what is behind it, so
+// instances have to be drawn back to front. fragment_oit instead accumulates weighted
+// blended order-independent transparency (McGuire and Bavoil, 2013), which
+// oit_composite.wgsl then resolves over the scene in any order. Neither writes a pick
+// ID, so blended meshes can't be picked and clicks go through to what is behind them.
+
+#include "shader.wgsl"
+
+fn shade_transparent(in: VertexOutput) -> vec4<f32> {
+    let object_colour = textureSample(t_diffuse, s_diffuse, in.tex_coords) * in.colour;
+#ifdef NORMAL_MAPPING
+    let object_normal = textureSample(t_normal, s_normal, in.tex_coords);
+#else
+    let object_normal = FLAT_NORMAL;
+#endif
+    let shaded = shade(in, object_colour, object_normal);
+    return vec4<f32>(object_colour.rgb * shaded.normal.rgb, object_colour.a * material.opacity);
+}
+
+@fragment
+fn fragment_blend(in: VertexOutput) -> @location(0) vec4<f32> {
+    return shade_transparent(in);
+}
+
+struct OitOutput {
+    // Premultiplied colour and alpha, summed with additive blending.
+    @location(0) accum: vec4<f32>,
+    // Alpha, which the blend state turns into the product of (1 - alpha).
+    @location(1) revealage: f32,
+}
+
+@fragment
+fn fragment_oit(in: VertexOutput) -> OitOutput {
+    let colour = shade_transparent(in);
+    // Favours fragments near the camera and those that are more opaque.
+    let depth = in.clip_position.z;
+    let weight = clamp(
+        pow(min(1.0, colour.a * 10.0) + 0.01, 3.0) * 1e8 * pow(1.0 - depth * 0.9, 3.0),
+        1e-2,
+        3e3,
+    );
+
+    var out: OitOutput;
+    out.accum = vec4<f32>(colour.rgb * colour.a, colour.a) * weight;
+    out.revealage = colour.a;
+    return out;
+}
@@ -0,0 +1,68 @@
+// The debug UI's meshes, as tessellated by egui. Positions are in points from the top left
+// corner of the window, and colours are premultiplied sRGB.
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coord: vec2<f32>,
+    @location(2) colour: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coord: vec2<f32>,
+    @location(1) colour: vec4<f32>,
+}
+
+// `UiUniform` in debug_ui.rs.
+struct Screen {
+    size_in_points: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> screen: Screen;
+
+@group(1) @binding(0)
+var t_ui: texture_2d<f32>;
+@group(1) @binding(1)
+var s_ui: sampler;
+
+fn linear_from_gamma(gamma: vec3<f32>) -> vec3<f32> {
+    let low = gamma / 12.92;
+    let high = pow((gamma + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, gamma < vec3<f32>(0.04045));
+}
+
+fn gamma_from_linear(linear: vec3<f32>) -> vec3<f32> {
+    let low = linear * 12.92;
+    let high = 1.055 * pow(linear, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, linear < vec3<f32>(0.0031308));
+}
+
+@vertex
+fn vertex_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    let ndc = in.position / screen.size_in_points * 2.0 - 1.0;
+    out.position = vec4<f32>(ndc.x, -ndc.y, 0.0, 1.0);
+    out.tex_coord = in.tex_coord;
+    out.colour = in.colour;
+    return out;
+}
+
+// egui blends in gamma space, so textures, which are sampled as linear, are converted
+// back before being tinted.
+fn gamma_colour(in: VertexOutput) -> vec4<f32> {
+    let texel = textureSample(t_ui, s_ui, in.tex_coord);
+    return in.colour * vec4<f32>(gamma_from_linear(texel.rgb), texel.a);
+}
+
+// For sRGB targets, which convert what is written back to gamma themselves.
+@fragment
+fn fragment_linear(in: VertexOutput) -> @location(0) vec4<f32> {
+    let colour = gamma_colour(in);
+    return vec4<f32>(linear_from_gamma(colour.rgb), colour.a);
+}
+
+@fragment
+fn fragment_gamma(in: VertexOutput) -> @location(0) vec4<f32> {
+    return gamma_colour(in);
+}
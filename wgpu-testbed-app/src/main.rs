@@ -1,7 +1,10 @@
 fn main() {
     #[cfg(debug_assertions)]
     enable_info_logging();
-    wgpu_testbed_lib::run();
+    if let Err(e) = wgpu_testbed_lib::run() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
 }
 
 fn enable_info_logging() {
@@ -0,0 +1,172 @@
+//! Bakes source assets into a single archive the runtime mounts at startup.
+//!
+//! Usage: `wgpu-testbed-bake [-o <archive>] [--tangents compute|mikktspace] [<path>...]`
+//!
+//! Paths are files or directories relative to the asset root, which is the current
+//! directory or `WGPU_TESTBED_ASSET_ROOT`, and default to `resources` and `shaders`.
+//! OBJ, glTF, PLY and STL models become `.bmdl` files, PNG, JPEG and TGA images become
+//! `.btex` files, and everything else is copied as is. Images embedded in a glTF are
+//! baked next to it. Tangents default to the averaging done by `compute_bitangents.wgsl`,
+//! run on the CPU.
+
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use wgpu_testbed_lib::{
+    BAKED_ARCHIVE, BAKED_MODEL_EXTENSION, BAKED_TEXTURE_EXTENSION, BakedModel, BakedTexture, Error,
+    Result, TangentMethod, TextureSource, baked_path,
+};
+
+const DEFAULT_INPUTS: [&str; 2] = ["resources", "shaders"];
+const USAGE: &str =
+    "Usage: wgpu-testbed-bake [-o <archive>] [--tangents compute|mikktspace] [<path>...]";
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
+    let mut output = PathBuf::from(BAKED_ARCHIVE);
+    let mut inputs = Vec::new();
+    let mut tangents = TangentMethod::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => match args.next() {
+                Some(path) => output = PathBuf::from(path),
+                None => usage(),
+            },
+            "--tangents" => match args.next().as_deref() {
+                Some("compute") => tangents = TangentMethod::Compute,
+                Some("mikktspace") => tangents = TangentMethod::MikkTSpace,
+                _ => usage(),
+            },
+            "-h" | "--help" => usage(),
+            _ => inputs.push(arg),
+        }
+    }
+    if inputs.is_empty() {
+        inputs.extend(DEFAULT_INPUTS.iter().map(|input| input.to_string()));
+    }
+
+    let root = std::env::var_os(wgpu_testbed_lib::ASSET_ROOT_VAR)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."));
+    let mut files = BTreeSet::new();
+    for input in &inputs {
+        for entry in walkdir::WalkDir::new(root.join(input)) {
+            let entry = entry.map_err(|e| Error::Io {
+                path: input.clone(),
+                source: e.into(),
+            })?;
+            if entry.file_type().is_file() {
+                files.insert(asset_path(&root, entry.path())?);
+            }
+        }
+    }
+
+    let archive_name = output.to_string_lossy().into_owned();
+    let archive_error = |source: std::io::Error| Error::Archive {
+        name: archive_name.clone(),
+        source,
+    };
+    let file = std::fs::File::create(&output).map_err(archive_error)?;
+    let mut archive = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+
+    let mut written = BTreeSet::new();
+    let mut referenced = BTreeSet::new();
+    let mut write = |name: String, contents: &[u8]| -> Result<()> {
+        info!("{} ({} bytes)", name, contents.len());
+        archive
+            .start_file(name.as_str(), options)
+            .map_err(|e| archive_error(e.into()))?;
+        archive.write_all(contents).map_err(archive_error)?;
+        written.insert(name);
+        Ok(())
+    };
+    for path in &files {
+        let (name, contents) = match extension(path).as_str() {
+            "obj" | "gltf" | "glb" | "ply" | "stl" => {
+                let mut model = futures::executor::block_on(BakedModel::bake(path, tangents))?;
+                for material in &mut model.materials {
+                    for source in [&mut material.diffuse, &mut material.normal] {
+                        if let TextureSource::Embedded { name, bytes } = source {
+                            let baked = baked_path(name, BAKED_TEXTURE_EXTENSION);
+                            write(baked.clone(), &BakedTexture::bake(name, bytes)?.encode())?;
+                            *source = TextureSource::Path(baked);
+                        }
+                        if let TextureSource::Path(path) = source {
+                            referenced.insert(path.clone());
+                        }
+                    }
+                }
+                (baked_path(path, BAKED_MODEL_EXTENSION), model.encode())
+            }
+            "png" | "jpg" | "jpeg" | "tga" => {
+                let bytes = read(&root, path)?;
+                let texture = BakedTexture::bake(path, &bytes)?;
+                (baked_path(path, BAKED_TEXTURE_EXTENSION), texture.encode())
+            }
+            // Baked models carry their materials and geometry, so the MTL files and glTF
+            // buffers are no longer needed.
+            "mtl" | "bin" => continue,
+            _ => (path.clone(), read(&root, path)?),
+        };
+
+        info!("Baked {}", path);
+        write(name, &contents)?;
+    }
+    archive.finish().map_err(|e| archive_error(e.into()))?;
+
+    for missing in referenced.difference(&written) {
+        warn!(
+            "{} is referenced by a baked model but was not baked",
+            missing
+        );
+    }
+    info!("Wrote {} assets to {}", written.len(), archive_name);
+    Ok(())
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
+
+/// The `/` separated path the runtime will request `path` by.
+fn asset_path(root: &Path, path: &Path) -> Result<String> {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let components = relative
+        .components()
+        .filter(|component| matches!(component, std::path::Component::Normal(_)))
+        .map(|component| {
+            component
+                .as_os_str()
+                .to_str()
+                .ok_or_else(|| Error::InvalidPath(path.to_path_buf()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(components.join("/"))
+}
+
+fn extension(path: &str) -> String {
+    Path::new(path)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+fn read(root: &Path, path: &str) -> Result<Vec<u8>> {
+    std::fs::read(root.join(path)).map_err(|source| Error::Io {
+        path: path.to_owned(),
+        source,
+    })
+}
@@ -0,0 +1,326 @@
+//! `#[derive(Bindable)]` for `wgpu-testbed-lib`, generating a bind group layout and the
+//! matching bind group entries from annotated fields.
+//!
+//! ```ignore
+//! #[derive(Bindable)]
+//! #[bindable(compute)]
+//! struct Binding {
+//!     #[storage]
+//!     vertices: wgpu::Buffer,
+//!     #[storage(read_only)]
+//!     indices: wgpu::Buffer,
+//!     #[uniform(binding = 3)]
+//!     info: wgpu::Buffer,
+//!     // Fields without a binding attribute are ignored.
+//!     count: u32,
+//! }
+//! ```
+//!
+//! Bindings are numbered in field order from 0, and `binding = N` moves the count on to
+//! `N`. Shader stages are set for the whole struct with `#[bindable(...)]`, and can be
+//! overridden per field with the same `vertex`, `fragment` and `compute` keywords.
+//!
+//! Buffers are bound whole, textures are filterable 2D float textures and samplers are
+//! filtering samplers. `#[texture(depth)]` and `#[texture(uint)]` bind depth and unsigned
+//! integer textures instead, such as the two aspects of a depth stencil texture, which
+//! can only be read with `textureLoad`. `#[texture]` fields must be `wgpu::TextureView`s
+//! and `#[sampler]` fields `wgpu::Sampler`s, or references to them.
+//!
+//! The generated impl names the trait as `crate::pipeline::Bindable`, which is where
+//! `wgpu-testbed-lib` defines it. Anywhere else, `#[bindable(path = some::Bindable)]`
+//! names the trait to implement instead.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{Data, DeriveInput, Fields, Ident, LitInt};
+
+#[proc_macro_derive(Bindable, attributes(bindable, uniform, storage, texture, sampler))]
+pub fn derive_bindable(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Uniform,
+    Storage { read_only: bool },
+    Texture(SampleType),
+    Sampler,
+}
+
+#[derive(Clone, Copy)]
+enum SampleType {
+    Float,
+    Depth,
+    Uint,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Stages {
+    vertex: bool,
+    fragment: bool,
+    compute: bool,
+}
+
+impl Stages {
+    fn is_empty(&self) -> bool {
+        !(self.vertex || self.fragment || self.compute)
+    }
+
+    /// Sets the stage named by `ident`, returning false if it isn't one.
+    fn set(&mut self, ident: &Ident) -> bool {
+        if ident == "vertex" {
+            self.vertex = true;
+        } else if ident == "fragment" {
+            self.fragment = true;
+        } else if ident == "compute" {
+            self.compute = true;
+        } else {
+            return false;
+        }
+        true
+    }
+
+    fn tokens(&self) -> TokenStream2 {
+        let mut stages = Vec::new();
+        if self.vertex {
+            stages.push(quote!(wgpu::ShaderStages::VERTEX));
+        }
+        if self.fragment {
+            stages.push(quote!(wgpu::ShaderStages::FRAGMENT));
+        }
+        if self.compute {
+            stages.push(quote!(wgpu::ShaderStages::COMPUTE));
+        }
+        quote!(#(#stages)|*)
+    }
+}
+
+struct Binding {
+    field: Ident,
+    /// Where the field's type was written, for errors about it not fitting the binding.
+    ty_span: proc_macro2::Span,
+    index: u32,
+    kind: Kind,
+    stages: Stages,
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new(
+                    input.ident.span(),
+                    "Bindable can only be derived for structs with named fields",
+                ));
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                input.ident.span(),
+                "Bindable can only be derived for structs",
+            ));
+        }
+    };
+
+    let mut default_stages = Stages::default();
+    let mut trait_path: syn::Path = syn::parse_quote!(crate::pipeline::Bindable);
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("bindable"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("path") {
+                trait_path = meta.value()?.parse()?;
+                return Ok(());
+            }
+            match meta.path.get_ident() {
+                Some(ident) if default_stages.set(ident) => Ok(()),
+                _ => Err(meta.error("expected `vertex`, `fragment`, `compute` or `path = ...`")),
+            }
+        })?;
+    }
+
+    let mut bindings = Vec::new();
+    let mut next_index = 0;
+    for field in fields {
+        let mut found = None;
+        for attr in &field.attrs {
+            let kind = if attr.path().is_ident("uniform") {
+                Kind::Uniform
+            } else if attr.path().is_ident("storage") {
+                Kind::Storage { read_only: false }
+            } else if attr.path().is_ident("texture") {
+                Kind::Texture(SampleType::Float)
+            } else if attr.path().is_ident("sampler") {
+                Kind::Sampler
+            } else {
+                continue;
+            };
+            if found.is_some() {
+                return Err(syn::Error::new(
+                    attr.span(),
+                    "a field can only have one binding attribute",
+                ));
+            }
+
+            let mut kind = kind;
+            let mut stages = Stages::default();
+            let mut index = next_index;
+            if let syn::Meta::List(_) = attr.meta {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("binding") {
+                        index = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                        return Ok(());
+                    }
+                    if meta.path.is_ident("read_only") {
+                        if let Kind::Storage { read_only } = &mut kind {
+                            *read_only = true;
+                            return Ok(());
+                        }
+                        return Err(meta.error("only storage buffers can be `read_only`"));
+                    }
+                    let sample_types = [("depth", SampleType::Depth), ("uint", SampleType::Uint)];
+                    for (name, sample_type) in sample_types {
+                        if meta.path.is_ident(name) {
+                            if let Kind::Texture(texture) = &mut kind {
+                                *texture = sample_type;
+                                return Ok(());
+                            }
+                            return Err(meta.error(format!("only textures can be `{}`", name)));
+                        }
+                    }
+                    match meta.path.get_ident() {
+                        Some(ident) if stages.set(ident) => Ok(()),
+                        _ => Err(meta.error(
+                            "expected `binding = N`, `read_only`, `depth`, `uint`, `vertex`, \
+                             `fragment` or `compute`",
+                        )),
+                    }
+                })?;
+            }
+            if stages.is_empty() {
+                stages = default_stages;
+            }
+            if stages.is_empty() {
+                return Err(syn::Error::new(
+                    attr.span(),
+                    "no shader stages given; add them here or in `#[bindable(...)]` on the struct",
+                ));
+            }
+            found = Some(Binding {
+                field: field.ident.clone().unwrap(),
+                ty_span: field.ty.span(),
+                index,
+                kind,
+                stages,
+            });
+            next_index = index + 1;
+        }
+        bindings.extend(found);
+    }
+
+    for (i, binding) in bindings.iter().enumerate() {
+        if let Some(other) = bindings[..i]
+            .iter()
+            .find(|other| other.index == binding.index)
+        {
+            return Err(syn::Error::new(
+                binding.field.span(),
+                format!(
+                    "binding {} is already used by `{}`",
+                    binding.index, other.field
+                ),
+            ));
+        }
+    }
+
+    let layout_entries = bindings.iter().map(|binding| {
+        let index = binding.index;
+        let visibility = binding.stages.tokens();
+        let ty = match binding.kind {
+            Kind::Uniform => quote! {
+                wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                }
+            },
+            Kind::Storage { read_only } => quote! {
+                wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: #read_only },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                }
+            },
+            Kind::Texture(sample_type) => {
+                let sample_type = match sample_type {
+                    SampleType::Float => {
+                        quote!(wgpu::TextureSampleType::Float { filterable: true })
+                    }
+                    SampleType::Depth => quote!(wgpu::TextureSampleType::Depth),
+                    SampleType::Uint => quote!(wgpu::TextureSampleType::Uint),
+                };
+                quote! {
+                    wgpu::BindingType::Texture {
+                        sample_type: #sample_type,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    }
+                }
+            }
+            Kind::Sampler => quote! {
+                wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering)
+            },
+        };
+        quote! {
+            wgpu::BindGroupLayoutEntry {
+                binding: #index,
+                visibility: #visibility,
+                ty: #ty,
+                count: None,
+            }
+        }
+    });
+
+    let bind_group_entries = bindings.iter().map(|binding| {
+        let index = binding.index;
+        let field = &binding.field;
+        let span = binding.ty_span;
+        let resource = match binding.kind {
+            Kind::Uniform | Kind::Storage { .. } => {
+                quote_spanned!(span=> self.#field.as_entire_binding())
+            }
+            Kind::Texture(_) => {
+                quote_spanned!(span=> wgpu::BindingResource::TextureView(&self.#field))
+            }
+            Kind::Sampler => quote_spanned!(span=> wgpu::BindingResource::Sampler(&self.#field)),
+        };
+        quote! {
+            wgpu::BindGroupEntry {
+                binding: #index,
+                resource: #resource,
+            }
+        }
+    });
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #trait_path for #name #ty_generics #where_clause {
+            fn layout_entries() -> Vec<wgpu::BindGroupLayoutEntry> {
+                vec![#(#layout_entries),*]
+            }
+
+            fn bind_group_entries(&self) -> Vec<wgpu::BindGroupEntry<'_>> {
+                vec![#(#bind_group_entries),*]
+            }
+        }
+    })
+}
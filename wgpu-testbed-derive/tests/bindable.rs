@@ -0,0 +1,211 @@
+use wgpu_testbed_derive::Bindable;
+
+/// The generated impl names `crate::pipeline::Bindable`, so this mirrors the trait from
+/// `wgpu-testbed-lib`.
+mod pipeline {
+    pub trait Bindable {
+        fn layout_entries() -> Vec<wgpu::BindGroupLayoutEntry>;
+        fn bind_group_entries(&self) -> Vec<wgpu::BindGroupEntry<'_>>;
+    }
+}
+
+use pipeline::Bindable;
+
+fn entry(
+    binding: u32,
+    visibility: wgpu::ShaderStages,
+    ty: wgpu::BindingType,
+) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty,
+        count: None,
+    }
+}
+
+fn buffer(ty: wgpu::BufferBindingType) -> wgpu::BindingType {
+    wgpu::BindingType::Buffer {
+        ty,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+    }
+}
+
+fn texture(sample_type: wgpu::TextureSampleType) -> wgpu::BindingType {
+    wgpu::BindingType::Texture {
+        sample_type,
+        view_dimension: wgpu::TextureViewDimension::D2,
+        multisampled: false,
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Bindable)]
+#[bindable(vertex, fragment)]
+struct Everything {
+    #[uniform]
+    uniform: wgpu::Buffer,
+    #[storage(fragment)]
+    storage: wgpu::Buffer,
+    #[storage(read_only, compute)]
+    read_only: wgpu::Buffer,
+    #[texture(binding = 5)]
+    texture: wgpu::TextureView,
+    #[sampler(fragment)]
+    sampler: wgpu::Sampler,
+    #[texture(depth, binding = 9)]
+    depth: wgpu::TextureView,
+    #[texture(uint, fragment)]
+    stencil: wgpu::TextureView,
+    ignored: u32,
+}
+
+#[test]
+fn layout_entries_follow_fields() {
+    let vertex_fragment = wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT;
+    let fragment = wgpu::ShaderStages::FRAGMENT;
+    assert_eq!(
+        Everything::layout_entries(),
+        [
+            entry(0, vertex_fragment, buffer(wgpu::BufferBindingType::Uniform)),
+            entry(
+                1,
+                fragment,
+                buffer(wgpu::BufferBindingType::Storage { read_only: false }),
+            ),
+            entry(
+                2,
+                wgpu::ShaderStages::COMPUTE,
+                buffer(wgpu::BufferBindingType::Storage { read_only: true }),
+            ),
+            // `binding = N` skips ahead, and the fields after it count on from there.
+            entry(
+                5,
+                vertex_fragment,
+                texture(wgpu::TextureSampleType::Float { filterable: true }),
+            ),
+            entry(
+                6,
+                fragment,
+                wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            ),
+            entry(9, vertex_fragment, texture(wgpu::TextureSampleType::Depth)),
+            entry(10, fragment, texture(wgpu::TextureSampleType::Uint)),
+        ]
+    );
+}
+
+mod other {
+    pub trait Layout {
+        fn layout_entries() -> Vec<wgpu::BindGroupLayoutEntry>;
+        fn bind_group_entries(&self) -> Vec<wgpu::BindGroupEntry<'_>>;
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Bindable)]
+#[bindable(compute, path = other::Layout)]
+struct ElsewhereBinding {
+    #[storage(binding = 2)]
+    values: wgpu::Buffer,
+}
+
+#[test]
+fn path_names_the_trait_to_implement() {
+    assert_eq!(
+        <ElsewhereBinding as other::Layout>::layout_entries(),
+        [entry(
+            2,
+            wgpu::ShaderStages::COMPUTE,
+            buffer(wgpu::BufferBindingType::Storage { read_only: false }),
+        )]
+    );
+}
+
+/// A device on the default adapter, or `None` on machines without one.
+fn device() -> Option<wgpu::Device> {
+    let instance = wgpu::Instance::default();
+    let adapter = futures::executor::block_on(
+        instance.request_adapter(&wgpu::RequestAdapterOptions::default()),
+    )
+    .ok()?;
+    let (device, _queue) =
+        futures::executor::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default()))
+            .ok()?;
+    Some(device)
+}
+
+fn bind_group<T>(
+    device: &wgpu::Device,
+    layout_entries: &[wgpu::BindGroupLayoutEntry],
+    entries: &[wgpu::BindGroupEntry],
+) -> wgpu::BindGroup {
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: None,
+        entries: layout_entries,
+    });
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(std::any::type_name::<T>()),
+        layout: &layout,
+        entries,
+    })
+}
+
+#[test]
+fn bind_group_entries_fit_the_layout() {
+    let Some(device) = device() else {
+        eprintln!("No adapter, skipping the bind group test");
+        return;
+    };
+    let buffer = |usage| {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 256,
+            usage,
+            mapped_at_creation: false,
+        })
+    };
+    let view = |format| {
+        device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d {
+                    width: 4,
+                    height: 4,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&Default::default())
+    };
+    let binding = Everything {
+        uniform: buffer(wgpu::BufferUsages::UNIFORM),
+        storage: buffer(wgpu::BufferUsages::STORAGE),
+        read_only: buffer(wgpu::BufferUsages::STORAGE),
+        texture: view(wgpu::TextureFormat::Rgba8Unorm),
+        sampler: device.create_sampler(&Default::default()),
+        depth: view(wgpu::TextureFormat::Depth32Float),
+        stencil: view(wgpu::TextureFormat::R32Uint),
+        ignored: 0,
+    };
+    let entries = binding.bind_group_entries();
+    let bindings: Vec<_> = entries.iter().map(|entry| entry.binding).collect();
+    assert_eq!(bindings, [0, 1, 2, 5, 6, 9, 10]);
+
+    // Any mismatch between the entries and the layout fails validation, which panics.
+    let _ = bind_group::<Everything>(&device, &Everything::layout_entries(), &entries);
+    let elsewhere = ElsewhereBinding {
+        values: buffer(wgpu::BufferUsages::STORAGE),
+    };
+    let _ = bind_group::<ElsewhereBinding>(
+        &device,
+        &<ElsewhereBinding as other::Layout>::layout_entries(),
+        &other::Layout::bind_group_entries(&elsewhere),
+    );
+}
@@ -0,0 +1,4 @@
+#[test]
+fn rejected_bindings() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,16 @@
+use wgpu_testbed_derive::Bindable;
+
+mod pipeline {
+    pub trait Bindable {
+        fn layout_entries() -> Vec<wgpu::BindGroupLayoutEntry>;
+        fn bind_group_entries(&self) -> Vec<wgpu::BindGroupEntry<'_>>;
+    }
+}
+
+#[derive(Bindable)]
+struct Binding {
+    #[uniform]
+    info: wgpu::Buffer,
+}
+
+fn main() {}
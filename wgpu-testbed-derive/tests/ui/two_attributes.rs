@@ -0,0 +1,18 @@
+use wgpu_testbed_derive::Bindable;
+
+mod pipeline {
+    pub trait Bindable {
+        fn layout_entries() -> Vec<wgpu::BindGroupLayoutEntry>;
+        fn bind_group_entries(&self) -> Vec<wgpu::BindGroupEntry<'_>>;
+    }
+}
+
+#[derive(Bindable)]
+#[bindable(compute)]
+struct Binding {
+    #[uniform]
+    #[storage]
+    info: wgpu::Buffer,
+}
+
+fn main() {}
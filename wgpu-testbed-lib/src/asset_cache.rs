@@ -0,0 +1,445 @@
+use std::convert::{TryFrom, TryInto};
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+use log::{debug, warn};
+
+use crate::model::ModelVertex;
+
+/// Environment variable overriding where processed assets are cached.
+pub const CACHE_DIR_VAR: &str = "WGPU_TESTBED_CACHE_DIR";
+
+/// Bumped whenever the layout of cache and baked files, or the processing that
+/// produced them, changes so stale entries are never read back.
+const CACHE_VERSION: u32 = 8;
+const MAGIC: &[u8; 4] = b"WTBC";
+
+static CACHE: LazyLock<AssetCache> = LazyLock::new(AssetCache::from_env);
+
+/// A cache key derived from the content of a source asset and the settings it
+/// was processed with. Any change to either produces a different key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheKey(u128);
+
+impl CacheKey {
+    pub fn new(kind: &str, source: &[u8], settings: &[u8]) -> Self {
+        let mut hasher = xxhash_rust::xxh3::Xxh3::new();
+        hasher.update(&CACHE_VERSION.to_le_bytes());
+        hasher.update(kind.as_bytes());
+        hasher.update(&(settings.len() as u64).to_le_bytes());
+        hasher.update(settings);
+        hasher.update(source);
+        Self(hasher.digest128())
+    }
+
+    /// Derives a key for the same source processed with additional settings.
+    pub fn with_settings(&self, settings: &[u8]) -> Self {
+        let mut hasher = xxhash_rust::xxh3::Xxh3::new();
+        hasher.update(&self.0.to_le_bytes());
+        hasher.update(settings);
+        Self(hasher.digest128())
+    }
+
+    fn file_name(&self, kind: &str) -> String {
+        format!("{:032x}.{}", self.0, kind)
+    }
+}
+
+/// A decoded texture with its full mip chain, tightly packed as RGBA8.
+pub struct TextureData {
+    pub width: u32,
+    pub height: u32,
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl TextureData {
+    /// Serializes the decoded levels for the cache. Baked `.btex` files keep theirs
+    /// compressed, see [`crate::BakedTexture`].
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.u32(self.width);
+        writer.u32(self.height);
+        writer.u32(self.levels.len() as u32);
+        for level in &self.levels {
+            writer.bytes(level);
+        }
+        writer.finish()
+    }
+
+    /// Returns `None` unless every level is the size its dimensions call for, so a
+    /// truncated or corrupt file never reaches the GPU.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader::new(unframe(bytes)?);
+        let width = reader.u32()?;
+        let height = reader.u32()?;
+        let level_count = reader.u32()?;
+        if width == 0 || height == 0 || level_count == 0 {
+            return None;
+        }
+        if level_count > 32 - width.max(height).leading_zeros() {
+            return None;
+        }
+        let levels = (0..level_count)
+            .map(|level| {
+                let bytes = reader.bytes()?;
+                (bytes.len() == Self::level_size(width, height, level)?).then(|| bytes.to_vec())
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self {
+            width,
+            height,
+            levels,
+        })
+    }
+
+    /// The bytes in mip `level` of a `width` by `height` texture.
+    fn level_size(width: u32, height: u32, level: u32) -> Option<usize> {
+        let width = (width >> level).max(1) as usize;
+        let height = (height >> level).max(1) as usize;
+        width.checked_mul(height)?.checked_mul(4)
+    }
+}
+
+/// A mesh whose tangents and bitangents have already been generated.
+pub struct MeshData {
+    pub name: String,
+    pub material: usize,
+    pub vertices: Vec<ModelVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    pub(crate) fn write_all(writer: &mut Writer, meshes: &[MeshData]) {
+        writer.u32(meshes.len() as u32);
+        for mesh in meshes {
+            writer.bytes(mesh.name.as_bytes());
+            writer.u32(mesh.material as u32);
+            writer.bytes(bytemuck::cast_slice(&mesh.vertices));
+            writer.bytes(bytemuck::cast_slice(&mesh.indices));
+        }
+    }
+
+    pub(crate) fn read_all(reader: &mut Reader) -> Option<Vec<MeshData>> {
+        let mesh_count = reader.u32()?;
+        (0..mesh_count)
+            .map(|_| {
+                Some(MeshData {
+                    name: reader.string()?,
+                    material: reader.u32()? as usize,
+                    vertices: pod_vec(reader.bytes()?)?,
+                    indices: pod_vec(reader.bytes()?)?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// On-disk cache of processed assets. Disabled on wasm, where there is no disk,
+/// and whenever the cache directory cannot be created.
+pub struct AssetCache {
+    dir: Option<PathBuf>,
+}
+
+impl AssetCache {
+    pub fn global() -> &'static AssetCache {
+        &CACHE
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn from_env() -> Self {
+        Self { dir: None }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn from_env() -> Self {
+        let dir = std::env::var_os(CACHE_DIR_VAR)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| std::env::temp_dir().join("wgpu-testbed-cache"));
+        match std::fs::create_dir_all(&dir) {
+            Ok(()) => Self { dir: Some(dir) },
+            Err(e) => {
+                warn!("Asset cache disabled, could not create {:?}: {}", dir, e);
+                Self { dir: None }
+            }
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    pub fn load_texture(&self, key: CacheKey) -> Option<TextureData> {
+        TextureData::decode(&self.read(key, "tex")?)
+    }
+
+    pub fn store_texture(&self, key: CacheKey, texture: &TextureData) {
+        self.write(key, "tex", &texture.encode());
+    }
+
+    pub fn load_meshes(&self, key: CacheKey) -> Option<Vec<MeshData>> {
+        let bytes = self.read(key, "mesh")?;
+        MeshData::read_all(&mut Reader::new(unframe(&bytes)?))
+    }
+
+    pub fn store_meshes(&self, key: CacheKey, meshes: &[MeshData]) {
+        let mut writer = Writer::default();
+        MeshData::write_all(&mut writer, meshes);
+        self.write(key, "mesh", &writer.finish());
+    }
+
+    /// Reads pipeline cache data saved for `adapter`. Data saved for another adapter or
+    /// driver, or that no longer matches its checksum, is ignored.
+    pub fn load_pipeline_cache(&self, key: CacheKey, adapter: &str) -> Option<Vec<u8>> {
+        let bytes = self.read(key, "pipeline")?;
+        let mut reader = Reader::new(unframe(&bytes)?);
+        if reader.bytes()? != adapter.as_bytes() {
+            warn!("Ignoring pipeline cache saved for another adapter or driver");
+            return None;
+        }
+        let checksum = reader.u64()?;
+        let data = reader.bytes()?;
+        if xxhash_rust::xxh3::xxh3_64(data) != checksum {
+            warn!("Ignoring corrupt pipeline cache");
+            return None;
+        }
+        Some(data.to_vec())
+    }
+
+    pub fn store_pipeline_cache(&self, key: CacheKey, adapter: &str, data: &[u8]) {
+        let mut writer = Writer::default();
+        writer.bytes(adapter.as_bytes());
+        writer.u64(xxhash_rust::xxh3::xxh3_64(data));
+        writer.bytes(data);
+        self.write(key, "pipeline", &writer.finish());
+    }
+
+    fn read(&self, key: CacheKey, kind: &str) -> Option<Vec<u8>> {
+        let path = self.dir.as_ref()?.join(key.file_name(kind));
+        let bytes = std::fs::read(&path).ok()?;
+        if unframe(&bytes).is_none() {
+            warn!("Ignoring stale cache entry {:?}", path);
+            return None;
+        }
+        debug!("Cache hit {:?}", path);
+        Some(bytes)
+    }
+
+    fn write(&self, key: CacheKey, kind: &str, framed: &[u8]) {
+        let Some(dir) = self.dir.as_ref() else {
+            return;
+        };
+        let path = dir.join(key.file_name(kind));
+
+        // Write then rename, so a crash mid-write never leaves a truncated entry behind.
+        let temp_path = path.with_extension(format!(
+            "{}.{}-{:?}.tmp",
+            kind,
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        if let Err(e) =
+            std::fs::write(&temp_path, framed).and_then(|_| std::fs::rename(&temp_path, &path))
+        {
+            warn!("Failed to write cache entry {:?}: {}", path, e);
+        }
+    }
+}
+
+/// Copies bytes into a correctly aligned vector, as cache files give no alignment guarantees.
+pub(crate) fn pod_vec<T: bytemuck::Pod>(bytes: &[u8]) -> Option<Vec<T>> {
+    let size = std::mem::size_of::<T>();
+    if !bytes.len().is_multiple_of(size) {
+        return None;
+    }
+    let mut values = vec![T::zeroed(); bytes.len() / size];
+    bytemuck::cast_slice_mut(&mut values).copy_from_slice(bytes);
+    Some(values)
+}
+
+/// Strips and checks the header written by [`Writer::finish`], returning the payload.
+pub(crate) fn unframe(bytes: &[u8]) -> Option<&[u8]> {
+    let mut reader = Reader::new(bytes);
+    if reader.take(MAGIC.len())? != MAGIC || reader.u32()? != CACHE_VERSION {
+        return None;
+    }
+    Some(&bytes[reader.offset..])
+}
+
+pub(crate) struct Writer(Vec<u8>);
+
+impl Default for Writer {
+    fn default() -> Self {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&CACHE_VERSION.to_le_bytes());
+        Self(bytes)
+    }
+}
+
+impl Writer {
+    /// Returns the written bytes, prefixed with the magic and version header.
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.0
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn f32(&mut self, value: f32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.0
+            .extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+        self.0.extend_from_slice(bytes);
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.offset.checked_add(len)?;
+        let slice = self.bytes.get(self.offset..end)?;
+        self.offset = end;
+        Some(slice)
+    }
+
+    pub(crate) fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    pub(crate) fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    pub(crate) fn f32(&mut self) -> Option<f32> {
+        Some(f32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    pub(crate) fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u64()?;
+        self.take(usize::try_from(len).ok()?)
+    }
+
+    pub(crate) fn string(&mut self) -> Option<String> {
+        String::from_utf8(self.bytes()?.to_vec()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texture() -> TextureData {
+        let level = |width: usize, height: usize| {
+            (0..width * height * 4).map(|i| i as u8).collect::<Vec<_>>()
+        };
+        TextureData {
+            width: 4,
+            height: 2,
+            levels: vec![level(4, 2), level(2, 1), level(1, 1)],
+        }
+    }
+
+    #[test]
+    fn reader_reads_what_writer_wrote() {
+        let mut writer = Writer::default();
+        writer.u32(7);
+        writer.u64(u64::MAX - 1);
+        writer.f32(-0.5);
+        writer.bytes(b"bytes");
+        writer.bytes("string".as_bytes());
+        let bytes = writer.finish();
+
+        let mut reader = Reader::new(unframe(&bytes).unwrap());
+        assert_eq!(reader.u32(), Some(7));
+        assert_eq!(reader.u64(), Some(u64::MAX - 1));
+        assert_eq!(reader.f32(), Some(-0.5));
+        assert_eq!(reader.bytes(), Some(&b"bytes"[..]));
+        assert_eq!(reader.string().as_deref(), Some("string"));
+        assert_eq!(reader.u32(), None);
+    }
+
+    #[test]
+    fn unframe_rejects_other_versions() {
+        let mut bytes = Writer::default().finish();
+        assert!(unframe(&bytes).is_some());
+        bytes[MAGIC.len()] ^= 1;
+        assert!(unframe(&bytes).is_none());
+        assert!(unframe(b"WTB").is_none());
+    }
+
+    #[test]
+    fn texture_round_trips() {
+        let texture = texture();
+        let decoded = TextureData::decode(&texture.encode()).unwrap();
+        assert_eq!((decoded.width, decoded.height), (4, 2));
+        assert_eq!(decoded.levels, texture.levels);
+    }
+
+    #[test]
+    fn texture_rejects_corrupt_payloads() {
+        let encoded = texture().encode();
+        for len in 0..encoded.len() {
+            assert!(TextureData::decode(&encoded[..len]).is_none(), "{}", len);
+        }
+
+        let mut short_level = texture();
+        short_level.levels[1].pop();
+        assert!(TextureData::decode(&short_level.encode()).is_none());
+
+        let mut extra_level = texture();
+        extra_level.levels.push(vec![0; 4]);
+        assert!(TextureData::decode(&extra_level.encode()).is_none());
+
+        let mut wrong_size = texture();
+        wrong_size.width = 8;
+        assert!(TextureData::decode(&wrong_size.encode()).is_none());
+
+        let mut huge_length = texture().encode();
+        // The first level's length follows the header and the three u32 fields.
+        let length = MAGIC.len() + 4 + 12;
+        huge_length[length..length + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(TextureData::decode(&huge_length).is_none());
+    }
+
+    #[test]
+    fn meshes_round_trip() {
+        let vertex_bytes: Vec<u8> = (0..3 * std::mem::size_of::<ModelVertex>())
+            .map(|i| i as u8)
+            .collect();
+        let meshes = [MeshData {
+            name: "mesh".to_owned(),
+            material: 3,
+            vertices: pod_vec(&vertex_bytes).unwrap(),
+            indices: vec![0, 1, 2],
+        }];
+        let mut writer = Writer::default();
+        MeshData::write_all(&mut writer, &meshes);
+        let bytes = writer.finish();
+
+        let decoded = MeshData::read_all(&mut Reader::new(unframe(&bytes).unwrap())).unwrap();
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].name, "mesh");
+        assert_eq!(decoded[0].material, 3);
+        assert_eq!(decoded[0].indices, meshes[0].indices);
+        assert_eq!(
+            bytemuck::cast_slice::<_, u8>(&decoded[0].vertices),
+            bytemuck::cast_slice::<_, u8>(&meshes[0].vertices)
+        );
+        assert!(MeshData::read_all(&mut Reader::new(&unframe(&bytes).unwrap()[..20])).is_none());
+    }
+}
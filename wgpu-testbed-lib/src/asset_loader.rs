@@ -0,0 +1,288 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures::channel::mpsc;
+
+use crate::error::Error;
+use crate::model::{Material, MeshOptions, Model, ModelLoader};
+use crate::pipeline::RenderPipelineCache;
+use crate::profiler::GpuProfiler;
+use crate::shader::ShaderCache;
+use crate::state::{SceneLayouts, ScenePipelines};
+use crate::texture::Texture;
+
+pub const PLACEHOLDER_DIFFUSE: [u8; 4] = [128, 128, 128, 255];
+pub const PLACEHOLDER_NORMAL: [u8; 4] = [128, 128, 255, 255];
+
+pub enum LoadEvent {
+    Pipelines(ScenePipelines),
+    Model(Model),
+    Texture {
+        material: usize,
+        kind: &'static str,
+        texture: Texture,
+    },
+    Failed(Error),
+}
+
+/// Counts scheduled and finished loads. The total grows as loads discover more
+/// work, e.g. once an OBJ has been parsed and its textures are known.
+#[derive(Clone, Default)]
+pub struct LoadProgress {
+    completed: Arc<AtomicUsize>,
+    total: Arc<AtomicUsize>,
+}
+
+impl LoadProgress {
+    fn schedule(&self, count: usize) {
+        self.total.fetch_add(count, Ordering::SeqCst);
+    }
+
+    fn complete(&self) {
+        self.completed.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn fraction(&self) -> f32 {
+        let total = self.total.load(Ordering::SeqCst);
+        if total == 0 {
+            return 1.0;
+        }
+        self.completed.load(Ordering::SeqCst) as f32 / total as f32
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.completed.load(Ordering::SeqCst) >= self.total.load(Ordering::SeqCst)
+    }
+}
+
+/// Loads scene assets in the background. Each load runs on its own worker thread
+/// natively and as a concurrent task on wasm, and finished assets are handed back
+/// through [`AssetLoader::poll`].
+pub struct AssetLoader {
+    receiver: mpsc::UnboundedReceiver<LoadEvent>,
+    progress: LoadProgress,
+}
+
+impl AssetLoader {
+    #[allow(clippy::too_many_arguments)]
+    pub fn start(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layouts: &SceneLayouts,
+        shaders: &ShaderCache,
+        pipelines: &RenderPipelineCache,
+        profiler: &GpuProfiler,
+        surface_format: wgpu::TextureFormat,
+        model_path: &str,
+        options: MeshOptions,
+    ) -> Self {
+        let (sender, receiver) = mpsc::unbounded();
+        let progress = LoadProgress::default();
+
+        // One unit per shader file, plus the model itself.
+        progress.schedule(ScenePipelines::SHADER_COUNT + 1);
+
+        spawn_task({
+            let device = device.clone();
+            let layouts = layouts.clone();
+            let shaders = shaders.clone();
+            let pipelines = pipelines.clone();
+            let sender = sender.clone();
+            let progress = progress.clone();
+            move || async move {
+                let event = match ScenePipelines::load(
+                    &device,
+                    &layouts,
+                    &shaders,
+                    &pipelines,
+                    surface_format,
+                    || progress.complete(),
+                )
+                .await
+                {
+                    Ok(pipelines) => LoadEvent::Pipelines(pipelines),
+                    Err(e) => LoadEvent::Failed(e),
+                };
+                let _ = sender.unbounded_send(event);
+            }
+        });
+
+        spawn_model_load(
+            device, queue, layouts, shaders, pipelines, profiler, model_path, options, sender,
+            &progress,
+        );
+
+        Self { receiver, progress }
+    }
+
+    /// Loads just a model, for swapping the scene's once the pipelines have loaded.
+    /// Anything the loader it replaces is still loading is dropped with that loader.
+    #[allow(clippy::too_many_arguments)]
+    pub fn start_model(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layouts: &SceneLayouts,
+        shaders: &ShaderCache,
+        pipelines: &RenderPipelineCache,
+        profiler: &GpuProfiler,
+        model_path: &str,
+        options: MeshOptions,
+    ) -> Self {
+        let (sender, receiver) = mpsc::unbounded();
+        let progress = LoadProgress::default();
+        progress.schedule(1);
+        spawn_model_load(
+            device, queue, layouts, shaders, pipelines, profiler, model_path, options, sender,
+            &progress,
+        );
+        Self { receiver, progress }
+    }
+
+    pub fn poll(&mut self) -> Option<LoadEvent> {
+        self.receiver.try_recv().ok()
+    }
+
+    pub fn progress(&self) -> &LoadProgress {
+        &self.progress
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_model_load(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layouts: &SceneLayouts,
+    shaders: &ShaderCache,
+    pipelines: &RenderPipelineCache,
+    profiler: &GpuProfiler,
+    model_path: &str,
+    options: MeshOptions,
+    sender: mpsc::UnboundedSender<LoadEvent>,
+    progress: &LoadProgress,
+) {
+    spawn_task({
+        let device = device.clone();
+        let queue = queue.clone();
+        let layouts = layouts.clone();
+        let shaders = shaders.clone();
+        let pipelines = pipelines.clone();
+        let profiler = profiler.clone();
+        let progress = progress.clone();
+        let model_path = model_path.to_owned();
+        move || async move {
+            if let Err(e) = load_model(
+                device,
+                queue,
+                layouts,
+                shaders,
+                pipelines,
+                profiler,
+                &model_path,
+                options,
+                sender.clone(),
+                progress,
+            )
+            .await
+            {
+                let _ = sender.unbounded_send(LoadEvent::Failed(e));
+            }
+        }
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn load_model(
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    layouts: SceneLayouts,
+    shaders: ShaderCache,
+    pipelines: RenderPipelineCache,
+    profiler: GpuProfiler,
+    path: &str,
+    options: MeshOptions,
+    sender: mpsc::UnboundedSender<LoadEvent>,
+    progress: LoadProgress,
+) -> crate::error::Result<()> {
+    let model_loader =
+        ModelLoader::new(&device, &shaders, pipelines.driver_cache(), &profiler).await?;
+    let scene = ModelLoader::parse(path, options).await?;
+
+    let diffuse_placeholder = Texture::placeholder(&device, &queue, PLACEHOLDER_DIFFUSE, false);
+    let normal_placeholder = Texture::placeholder(&device, &queue, PLACEHOLDER_NORMAL, true);
+
+    progress.schedule(scene.materials.len() * 2);
+    for (index, material) in scene.materials.iter().enumerate() {
+        for (kind, texture_source, is_normal_map) in [
+            ("diffuse", &material.diffuse, false),
+            ("normal", &material.normal, true),
+        ] {
+            spawn_task({
+                let device = device.clone();
+                let queue = queue.clone();
+                let texture_source = texture_source.clone();
+                let sender = sender.clone();
+                let progress = progress.clone();
+                move || async move {
+                    let texture =
+                        Texture::load_source(&device, &queue, &texture_source, is_normal_map);
+                    let event = match texture.await {
+                        Ok(texture) => LoadEvent::Texture {
+                            material: index,
+                            kind,
+                            texture,
+                        },
+                        Err(e) => LoadEvent::Failed(e),
+                    };
+                    progress.complete();
+                    let _ = sender.unbounded_send(event);
+                }
+            });
+        }
+    }
+
+    let mut materials: Vec<_> = scene
+        .materials
+        .iter()
+        .map(|material| {
+            Material::new(
+                &device,
+                &layouts.texture,
+                &material.name,
+                diffuse_placeholder.clone(),
+                normal_placeholder.clone(),
+                material.params,
+            )
+        })
+        .collect();
+    if materials.is_empty() {
+        materials.push(Material::untextured(&device, &queue, &layouts.texture));
+    }
+    let meshes = model_loader.build_meshes(&device, &queue, &scene, options)?;
+
+    progress.complete();
+    let _ = sender.unbounded_send(LoadEvent::Model(Model {
+        meshes,
+        materials,
+        vertex_layout: options.vertex_layout,
+    }));
+    Ok(())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn spawn_task<F, Fut>(task: F)
+where
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + 'static,
+{
+    std::thread::spawn(move || futures::executor::block_on(task()));
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn spawn_task<F, Fut>(task: F)
+where
+    F: FnOnce() -> Fut + 'static,
+    Fut: Future<Output = ()> + 'static,
+{
+    wasm_bindgen_futures::spawn_local(task());
+}
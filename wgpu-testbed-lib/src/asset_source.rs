@@ -0,0 +1,261 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::Read;
+
+use futures::future::LocalBoxFuture;
+
+use crate::error::{Error, Result};
+
+/// A place assets can be read from. Returning `Ok(None)` means the source does not
+/// contain the path, so lookup falls through to the next mounted source.
+pub trait AssetSource: Send + Sync {
+    fn read<'a>(&'a self, path: &'a str) -> LocalBoxFuture<'a, Result<Option<Vec<u8>>>>;
+}
+
+/// Reads assets relative to a directory on disk, or relative to a base URL on wasm.
+pub struct DirectorySource {
+    root: String,
+}
+
+impl DirectorySource {
+    pub fn new(root: impl Into<String>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub(crate) fn resolve(&self, path: &str) -> String {
+        if self.root.is_empty() {
+            path.to_owned()
+        } else {
+            format!("{}/{}", self.root.trim_end_matches('/'), path)
+        }
+    }
+}
+
+impl AssetSource for DirectorySource {
+    fn read<'a>(&'a self, path: &'a str) -> LocalBoxFuture<'a, Result<Option<Vec<u8>>>> {
+        Box::pin(async move {
+            let full_path = self.resolve(path);
+
+            #[cfg(target_arch = "wasm32")]
+            return read_url(&full_path).await;
+
+            #[cfg(not(target_arch = "wasm32"))]
+            return match std::fs::read(&full_path) {
+                Ok(bytes) => Ok(Some(bytes)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(source) => Err(Error::Io {
+                    path: full_path,
+                    source,
+                }),
+            };
+        })
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn read_url(url: &str) -> Result<Option<Vec<u8>>> {
+    use js_sys::ArrayBuffer;
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+    use web_sys::Response;
+
+    let io_error = |message: String| Error::Io {
+        path: url.to_owned(),
+        source: std::io::Error::other(message),
+    };
+
+    let window = web_sys::window().ok_or_else(|| io_error("No window available".into()))?;
+    let response_js = JsFuture::from(window.fetch_with_str(url))
+        .await
+        .map_err(|e| io_error(format!("Failed to fetch file: {:?}", e)))?;
+    let response: Response = response_js
+        .dyn_into()
+        .map_err(|_| io_error("Fetch did not return a Response".into()))?;
+    if response.status() == 404 {
+        return Ok(None);
+    }
+    if !response.ok() {
+        return Err(io_error(format!("HTTP status {}", response.status())));
+    }
+
+    let buffer_js = JsFuture::from(
+        response
+            .array_buffer()
+            .map_err(|e| io_error(format!("Failed to read array buffer: {:?}", e)))?,
+    )
+    .await
+    .map_err(|e| io_error(format!("Could not read response body buffer: {:?}", e)))?;
+    let buffer: ArrayBuffer = buffer_js
+        .dyn_into()
+        .map_err(|_| io_error("Response body was not an ArrayBuffer".into()))?;
+    let u8_buffer: js_sys::Uint8Array = js_sys::Uint8Array::new(&buffer);
+    let mut buff_vec = vec![0; u8_buffer.length() as usize];
+    u8_buffer.copy_to(&mut buff_vec[..]);
+    Ok(Some(buff_vec))
+}
+
+/// Assets held in memory, either built up at runtime, compiled into the binary with
+/// [`embed_assets!`](crate::embed_assets), or unpacked from a zip or tar archive.
+#[derive(Default)]
+pub struct MemorySource {
+    files: HashMap<String, Cow<'static, [u8]>>,
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, path: &str, bytes: impl Into<Cow<'static, [u8]>>) {
+        self.files.insert(normalize_path(path), bytes.into());
+    }
+
+    pub fn from_zip(name: &str, bytes: &[u8]) -> Result<Self> {
+        let archive_error = |source: std::io::Error| Error::Archive {
+            name: name.to_owned(),
+            source,
+        };
+
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes))
+            .map_err(|e| archive_error(e.into()))?;
+        let mut source = Self::new();
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i).map_err(|e| archive_error(e.into()))?;
+            if !file.is_file() {
+                continue;
+            }
+            let mut contents = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut contents).map_err(archive_error)?;
+            source.insert(file.name(), contents);
+        }
+        Ok(source)
+    }
+
+    pub fn from_tar(name: &str, bytes: &[u8]) -> Result<Self> {
+        let archive_error = |source: std::io::Error| Error::Archive {
+            name: name.to_owned(),
+            source,
+        };
+
+        let mut archive = tar::Archive::new(bytes);
+        let mut source = Self::new();
+        for entry in archive.entries().map_err(archive_error)? {
+            let mut entry = entry.map_err(archive_error)?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let path = entry
+                .path()
+                .map_err(archive_error)?
+                .to_string_lossy()
+                .into_owned();
+            let mut contents = Vec::with_capacity(entry.size() as usize);
+            entry.read_to_end(&mut contents).map_err(archive_error)?;
+            source.insert(&path, contents);
+        }
+        Ok(source)
+    }
+}
+
+impl AssetSource for MemorySource {
+    fn read<'a>(&'a self, path: &'a str) -> LocalBoxFuture<'a, Result<Option<Vec<u8>>>> {
+        let bytes = self.files.get(path).map(|bytes| bytes.to_vec());
+        Box::pin(futures::future::ready(Ok(bytes)))
+    }
+}
+
+/// Builds a [`MemorySource`] from files compiled into the binary with `include_bytes!`.
+#[macro_export]
+macro_rules! embed_assets {
+    ($($path:literal => $file:literal),* $(,)?) => {{
+        let mut source = $crate::MemorySource::new();
+        $(source.insert($path, &include_bytes!($file)[..]);)*
+        source
+    }};
+}
+
+/// Asset paths are always `/` separated and relative, regardless of platform.
+pub(crate) fn normalize_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut normalized = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use futures::executor::block_on;
+
+    use super::*;
+
+    const FILES: [(&str, &str); 2] = [("a.txt", "first"), ("dir/b.txt", "second")];
+
+    fn assert_reads_files(source: &MemorySource) {
+        for (path, contents) in FILES {
+            let bytes = block_on(source.read(path)).unwrap();
+            assert_eq!(bytes.as_deref(), Some(contents.as_bytes()), "{}", path);
+        }
+        assert_eq!(block_on(source.read("missing.txt")).unwrap(), None);
+    }
+
+    #[test]
+    fn normalize_path_resolves_components() {
+        assert_eq!(normalize_path("a/b/../c"), "a/c");
+        assert_eq!(normalize_path("./a/./b"), "a/b");
+        assert_eq!(normalize_path("a\\b\\c.obj"), "a/b/c.obj");
+        assert_eq!(normalize_path("a//b///c"), "a/b/c");
+        assert_eq!(normalize_path("/a/b/"), "a/b");
+        assert_eq!(normalize_path("../a/../../b"), "b");
+        assert_eq!(normalize_path(""), "");
+    }
+
+    #[test]
+    fn memory_source_reads_zip() {
+        let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated);
+        archive.add_directory("dir/", options).unwrap();
+        for (path, contents) in FILES {
+            archive.start_file(path, options).unwrap();
+            archive.write_all(contents.as_bytes()).unwrap();
+        }
+        let bytes = archive.finish().unwrap().into_inner();
+
+        assert_reads_files(&MemorySource::from_zip("test.zip", &bytes).unwrap());
+    }
+
+    #[test]
+    fn memory_source_reads_tar() {
+        let mut archive = tar::Builder::new(Vec::new());
+        for (path, contents) in FILES {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            archive
+                .append_data(&mut header, path, contents.as_bytes())
+                .unwrap();
+        }
+        let bytes = archive.into_inner().unwrap();
+
+        assert_reads_files(&MemorySource::from_tar("test.tar", &bytes).unwrap());
+    }
+
+    #[test]
+    fn memory_source_rejects_a_bad_archive() {
+        assert!(matches!(
+            MemorySource::from_zip("bad.zip", b"not a zip"),
+            Err(Error::Archive { .. })
+        ));
+    }
+}
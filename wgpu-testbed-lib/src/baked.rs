@@ -0,0 +1,677 @@
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::{GenericImageView, ImageFormat};
+
+use crate::asset_cache::{MeshData, Reader, TextureData, Writer, unframe};
+use crate::error::{Error, Result};
+use crate::model::{
+    self, AlphaMode, MaterialParams, MaterialSource, MeshOptions, ModelLoader, TangentMethod,
+    TextureSource,
+};
+use crate::texture::Texture;
+
+/// Extension of baked models, which replace the OBJ they were baked from.
+pub const BAKED_MODEL_EXTENSION: &str = "bmdl";
+/// Extension of baked textures, which replace the image they were baked from.
+pub const BAKED_TEXTURE_EXTENSION: &str = "btex";
+/// The archive written by `wgpu-testbed-bake`, mounted over the asset root at startup.
+pub const BAKED_ARCHIVE: &str = "baked.zip";
+/// Environment variable that makes native builds mount [`BAKED_ARCHIVE`] too.
+pub const USE_BAKED_VAR: &str = "WGPU_TESTBED_USE_BAKED";
+
+/// Whether to mount [`BAKED_ARCHIVE`] at startup. The web build always does, to fetch one
+/// archive instead of every asset. Natively a stale archive would shadow edits to the
+/// source assets, so it is only mounted when [`USE_BAKED_VAR`] is set.
+pub fn use_baked_archive() -> bool {
+    cfg!(target_arch = "wasm32") || std::env::var_os(USE_BAKED_VAR).is_some()
+}
+
+/// Quality of the JPEG mip levels baked from JPEG sources.
+const JPEG_QUALITY: u8 = 90;
+/// Vertices that fit in the post-transform cache model used to order triangles.
+const VERTEX_CACHE_SIZE: usize = 32;
+
+/// A model with tangents generated and meshes optimized ahead of time, so loading it
+/// needs no parsing or GPU compute.
+pub struct BakedModel {
+    pub materials: Vec<MaterialSource>,
+    pub meshes: Vec<MeshData>,
+    /// How the meshes' tangents were generated.
+    pub tangents: TangentMethod,
+}
+
+impl BakedModel {
+    /// Parses a model, generates its tangents and reorders its meshes for the vertex
+    /// cache. Texture paths are rewritten to point at their baked equivalents, and
+    /// embedded textures are kept for the caller to bake.
+    pub async fn bake(path: &str, tangents: TangentMethod) -> Result<Self> {
+        let options = MeshOptions {
+            tangents,
+            ..MeshOptions::default()
+        };
+        let scene = ModelLoader::parse_source(path, options).await?;
+
+        let meshes = scene
+            .models
+            .iter()
+            .map(|obj_model| {
+                let mut mesh = model::mesh_data_from_obj(obj_model, tangents);
+                optimize_vertex_cache(&mut mesh);
+                mesh
+            })
+            .collect();
+
+        let materials = scene
+            .materials
+            .into_iter()
+            .map(|material| MaterialSource {
+                diffuse: baked_texture_source(material.diffuse),
+                normal: baked_texture_source(material.normal),
+                params: material.params,
+                name: material.name,
+            })
+            .collect();
+
+        Ok(Self {
+            materials,
+            meshes,
+            tangents,
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.u32(self.tangents as u32);
+        writer.u32(self.materials.len() as u32);
+        for material in &self.materials {
+            writer.bytes(material.name.as_bytes());
+            write_texture_source(&mut writer, &material.diffuse);
+            write_texture_source(&mut writer, &material.normal);
+            writer.u32(material.params.alpha_mode as u32);
+            writer.f32(material.params.alpha_cutoff);
+            writer.f32(material.params.opacity);
+        }
+        MeshData::write_all(&mut writer, &self.meshes);
+        writer.finish()
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader::new(unframe(bytes)?);
+        let tangents = match reader.u32()? {
+            0 => TangentMethod::Compute,
+            1 => TangentMethod::MikkTSpace,
+            _ => return None,
+        };
+        let material_count = reader.u32()?;
+        let materials = (0..material_count)
+            .map(|_| {
+                Some(MaterialSource {
+                    name: reader.string()?,
+                    diffuse: read_texture_source(&mut reader)?,
+                    normal: read_texture_source(&mut reader)?,
+                    params: MaterialParams {
+                        alpha_mode: match reader.u32()? {
+                            0 => AlphaMode::Opaque,
+                            1 => AlphaMode::Mask,
+                            2 => AlphaMode::Blend,
+                            _ => return None,
+                        },
+                        alpha_cutoff: reader.f32()?,
+                        opacity: reader.f32()?,
+                    },
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let meshes = MeshData::read_all(&mut reader)?;
+        Some(Self {
+            materials,
+            meshes,
+            tangents,
+        })
+    }
+}
+
+fn baked_texture_source(source: TextureSource) -> TextureSource {
+    match source {
+        TextureSource::Path(path) => {
+            TextureSource::Path(baked_path(&path, BAKED_TEXTURE_EXTENSION))
+        }
+        source => source,
+    }
+}
+
+fn write_texture_source(writer: &mut Writer, source: &TextureSource) {
+    match source {
+        TextureSource::None => writer.u32(0),
+        TextureSource::Path(path) => {
+            writer.u32(1);
+            writer.bytes(path.as_bytes());
+        }
+        TextureSource::Embedded { name, bytes } => {
+            writer.u32(2);
+            writer.bytes(name.as_bytes());
+            writer.bytes(bytes);
+        }
+    }
+}
+
+fn read_texture_source(reader: &mut Reader) -> Option<TextureSource> {
+    Some(match reader.u32()? {
+        0 => TextureSource::None,
+        1 => TextureSource::Path(reader.string()?),
+        2 => TextureSource::Embedded {
+            name: reader.string()?,
+            bytes: reader.bytes()?.into(),
+        },
+        _ => return None,
+    })
+}
+
+/// A texture with its mip chain generated ahead of time, kept compressed in the format
+/// of its source. Level 0 is the source file itself (TGA is re-encoded as PNG) and each
+/// smaller level is encoded the same way, so a baked texture stays within about twice the
+/// size of its source instead of growing to the size of the raw pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BakedTexture {
+    pub width: u32,
+    pub height: u32,
+    /// One encoded PNG or JPEG per mip level, largest first.
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl BakedTexture {
+    /// Decodes a PNG, JPEG or TGA, generates its mip chain and encodes the smaller levels.
+    pub fn bake(path: &str, bytes: &[u8]) -> Result<Self> {
+        let decode_error = |source| Error::Decode {
+            path: path.to_owned(),
+            source,
+        };
+        let format = if path.contains(".tga") {
+            image::ImageFormat::Tga
+        } else {
+            image::guess_format(bytes).map_err(decode_error)?
+        };
+        let img = image::load_from_memory_with_format(bytes, format).map_err(decode_error)?;
+        let mips = Texture::generate_mips(&img);
+
+        let mut levels = Vec::with_capacity(mips.levels.len());
+        for (level, pixels) in mips.levels.into_iter().enumerate() {
+            if level == 0 && matches!(format, ImageFormat::Png | ImageFormat::Jpeg) {
+                levels.push(bytes.to_vec());
+                continue;
+            }
+            let (width, height) = mip_size(mips.width, mips.height, level as u32);
+            let pixels = image::RgbaImage::from_raw(width, height, pixels)
+                .expect("mip levels match their dimensions");
+            levels.push(encode_level(path, pixels, format)?);
+        }
+        Ok(Self {
+            width: mips.width,
+            height: mips.height,
+            levels,
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.u32(self.width);
+        writer.u32(self.height);
+        writer.u32(self.levels.len() as u32);
+        for level in &self.levels {
+            writer.bytes(level);
+        }
+        writer.finish()
+    }
+
+    /// Returns `None` if the file is truncated or was baked by another version. The
+    /// levels themselves are only checked by [`BakedTexture::decompress`].
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader::new(unframe(bytes)?);
+        let width = reader.u32()?;
+        let height = reader.u32()?;
+        let level_count = reader.u32()?;
+        let levels = (0..level_count)
+            .map(|_| reader.bytes().map(<[u8]>::to_vec))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self {
+            width,
+            height,
+            levels,
+        })
+    }
+
+    /// Decodes every level to RGBA8, failing unless each is the size its level calls for.
+    pub fn decompress(&self, path: &str) -> Result<TextureData> {
+        let invalid = || Error::InvalidBakedAsset {
+            path: path.to_owned(),
+        };
+        if self.width == 0
+            || self.height == 0
+            || self.levels.is_empty()
+            || self.levels.len() as u32 > 32 - self.width.max(self.height).leading_zeros()
+        {
+            return Err(invalid());
+        }
+
+        let levels = self
+            .levels
+            .iter()
+            .enumerate()
+            .map(|(level, bytes)| {
+                let img = image::load_from_memory(bytes).map_err(|source| Error::Decode {
+                    path: path.to_owned(),
+                    source,
+                })?;
+                if img.dimensions() != mip_size(self.width, self.height, level as u32) {
+                    return Err(invalid());
+                }
+                Ok(img.into_rgba8().into_raw())
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(TextureData {
+            width: self.width,
+            height: self.height,
+            levels,
+        })
+    }
+}
+
+fn mip_size(width: u32, height: u32, level: u32) -> (u32, u32) {
+    ((width >> level).max(1), (height >> level).max(1))
+}
+
+/// Encodes a mip level as a JPEG if the source was one, and as a PNG otherwise.
+fn encode_level(path: &str, pixels: image::RgbaImage, format: ImageFormat) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let img = image::DynamicImage::ImageRgba8(pixels);
+    let result = if format == ImageFormat::Jpeg {
+        JpegEncoder::new_with_quality(&mut bytes, JPEG_QUALITY).encode_image(&img.to_rgb8())
+    } else {
+        img.write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+    };
+    result.map_err(|source| Error::Encode {
+        path: path.to_owned(),
+        source,
+    })?;
+    Ok(bytes)
+}
+
+/// Swaps the extension of an asset path, e.g. `cube/cube.obj` to `cube/cube.bmdl`.
+pub fn baked_path(path: &str, extension: &str) -> String {
+    let file_start = path.rfind('/').map_or(0, |slash| slash + 1);
+    let stem = match path[file_start..].rfind('.') {
+        Some(dot) => &path[..file_start + dot],
+        None => path,
+    };
+    format!("{}.{}", stem, extension)
+}
+
+/// Reorders triangles with Tom Forsyth's linear-speed vertex cache optimisation, then
+/// reorders vertices by first use so vertex fetches are mostly sequential.
+fn optimize_vertex_cache(mesh: &mut MeshData) {
+    let triangle_count = mesh.indices.len() / 3;
+    let vertex_count = mesh.vertices.len();
+
+    let mut vertex_triangles = vec![Vec::new(); vertex_count];
+    for (triangle, corners) in mesh.indices.chunks_exact(3).enumerate() {
+        for &index in corners {
+            vertex_triangles[index as usize].push(triangle);
+        }
+    }
+
+    let mut cache_positions: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut vertex_scores: Vec<f32> = vertex_triangles
+        .iter()
+        .map(|triangles| vertex_score(None, triangles.len()))
+        .collect();
+    let triangle_score = |scores: &[f32], triangle: usize| -> f32 {
+        mesh.indices[triangle * 3..triangle * 3 + 3]
+            .iter()
+            .map(|&index| scores[index as usize])
+            .sum()
+    };
+    let mut triangle_scores: Vec<f32> = (0..triangle_count)
+        .map(|triangle| triangle_score(&vertex_scores, triangle))
+        .collect();
+    let mut emitted = vec![false; triangle_count];
+
+    let mut cache: Vec<u32> = Vec::with_capacity(VERTEX_CACHE_SIZE + 3);
+    let mut order = Vec::with_capacity(triangle_count);
+    let mut best = None;
+    let mut scan_from = 0;
+
+    while order.len() < triangle_count {
+        // When no triangle touches the cache, fall back to the best unemitted one.
+        let triangle = match best.take() {
+            Some(triangle) => triangle,
+            None => {
+                while emitted[scan_from] {
+                    scan_from += 1;
+                }
+                (scan_from..triangle_count)
+                    .filter(|&triangle| !emitted[triangle])
+                    .max_by(|&a, &b| triangle_scores[a].total_cmp(&triangle_scores[b]))
+                    .unwrap()
+            }
+        };
+        emitted[triangle] = true;
+        order.push(triangle);
+
+        let corners = &mesh.indices[triangle * 3..triangle * 3 + 3];
+        for &index in corners {
+            vertex_triangles[index as usize].retain(|&other| other != triangle);
+        }
+
+        let mut touched = cache.clone();
+        cache.retain(|index| !corners.contains(index));
+        for &index in corners.iter().rev() {
+            if !cache.contains(&index) {
+                cache.insert(0, index);
+            }
+            touched.push(index);
+        }
+        for &evicted in cache.iter().skip(VERTEX_CACHE_SIZE) {
+            cache_positions[evicted as usize] = None;
+        }
+        cache.truncate(VERTEX_CACHE_SIZE);
+        for (position, &index) in cache.iter().enumerate() {
+            cache_positions[index as usize] = Some(position);
+        }
+
+        for &index in &touched {
+            let index = index as usize;
+            vertex_scores[index] =
+                vertex_score(cache_positions[index], vertex_triangles[index].len());
+        }
+        let mut best_score = f32::NEG_INFINITY;
+        for &index in &touched {
+            for &other in &vertex_triangles[index as usize] {
+                triangle_scores[other] = triangle_score(&vertex_scores, other);
+                if triangle_scores[other] > best_score {
+                    best_score = triangle_scores[other];
+                    best = Some(other);
+                }
+            }
+        }
+    }
+
+    let mut remap = vec![u32::MAX; vertex_count];
+    let mut vertices = Vec::with_capacity(vertex_count);
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+    for triangle in order {
+        for &index in &mesh.indices[triangle * 3..triangle * 3 + 3] {
+            let remapped = &mut remap[index as usize];
+            if *remapped == u32::MAX {
+                *remapped = vertices.len() as u32;
+                vertices.push(mesh.vertices[index as usize]);
+            }
+            indices.push(*remapped);
+        }
+    }
+    mesh.vertices = vertices;
+    mesh.indices = indices;
+}
+
+fn vertex_score(cache_position: Option<usize>, remaining_triangles: usize) -> f32 {
+    if remaining_triangles == 0 {
+        return -1.0;
+    }
+    let cache_score = match cache_position {
+        // The last triangle's vertices get a fixed score, so the next triangle
+        // doesn't simply reuse the most recent edge.
+        Some(position) if position < 3 => 0.75,
+        Some(position) => (1.0 - (position - 3) as f32 / (VERTEX_CACHE_SIZE - 3) as f32).powf(1.5),
+        None => 0.0,
+    };
+    cache_score + 2.0 * (remaining_triangles as f32).powf(-0.5)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::asset_cache::pod_vec;
+    use crate::model::ModelVertex;
+
+    /// A vertex whose position's x is `id`, so it can be told apart after reordering.
+    fn vertex(id: u32) -> ModelVertex {
+        let mut bytes = vec![0; std::mem::size_of::<ModelVertex>()];
+        bytes[..4].copy_from_slice(&(id as f32).to_le_bytes());
+        pod_vec(&bytes).unwrap()[0]
+    }
+
+    fn vertex_id(vertex: &ModelVertex) -> u32 {
+        bytemuck::cast_slice::<_, f32>(std::slice::from_ref(vertex))[0] as u32
+    }
+
+    /// A grid of `size` by `size` quads, with its triangles in a scrambled order.
+    fn grid(size: u32) -> MeshData {
+        let mut triangles = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                let corner = y * (size + 1) + x;
+                triangles.push([corner, corner + 1, corner + size + 1]);
+                triangles.push([corner + 1, corner + size + 2, corner + size + 1]);
+            }
+        }
+        let count = triangles.len();
+        let scrambled = (0..count).map(|i| triangles[i * 37 % count]);
+        MeshData {
+            name: "grid".to_owned(),
+            material: 0,
+            vertices: (0..(size + 1) * (size + 1)).map(vertex).collect(),
+            indices: scrambled.flatten().collect(),
+        }
+    }
+
+    /// Triangles by the ids of their vertices, sorted so orders can be compared.
+    fn triangles(mesh: &MeshData) -> Vec<[u32; 3]> {
+        let mut triangles: Vec<[u32; 3]> = mesh
+            .indices
+            .chunks_exact(3)
+            .map(|t| [0, 1, 2].map(|i| vertex_id(&mesh.vertices[t[i] as usize])))
+            .collect();
+        triangles.sort_unstable();
+        triangles
+    }
+
+    /// Vertices transformed per triangle with a FIFO cache of `size` entries.
+    fn average_cache_miss_ratio(indices: &[u32], size: usize) -> f32 {
+        let mut cache = std::collections::VecDeque::new();
+        let mut misses = 0;
+        for &index in indices {
+            if !cache.contains(&index) {
+                misses += 1;
+                cache.push_back(index);
+                if cache.len() > size {
+                    cache.pop_front();
+                }
+            }
+        }
+        misses as f32 / (indices.len() / 3) as f32
+    }
+
+    #[test]
+    fn vertex_cache_order_keeps_triangles_and_reuses_vertices() {
+        let original = grid(16);
+        let mut optimized = MeshData {
+            vertices: original.vertices.clone(),
+            indices: original.indices.clone(),
+            ..grid(0)
+        };
+        optimize_vertex_cache(&mut optimized);
+
+        assert_eq!(triangles(&optimized), triangles(&original));
+        assert_eq!(optimized.vertices.len(), original.vertices.len());
+        // Vertices are numbered in the order the triangles first use them.
+        let mut next = 0;
+        for &index in &optimized.indices {
+            assert!(index <= next);
+            if index == next {
+                next += 1;
+            }
+        }
+
+        let before = average_cache_miss_ratio(&original.indices, 16);
+        let after = average_cache_miss_ratio(&optimized.indices, 16);
+        assert!(after < 0.8, "{} -> {}", before, after);
+        assert!(after < before * 0.6, "{} -> {}", before, after);
+    }
+
+    #[test]
+    fn vertex_cache_order_drops_unused_vertices() {
+        let mut mesh = MeshData {
+            vertices: (0..5).map(vertex).collect(),
+            indices: vec![4, 2, 0],
+            ..grid(0)
+        };
+        optimize_vertex_cache(&mut mesh);
+        assert_eq!(mesh.indices, [0, 1, 2]);
+        assert_eq!(
+            mesh.vertices.iter().map(vertex_id).collect::<Vec<_>>(),
+            [4, 2, 0]
+        );
+    }
+
+    fn model() -> BakedModel {
+        let material = |name: &str, diffuse, normal, alpha_mode| MaterialSource {
+            name: name.to_owned(),
+            diffuse,
+            normal,
+            params: MaterialParams {
+                alpha_mode,
+                alpha_cutoff: 0.25,
+                opacity: 0.75,
+            },
+        };
+        BakedModel {
+            materials: vec![
+                material(
+                    "painted",
+                    TextureSource::Path("cube/cube-diffuse.btex".to_owned()),
+                    TextureSource::None,
+                    AlphaMode::Mask,
+                ),
+                material(
+                    "glass",
+                    TextureSource::None,
+                    TextureSource::Embedded {
+                        name: "scene/image0.png".to_owned(),
+                        bytes: Arc::from(&b"\x89PNG"[..]),
+                    },
+                    AlphaMode::Blend,
+                ),
+            ],
+            meshes: vec![grid(2), grid(1)],
+            tangents: TangentMethod::MikkTSpace,
+        }
+    }
+
+    #[test]
+    fn model_round_trips() {
+        let model = model();
+        let decoded = BakedModel::decode(&model.encode()).unwrap();
+        assert_eq!(decoded.tangents, TangentMethod::MikkTSpace);
+        assert_eq!(decoded.materials, model.materials);
+        assert_eq!(decoded.meshes.len(), 2);
+        for (decoded, mesh) in decoded.meshes.iter().zip(&model.meshes) {
+            assert_eq!(decoded.name, mesh.name);
+            assert_eq!(decoded.indices, mesh.indices);
+            assert_eq!(triangles(decoded), triangles(mesh));
+        }
+    }
+
+    #[test]
+    fn model_rejects_corrupt_files() {
+        let encoded = model().encode();
+        for len in 0..encoded.len() {
+            assert!(BakedModel::decode(&encoded[..len]).is_none(), "{}", len);
+        }
+        let header = encoded.len() - unframe(&encoded).unwrap().len();
+        let mut bad_tangents = encoded.clone();
+        bad_tangents[header] = 7;
+        assert!(BakedModel::decode(&bad_tangents).is_none());
+    }
+
+    fn image(width: u32, height: u32) -> image::DynamicImage {
+        image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([(x * 40) as u8, (y * 60) as u8, 200, 255 - x as u8])
+        }))
+    }
+
+    fn encoded(img: &image::DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match format {
+            ImageFormat::Jpeg => img.to_rgb8().write_to(&mut Cursor::new(&mut bytes), format),
+            _ => img.write_to(&mut Cursor::new(&mut bytes), format),
+        }
+        .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn texture_keeps_png_source_and_lossless_mips() {
+        let img = image(6, 3);
+        let png = encoded(&img, ImageFormat::Png);
+        let baked = BakedTexture::bake("a/b.png", &png).unwrap();
+        assert_eq!((baked.width, baked.height), (6, 3));
+        assert_eq!(baked.levels.len(), 3);
+        assert_eq!(baked.levels[0], png);
+
+        let decoded = BakedTexture::decode(&baked.encode()).unwrap();
+        assert_eq!(decoded, baked);
+        let data = decoded.decompress("a/b.btex").unwrap();
+        assert_eq!(data.levels, Texture::generate_mips(&img).levels);
+    }
+
+    #[test]
+    fn texture_keeps_jpeg_as_jpeg_and_tga_as_png() {
+        let img = image(4, 4);
+        let jpeg = encoded(&img, ImageFormat::Jpeg);
+        let baked = BakedTexture::bake("a.jpg", &jpeg).unwrap();
+        assert_eq!(baked.levels[0], jpeg);
+        for level in &baked.levels {
+            assert_eq!(image::guess_format(level).unwrap(), ImageFormat::Jpeg);
+        }
+        let data = baked.decompress("a.btex").unwrap();
+        assert_eq!(data.levels.len(), 3);
+        assert_eq!(data.levels[2].len(), 4);
+
+        let tga = encoded(&img, ImageFormat::Tga);
+        let baked = BakedTexture::bake("a.tga", &tga).unwrap();
+        for level in &baked.levels {
+            assert_eq!(image::guess_format(level).unwrap(), ImageFormat::Png);
+        }
+        assert_eq!(
+            baked.decompress("a.btex").unwrap().levels,
+            Texture::generate_mips(&img).levels
+        );
+    }
+
+    #[test]
+    fn texture_rejects_corrupt_files() {
+        let baked = BakedTexture::bake("a.png", &encoded(&image(4, 2), ImageFormat::Png)).unwrap();
+        let encoded = baked.encode();
+        for len in 0..encoded.len() {
+            assert!(BakedTexture::decode(&encoded[..len]).is_none(), "{}", len);
+        }
+
+        let mut swapped = baked.clone();
+        swapped.levels.swap(0, 1);
+        assert!(matches!(
+            swapped.decompress("a.btex"),
+            Err(Error::InvalidBakedAsset { .. })
+        ));
+        let mut extra = baked.clone();
+        extra.levels.push(extra.levels[2].clone());
+        assert!(extra.decompress("a.btex").is_err());
+        let mut garbage = baked;
+        garbage.levels[1] = b"not an image".to_vec();
+        assert!(matches!(
+            garbage.decompress("a.btex"),
+            Err(Error::Decode { .. })
+        ));
+    }
+}
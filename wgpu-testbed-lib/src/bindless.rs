@@ -0,0 +1,141 @@
+//! Bindless-style materials: every material's textures in one bind group of texture
+//! binding arrays, indexed in the shader by a material id that travels with each vertex.
+//! A whole scene can then be drawn with one bind group and one indirect call, however
+//! many materials it uses.
+//!
+//! Only available where the adapter supports texture binding arrays with non-uniform
+//! indexing, so WebGPU and WebGL2 keep using a bind group per material.
+
+use crate::model::Material;
+
+/// The most materials a bindless bind group holds, even on adapters that allow more.
+pub const MAX_MATERIALS: u32 = 256;
+
+pub fn required_features() -> wgpu::Features {
+    wgpu::Features::TEXTURE_BINDING_ARRAY
+        | wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING
+}
+
+/// How many materials fit in one bind group on `adapter`, or `None` if it can't index
+/// texture arrays from a fragment shader.
+pub fn capacity(adapter: &wgpu::Adapter) -> Option<u32> {
+    if !adapter.features().contains(required_features()) {
+        return None;
+    }
+    // Each material takes a diffuse and a normal texture.
+    let limits = adapter.limits();
+    let capacity = MAX_MATERIALS
+        .min(limits.max_binding_array_elements_per_shader_stage / 2)
+        .min(limits.max_sampled_textures_per_shader_stage / 2);
+    (capacity > 0).then_some(capacity)
+}
+
+/// The limits a device needs for bind groups of `capacity` materials.
+pub fn required_limits(capacity: u32, base: wgpu::Limits) -> wgpu::Limits {
+    wgpu::Limits {
+        max_binding_array_elements_per_shader_stage: base
+            .max_binding_array_elements_per_shader_stage
+            .max(capacity * 2),
+        max_sampled_textures_per_shader_stage: base
+            .max_sampled_textures_per_shader_stage
+            .max(capacity * 2),
+        ..base
+    }
+}
+
+/// The layout of a bindless material bind group, and how many materials it holds.
+#[derive(Clone)]
+pub struct BindlessLayout {
+    pub layout: wgpu::BindGroupLayout,
+    pub capacity: u32,
+}
+
+impl BindlessLayout {
+    pub fn new(device: &wgpu::Device, capacity: u32) -> Self {
+        let texture_array = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: std::num::NonZeroU32::new(capacity),
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bindless material bind group layout"),
+            entries: &[
+                texture_array(0),
+                texture_array(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        Self { layout, capacity }
+    }
+}
+
+pub struct BindlessMaterials {
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl BindlessMaterials {
+    /// Binds the textures of every material in `materials`. Returns `None` if there are
+    /// none, or more than the layout has room for, in which case the scene has to be drawn
+    /// with a bind group per material instead.
+    pub fn new(
+        device: &wgpu::Device,
+        layout: &BindlessLayout,
+        materials: &[Material],
+    ) -> Option<Self> {
+        if materials.is_empty() || materials.len() > layout.capacity as usize {
+            return None;
+        }
+
+        // Every slot has to be filled, so the spare ones repeat the first material.
+        let views = |kind: &str| -> Vec<&wgpu::TextureView> {
+            (0..layout.capacity as usize)
+                .map(|slot| &materials.get(slot).unwrap_or(&materials[0]).textures[kind].view)
+                .collect()
+        };
+        let diffuse = views("diffuse");
+        let normal = views("normal");
+
+        // Matches the samplers `Texture` creates for each material.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Bindless material sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::MipmapFilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bindless material bind group"),
+            layout: &layout.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureViewArray(&diffuse),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureViewArray(&normal),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        Some(Self { bind_group })
+    }
+}
@@ -11,6 +11,7 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     0.0, 0.0, 0.5, 1.0,
 );
 
+#[derive(Debug, Clone, Copy)]
 pub struct Camera {
     pub eye: cgmath::Point3<f32>,
     pub target: cgmath::Point3<f32>,
@@ -37,7 +38,8 @@ impl Camera {
 }
 
 pub struct CameraController {
-    speed: f32,
+    /// How far the camera moves each frame a key is held.
+    pub speed: f32,
     up_pressed: bool,
     down_pressed: bool,
     forward_pressed: bool,
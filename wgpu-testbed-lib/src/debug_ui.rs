@@ -0,0 +1,383 @@
+//! The debug UI, toggled with F2: egui panels for tweaking the renderer while it runs.
+//!
+//! egui's own wgpu backend trails the wgpu this crate uses, so its output is drawn here
+//! instead. Each frame's shapes are tessellated into one vertex and index buffer, and every
+//! mesh egui asks for becomes a draw clipped to its rectangle through `ui.wgsl`. The
+//! panels themselves are laid out by [`crate::state::State`], which owns what they edit.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use wgpu::util::DeviceExt;
+use winit::event::WindowEvent;
+use winit::window::Window;
+
+use crate::pipeline::{Bindable, Binder, UniformBinding};
+
+/// Points are a logical pixel, so the UI keeps its size as the window's scale factor
+/// changes.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct UiUniform {
+    size_in_points: [f32; 2],
+    _padding: [f32; 2],
+}
+
+/// One of egui's textures, with how it is sampled.
+#[derive(Bindable)]
+#[bindable(fragment)]
+pub struct UiTextureBinding {
+    #[texture]
+    pub texture: wgpu::TextureView,
+    #[sampler]
+    pub sampler: wgpu::Sampler,
+}
+
+pub fn vertex_desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+    use std::mem;
+    wgpu::VertexBufferLayout {
+        array_stride: mem::size_of::<egui::epaint::Vertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &[
+            wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Float32x2,
+                offset: 0,
+                shader_location: 0,
+            },
+            wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Float32x2,
+                offset: mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                shader_location: 1,
+            },
+            wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Unorm8x4,
+                offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                shader_location: 2,
+            },
+        ],
+    }
+}
+
+struct UiTexture {
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+/// A mesh from the last frame, within the shared buffers.
+struct UiDraw {
+    texture: egui::TextureId,
+    /// The clip rectangle in pixels: x, y, width and height.
+    scissor: [u32; 4],
+    indices: Range<u32>,
+    base_vertex: i32,
+}
+
+pub struct DebugUi {
+    pub visible: bool,
+    input: egui_winit::State,
+    texture_binder: Binder<UiTextureBinding>,
+    textures: HashMap<egui::TextureId, UiTexture>,
+    /// Textures egui is done with, freed once the frame that last drew them has.
+    textures_to_free: Vec<egui::TextureId>,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    screen_buffer: wgpu::Buffer,
+    screen_bind_group: wgpu::BindGroup,
+    draws: Vec<UiDraw>,
+}
+
+impl DebugUi {
+    pub fn new(
+        device: &wgpu::Device,
+        window: &Window,
+        uniform_binder: &Binder<UniformBinding>,
+        texture_binder: &Binder<UiTextureBinding>,
+    ) -> Self {
+        let context = egui::Context::default();
+        let input = egui_winit::State::new(
+            context,
+            egui::ViewportId::ROOT,
+            window,
+            Some(window.scale_factor() as f32),
+            None,
+            Some(device.limits().max_texture_dimension_2d as usize),
+        );
+        let screen_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Debug UI Screen Buffer"),
+            contents: bytemuck::bytes_of(&UiUniform::default()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let screen_bind_group = uniform_binder.create_bind_group(
+            &UniformBinding {
+                buffer: screen_buffer.clone(),
+            },
+            device,
+            Some("Debug UI Screen Bind Group"),
+        );
+
+        Self {
+            visible: false,
+            input,
+            texture_binder: texture_binder.clone(),
+            textures: HashMap::new(),
+            textures_to_free: Vec::new(),
+            vertex_buffer: Self::create_buffer(
+                device,
+                "Debug UI Vertex Buffer",
+                wgpu::BufferUsages::VERTEX,
+                1 << 16,
+            ),
+            index_buffer: Self::create_buffer(
+                device,
+                "Debug UI Index Buffer",
+                wgpu::BufferUsages::INDEX,
+                1 << 16,
+            ),
+            screen_buffer,
+            screen_bind_group,
+            draws: Vec::new(),
+        }
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        label: &str,
+        usage: wgpu::BufferUsages,
+        size: u64,
+    ) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn context(&self) -> &egui::Context {
+        self.input.egui_ctx()
+    }
+
+    /// Passes `event` on to egui, returning whether egui wants it to itself, such as
+    /// clicks on a panel or keys typed into a text field.
+    pub fn on_window_event(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        self.input.on_window_event(window, event).consumed
+    }
+
+    /// The input gathered since the last frame, to run the UI with.
+    pub fn take_input(&mut self, window: &Window) -> egui::RawInput {
+        self.input.take_egui_input(window)
+    }
+
+    /// Applies what running the UI produced: the cursor and clipboard, texture changes,
+    /// and the meshes to draw into a target of `screen_size` pixels.
+    pub fn finish(
+        &mut self,
+        window: &Window,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        screen_size: [u32; 2],
+        output: egui::FullOutput,
+    ) {
+        self.input
+            .handle_platform_output(window, output.platform_output);
+
+        for id in self.textures_to_free.drain(..) {
+            self.textures.remove(&id);
+        }
+        for (id, delta) in output.textures_delta.set {
+            self.set_texture(device, queue, id, delta);
+        }
+        self.textures_to_free = output.textures_delta.free;
+
+        let pixels_per_point = output.pixels_per_point;
+        let primitives = self.context().tessellate(output.shapes, pixels_per_point);
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        self.draws.clear();
+        for primitive in primitives {
+            let egui::epaint::Primitive::Mesh(mesh) = primitive.primitive else {
+                continue;
+            };
+            let Some(scissor) = scissor(primitive.clip_rect, pixels_per_point, screen_size) else {
+                continue;
+            };
+            let first_index = indices.len() as u32;
+            self.draws.push(UiDraw {
+                texture: mesh.texture_id,
+                scissor,
+                indices: first_index..first_index + mesh.indices.len() as u32,
+                base_vertex: vertices.len() as i32,
+            });
+            vertices.extend_from_slice(&mesh.vertices);
+            indices.extend_from_slice(&mesh.indices);
+        }
+
+        let vertex_bytes: &[u8] = bytemuck::cast_slice(&vertices);
+        if vertex_bytes.len() as u64 > self.vertex_buffer.size() {
+            self.vertex_buffer = Self::create_buffer(
+                device,
+                "Debug UI Vertex Buffer",
+                wgpu::BufferUsages::VERTEX,
+                (vertex_bytes.len() as u64).next_power_of_two(),
+            );
+        }
+        let index_bytes: &[u8] = bytemuck::cast_slice(&indices);
+        if index_bytes.len() as u64 > self.index_buffer.size() {
+            self.index_buffer = Self::create_buffer(
+                device,
+                "Debug UI Index Buffer",
+                wgpu::BufferUsages::INDEX,
+                (index_bytes.len() as u64).next_power_of_two(),
+            );
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, vertex_bytes);
+        queue.write_buffer(&self.index_buffer, 0, index_bytes);
+        queue.write_buffer(
+            &self.screen_buffer,
+            0,
+            bytemuck::bytes_of(&UiUniform {
+                size_in_points: [
+                    screen_size[0] as f32 / pixels_per_point,
+                    screen_size[1] as f32 / pixels_per_point,
+                ],
+                _padding: [0.0; 2],
+            }),
+        );
+    }
+
+    /// Creates a texture, or updates part of one egui created before.
+    fn set_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        id: egui::TextureId,
+        delta: egui::epaint::ImageDelta,
+    ) {
+        let egui::ImageData::Color(image) = &delta.image;
+        let size = wgpu::Extent3d {
+            width: image.width() as u32,
+            height: image.height() as u32,
+            depth_or_array_layers: 1,
+        };
+        let origin = match delta.pos {
+            Some([x, y]) => wgpu::Origin3d {
+                x: x as u32,
+                y: y as u32,
+                z: 0,
+            },
+            None => {
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("Debug UI Texture"),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                    view_formats: &[],
+                });
+                let bind_group = self.texture_binder.create_bind_group(
+                    &UiTextureBinding {
+                        texture: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                        sampler: create_sampler(device, delta.options),
+                    },
+                    device,
+                    Some("Debug UI Texture Bind Group"),
+                );
+                self.textures.insert(
+                    id,
+                    UiTexture {
+                        texture,
+                        bind_group,
+                    },
+                );
+                wgpu::Origin3d::ZERO
+            }
+        };
+        let Some(target) = self.textures.get(&id) else {
+            log::warn!("egui updated {:?} before creating it", id);
+            return;
+        };
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &target.texture,
+                mip_level: 0,
+                origin,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&image.pixels),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * size.width),
+                rows_per_image: Some(size.height),
+            },
+            size,
+        );
+    }
+}
+
+fn create_sampler(device: &wgpu::Device, options: egui::TextureOptions) -> wgpu::Sampler {
+    let filter = |filter| match filter {
+        egui::TextureFilter::Nearest => wgpu::FilterMode::Nearest,
+        egui::TextureFilter::Linear => wgpu::FilterMode::Linear,
+    };
+    let address_mode = match options.wrap_mode {
+        egui::TextureWrapMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+        egui::TextureWrapMode::Repeat => wgpu::AddressMode::Repeat,
+        egui::TextureWrapMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+    };
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Debug UI Sampler"),
+        address_mode_u: address_mode,
+        address_mode_v: address_mode,
+        mag_filter: filter(options.magnification),
+        min_filter: filter(options.minification),
+        ..Default::default()
+    })
+}
+
+/// `clip_rect` in pixels, clamped to the screen, or `None` if nothing of it is on screen.
+fn scissor(
+    clip_rect: egui::Rect,
+    pixels_per_point: f32,
+    screen_size: [u32; 2],
+) -> Option<[u32; 4]> {
+    let min = clip_rect.min * pixels_per_point;
+    let max = clip_rect.max * pixels_per_point;
+    let x = (min.x.round().max(0.0) as u32).min(screen_size[0]);
+    let y = (min.y.round().max(0.0) as u32).min(screen_size[1]);
+    let right = (max.x.round().max(0.0) as u32).clamp(x, screen_size[0]);
+    let bottom = (max.y.round().max(0.0) as u32).clamp(y, screen_size[1]);
+    (right > x && bottom > y).then(|| [x, y, right - x, bottom - y])
+}
+
+pub trait DrawUi<'a, 'b>
+where
+    'b: 'a,
+{
+    /// Draws what `ui` last finished, with the UI pipeline already set.
+    fn draw_ui(&mut self, ui: &'b DebugUi);
+}
+
+impl<'a, 'b> DrawUi<'a, 'b> for wgpu::RenderPass<'a>
+where
+    'b: 'a,
+{
+    fn draw_ui(&mut self, ui: &'b DebugUi) {
+        if ui.draws.is_empty() {
+            return;
+        }
+        self.set_bind_group(0, &ui.screen_bind_group, &[]);
+        self.set_vertex_buffer(0, ui.vertex_buffer.slice(..));
+        self.set_index_buffer(ui.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for draw in &ui.draws {
+            let Some(texture) = ui.textures.get(&draw.texture) else {
+                continue;
+            };
+            let [x, y, width, height] = draw.scissor;
+            self.set_scissor_rect(x, y, width, height);
+            self.set_bind_group(1, &texture.bind_group, &[]);
+            self.draw_indexed(draw.indices.clone(), draw.base_vertex, 0..1);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+//! Debug visualisations of the meshes, the G-buffer, depth and stencil, switched with V or
+//! [`State::set_debug_view`](crate::state::State::set_debug_view).
+//!
+//! Views work in one of three ways. The UV checker and mip level heatmap replace the
+//! materials while the G-buffer is drawn, in `debug_material.wgsl`. Views of a single
+//! texture replace the output pass, in `debug_view.wgsl`. The wireframe and the normal,
+//! tangent and bitangent lines are drawn over the lit scene from the meshes' own buffers,
+//! bound as storage in `debug_geometry.wgsl`. Where the device supports a line polygon
+//! mode the wireframe uses it instead.
+
+use std::ops::Range;
+
+use wgpu::util::DeviceExt;
+
+use crate::camera::Camera;
+use crate::model::{Model, VertexLayout};
+use crate::pipeline::{Bindable, Binder};
+
+/// What the frame shows in place of, or on top of, the lit scene.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DebugView {
+    /// The scene as normal.
+    #[default]
+    Lit,
+    /// Triangle edges over the lit scene.
+    Wireframe,
+    /// Each vertex's tangent (red), bitangent (green) and normal (blue) over the lit scene.
+    Vectors,
+    /// A checkerboard in UV space, tinted by the coordinates.
+    UvChecker,
+    /// A heatmap of the diffuse texture mip level each pixel reads.
+    MipLevel,
+    /// A heatmap of how many fragments are drawn at each pixel, hidden or not.
+    Overdraw,
+    /// Linear depth, white near the camera.
+    Depth,
+    /// A colour for each stencil value.
+    Stencil,
+    /// The G-buffer's diffuse colour.
+    Diffuse,
+    /// The G-buffer's diffuse alpha.
+    Alpha,
+    /// The G-buffer's lighting.
+    Lighting,
+}
+
+impl DebugView {
+    pub const ALL: [Self; 11] = [
+        Self::Lit,
+        Self::Wireframe,
+        Self::Vectors,
+        Self::UvChecker,
+        Self::MipLevel,
+        Self::Overdraw,
+        Self::Depth,
+        Self::Stencil,
+        Self::Diffuse,
+        Self::Alpha,
+        Self::Lighting,
+    ];
+
+    /// The view after this one in [`Self::ALL`], wrapping around.
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&view| view == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// Whether blended materials are drawn over the scene. Views of the G-buffer leave
+    /// them out, and views replacing the materials draw them into the G-buffer.
+    pub fn draws_transparency(self) -> bool {
+        matches!(self, Self::Lit | Self::Wireframe | Self::Vectors)
+    }
+
+    /// The texture `debug_view.wgsl` shows in place of the lit scene, if any.
+    pub fn screen_view(self) -> Option<ScreenView> {
+        match self {
+            Self::Depth => Some(ScreenView::Depth),
+            Self::Stencil => Some(ScreenView::Stencil),
+            Self::Diffuse => Some(ScreenView::Diffuse),
+            Self::Alpha => Some(ScreenView::Alpha),
+            Self::Lighting => Some(ScreenView::Lighting),
+            Self::Overdraw => Some(ScreenView::Overdraw),
+            _ => None,
+        }
+    }
+}
+
+/// The views `debug_view.wgsl` draws, numbered to match its `VIEW_` constants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ScreenView {
+    Depth,
+    Stencil,
+    Diffuse,
+    Alpha,
+    Lighting,
+    /// The counts drawn into the accumulation target by `fragment_overdraw`.
+    Overdraw,
+}
+
+/// The device features debug views can use on `adapter`, if it has them.
+pub fn optional_features(adapter: &wgpu::Adapter) -> wgpu::Features {
+    adapter.features() & wgpu::Features::POLYGON_MODE_LINE
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DebugViewUniform {
+    view: u32,
+    z_near: f32,
+    z_far: f32,
+    _padding: u32,
+}
+
+impl DebugViewUniform {
+    pub fn new(view: ScreenView, camera: &Camera) -> Self {
+        Self {
+            view: view as u32,
+            z_near: camera.z_near,
+            z_far: camera.z_far,
+            _padding: 0,
+        }
+    }
+}
+
+/// The render targets, as `debug_view.wgsl` reads them.
+#[derive(Bindable)]
+#[bindable(fragment)]
+pub struct DebugViewBinding {
+    #[texture]
+    pub diffuse: wgpu::TextureView,
+    #[texture]
+    pub specular: wgpu::TextureView,
+    #[texture(depth)]
+    pub depth: wgpu::TextureView,
+    #[texture(uint)]
+    pub stencil: wgpu::TextureView,
+    #[texture]
+    pub overdraw: wgpu::TextureView,
+    /// A [`DebugViewUniform`].
+    #[uniform]
+    pub params: wgpu::Buffer,
+}
+
+/// How `debug_geometry.wgsl` reads one mesh's buffers.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct DebugMeshInfo {
+    /// Words per vertex.
+    stride: u32,
+    quantized: u32,
+    short_indices: u32,
+    vector_length: f32,
+}
+
+#[derive(Bindable)]
+#[bindable(vertex)]
+pub struct DebugMeshBinding {
+    #[storage(read_only)]
+    vertices: wgpu::Buffer,
+    #[storage(read_only)]
+    indices: wgpu::Buffer,
+    #[uniform]
+    info: wgpu::Buffer,
+}
+
+struct DebugMesh {
+    bind_group: wgpu::BindGroup,
+    vertex_count: u32,
+    index_count: u32,
+}
+
+/// A bind group for each of a model's meshes, for drawing them in `debug_geometry.wgsl`.
+pub struct DebugMeshes {
+    meshes: Vec<DebugMesh>,
+}
+
+impl DebugMeshes {
+    /// Vector lines are this fraction of the model's bounding radius long.
+    const VECTOR_LENGTH: f32 = 0.1;
+
+    pub fn new(device: &wgpu::Device, binder: &Binder<DebugMeshBinding>, model: &Model) -> Self {
+        let stride = model.vertex_layout.desc().array_stride;
+        let vector_length = (model.bounds().radius * Self::VECTOR_LENGTH).max(1e-3);
+        let meshes = model
+            .meshes
+            .iter()
+            .map(|mesh| {
+                let info = DebugMeshInfo {
+                    stride: (stride / 4) as u32,
+                    quantized: (model.vertex_layout == VertexLayout::Quantized) as u32,
+                    short_indices: (mesh.index_format == wgpu::IndexFormat::Uint16) as u32,
+                    vector_length,
+                };
+                let info = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(&format!("{} Debug Info Buffer", mesh.name)),
+                    contents: bytemuck::bytes_of(&info),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+                let bind_group = binder.create_bind_group(
+                    &DebugMeshBinding {
+                        vertices: mesh.vertex_buffer.clone(),
+                        indices: mesh.index_buffer.clone(),
+                        info,
+                    },
+                    device,
+                    Some(&format!("{} Debug Bind Group", mesh.name)),
+                );
+                DebugMesh {
+                    bind_group,
+                    vertex_count: (mesh.vertex_buffer.size() / stride) as u32,
+                    index_count: mesh.num_elements,
+                }
+            })
+            .collect();
+        Self { meshes }
+    }
+}
+
+pub trait DrawDebugMeshes<'a, 'b>
+where
+    'b: 'a,
+{
+    /// Draws the triangle edges of every mesh with `vertex_wireframe`. The instance buffer
+    /// must already be bound to slot 0.
+    fn draw_debug_wireframe(
+        &mut self,
+        meshes: &'b DebugMeshes,
+        instances: Range<u32>,
+        uniforms: &'b wgpu::BindGroup,
+    );
+
+    /// Draws the vector lines of every mesh's vertices with `vertex_vectors`. The instance
+    /// buffer must already be bound to slot 0.
+    fn draw_debug_vectors(
+        &mut self,
+        meshes: &'b DebugMeshes,
+        instances: Range<u32>,
+        uniforms: &'b wgpu::BindGroup,
+    );
+}
+
+impl<'a, 'b> DrawDebugMeshes<'a, 'b> for wgpu::RenderPass<'a>
+where
+    'b: 'a,
+{
+    fn draw_debug_wireframe(
+        &mut self,
+        meshes: &'b DebugMeshes,
+        instances: Range<u32>,
+        uniforms: &'b wgpu::BindGroup,
+    ) {
+        self.set_bind_group(1, uniforms, &[]);
+        for mesh in &meshes.meshes {
+            self.set_bind_group(0, &mesh.bind_group, &[]);
+            self.draw(0..mesh.index_count, instances.clone());
+        }
+    }
+
+    fn draw_debug_vectors(
+        &mut self,
+        meshes: &'b DebugMeshes,
+        instances: Range<u32>,
+        uniforms: &'b wgpu::BindGroup,
+    ) {
+        self.set_bind_group(1, uniforms, &[]);
+        for mesh in &meshes.meshes {
+            self.set_bind_group(0, &mesh.bind_group, &[]);
+            self.draw(0..mesh.vertex_count * 6, instances.clone());
+        }
+    }
+}
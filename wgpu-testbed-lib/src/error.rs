@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to read {path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Failed to read archive {name}: {source}")]
+    Archive {
+        name: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Failed to decode image {path}: {source}")]
+    Decode {
+        path: String,
+        #[source]
+        source: image::ImageError,
+    },
+    #[error("Failed to encode image {path}: {source}")]
+    Encode {
+        path: String,
+        #[source]
+        source: image::ImageError,
+    },
+    #[error("Failed to parse OBJ {path}: {source}")]
+    ObjParse {
+        path: String,
+        #[source]
+        source: tobj::LoadError,
+    },
+    #[error("Failed to parse glTF {path}: {source}")]
+    GltfParse {
+        path: String,
+        #[source]
+        source: gltf::Error,
+    },
+    #[error("Failed to parse mesh {path}: {message}")]
+    MeshParse { path: String, message: String },
+    #[error("Path is not valid UTF-8: {0:?}")]
+    InvalidPath(PathBuf),
+    #[error("Material {material} has no {kind} texture")]
+    MissingTexture {
+        material: String,
+        kind: &'static str,
+    },
+    #[error("{path} is not valid UTF-8: {source}")]
+    InvalidUtf8 {
+        path: String,
+        #[source]
+        source: std::str::Utf8Error,
+    },
+    #[error("{path} is not a baked asset, or was baked by an incompatible version")]
+    InvalidBakedAsset { path: String },
+    #[error("Failed to preprocess {path}, line {line}: {message}")]
+    ShaderPreprocess {
+        path: String,
+        line: usize,
+        message: String,
+    },
+    #[error("Failed to parse scene {path}, line {line}: {message}")]
+    SceneParse {
+        path: String,
+        line: usize,
+        message: String,
+    },
+    #[error("Shader validation failed for {label}: {message}")]
+    ShaderValidation { label: String, message: String },
+    #[error("Vertex buffers of {label} don't match `{entry_point}`: {message}")]
+    VertexLayoutMismatch {
+        label: String,
+        entry_point: String,
+        message: String,
+    },
+    #[error("Bind group {group} of {label} doesn't match its layout: {message}")]
+    BindingMismatch {
+        label: String,
+        group: u32,
+        message: String,
+    },
+    #[error("Failed to create surface: {0}")]
+    Surface(#[from] wgpu::CreateSurfaceError),
+    #[error("Validation error when acquiring next surface texture")]
+    SurfaceValidation,
+    #[error("No suitable adapter available: {0}")]
+    Adapter(#[from] wgpu::RequestAdapterError),
+    #[error("Could not get device from adapter: {0}")]
+    Device(#[from] wgpu::RequestDeviceError),
+    #[error("Error while waiting on the device: {0}")]
+    Poll(#[from] wgpu::PollError),
+    #[error("Failed to map buffer for reading: {0}")]
+    BufferMap(#[from] wgpu::BufferAsyncError),
+    #[error("Failed to create window: {0}")]
+    Window(#[from] winit::error::OsError),
+    #[error("Event loop error: {0}")]
+    EventLoop(#[from] winit::error::EventLoopError),
+}
@@ -1,60 +1,198 @@
+use std::sync::{Arc, LazyLock, RwLock};
+
+use log::debug;
+
+use crate::asset_source::{AssetSource, DirectorySource, MemorySource, normalize_path};
+use crate::error::{Error, Result};
+
+/// Environment variable overriding the directory the default mount reads from.
+pub const ASSET_ROOT_VAR: &str = "WGPU_TESTBED_ASSET_ROOT";
+
+struct Mount {
+    prefix: String,
+    source: Arc<dyn AssetSource>,
+}
+
+/// Sources in the order they were mounted. Later mounts shadow earlier ones.
+#[derive(Default)]
+struct Mounts(Vec<Mount>);
+
+impl Mounts {
+    fn mount(&mut self, prefix: &str, source: impl AssetSource + 'static) {
+        self.0.push(Mount {
+            prefix: normalize_path(prefix),
+            source: Arc::new(source),
+        });
+    }
+
+    /// The sources that could hold `path`, latest first, each with the path relative
+    /// to it. `path` must already be normalized.
+    fn lookup(&self, path: &str) -> Vec<(String, Arc<dyn AssetSource>)> {
+        self.0
+            .iter()
+            .rev()
+            .filter_map(|mount| {
+                let relative = if mount.prefix.is_empty() {
+                    path
+                } else {
+                    path.strip_prefix(mount.prefix.as_str())?
+                        .strip_prefix('/')?
+                };
+                Some((relative.to_owned(), mount.source.clone()))
+            })
+            .collect()
+    }
+}
+
+/// Reads `path` from the first of `sources` that has it.
+async fn read_first(
+    path: &str,
+    sources: Vec<(String, Arc<dyn AssetSource>)>,
+) -> Result<Option<Vec<u8>>> {
+    for (relative, source) in sources {
+        if let Some(bytes) = source.read(&relative).await? {
+            debug!("Read {} ({} bytes)", path, bytes.len());
+            return Ok(Some(bytes));
+        }
+    }
+    Ok(None)
+}
+
+/// The directory the default mount reads from, and [`FileReader::write_file`] writes to.
+static ROOT: LazyLock<String> = LazyLock::new(default_root);
+
+// The default directory is mounted first, so everything else shadows it.
+static MOUNTS: LazyLock<RwLock<Mounts>> = LazyLock::new(|| {
+    let mut mounts = Mounts::default();
+    mounts.mount("", DirectorySource::new(ROOT.as_str()));
+    RwLock::new(mounts)
+});
+
 #[cfg(target_arch = "wasm32")]
-use js_sys::ArrayBuffer;
-#[cfg(target_arch = "wasm32")]
-use wasm_bindgen::JsCast;
-#[cfg(target_arch = "wasm32")]
-use wasm_bindgen_futures::JsFuture;
-#[cfg(target_arch = "wasm32")]
-use web_sys::Response;
+fn default_root() -> String {
+    String::new()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn default_root() -> String {
+    std::env::var(ASSET_ROOT_VAR)
+        .ok()
+        .or_else(|| {
+            std::env::current_dir()
+                .ok()
+                .map(|dir| dir.to_string_lossy().into_owned())
+        })
+        .unwrap_or_default()
+}
 
 pub struct FileReader {}
 
 impl FileReader {
-    pub async fn read_file(path: &str) -> Vec<u8> {
-        #[cfg(target_arch = "wasm32")]
-        return FileReader::read_file_wasm(path).await;
-
-        #[cfg(not(target_arch = "wasm32"))]
-        return FileReader::read_file_native(path).await;
-    }
-
-    #[cfg(target_arch = "wasm32")]
-    async fn read_file_wasm(path: &str) -> Vec<u8> {
-        let window = web_sys::window().expect("Failed to create reference to window");
-        let response_js = JsFuture::from(window.fetch_with_str(path))
-            .await
-            .expect("Failed to fetch file");
-        assert!(response_js.is_instance_of::<Response>());
-        let response: Response = response_js.dyn_into().unwrap();
-
-        let buffer_js = JsFuture::from(
-            response
-                .array_buffer()
-                .expect("Failed to read array buffer"),
-        )
-        .await
-        .expect("Could not read response body buffer");
-        assert!(buffer_js.is_instance_of::<ArrayBuffer>());
-        let buffer: ArrayBuffer = buffer_js.dyn_into().unwrap();
-        let u8_buffer: js_sys::Uint8Array = js_sys::Uint8Array::new(&buffer);
-        let mut buff_vec = vec![0; u8_buffer.length() as usize];
-        u8_buffer.copy_to(&mut buff_vec[..]);
-        buff_vec
+    /// Mounts `source` at `prefix`, above every existing mount. Paths under `prefix`
+    /// are looked up in `source` with the prefix stripped.
+    pub fn mount(prefix: &str, source: impl AssetSource + 'static) {
+        MOUNTS.write().unwrap().mount(prefix, source);
     }
 
+    /// Removes every mount, including the default asset directory.
+    pub fn clear_mounts() {
+        MOUNTS.write().unwrap().0.clear();
+    }
+
+    /// Reads an archive through the existing mounts and mounts its contents at `prefix`.
+    /// The archive type is picked from its extension, `.tar` or otherwise zip. Returns
+    /// `false` without mounting anything if the archive does not exist.
+    pub async fn mount_archive(prefix: &str, path: &str) -> Result<bool> {
+        let Some(bytes) = FileReader::try_read_file(path).await? else {
+            return Ok(false);
+        };
+        let source = if path.ends_with(".tar") {
+            MemorySource::from_tar(path, &bytes)?
+        } else {
+            MemorySource::from_zip(path, &bytes)?
+        };
+        FileReader::mount(prefix, source);
+        Ok(true)
+    }
+
+    pub async fn read_file(path: &str) -> Result<Vec<u8>> {
+        FileReader::try_read_file(path)
+            .await?
+            .ok_or_else(|| Error::Io {
+                path: normalize_path(path),
+                source: std::io::ErrorKind::NotFound.into(),
+            })
+    }
+
+    /// Like [`FileReader::read_file`], but returns `Ok(None)` if no mount has the file.
+    pub async fn try_read_file(path: &str) -> Result<Option<Vec<u8>>> {
+        let path = normalize_path(path);
+        // The lock can't be held across the reads, so the sources are collected first.
+        let sources = MOUNTS.read().unwrap().lookup(&path);
+        read_first(&path, sources).await
+    }
+
+    /// Writes `path` into the default asset directory, where reads find it again unless
+    /// another mount shadows it.
     #[cfg(not(target_arch = "wasm32"))]
-    async fn read_file_native(path: &str) -> Vec<u8> {
-        use std::env;
-        use std::fs::File;
-        use std::io::Read;
-
-        let mut total_path = env::current_dir().unwrap();
-        total_path.push(path);
-        println!("reading file {:?}", total_path);
-        let mut file = File::open(total_path).expect("Failed to open file");
-        let mut buff_vec = Vec::<u8>::new();
-        file.read_to_end(&mut buff_vec)
-            .expect("Could not read bytes from file");
-        buff_vec
+    pub fn write_file(path: &str, bytes: &[u8]) -> Result<()> {
+        let path = DirectorySource::new(ROOT.as_str()).resolve(&normalize_path(path));
+        std::fs::write(&path, bytes).map_err(|source| Error::Io { path, source })
+    }
+
+    pub async fn read_string(path: &str) -> Result<String> {
+        let buffer = FileReader::read_file(path).await?;
+        String::from_utf8(buffer).map_err(|e| Error::InvalidUtf8 {
+            path: path.to_owned(),
+            source: e.utf8_error(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+
+    fn memory(files: &[(&str, &str)]) -> MemorySource {
+        let mut source = MemorySource::new();
+        for (path, contents) in files {
+            source.insert(path, contents.as_bytes().to_vec());
+        }
+        source
+    }
+
+    fn read(mounts: &Mounts, path: &str) -> Option<String> {
+        let path = normalize_path(path);
+        block_on(read_first(&path, mounts.lookup(&path)))
+            .unwrap()
+            .map(|bytes| String::from_utf8(bytes).unwrap())
+    }
+
+    #[test]
+    fn later_mount_shadows_earlier() {
+        let mut mounts = Mounts::default();
+        mounts.mount("", memory(&[("a.txt", "shipped"), ("b.txt", "shipped")]));
+        mounts.mount("", memory(&[("a.txt", "fixture")]));
+
+        assert_eq!(read(&mounts, "a.txt").as_deref(), Some("fixture"));
+        assert_eq!(read(&mounts, "b.txt").as_deref(), Some("shipped"));
+        assert_eq!(read(&mounts, "c.txt"), None);
+    }
+
+    #[test]
+    fn prefix_mount_only_serves_its_prefix() {
+        let mut mounts = Mounts::default();
+        mounts.mount("fixtures/", memory(&[("a.txt", "fixture")]));
+
+        assert_eq!(read(&mounts, "fixtures/a.txt").as_deref(), Some("fixture"));
+        assert_eq!(
+            read(&mounts, "./fixtures//a.txt").as_deref(),
+            Some("fixture")
+        );
+        assert_eq!(read(&mounts, "a.txt"), None);
+        assert_eq!(read(&mounts, "fixturesx/a.txt"), None);
+        assert_eq!(read(&mounts, "other/fixtures/a.txt"), None);
     }
 }
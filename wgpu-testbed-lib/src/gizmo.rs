@@ -0,0 +1,316 @@
+//! Translate, rotate and scale gizmos for the selected instance, with undo and redo.
+//!
+//! The gizmo is painted with egui behind the debug UI's windows, so it shows while the UI
+//! does. Its handles are hit tested against the cursor before a click is taken as a pick,
+//! and dragging one gives a new [`Instance`] for `State` to upload. Each finished drag is
+//! an [`Edit`], kept in a [`History`] to undo and redo.
+
+use cgmath::{
+    EuclideanSpace, InnerSpace, Matrix4, MetricSpace, Point3, Quaternion, Rad, Rotation3, Vector2,
+    Vector3,
+};
+
+use crate::camera::Camera;
+use crate::instance::Instance;
+
+/// Each axis's colour, for x, y and z.
+const AXIS_COLOURS: [egui::Color32; 3] = [
+    egui::Color32::from_rgb(230, 60, 60),
+    egui::Color32::from_rgb(80, 200, 80),
+    egui::Color32::from_rgb(70, 110, 240),
+];
+/// The gizmo's size, as a fraction of its distance from the camera, so it stays the same
+/// size on screen.
+const SIZE: f32 = 0.15;
+/// How near a handle the cursor has to be to grab it, in pixels.
+const GRAB_DISTANCE: f32 = 8.0;
+const RING_SEGMENTS: usize = 48;
+/// The smallest scale a drag leaves on an axis.
+const MIN_SCALE: f32 = 0.01;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GizmoMode {
+    /// Arrows along the world axes.
+    #[default]
+    Translate,
+    /// Rings around the world axes.
+    Rotate,
+    /// Arrows along the instance's own axes.
+    Scale,
+}
+
+impl GizmoMode {
+    pub const ALL: [Self; 3] = [Self::Translate, Self::Rotate, Self::Scale];
+
+    /// The directions the handles point in, or turn around.
+    fn axes(self, instance: &Instance) -> [Vector3<f32>; 3] {
+        let axes = [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()];
+        match self {
+            Self::Translate | Self::Rotate => axes,
+            Self::Scale => axes.map(|axis| instance.rotation * axis),
+        }
+    }
+}
+
+/// How the camera sees the gizmo, in the window's physical pixels.
+pub struct GizmoView {
+    view_proj: Matrix4<f32>,
+    eye: Point3<f32>,
+    size: [f32; 2],
+}
+
+impl GizmoView {
+    pub fn new(camera: &Camera, size: [u32; 2]) -> Self {
+        Self {
+            view_proj: camera.build_view_projection_matrix(),
+            eye: camera.eye,
+            size: [size[0] as f32, size[1] as f32],
+        }
+    }
+
+    /// Where `point` lands in the window, or `None` if it is behind the camera.
+    fn project(&self, point: Point3<f32>) -> Option<Vector2<f32>> {
+        let clip = self.view_proj * point.to_homogeneous();
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let ndc = clip.truncate() / clip.w;
+        Some(Vector2::new(
+            (ndc.x + 1.0) * 0.5 * self.size[0],
+            (1.0 - ndc.y) * 0.5 * self.size[1],
+        ))
+    }
+}
+
+/// A change to one instance.
+#[derive(Debug, Clone, Copy)]
+pub struct Edit {
+    pub index: usize,
+    pub before: Instance,
+    pub after: Instance,
+}
+
+/// Edits that can be undone, and undone edits that can be redone.
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl History {
+    /// Records `edit`, which can no longer be followed by anything that was undone.
+    pub fn push(&mut self, edit: Edit) {
+        self.undo.push(edit);
+        self.redo.clear();
+    }
+
+    /// The last edit, to be reverted to its `before`.
+    pub fn undo(&mut self) -> Option<Edit> {
+        let edit = self.undo.pop()?;
+        self.redo.push(edit);
+        Some(edit)
+    }
+
+    /// The last edit undone, to be applied again.
+    pub fn redo(&mut self) -> Option<Edit> {
+        let edit = self.redo.pop()?;
+        self.undo.push(edit);
+        Some(edit)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+/// A handle as drawn, a line or ring in window pixels.
+struct Handle {
+    axis: usize,
+    points: Vec<Vector2<f32>>,
+}
+
+impl Handle {
+    fn distance(&self, point: Vector2<f32>) -> f32 {
+        self.points
+            .windows(2)
+            .map(|segment| segment_distance(point, segment[0], segment[1]))
+            .fold(f32::INFINITY, f32::min)
+    }
+}
+
+struct Drag {
+    /// The mode the handle was grabbed in, kept if the gizmo's changes mid-drag.
+    mode: GizmoMode,
+    axis: usize,
+    /// The instance as it was when the handle was grabbed.
+    start: Instance,
+    cursor: Vector2<f32>,
+}
+
+#[derive(Default)]
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    drag: Option<Drag>,
+}
+
+impl Gizmo {
+    /// The handles' length in world units.
+    fn length(view: &GizmoView, instance: &Instance) -> f32 {
+        SIZE * view.eye.distance(Point3::from_vec(instance.position))
+    }
+
+    fn handles(&self, view: &GizmoView, instance: &Instance) -> Vec<Handle> {
+        let origin = Point3::from_vec(instance.position);
+        let length = Self::length(view, instance);
+        let axes = self.mode.axes(instance);
+        (0..3)
+            .filter_map(|axis| {
+                let points: Vec<Point3<f32>> = match self.mode {
+                    GizmoMode::Translate | GizmoMode::Scale => {
+                        vec![origin, origin + axes[axis] * length]
+                    }
+                    GizmoMode::Rotate => {
+                        let (u, v) = (axes[(axis + 1) % 3], axes[(axis + 2) % 3]);
+                        (0..=RING_SEGMENTS)
+                            .map(|segment| {
+                                let angle =
+                                    segment as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+                                origin + (u * angle.cos() + v * angle.sin()) * length
+                            })
+                            .collect()
+                    }
+                };
+                let points = points
+                    .into_iter()
+                    .map(|point| view.project(point))
+                    .collect::<Option<_>>()?;
+                Some(Handle { axis, points })
+            })
+            .collect()
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    /// Grabs the handle nearest `cursor`, if it is near enough to one.
+    pub fn grab(&mut self, view: &GizmoView, instance: &Instance, cursor: [f32; 2]) -> bool {
+        let cursor = Vector2::from(cursor);
+        let nearest = self
+            .handles(view, instance)
+            .into_iter()
+            .map(|handle| (handle.distance(cursor), handle.axis))
+            .filter(|&(distance, _)| distance <= GRAB_DISTANCE)
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        self.drag = nearest.map(|(_, axis)| Drag {
+            mode: self.mode,
+            axis,
+            start: *instance,
+            cursor,
+        });
+        self.drag.is_some()
+    }
+
+    /// The grabbed instance with its handle moved to `cursor`.
+    pub fn drag(&self, view: &GizmoView, cursor: [f32; 2]) -> Option<Instance> {
+        let drag = self.drag.as_ref()?;
+        let cursor = Vector2::from(cursor);
+        let start = drag.start;
+        let axis = drag.mode.axes(&start)[drag.axis];
+        let origin = Point3::from_vec(start.position);
+        let centre = view.project(origin)?;
+        let mut instance = start;
+        match drag.mode {
+            GizmoMode::Translate | GizmoMode::Scale => {
+                let length = Self::length(view, &start);
+                // How far the cursor has moved along the handle, in handle lengths.
+                let along = view.project(origin + axis * length)? - centre;
+                if along.magnitude2() < 1.0 {
+                    return None;
+                }
+                let moved = (cursor - drag.cursor).dot(along) / along.magnitude2();
+                match drag.mode {
+                    GizmoMode::Translate => instance.position += axis * moved * length,
+                    _ => {
+                        let scale = &mut instance.scale[drag.axis];
+                        *scale = (*scale * (1.0 + moved)).max(MIN_SCALE);
+                    }
+                }
+            }
+            GizmoMode::Rotate => {
+                let angle = |point: Vector2<f32>| {
+                    let offset = point - centre;
+                    offset.y.atan2(offset.x)
+                };
+                // The window's y points down, so turning clockwise on screen is turning
+                // anticlockwise about an axis pointing at the camera.
+                let turned = angle(cursor) - angle(drag.cursor);
+                let facing = axis.dot(view.eye - origin) > 0.0;
+                let turned = if facing { -turned } else { turned };
+                instance.rotation =
+                    (Quaternion::from_axis_angle(axis, Rad(turned)) * start.rotation).normalize();
+            }
+        }
+        Some(instance)
+    }
+
+    /// Lets go of the handle, giving back the instance as it was when grabbed.
+    pub fn release(&mut self) -> Option<Instance> {
+        self.drag.take().map(|drag| drag.start)
+    }
+
+    /// Paints the handles for `instance`, the grabbed one highlighted.
+    pub fn paint(
+        &self,
+        painter: &egui::Painter,
+        view: &GizmoView,
+        instance: &Instance,
+        pixels_per_point: f32,
+    ) {
+        let to_pos = |point: Vector2<f32>| egui::pos2(point.x, point.y) / pixels_per_point;
+        for handle in self.handles(view, instance) {
+            let grabbed = self.drag.as_ref().map(|drag| drag.axis) == Some(handle.axis);
+            let colour = if grabbed {
+                egui::Color32::YELLOW
+            } else {
+                AXIS_COLOURS[handle.axis]
+            };
+            let stroke = egui::Stroke::new(3.0, colour);
+            let points: Vec<egui::Pos2> = handle.points.iter().copied().map(to_pos).collect();
+            painter.add(egui::Shape::line(points.clone(), stroke));
+            match (self.mode, points.last().copied()) {
+                (GizmoMode::Translate, Some(tip)) => {
+                    painter.circle_filled(tip, 5.0, colour);
+                }
+                (GizmoMode::Scale, Some(tip)) => {
+                    let square = egui::Rect::from_center_size(tip, egui::vec2(9.0, 9.0));
+                    painter.rect_filled(square, 0.0, colour);
+                }
+                _ => {}
+            }
+        }
+        if let Some(centre) = view.project(Point3::from_vec(instance.position)) {
+            painter.circle_filled(to_pos(centre), 3.0, egui::Color32::WHITE);
+        }
+    }
+}
+
+/// How far `point` is from the segment from `a` to `b`.
+fn segment_distance(point: Vector2<f32>, a: Vector2<f32>, b: Vector2<f32>) -> f32 {
+    let ab = b - a;
+    let t = if ab.magnitude2() > 0.0 {
+        ((point - a).dot(ab) / ab.magnitude2()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (a + ab * t).distance(point)
+}
@@ -0,0 +1,547 @@
+//! Loads glTF 2.0 models, as `.gltf` files with external or embedded buffers, or as
+//! binary `.glb`. Every triangle primitive of the default scene becomes a `tobj` mesh
+//! with its node's transform applied, so tangent generation, caching and baking treat
+//! glTF as they do OBJ.
+
+use std::sync::Arc;
+
+use base64::Engine;
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, Point3, SquareMatrix, Transform, Vector3};
+use gltf::mesh::Mode;
+use log::warn;
+
+use crate::asset_cache::CacheKey;
+use crate::asset_source::normalize_path;
+use crate::error::{Error, Result};
+use crate::file_reader::FileReader;
+use crate::model::{MaterialParams, MaterialSource, TextureSource};
+
+/// A parsed glTF, before its meshes have been processed.
+pub struct GltfScene {
+    pub models: Vec<tobj::Model>,
+    pub materials: Vec<MaterialSource>,
+    /// Covers the file and every external buffer it reads.
+    pub cache_key: CacheKey,
+}
+
+/// Parses a glTF or GLB, reading its external buffers relative to `path`. Images in
+/// files stay paths, and images in buffers or data URIs are embedded.
+pub async fn load_gltf(path: &str, bytes: &[u8]) -> Result<GltfScene> {
+    let parse_error = |message: String| Error::MeshParse {
+        path: path.to_owned(),
+        message,
+    };
+
+    let gltf::Gltf { document, mut blob } =
+        gltf::Gltf::from_slice(bytes).map_err(|source| Error::GltfParse {
+            path: path.to_owned(),
+            source,
+        })?;
+
+    let mut cache_key = CacheKey::new("gltf", bytes, &[]);
+    let mut buffers = Vec::new();
+    for buffer in document.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Bin => blob
+                .take()
+                .ok_or_else(|| parse_error("missing binary chunk".into()))?,
+            gltf::buffer::Source::Uri(uri) => {
+                let data = match decode_data_uri(uri) {
+                    Some(data) => data.map_err(parse_error)?.1,
+                    None => FileReader::read_file(&resolve_uri(path, uri)).await?,
+                };
+                cache_key = cache_key.with_settings(&data);
+                data
+            }
+        };
+        if data.len() < buffer.length() {
+            return Err(parse_error(format!(
+                "buffer {} is shorter than declared",
+                buffer.index()
+            )));
+        }
+        buffers.push(data);
+    }
+
+    let mut materials = document
+        .materials()
+        .map(|material| {
+            let index = material.index().unwrap_or_default();
+            let pbr = material.pbr_metallic_roughness();
+            Ok(MaterialSource {
+                name: material
+                    .name()
+                    .map_or_else(|| format!("material{}", index), str::to_owned),
+                diffuse: texture_source(
+                    path,
+                    &buffers,
+                    pbr.base_color_texture().map(|info| info.texture()),
+                )
+                .map_err(parse_error)?,
+                normal: texture_source(
+                    path,
+                    &buffers,
+                    material.normal_texture().map(|normal| normal.texture()),
+                )
+                .map_err(parse_error)?,
+                params: MaterialParams::from_gltf(&material),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // Primitives without a material get glTF's default one, after the file's own.
+    let default_material = materials.len();
+
+    let mut nodes = Vec::new();
+    match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => {
+            for node in scene.nodes() {
+                collect_meshes(node, Matrix4::identity(), &mut nodes);
+            }
+        }
+        None => nodes.extend(document.meshes().map(|mesh| (mesh, Matrix4::identity()))),
+    }
+
+    let mut models = Vec::new();
+    for (mesh, transform) in nodes {
+        let mesh_name = mesh
+            .name()
+            .map_or_else(|| format!("mesh{}", mesh.index()), str::to_owned);
+        let primitive_count = mesh.primitives().len();
+        for primitive in mesh.primitives() {
+            let name = if primitive_count > 1 {
+                format!("{}.{}", mesh_name, primitive.index())
+            } else {
+                mesh_name.clone()
+            };
+            if !matches!(
+                primitive.mode(),
+                Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan
+            ) {
+                warn!(
+                    "Skipping {} in {}, only triangle primitives are drawn",
+                    name, path
+                );
+                continue;
+            }
+
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+            let positions: Vec<[f32; 3]> = reader
+                .read_positions()
+                .ok_or_else(|| parse_error(format!("{} has no positions", name)))?
+                .collect();
+            let vertex_count = positions.len() as u32;
+            let indices = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..vertex_count).collect::<Vec<_>>(),
+            };
+            if indices.iter().any(|&index| index >= vertex_count) {
+                return Err(parse_error(format!("{} has an index out of range", name)));
+            }
+
+            let mut model_mesh = tobj::Mesh {
+                indices: triangulate(primitive.mode(), &indices),
+                material_id: Some(primitive.material().index().unwrap_or(default_material)),
+                ..Default::default()
+            };
+            // A mirroring transform turns triangles inside out, so flip them back.
+            if transform.determinant() < 0.0 {
+                for triangle in model_mesh.indices.chunks_exact_mut(3) {
+                    triangle.swap(1, 2);
+                }
+            }
+            for position in positions {
+                let position = transform.transform_point(Point3::from(position));
+                model_mesh
+                    .positions
+                    .extend_from_slice(&[position.x, position.y, position.z]);
+            }
+            if let Some(normals) = reader.read_normals() {
+                let normal_matrix = normal_matrix(&transform);
+                for normal in normals {
+                    let normal = normal_matrix * Vector3::from(normal);
+                    let normal = if normal.magnitude2() > 0.0 {
+                        normal.normalize()
+                    } else {
+                        normal
+                    };
+                    model_mesh
+                        .normals
+                        .extend_from_slice(&[normal.x, normal.y, normal.z]);
+                }
+            }
+            if let Some(tex_coords) = reader.read_tex_coords(0) {
+                model_mesh.texcoords.extend(tex_coords.into_f32().flatten());
+            }
+            if let Some(colours) = reader.read_colors(0) {
+                model_mesh
+                    .vertex_color
+                    .extend(colours.into_rgb_f32().flatten());
+            }
+            models.push(tobj::Model::new(model_mesh, name));
+        }
+    }
+
+    if models
+        .iter()
+        .any(|model| model.mesh.material_id == Some(default_material))
+    {
+        materials.push(MaterialSource {
+            name: "default".to_owned(),
+            diffuse: TextureSource::None,
+            normal: TextureSource::None,
+            params: MaterialParams::default(),
+        });
+    }
+
+    Ok(GltfScene {
+        models,
+        materials,
+        cache_key,
+    })
+}
+
+/// Adds the meshes under `node` along with their transforms to world space.
+fn collect_meshes<'a>(
+    node: gltf::Node<'a>,
+    parent: Matrix4<f32>,
+    meshes: &mut Vec<(gltf::Mesh<'a>, Matrix4<f32>)>,
+) {
+    let transform = parent * Matrix4::from(node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        meshes.push((mesh, transform));
+    }
+    for child in node.children() {
+        collect_meshes(child, transform, meshes);
+    }
+}
+
+/// The inverse transpose of `transform`'s upper 3x3, which keeps normals perpendicular
+/// to surfaces under non-uniform scales.
+fn normal_matrix(transform: &Matrix4<f32>) -> Matrix3<f32> {
+    let linear = Matrix3::from_cols(
+        transform.x.truncate(),
+        transform.y.truncate(),
+        transform.z.truncate(),
+    );
+    linear
+        .invert()
+        .map_or_else(Matrix3::identity, |inverse| inverse.transpose())
+}
+
+/// Expands strips and fans into a triangle list, keeping their winding.
+fn triangulate(mode: Mode, indices: &[u32]) -> Vec<u32> {
+    match mode {
+        Mode::TriangleStrip => (0..indices.len().saturating_sub(2))
+            .flat_map(|i| {
+                if i % 2 == 0 {
+                    [indices[i], indices[i + 1], indices[i + 2]]
+                } else {
+                    [indices[i], indices[i + 2], indices[i + 1]]
+                }
+            })
+            .collect(),
+        Mode::TriangleFan => (1..indices.len().saturating_sub(1))
+            .flat_map(|i| [indices[0], indices[i], indices[i + 1]])
+            .collect(),
+        _ => indices[..indices.len() / 3 * 3].to_vec(),
+    }
+}
+
+/// Where a material's texture is, resolved against the glTF at `path`.
+fn texture_source(
+    path: &str,
+    buffers: &[Vec<u8>],
+    texture: Option<gltf::Texture>,
+) -> std::result::Result<TextureSource, String> {
+    let Some(image) = texture.map(|texture| texture.source()) else {
+        return Ok(TextureSource::None);
+    };
+    let (mime_type, bytes) = match image.source() {
+        gltf::image::Source::Uri { uri, mime_type } => match decode_data_uri(uri) {
+            Some(data) => {
+                let (data_mime_type, bytes) = data?;
+                (mime_type.unwrap_or(data_mime_type).to_owned(), bytes)
+            }
+            None => return Ok(TextureSource::Path(resolve_uri(path, uri))),
+        },
+        gltf::image::Source::View { view, mime_type } => {
+            let bytes = buffers
+                .get(view.buffer().index())
+                .and_then(|buffer| buffer.get(view.offset()..view.offset() + view.length()))
+                .ok_or_else(|| format!("image {} is outside its buffer", image.index()))?;
+            (mime_type.to_owned(), bytes.to_vec())
+        }
+    };
+    let extension = if mime_type == "image/jpeg" {
+        "jpg"
+    } else {
+        "png"
+    };
+    let file_start = path.rfind('/').map_or(0, |slash| slash + 1);
+    let stem = match path[file_start..].rfind('.') {
+        Some(dot) => &path[..file_start + dot],
+        None => path,
+    };
+    Ok(TextureSource::Embedded {
+        name: format!("{}/image{}.{}", stem, image.index(), extension),
+        bytes: Arc::from(bytes),
+    })
+}
+
+/// Decodes a base64 `data:` URI into its MIME type and bytes, or returns `None` if
+/// `uri` is not one.
+fn decode_data_uri(uri: &str) -> Option<std::result::Result<(&str, Vec<u8>), String>> {
+    let data = uri.strip_prefix("data:")?;
+    Some(
+        data.split_once(";base64,")
+            .ok_or_else(|| "data URI is not base64".to_owned())
+            .and_then(|(mime_type, data)| {
+                base64::engine::general_purpose::STANDARD
+                    .decode(data)
+                    .map(|bytes| (mime_type, bytes))
+                    .map_err(|e| format!("invalid data URI: {}", e))
+            }),
+    )
+}
+
+/// Resolves a relative URI against the directory of the glTF at `path`.
+fn resolve_uri(path: &str, uri: &str) -> String {
+    let uri = percent_encoding::percent_decode_str(uri).decode_utf8_lossy();
+    let directory = path.rsplit_once('/').map_or("", |(directory, _)| directory);
+    normalize_path(&format!("{}/{}", directory, uri))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::model::AlphaMode;
+
+    /// A unit quad drawn as a strip, facing +Z: positions, normals, UVs, then indices.
+    fn quad_buffer(indices: [u16; 4]) -> Vec<u8> {
+        let positions = [
+            [1.0f32, 0.0, 0.0],
+            [0.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+        ];
+        let mut buffer = Vec::new();
+        for position in positions {
+            buffer.extend(position.iter().flat_map(|f| f.to_le_bytes()));
+        }
+        for _ in 0..4 {
+            buffer.extend([0.0f32, 0.0, 1.0].iter().flat_map(|f| f.to_le_bytes()));
+        }
+        for position in positions {
+            buffer.extend(position[..2].iter().flat_map(|f| f.to_le_bytes()));
+        }
+        buffer.extend(indices.iter().flat_map(|i| i.to_le_bytes()));
+        buffer
+    }
+
+    /// A glTF with a node tree that mirrors the quad, whose first primitive is an indexed
+    /// strip with a material and whose second is a plain triangle list without one.
+    fn document(buffer: &str, image: &str) -> String {
+        format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scene": 0,
+                "scenes": [{{ "nodes": [0] }}],
+                "nodes": [
+                    {{ "children": [1], "translation": [1, 0, 0] }},
+                    {{ "mesh": 0, "scale": [-1, 1, 1] }}
+                ],
+                "meshes": [{{
+                    "name": "quad",
+                    "primitives": [
+                        {{
+                            "attributes": {{ "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2 }},
+                            "indices": 3,
+                            "mode": 5,
+                            "material": 0
+                        }},
+                        {{ "attributes": {{ "POSITION": 0 }} }}
+                    ]
+                }}],
+                "materials": [{{
+                    "name": "painted",
+                    "pbrMetallicRoughness": {{
+                        "baseColorTexture": {{ "index": 0 }},
+                        "baseColorFactor": [1, 1, 1, 0.5]
+                    }},
+                    "normalTexture": {{ "index": 1 }},
+                    "alphaMode": "BLEND"
+                }}],
+                "textures": [{{ "source": 0 }}, {{ "source": 1 }}],
+                "images": [{{ "uri": "textures/paint%20ed.png" }}, {image}],
+                "buffers": [{buffer}],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteOffset": 0, "byteLength": 48 }},
+                    {{ "buffer": 0, "byteOffset": 48, "byteLength": 48 }},
+                    {{ "buffer": 0, "byteOffset": 96, "byteLength": 32 }},
+                    {{ "buffer": 0, "byteOffset": 128, "byteLength": 8 }},
+                    {{ "buffer": 0, "byteOffset": 136, "byteLength": 4 }}
+                ],
+                "accessors": [
+                    {{ "bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3",
+                       "min": [0, 0, 0], "max": [1, 1, 0] }},
+                    {{ "bufferView": 1, "componentType": 5126, "count": 4, "type": "VEC3" }},
+                    {{ "bufferView": 2, "componentType": 5126, "count": 4, "type": "VEC2" }},
+                    {{ "bufferView": 3, "componentType": 5123, "count": 4, "type": "SCALAR" }}
+                ]
+            }}"#,
+            buffer = buffer,
+            image = image,
+        )
+    }
+
+    /// The quad's buffer followed by four bytes standing in for an encoded image.
+    fn buffer_with_image(indices: [u16; 4]) -> Vec<u8> {
+        let mut buffer = quad_buffer(indices);
+        buffer.extend_from_slice(b"\x89PNG");
+        buffer
+    }
+
+    fn gltf(indices: [u16; 4]) -> String {
+        let buffer = buffer_with_image(indices);
+        let uri = base64::engine::general_purpose::STANDARD.encode(&buffer);
+        document(
+            &format!(
+                r#"{{ "byteLength": {}, "uri": "data:application/octet-stream;base64,{}" }}"#,
+                buffer.len(),
+                uri
+            ),
+            r#"{ "uri": "data:image/jpeg;base64,/9j/" }"#,
+        )
+    }
+
+    fn glb() -> Vec<u8> {
+        let buffer = buffer_with_image([0, 1, 2, 3]);
+        let mut json = document(
+            &format!(r#"{{ "byteLength": {} }}"#, buffer.len()),
+            r#"{ "bufferView": 4, "mimeType": "image/png" }"#,
+        )
+        .into_bytes();
+        while !json.len().is_multiple_of(4) {
+            json.push(b' ');
+        }
+        let mut bin = buffer;
+        while !bin.len().is_multiple_of(4) {
+            bin.push(0);
+        }
+
+        let mut glb = Vec::new();
+        glb.extend_from_slice(b"glTF");
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+        glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"JSON");
+        glb.extend_from_slice(&json);
+        glb.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"BIN\0");
+        glb.extend_from_slice(&bin);
+        glb
+    }
+
+    fn positions(model: &tobj::Model) -> Vec<[f32; 3]> {
+        model
+            .mesh
+            .positions
+            .chunks_exact(3)
+            .map(|p| [p[0], p[1], p[2]])
+            .collect()
+    }
+
+    #[test]
+    fn loads_meshes_with_their_node_transforms() {
+        let scene = block_on(load_gltf("models/quad.gltf", gltf([0, 1, 2, 3]).as_bytes())).unwrap();
+
+        assert_eq!(scene.models.len(), 2);
+        let strip = &scene.models[0];
+        assert_eq!(strip.name, "quad.0");
+        // Mirrored in X, then moved one along it, so the quad lands back on [0, 1].
+        assert_eq!(
+            positions(strip),
+            [
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [1.0, 1.0, 0.0]
+            ]
+        );
+        assert_eq!(strip.mesh.normals[..3], [0.0, 0.0, 1.0]);
+        assert_eq!(strip.mesh.texcoords[..2], [1.0, 0.0]);
+        // The strip's triangles, with their winding flipped back after the mirror.
+        assert_eq!(strip.mesh.indices, [0, 2, 1, 1, 2, 3]);
+        assert_eq!(strip.mesh.material_id, Some(0));
+
+        let list = &scene.models[1];
+        assert_eq!(list.name, "quad.1");
+        assert_eq!(list.mesh.indices, [0, 2, 1]);
+        assert!(list.mesh.normals.is_empty());
+        assert_eq!(list.mesh.material_id, Some(1));
+    }
+
+    #[test]
+    fn reads_materials_and_their_textures() {
+        let scene = block_on(load_gltf("models/quad.gltf", gltf([0, 1, 2, 3]).as_bytes())).unwrap();
+
+        assert_eq!(scene.materials.len(), 2);
+        let painted = &scene.materials[0];
+        assert_eq!(painted.name, "painted");
+        assert_eq!(painted.params.alpha_mode, AlphaMode::Blend);
+        assert_eq!(painted.params.opacity, 0.5);
+        assert_eq!(
+            painted.diffuse,
+            TextureSource::Path("models/textures/paint ed.png".to_owned())
+        );
+        assert_eq!(
+            painted.normal,
+            TextureSource::Embedded {
+                name: "models/quad/image1.jpg".to_owned(),
+                bytes: Arc::from(&[0xff, 0xd8, 0xff][..]),
+            }
+        );
+
+        let default = &scene.materials[1];
+        assert_eq!(default.diffuse, TextureSource::None);
+        assert_eq!(default.normal, TextureSource::None);
+        assert_eq!(default.params, MaterialParams::default());
+    }
+
+    #[test]
+    fn loads_binary_gltf() {
+        let scene = block_on(load_gltf("models/quad.glb", &glb())).unwrap();
+
+        assert_eq!(scene.models.len(), 2);
+        assert_eq!(scene.models[0].mesh.indices, [0, 2, 1, 1, 2, 3]);
+        assert_eq!(
+            scene.materials[0].normal,
+            TextureSource::Embedded {
+                name: "models/quad/image1.png".to_owned(),
+                bytes: Arc::from(&b"\x89PNG"[..]),
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_indices_out_of_range() {
+        let result = block_on(load_gltf("models/quad.gltf", gltf([0, 1, 2, 4]).as_bytes()));
+        assert!(matches!(result, Err(Error::MeshParse { .. })));
+        let result = block_on(load_gltf("models/quad.gltf", b"{ not json"));
+        assert!(matches!(result, Err(Error::GltfParse { .. })));
+    }
+
+    #[test]
+    fn triangulates_fans() {
+        assert_eq!(
+            triangulate(Mode::TriangleFan, &[0, 1, 2, 3, 4]),
+            [0, 1, 2, 0, 2, 3, 0, 3, 4]
+        );
+        assert_eq!(triangulate(Mode::Triangles, &[0, 1, 2, 3]), [0, 1, 2]);
+    }
+}
@@ -0,0 +1,366 @@
+//! GPU-driven drawing of a whole model from one merged vertex and index buffer.
+//!
+//! Each mesh becomes one indirect draw, sorted by material so a scene costs a bind group
+//! switch per material rather than a full rebind per mesh. Materials are grouped by
+//! [`AlphaMode`] first, so each pass can draw just the materials it handles. The per-draw records are
+//! also kept in a storage buffer, so compute passes such as culling can find each
+//! draw's material and bounds and edit the indirect arguments in place.
+//!
+//! Every vertex also carries its mesh's material and mesh index in a separate buffer, so
+//! with [`bindless`](crate::bindless) materials the whole scene is one indirect call, and
+//! picking can tell the merged meshes apart.
+
+use std::ops::Range;
+
+use wgpu::util::{DeviceExt, DrawIndexedIndirectArgs};
+
+use crate::model::{AlphaMode, Model, VERTEX_IDS_SLOT, VertexIds};
+use crate::stats::DrawStats;
+
+/// A mesh's slice of the merged buffers, laid out for reading from a storage buffer.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DrawRecord {
+    pub material: u32,
+    pub first_index: u32,
+    pub index_count: u32,
+    pub base_vertex: i32,
+    pub bounds_center: [f32; 3],
+    pub bounds_radius: f32,
+}
+
+/// How a [`GpuScene`] issues its draws.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawMode {
+    /// One `multi_draw_indexed_indirect` per material.
+    Indirect,
+    /// A `draw_indexed` per mesh, for devices that can't read draw arguments from a
+    /// buffer.
+    DirectLoop,
+}
+
+impl DrawMode {
+    /// The fastest mode the adapter supports.
+    pub fn supported(adapter: &wgpu::Adapter) -> Self {
+        let flags = adapter.get_downlevel_capabilities().flags;
+        if flags.contains(wgpu::DownlevelFlags::INDIRECT_EXECUTION) {
+            Self::Indirect
+        } else {
+            Self::DirectLoop
+        }
+    }
+}
+
+pub struct GpuScene {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_format: wgpu::IndexFormat,
+    /// The [`VertexIds`] of every vertex in `vertex_buffer`.
+    ids_buffer: wgpu::Buffer,
+    indirect_buffer: wgpu::Buffer,
+    /// The [`DrawRecord`] of every indirect draw, in the same order.
+    pub draw_buffer: wgpu::Buffer,
+    draws: Vec<DrawRecord>,
+    /// Each material and the draws that use it.
+    material_ranges: Vec<(usize, Range<u32>)>,
+    /// Each alpha mode in use and the draws whose material has it.
+    alpha_ranges: Vec<(AlphaMode, Range<u32>)>,
+    instance_count: u32,
+}
+
+impl GpuScene {
+    /// Copies every mesh of `model` into shared buffers. The model's own buffers are left
+    /// alone, so it can still be drawn mesh by mesh.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        model: &Model,
+        instance_count: u32,
+    ) -> Self {
+        let stride = model.vertex_layout.desc().array_stride;
+        let index_format = model
+            .meshes
+            .first()
+            .map_or(wgpu::IndexFormat::Uint32, |mesh| mesh.index_format);
+        let index_size = match index_format {
+            wgpu::IndexFormat::Uint16 => 2,
+            wgpu::IndexFormat::Uint32 => 4,
+        };
+
+        let mut order: Vec<usize> = (0..model.meshes.len()).collect();
+        let alpha_mode = |material: usize| model.materials[material].params.alpha_mode;
+        order.sort_by_key(|&mesh| {
+            let material = model.meshes[mesh].material;
+            (alpha_mode(material), material)
+        });
+
+        // Index buffers are padded to 4 bytes, so each mesh starts on that boundary.
+        let mut vertex_offset = 0;
+        let mut index_offset = 0;
+        let mut placements = Vec::with_capacity(order.len());
+        let mut draws = Vec::with_capacity(order.len());
+        let mut material_ranges: Vec<(usize, Range<u32>)> = Vec::new();
+        let mut alpha_ranges: Vec<(AlphaMode, Range<u32>)> = Vec::new();
+        let mut ids: Vec<VertexIds> = Vec::new();
+        for &mesh_index in &order {
+            let mesh = &model.meshes[mesh_index];
+            debug_assert_eq!(mesh.index_format, index_format);
+            placements.push((mesh, vertex_offset, index_offset));
+            draws.push(DrawRecord {
+                material: mesh.material as u32,
+                first_index: (index_offset / index_size) as u32,
+                index_count: mesh.num_elements,
+                base_vertex: (vertex_offset / stride) as i32,
+                bounds_center: mesh.bounds.center.into(),
+                bounds_radius: mesh.bounds.radius,
+            });
+
+            let draw = draws.len() as u32 - 1;
+            match material_ranges.last_mut() {
+                Some((material, range)) if *material == mesh.material => range.end = draw + 1,
+                _ => material_ranges.push((mesh.material, draw..draw + 1)),
+            }
+            match alpha_ranges.last_mut() {
+                Some((mode, range)) if *mode == alpha_mode(mesh.material) => range.end = draw + 1,
+                _ => alpha_ranges.push((alpha_mode(mesh.material), draw..draw + 1)),
+            }
+
+            let vertex_count = (mesh.vertex_buffer.size() / stride) as usize;
+            ids.resize(
+                ids.len() + vertex_count,
+                VertexIds {
+                    material: mesh.material as u32,
+                    mesh: mesh_index as u32,
+                },
+            );
+
+            vertex_offset += mesh.vertex_buffer.size();
+            index_offset += mesh.index_buffer.size();
+        }
+
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Merged Vertex Buffer"),
+            size: vertex_offset.max(wgpu::COPY_BUFFER_ALIGNMENT),
+            usage: wgpu::BufferUsages::VERTEX
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let index_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Merged Index Buffer"),
+            size: index_offset.max(wgpu::COPY_BUFFER_ALIGNMENT),
+            usage: wgpu::BufferUsages::INDEX
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let ids_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Merged Ids Buffer"),
+            contents: bytemuck::cast_slice(&ids),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Merge Geometry Encoder"),
+        });
+        for (mesh, vertex_offset, index_offset) in placements {
+            encoder.copy_buffer_to_buffer(
+                &mesh.vertex_buffer,
+                0,
+                &vertex_buffer,
+                vertex_offset,
+                mesh.vertex_buffer.size(),
+            );
+            encoder.copy_buffer_to_buffer(
+                &mesh.index_buffer,
+                0,
+                &index_buffer,
+                index_offset,
+                mesh.index_buffer.size(),
+            );
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let indirect_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Indirect Draw Buffer"),
+            contents: &indirect_args(&draws, instance_count),
+            usage: wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST,
+        });
+        let draw_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Draw Record Buffer"),
+            contents: bytemuck::cast_slice(&draws),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            index_format,
+            ids_buffer,
+            indirect_buffer,
+            draw_buffer,
+            draws,
+            material_ranges,
+            alpha_ranges,
+            instance_count,
+        }
+    }
+
+    /// The draws whose material has `alpha_mode`, which are contiguous.
+    pub fn alpha_draws(&self, alpha_mode: AlphaMode) -> Range<u32> {
+        self.alpha_ranges
+            .iter()
+            .find(|(mode, _)| *mode == alpha_mode)
+            .map_or(0..0, |(_, draws)| draws.clone())
+    }
+
+    pub fn set_instance_count(&mut self, queue: &wgpu::Queue, instance_count: u32) {
+        if instance_count != self.instance_count {
+            self.instance_count = instance_count;
+            queue.write_buffer(
+                &self.indirect_buffer,
+                0,
+                &indirect_args(&self.draws, instance_count),
+            );
+        }
+    }
+
+    pub fn draw_count(&self) -> usize {
+        self.draws.len()
+    }
+
+    /// What [`DrawGpuScene::draw_gpu_scene`] draws for `alpha_mode` in `mode`.
+    pub fn draw_stats(&self, mode: DrawMode, alpha_mode: AlphaMode, bindless: bool) -> DrawStats {
+        let draws = self.alpha_draws(alpha_mode);
+        let calls = match mode {
+            _ if draws.is_empty() => 0,
+            DrawMode::Indirect if bindless => 1,
+            DrawMode::Indirect => self
+                .material_ranges
+                .iter()
+                .filter(|(_, material_draws)| draws.contains(&material_draws.start))
+                .count() as u32,
+            DrawMode::DirectLoop => draws.len() as u32,
+        };
+        let instances = self.instance_count as u64;
+        let triangles: u64 = self.draws[draws.start as usize..draws.end as usize]
+            .iter()
+            .map(|draw| draw.index_count as u64 / 3)
+            .sum();
+        DrawStats {
+            draw_calls: calls,
+            instances: draws.len() as u64 * instances,
+            triangles: triangles * instances,
+        }
+    }
+}
+
+fn indirect_args(draws: &[DrawRecord], instance_count: u32) -> Vec<u8> {
+    draws
+        .iter()
+        .flat_map(|draw| {
+            DrawIndexedIndirectArgs {
+                index_count: draw.index_count,
+                instance_count,
+                first_index: draw.first_index,
+                base_vertex: draw.base_vertex,
+                first_instance: 0,
+            }
+            .as_bytes()
+            .to_vec()
+        })
+        .collect()
+}
+
+pub trait DrawGpuScene<'a, 'b>
+where
+    'b: 'a,
+{
+    /// Draws the meshes of `model` whose material has `alpha_mode` from `scene`. The
+    /// instance buffer, if the pipeline takes one, must already be bound to slot 1, and
+    /// each vertex's [`VertexIds`] are bound to slot [`VERTEX_IDS_SLOT`].
+    ///
+    /// With a `bindless` material bind group all the draws go out together and the
+    /// pipeline must read materials from the ids; without one they are split by
+    /// material, each binding its own group 0.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_gpu_scene(
+        &mut self,
+        scene: &'b GpuScene,
+        model: &'b Model,
+        mode: DrawMode,
+        alpha_mode: AlphaMode,
+        uniforms: &'b wgpu::BindGroup,
+        light: &'b wgpu::BindGroup,
+        bindless: Option<&'b wgpu::BindGroup>,
+    );
+}
+
+impl<'a, 'b> DrawGpuScene<'a, 'b> for wgpu::RenderPass<'a>
+where
+    'b: 'a,
+{
+    fn draw_gpu_scene(
+        &mut self,
+        scene: &'b GpuScene,
+        model: &'b Model,
+        mode: DrawMode,
+        alpha_mode: AlphaMode,
+        uniforms: &'b wgpu::BindGroup,
+        light: &'b wgpu::BindGroup,
+        bindless: Option<&'b wgpu::BindGroup>,
+    ) {
+        let draws = scene.alpha_draws(alpha_mode);
+        if draws.is_empty() {
+            return;
+        }
+        self.set_vertex_buffer(0, scene.vertex_buffer.slice(..));
+        self.set_vertex_buffer(VERTEX_IDS_SLOT, scene.ids_buffer.slice(..));
+        self.set_index_buffer(scene.index_buffer.slice(..), scene.index_format);
+        self.set_bind_group(1, uniforms, &[]);
+        self.set_bind_group(2, light, &[]);
+        match bindless {
+            Some(materials) => {
+                self.set_bind_group(3, materials, &[]);
+                draw_range(self, scene, mode, draws);
+            }
+            None => {
+                for (material, material_draws) in &scene.material_ranges {
+                    if draws.contains(&material_draws.start) {
+                        self.set_bind_group(0, &model.materials[*material].bind_group, &[]);
+                        draw_range(self, scene, mode, material_draws.clone());
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn draw_range(
+    pass: &mut wgpu::RenderPass<'_>,
+    scene: &GpuScene,
+    mode: DrawMode,
+    draws: Range<u32>,
+) {
+    const ARGS_SIZE: u64 = std::mem::size_of::<DrawIndexedIndirectArgs>() as u64;
+
+    match mode {
+        DrawMode::Indirect => pass.multi_draw_indexed_indirect(
+            &scene.indirect_buffer,
+            draws.start as u64 * ARGS_SIZE,
+            draws.end - draws.start,
+        ),
+        DrawMode::DirectLoop => {
+            for draw in &scene.draws[draws.start as usize..draws.end as usize] {
+                pass.draw_indexed(
+                    draw.first_index..draw.first_index + draw.index_count,
+                    draw.base_vertex,
+                    0..scene.instance_count,
+                );
+            }
+        }
+    }
+}
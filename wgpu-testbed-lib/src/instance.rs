@@ -52,18 +52,29 @@ impl InstanceRaw {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
 pub struct Instance {
     pub position: cgmath::Vector3<f32>,
     pub rotation: cgmath::Quaternion<f32>,
+    /// Along the instance's own axes, before it is rotated.
+    pub scale: cgmath::Vector3<f32>,
 }
 
 impl Instance {
     pub fn to_raw(&self) -> InstanceRaw {
+        let scale = self.scale;
+        // Normals take the inverse transpose, which undoes the scale but not the rotation.
+        let inverse_scale = cgmath::Matrix3::from_cols(
+            cgmath::Vector3::unit_x() / scale.x,
+            cgmath::Vector3::unit_y() / scale.y,
+            cgmath::Vector3::unit_z() / scale.z,
+        );
         InstanceRaw {
             model: (cgmath::Matrix4::from_translation(self.position)
-                * cgmath::Matrix4::from(self.rotation))
+                * cgmath::Matrix4::from(self.rotation)
+                * cgmath::Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z))
             .into(),
-            normal: cgmath::Matrix3::from(self.rotation).into(),
+            normal: (cgmath::Matrix3::from(self.rotation) * inverse_scale).into(),
         }
     }
 }
@@ -25,14 +25,58 @@ use wasm_bindgen::prelude::*;
 use crate::state::MappedTextureView;
 use crate::state::State;
 
+pub use crate::asset_cache::TextureData;
+pub use crate::asset_source::{AssetSource, DirectorySource, MemorySource};
+pub use crate::baked::{
+    BAKED_ARCHIVE, BAKED_MODEL_EXTENSION, BAKED_TEXTURE_EXTENSION, BakedModel, BakedTexture,
+    USE_BAKED_VAR, baked_path, use_baked_archive,
+};
+pub use crate::camera::Camera;
+pub use crate::debug_view::DebugView;
+pub use crate::error::{Error, Result};
+pub use crate::file_reader::{ASSET_ROOT_VAR, FileReader};
+pub use crate::gpu_scene::{DrawMode, DrawRecord, GpuScene};
+pub use crate::instance::Instance;
+pub use crate::light::Light;
+pub use crate::mesh_processing::{Bounds, NormalMode, UvProjection};
+pub use crate::model::{
+    AlphaMode, MaterialParams, MeshOptions, TangentMethod, TextureSource, VertexLayout,
+};
+pub use crate::picking::{Pick, PickCallback};
+pub use crate::scene_file::{SCENE_FILE, SceneFile};
+pub use crate::transparency::TransparencyMode;
+
+mod asset_cache;
+mod asset_loader;
+mod asset_source;
+mod baked;
+mod bindless;
 mod camera;
+mod debug_ui;
+mod debug_view;
+mod error;
 mod file_reader;
+mod gizmo;
+mod gltf_import;
+mod gpu_scene;
 mod instance;
 mod light;
+mod mesh_import;
+mod mesh_processing;
 mod model;
+mod picking;
 mod pipeline;
+mod pipeline_cache;
+mod profiler;
+mod reflection;
+mod render_targets;
+mod scene_file;
+mod shader;
 mod state;
+mod stats;
+mod text;
 mod texture;
+mod transparency;
 mod uniform;
 mod vertex;
 
@@ -43,6 +87,7 @@ struct App {
     texture_copy_sender: Option<mpsc::Sender<MappedTextureView>>,
     #[cfg(not(target_arch = "wasm32"))]
     screenshot_writer: Option<std::thread::JoinHandle<()>>,
+    error: Option<Error>,
 }
 
 impl App {
@@ -54,8 +99,15 @@ impl App {
             texture_copy_sender: None,
             #[cfg(not(target_arch = "wasm32"))]
             screenshot_writer: None,
+            error: None,
         }
     }
+
+    fn fail(&mut self, event_loop: &ActiveEventLoop, error: Error) {
+        log::error!("{}", error);
+        self.error = Some(error);
+        event_loop.exit();
+    }
 }
 
 impl ApplicationHandler for App {
@@ -69,11 +121,10 @@ impl ApplicationHandler for App {
             .with_title("WGPU Rendering")
             .with_inner_size(window_size);
 
-        let window = Arc::new(
-            event_loop
-                .create_window(window_attrs)
-                .expect("Failed to create window!"),
-        );
+        let window = match event_loop.create_window(window_attrs) {
+            Ok(window) => Arc::new(window),
+            Err(e) => return self.fail(event_loop, e.into()),
+        };
 
         #[cfg(not(target_arch = "wasm32"))]
         {
@@ -154,11 +205,13 @@ impl ApplicationHandler for App {
 
         #[cfg(not(target_arch = "wasm32"))]
         {
-            let state = futures::executor::block_on(State::new(
+            match futures::executor::block_on(State::new(
                 window,
                 self.texture_copy_sender.as_ref().unwrap().clone(),
-            ));
-            self.state = Some(state);
+            )) {
+                Ok(state) => self.state = Some(state),
+                Err(e) => self.fail(event_loop, e),
+            }
         }
 
         #[cfg(target_arch = "wasm32")]
@@ -170,8 +223,12 @@ impl ApplicationHandler for App {
             // which outlives the spawn. We use a shared Rc so the closure can store it.
             let state_cell_clone = state_cell.clone();
             wasm_bindgen_futures::spawn_local(async move {
-                let state = State::new(window).await;
-                *state_cell_clone.borrow_mut() = Some(state);
+                match State::new(window).await {
+                    Ok(state) => *state_cell_clone.borrow_mut() = Some(state),
+                    Err(e) => web_sys::console::error_1(
+                        &format!("Failed to initialise renderer: {}", e).into(),
+                    ),
+                }
             });
             // Replace self.state with the cell's contents once filled; for wasm
             // we use a side-channel via the Rc. Store the Rc in a thread-local
@@ -238,13 +295,20 @@ impl ApplicationHandler for App {
                 }
             }
             WindowEvent::RedrawRequested => {
-                state.update();
-                state.render();
+                if let Err(e) = state.update().and_then(|_| state.render()) {
+                    self.fail(event_loop, e);
+                }
             }
             _ => {}
         }
     }
 
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(state) = self.state.as_ref() {
+            state.save_pipeline_cache();
+        }
+    }
+
     fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
         if let Some(window) = self.window.as_ref() {
             window.request_redraw();
@@ -264,15 +328,20 @@ pub fn run_wasm() {
     use std::panic;
 
     panic::set_hook(Box::new(console_error_panic_hook::hook));
-    run();
+    if let Err(e) = run() {
+        web_sys::console::error_1(&format!("{}", e).into());
+    }
 }
 
-pub fn run() {
+pub fn run() -> Result<()> {
     env_logger::init();
 
-    let evt_loop = EventLoop::new().expect("Failed to create event loop!");
+    let evt_loop = EventLoop::new()?;
     let mut app = App::new();
-    evt_loop
-        .run_app(&mut app)
-        .expect("Failed to run event loop!");
+    evt_loop.run_app(&mut app)?;
+
+    match app.error.take() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
 }
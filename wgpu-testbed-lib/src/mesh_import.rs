@@ -0,0 +1,554 @@
+//! Loaders for mesh formats other than OBJ. They produce the same `tobj` meshes as the
+//! OBJ loader, so tangent generation, caching and baking treat every format alike.
+
+use std::convert::{TryFrom, TryInto};
+
+use cgmath::{InnerSpace, Vector3, Zero};
+
+use crate::error::{Error, Result};
+use crate::mesh_processing::face_normal;
+
+/// Parses an ASCII or binary PLY. Polygons are triangulated as fans, and normals are
+/// left empty if the file has none.
+pub fn load_ply(path: &str, bytes: &[u8]) -> Result<tobj::Model> {
+    let parse_error = |message: String| Error::MeshParse {
+        path: path.to_owned(),
+        message,
+    };
+
+    let header_end = find_subslice(bytes, b"end_header")
+        .ok_or_else(|| parse_error("missing end_header".into()))?;
+    let header = std::str::from_utf8(&bytes[..header_end])
+        .map_err(|_| parse_error("header is not valid UTF-8".into()))?;
+    let body_start = bytes[header_end..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map(|newline| header_end + newline + 1)
+        .unwrap_or(bytes.len());
+
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        return Err(parse_error("missing ply magic".into()));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", name, _version] => {
+                format = Some(match *name {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::LittleEndian,
+                    "binary_big_endian" => PlyFormat::BigEndian,
+                    other => return Err(parse_error(format!("unknown format {}", other))),
+                })
+            }
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| parse_error(format!("invalid element count {}", count)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_type, item_type, name] => elements
+                .last_mut()
+                .ok_or_else(|| parse_error("property before element".into()))?
+                .properties
+                .push(PlyProperty {
+                    name: name.to_string(),
+                    kind: PlyType::parse(item_type).ok_or_else(|| {
+                        parse_error(format!("unknown property type {}", item_type))
+                    })?,
+                    list_count: Some(PlyType::parse(count_type).ok_or_else(|| {
+                        parse_error(format!("unknown property type {}", count_type))
+                    })?),
+                }),
+            ["property", kind, name] => elements
+                .last_mut()
+                .ok_or_else(|| parse_error("property before element".into()))?
+                .properties
+                .push(PlyProperty {
+                    name: name.to_string(),
+                    kind: PlyType::parse(kind)
+                        .ok_or_else(|| parse_error(format!("unknown property type {}", kind)))?,
+                    list_count: None,
+                }),
+            _ => {}
+        }
+    }
+    let format = format.ok_or_else(|| parse_error("missing format".into()))?;
+
+    let mut reader = PlyReader {
+        format,
+        bytes: &bytes[body_start..],
+        offset: 0,
+    };
+    let mut mesh = tobj::Mesh::default();
+    let mut has_normals = false;
+    for element in &elements {
+        let find = |name: &str| element.properties.iter().position(|p| p.name == name);
+        let position = [find("x"), find("y"), find("z")];
+        let normal = [find("nx"), find("ny"), find("nz")];
+        let colour = [find("red"), find("green"), find("blue")];
+        let uv = [
+            find("u")
+                .or_else(|| find("s"))
+                .or_else(|| find("texture_u")),
+            find("v")
+                .or_else(|| find("t"))
+                .or_else(|| find("texture_v")),
+        ];
+        let face = find("vertex_indices").or_else(|| find("vertex_index"));
+        has_normals |= element.name == "vertex" && normal.iter().all(Option::is_some);
+
+        for _ in 0..element.count {
+            let values = element
+                .properties
+                .iter()
+                .map(|property| reader.read_property(property))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| parse_error(format!("truncated {} element", element.name)))?;
+            let scalar = |index: Option<usize>| index.map(|index| values[index][0]);
+
+            match element.name.as_str() {
+                "vertex" => {
+                    for index in position {
+                        mesh.positions.push(scalar(index).unwrap_or(0.0) as f32);
+                    }
+                    if has_normals {
+                        for index in normal {
+                            mesh.normals.push(scalar(index).unwrap_or(0.0) as f32);
+                        }
+                    }
+                    if colour.iter().all(Option::is_some) {
+                        for index in colour {
+                            let value = scalar(index).unwrap_or(0.0);
+                            // Integer colours are 0-255, float colours are already 0-1.
+                            let value = match element.properties[index.unwrap()].kind {
+                                PlyType::Float32 | PlyType::Float64 => value,
+                                _ => value / 255.0,
+                            };
+                            mesh.vertex_color.push(value as f32);
+                        }
+                    }
+                    if uv.iter().all(Option::is_some) {
+                        for index in uv {
+                            mesh.texcoords.push(scalar(index).unwrap_or(0.0) as f32);
+                        }
+                    }
+                }
+                "face" => {
+                    let face = face
+                        .map(|index| &values[index])
+                        .ok_or_else(|| parse_error("face has no vertex_indices".into()))?;
+                    for i in 1..face.len().saturating_sub(1) {
+                        for corner in [face[0], face[i], face[i + 1]] {
+                            // Negative indices would otherwise saturate to vertex 0.
+                            let corner = u32::try_from(corner as i64)
+                                .map_err(|_| parse_error("face index out of range".into()))?;
+                            mesh.indices.push(corner);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let vertex_count = (mesh.positions.len() / 3) as u32;
+    if mesh.indices.iter().any(|&index| index >= vertex_count) {
+        return Err(parse_error("face index out of range".into()));
+    }
+    Ok(tobj::Model::new(mesh, file_stem(path)))
+}
+
+/// Parses an ASCII or binary STL. Facets keep their own vertices so they stay flat,
+/// and facet normals missing from the file are generated from the winding.
+pub fn load_stl(path: &str, bytes: &[u8]) -> Result<tobj::Model> {
+    let parse_error = |message: &str| Error::MeshParse {
+        path: path.to_owned(),
+        message: message.to_owned(),
+    };
+
+    // Binary files may also start with "solid", so trust the size check first. Some
+    // exporters pad binary files, so a longer file still counts. In an ASCII file the
+    // count's high byte is text, which makes the expected size far larger than the file.
+    let binary_size = bytes.get(80..84).and_then(|count| {
+        (u32::from_le_bytes(count.try_into().unwrap()) as usize)
+            .checked_mul(50)?
+            .checked_add(84)
+    });
+    let facets = match binary_size {
+        Some(size) if bytes.len() >= size => bytes[84..size]
+            .chunks_exact(50)
+            .map(|facet| {
+                let mut floats = facet[..48]
+                    .chunks_exact(4)
+                    .map(|f| f32::from_le_bytes(f.try_into().unwrap()));
+                let mut vector = || {
+                    Vector3::new(
+                        floats.next().unwrap(),
+                        floats.next().unwrap(),
+                        floats.next().unwrap(),
+                    )
+                };
+                (vector(), [vector(), vector(), vector()])
+            })
+            .collect::<Vec<_>>(),
+        _ => {
+            let text =
+                std::str::from_utf8(bytes).map_err(|_| parse_error("not a valid STL file"))?;
+            let mut tokens = text.split_whitespace();
+            let vector = |tokens: &mut std::str::SplitWhitespace| -> Result<Vector3<f32>> {
+                let mut component = || {
+                    tokens
+                        .next()
+                        .and_then(|token| token.parse().ok())
+                        .ok_or_else(|| parse_error("invalid vector"))
+                };
+                Ok(Vector3::new(component()?, component()?, component()?))
+            };
+            let mut facets = Vec::new();
+            while let Some(token) = tokens.next() {
+                if token != "facet" {
+                    continue;
+                }
+                if tokens.next() != Some("normal") {
+                    return Err(parse_error("facet without normal"));
+                }
+                let normal = vector(&mut tokens)?;
+                let mut corners = [Vector3::zero(); 3];
+                for corner in &mut corners {
+                    if !tokens.any(|token| token == "vertex") {
+                        return Err(parse_error("facet with fewer than three vertices"));
+                    }
+                    *corner = vector(&mut tokens)?;
+                }
+                facets.push((normal, corners));
+            }
+            facets
+        }
+    };
+
+    let mut mesh = tobj::Mesh::default();
+    for (normal, corners) in facets {
+        let normal = if normal.magnitude2() > 0.0 {
+            normal.normalize()
+        } else {
+            face_normal(corners[0], corners[1], corners[2])
+        };
+        for corner in corners {
+            mesh.indices.push((mesh.positions.len() / 3) as u32);
+            mesh.positions
+                .extend_from_slice(&[corner.x, corner.y, corner.z]);
+            mesh.normals
+                .extend_from_slice(&[normal.x, normal.y, normal.z]);
+        }
+    }
+    Ok(tobj::Model::new(mesh, file_stem(path)))
+}
+
+fn file_stem(path: &str) -> String {
+    std::path::Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn find_subslice(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[derive(Clone, Copy)]
+enum PlyFormat {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum PlyType {
+    Int8,
+    Uint8,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Float32,
+    Float64,
+}
+
+impl PlyType {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Self::Int8,
+            "uchar" | "uint8" => Self::Uint8,
+            "short" | "int16" => Self::Int16,
+            "ushort" | "uint16" => Self::Uint16,
+            "int" | "int32" => Self::Int32,
+            "uint" | "uint32" => Self::Uint32,
+            "float" | "float32" => Self::Float32,
+            "double" | "float64" => Self::Float64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::Int8 | Self::Uint8 => 1,
+            Self::Int16 | Self::Uint16 => 2,
+            Self::Int32 | Self::Uint32 | Self::Float32 => 4,
+            Self::Float64 => 8,
+        }
+    }
+}
+
+struct PlyProperty {
+    name: String,
+    kind: PlyType,
+    list_count: Option<PlyType>,
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+struct PlyReader<'a> {
+    format: PlyFormat,
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl PlyReader<'_> {
+    /// Reads one property as a list of values, which has a single entry for scalars.
+    fn read_property(&mut self, property: &PlyProperty) -> Option<Vec<f64>> {
+        match property.list_count {
+            Some(count_type) => {
+                let count = self.read_value(count_type)? as usize;
+                (0..count).map(|_| self.read_value(property.kind)).collect()
+            }
+            None => Some(vec![self.read_value(property.kind)?]),
+        }
+    }
+
+    fn read_value(&mut self, kind: PlyType) -> Option<f64> {
+        if let PlyFormat::Ascii = self.format {
+            let rest = &self.bytes[self.offset..];
+            let start = rest.iter().position(|b| !b.is_ascii_whitespace())?;
+            let len = rest[start..]
+                .iter()
+                .position(u8::is_ascii_whitespace)
+                .unwrap_or(rest.len() - start);
+            self.offset += start + len;
+            return std::str::from_utf8(&rest[start..start + len])
+                .ok()?
+                .parse()
+                .ok();
+        }
+
+        let mut raw = self
+            .bytes
+            .get(self.offset..self.offset + kind.size())?
+            .to_vec();
+        self.offset += kind.size();
+        if let PlyFormat::BigEndian = self.format {
+            raw.reverse();
+        }
+        Some(match kind {
+            PlyType::Int8 => raw[0] as i8 as f64,
+            PlyType::Uint8 => raw[0] as f64,
+            PlyType::Int16 => i16::from_le_bytes(raw.try_into().ok()?) as f64,
+            PlyType::Uint16 => u16::from_le_bytes(raw.try_into().ok()?) as f64,
+            PlyType::Int32 => i32::from_le_bytes(raw.try_into().ok()?) as f64,
+            PlyType::Uint32 => u32::from_le_bytes(raw.try_into().ok()?) as f64,
+            PlyType::Float32 => f32::from_le_bytes(raw.try_into().ok()?) as f64,
+            PlyType::Float64 => f64::from_le_bytes(raw.try_into().ok()?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangles(model: &tobj::Model) -> Vec<[u32; 3]> {
+        model
+            .mesh
+            .indices
+            .chunks_exact(3)
+            .map(|t| [t[0], t[1], t[2]])
+            .collect()
+    }
+
+    #[test]
+    fn ascii_ply_reads_colours_normals_and_fans() {
+        let ply = b"ply
+format ascii 1.0
+comment a pentagon with a stray edge element
+element vertex 5
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+0 0 0 0 0 1 255 0 0
+1 0 0 0 0 1 0 255 0
+2 1 0 0 0 1 0 0 255
+1 2 0 0 0 1 255 255 255
+0 1 0 0 0 1 0 0 0
+5 0 1 2 3 4
+0 1
+";
+        let model = load_ply("models/pentagon.ply", ply).unwrap();
+        assert_eq!(model.name, "pentagon");
+        assert_eq!(model.mesh.positions.len(), 15);
+        assert_eq!(model.mesh.positions[6..9], [2.0, 1.0, 0.0]);
+        assert_eq!(model.mesh.normals.len(), 15);
+        assert_eq!(model.mesh.normals[..3], [0.0, 0.0, 1.0]);
+        assert_eq!(model.mesh.vertex_color[..6], [1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        assert!(model.mesh.texcoords.is_empty());
+        assert_eq!(triangles(&model), [[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+    }
+
+    /// A quad with float colours and UVs, in one of the binary formats.
+    fn binary_ply(
+        format: &str,
+        to_bytes: fn(f32) -> [u8; 4],
+        index: impl Fn(i32) -> [u8; 4],
+    ) -> Vec<u8> {
+        let mut ply = format!(
+            "ply\nformat {} 1.0\nelement vertex 4\nproperty float x\nproperty float y\n\
+             property float z\nproperty float red\nproperty float green\n\
+             property float blue\nproperty float s\nproperty float t\nelement face 1\n\
+             property list uchar int vertex_index\nend_header\n",
+            format
+        )
+        .into_bytes();
+        for (x, y) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+            for value in [x, y, 0.0, 0.5, 0.25, 1.0, x, y] {
+                ply.extend(to_bytes(value));
+            }
+        }
+        ply.push(4);
+        for corner in [0, 1, 2, 3] {
+            ply.extend(index(corner));
+        }
+        ply
+    }
+
+    #[test]
+    fn binary_ply_reads_both_endiannesses() {
+        let little = binary_ply("binary_little_endian", f32::to_le_bytes, i32::to_le_bytes);
+        let big = binary_ply("binary_big_endian", f32::to_be_bytes, i32::to_be_bytes);
+        for ply in [little, big] {
+            let model = load_ply("quad.ply", &ply).unwrap();
+            assert_eq!(model.mesh.positions[6..9], [1.0, 1.0, 0.0]);
+            assert_eq!(model.mesh.vertex_color[..3], [0.5, 0.25, 1.0]);
+            assert_eq!(model.mesh.texcoords[2..4], [1.0, 0.0]);
+            assert!(model.mesh.normals.is_empty());
+            assert_eq!(triangles(&model), [[0, 1, 2], [0, 2, 3]]);
+        }
+    }
+
+    #[test]
+    fn ply_rejects_bad_indices_and_truncation() {
+        for corner in [4, -1] {
+            let ply = binary_ply("binary_little_endian", f32::to_le_bytes, |i| {
+                (if i == 3 { corner } else { i }).to_le_bytes()
+            });
+            assert!(matches!(
+                load_ply("quad.ply", &ply),
+                Err(Error::MeshParse { .. })
+            ));
+        }
+
+        let ply = binary_ply("binary_little_endian", f32::to_le_bytes, i32::to_le_bytes);
+        assert!(load_ply("quad.ply", &ply[..ply.len() - 1]).is_err());
+        assert!(load_ply("quad.ply", b"ply\nformat ascii 1.0\n").is_err());
+        assert!(load_ply("quad.ply", b"plyx\nformat ascii 1.0\nend_header\n").is_err());
+    }
+
+    #[test]
+    fn ascii_stl_normalizes_or_generates_normals() {
+        let stl = b"solid two
+facet normal 0 0 2
+  outer loop
+    vertex 0 0 0
+    vertex 1 0 0
+    vertex 0 1 0
+  endloop
+endfacet
+facet normal 0 0 0
+  outer loop
+    vertex 0 0 0
+    vertex 0 1 0
+    vertex 0 0 1
+  endloop
+endfacet
+endsolid two
+";
+        let model = load_stl("models/two.stl", stl).unwrap();
+        assert_eq!(model.name, "two");
+        assert_eq!(model.mesh.positions.len(), 18);
+        assert_eq!(triangles(&model), [[0, 1, 2], [3, 4, 5]]);
+        assert_eq!(model.mesh.normals[..3], [0.0, 0.0, 1.0]);
+        assert_eq!(model.mesh.normals[9..12], [1.0, 0.0, 0.0]);
+
+        assert!(load_stl("bad.stl", b"solid bad\nfacet normal 0 0 1\nvertex 0 0 0\n").is_err());
+    }
+
+    fn binary_stl(header: &[u8], count: u32) -> Vec<u8> {
+        let mut stl = header.to_vec();
+        stl.resize(80, 0);
+        stl.extend(count.to_le_bytes());
+        for vector in [
+            [0.0f32; 3],
+            [0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [1.0, 0.0, 0.0],
+        ] {
+            for value in vector {
+                stl.extend(value.to_le_bytes());
+            }
+        }
+        stl.extend([0, 0]);
+        stl
+    }
+
+    #[test]
+    fn binary_stl_generates_normals_and_allows_trailing_bytes() {
+        // Starts with "solid" like an ASCII file, and has padding after its one facet.
+        let mut stl = binary_stl(b"solid exported", 1);
+        stl.extend([0; 7]);
+        let model = load_stl("padded.stl", &stl).unwrap();
+        assert_eq!(model.mesh.positions[3..9], [0.0, 0.0, 1.0, 1.0, 0.0, 0.0]);
+        assert_eq!(model.mesh.normals[..3], [0.0, 1.0, 0.0]);
+        assert_eq!(triangles(&model), [[0, 1, 2]]);
+    }
+
+    #[test]
+    fn binary_stl_shorter_than_its_count_is_not_binary() {
+        // Too short for their counts, so both are read as text, which they are not.
+        for count in [2, u32::MAX] {
+            assert!(matches!(
+                load_stl("short.stl", &binary_stl(b"solid", count)),
+                Err(Error::MeshParse { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,485 @@
+//! Clean-up passes run on parsed meshes before their vertices are built: generating the
+//! normals and UVs a file left out, welding duplicate vertices and measuring bounds.
+//!
+//! Every pass works on single-index `tobj` meshes, which is what all the loaders produce.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+use cgmath::{EuclideanSpace, InnerSpace, MetricSpace, Point3, Vector3, Zero};
+
+/// How generated normals are shared between faces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NormalMode {
+    /// Area weighted averages of the faces around each position, for curved surfaces.
+    #[default]
+    Smooth,
+    /// Each face keeps its own normal, splitting every vertex it shares.
+    Flat,
+}
+
+/// How generated texture coordinates are projected onto a mesh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UvProjection {
+    /// A single projection along the axis the mesh is thinnest in.
+    Planar,
+    /// Each face is projected along the axis its normal points down most, splitting
+    /// vertices where neighbouring faces pick different axes.
+    #[default]
+    Box,
+}
+
+/// An axis-aligned bounding box and the bounding sphere around its centre, in the space
+/// of the vertices they were measured from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl Default for Bounds {
+    fn default() -> Self {
+        Self {
+            min: Point3::origin(),
+            max: Point3::origin(),
+            center: Point3::origin(),
+            radius: 0.0,
+        }
+    }
+}
+
+impl Bounds {
+    /// Measures a set of points. The sphere is centred on the box but only as large as
+    /// the furthest point needs, which is usually tighter than the box's half diagonal.
+    pub fn from_points<I: IntoIterator<Item = [f32; 3]> + Clone>(points: I) -> Self {
+        let mut points_iter = points.clone().into_iter().map(Point3::from);
+        let Some(first) = points_iter.next() else {
+            return Self::default();
+        };
+        let (min, max) = points_iter.fold((first, first), |(min, max), point| {
+            (
+                Point3::new(min.x.min(point.x), min.y.min(point.y), min.z.min(point.z)),
+                Point3::new(max.x.max(point.x), max.y.max(point.y), max.z.max(point.z)),
+            )
+        });
+        let center = min.midpoint(max);
+        let radius = points
+            .into_iter()
+            .map(|point| center.distance2(Point3::from(point)))
+            .fold(0.0, f32::max)
+            .sqrt();
+        Self {
+            min,
+            max,
+            center,
+            radius,
+        }
+    }
+
+    /// Bounds enclosing both `self` and `other`.
+    pub fn union(&self, other: &Bounds) -> Self {
+        let min = Point3::new(
+            self.min.x.min(other.min.x),
+            self.min.y.min(other.min.y),
+            self.min.z.min(other.min.z),
+        );
+        let max = Point3::new(
+            self.max.x.max(other.max.x),
+            self.max.y.max(other.max.y),
+            self.max.z.max(other.max.z),
+        );
+        let center = min.midpoint(max);
+        let radius = [self, other]
+            .iter()
+            .map(|bounds| center.distance(bounds.center) + bounds.radius)
+            .fold(0.0, f32::max);
+        Self {
+            min,
+            max,
+            center,
+            radius,
+        }
+    }
+
+    pub fn extent(&self) -> Vector3<f32> {
+        self.max - self.min
+    }
+}
+
+/// Fills in whatever a file left out so every mesh can be shaded and textured, then
+/// welds the vertices that ended up identical. Normals that don't cover every vertex are
+/// regenerated with `normals`, and missing UVs are projected with `uvs`.
+pub fn prepare(mesh: &mut tobj::Mesh, normals: NormalMode, uvs: UvProjection) {
+    let vertex_count = mesh.positions.len() / 3;
+    if mesh.normals.len() != vertex_count * 3 {
+        generate_normals(mesh, normals);
+    }
+    if mesh.texcoords.len() != vertex_count * 2 {
+        generate_uvs(mesh, uvs);
+    }
+    weld(mesh);
+}
+
+/// Replaces the normals of a mesh with ones generated from its triangles.
+pub fn generate_normals(mesh: &mut tobj::Mesh, mode: NormalMode) {
+    match mode {
+        NormalMode::Smooth => mesh.normals = smooth_normals(&mesh.positions, &mesh.indices),
+        NormalMode::Flat => {
+            unweld(mesh);
+            mesh.normals = mesh
+                .positions
+                .chunks_exact(9)
+                .flat_map(|triangle| {
+                    let [a, b, c] = [0, 1, 2].map(|i| position(triangle, i));
+                    let normal = face_normal(a, b, c);
+                    [normal.x, normal.y, normal.z].repeat(3)
+                })
+                .collect();
+        }
+    }
+}
+
+/// Replaces the texture coordinates of a mesh with a projection of its positions,
+/// scaled so the largest side of its bounding box spans 0 to 1.
+pub fn generate_uvs(mesh: &mut tobj::Mesh, projection: UvProjection) {
+    let bounds = Bounds::from_points(mesh.positions.chunks_exact(3).map(to_array));
+    let extent = bounds.extent();
+    let scale = extent.x.max(extent.y).max(extent.z);
+    let scale = if scale > 0.0 { 1.0 / scale } else { 1.0 };
+    let project = |point: Vector3<f32>, axis: usize| {
+        let offset = (point - bounds.min.to_vec()) * scale;
+        // Keep a right-handed layout on each face, so text reads the right way round
+        // from the positive side.
+        match axis {
+            0 => [offset.z, offset.y],
+            1 => [offset.x, offset.z],
+            _ => [offset.x, offset.y],
+        }
+    };
+
+    match projection {
+        UvProjection::Planar => {
+            let thinnest =
+                dominant_axis(Vector3::new(1.0 / extent.x, 1.0 / extent.y, 1.0 / extent.z));
+            mesh.texcoords = (0..mesh.positions.len() / 3)
+                .flat_map(|i| project(position(&mesh.positions, i), thinnest))
+                .collect();
+        }
+        UvProjection::Box => {
+            unweld(mesh);
+            mesh.texcoords = mesh
+                .positions
+                .chunks_exact(9)
+                .flat_map(|triangle| {
+                    let corners = [0, 1, 2].map(|i| position(triangle, i));
+                    let axis = dominant_axis(face_normal(corners[0], corners[1], corners[2]));
+                    IntoIterator::into_iter(corners).flat_map(move |corner| project(corner, axis))
+                })
+                .collect();
+        }
+    }
+}
+
+/// Merges vertices whose attributes are bit-for-bit identical and rewrites the indices
+/// to match, dropping vertices no triangle uses.
+pub fn weld(mesh: &mut tobj::Mesh) {
+    let attributes = attributes(mesh);
+    let mut welded: Vec<Vec<f32>> = vec![Vec::new(); attributes.len()];
+    let mut remap: HashMap<Vec<u32>, u32> = HashMap::new();
+    let mut key = Vec::new();
+    for index in mesh.indices.iter_mut() {
+        let vertex = *index as usize;
+        key.clear();
+        for (values, width) in &attributes {
+            key.extend(
+                values[vertex * width..(vertex + 1) * width]
+                    .iter()
+                    .map(|value| value.to_bits()),
+            );
+        }
+        let next = remap.len() as u32;
+        *index = *remap.entry(key.clone()).or_insert_with(|| {
+            for ((values, width), welded) in attributes.iter().zip(&mut welded) {
+                welded.extend_from_slice(&values[vertex * width..(vertex + 1) * width]);
+            }
+            next
+        });
+    }
+    set_attributes(mesh, welded);
+}
+
+/// Gives every triangle corner its own vertex, so passes can assign per-face values.
+fn unweld(mesh: &mut tobj::Mesh) {
+    let unwelded = attributes(mesh)
+        .iter()
+        .map(|(values, width)| {
+            mesh.indices
+                .iter()
+                .flat_map(|&index| {
+                    let vertex = index as usize;
+                    values[vertex * width..(vertex + 1) * width].iter().copied()
+                })
+                .collect()
+        })
+        .collect();
+    set_attributes(mesh, unwelded);
+    mesh.indices = (0..mesh.indices.len() as u32).collect();
+}
+
+/// The per-vertex attributes of a mesh and their widths, skipping any that don't cover
+/// every vertex.
+fn attributes(mesh: &tobj::Mesh) -> Vec<(Vec<f32>, usize)> {
+    let vertex_count = mesh.positions.len() / 3;
+    [
+        (&mesh.positions, 3),
+        (&mesh.normals, 3),
+        (&mesh.texcoords, 2),
+        (&mesh.vertex_color, 3),
+    ]
+    .iter()
+    .map(|&(values, width)| {
+        if values.len() == vertex_count * width {
+            (values.clone(), width)
+        } else {
+            (Vec::new(), 0)
+        }
+    })
+    .collect()
+}
+
+fn set_attributes(mesh: &mut tobj::Mesh, attributes: Vec<Vec<f32>>) {
+    let [positions, normals, texcoords, vertex_color]: [Vec<f32>; 4] =
+        attributes.try_into().unwrap();
+    mesh.positions = positions;
+    mesh.normals = normals;
+    mesh.texcoords = texcoords;
+    mesh.vertex_color = vertex_color;
+}
+
+/// Area weighted vertex normals. Vertices at the same position share their normal, so
+/// UV seams don't show up as shading seams.
+pub(crate) fn smooth_normals(positions: &[f32], indices: &[u32]) -> Vec<f32> {
+    let mut position_ids: HashMap<[u32; 3], usize> = HashMap::new();
+    let vertex_position_ids: Vec<usize> = positions
+        .chunks_exact(3)
+        .map(|position| {
+            let next = position_ids.len();
+            *position_ids
+                .entry([0, 1, 2].map(|i| position[i].to_bits()))
+                .or_insert(next)
+        })
+        .collect();
+
+    let mut normals = vec![Vector3::zero(); position_ids.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|corner| position(positions, triangle[corner] as usize));
+        // Left unnormalized, so larger faces contribute more.
+        let weighted = (b - a).cross(c - a);
+        for &index in triangle {
+            normals[vertex_position_ids[index as usize]] += weighted;
+        }
+    }
+    vertex_position_ids
+        .into_iter()
+        .flat_map(|id| {
+            let normal = normals[id];
+            let normal = if normal.magnitude2() > 0.0 {
+                normal.normalize()
+            } else {
+                normal
+            };
+            [normal.x, normal.y, normal.z]
+        })
+        .collect()
+}
+
+pub(crate) fn face_normal(a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>) -> Vector3<f32> {
+    let normal = (b - a).cross(c - a);
+    if normal.magnitude2() > 0.0 {
+        normal.normalize()
+    } else {
+        normal
+    }
+}
+
+fn position(positions: &[f32], vertex: usize) -> Vector3<f32> {
+    let i = vertex * 3;
+    Vector3::new(positions[i], positions[i + 1], positions[i + 2])
+}
+
+fn to_array(values: &[f32]) -> [f32; 3] {
+    [values[0], values[1], values[2]]
+}
+
+/// The axis a vector has its largest component along.
+fn dominant_axis(v: Vector3<f32>) -> usize {
+    let v = Vector3::new(v.x.abs(), v.y.abs(), v.z.abs());
+    if v.x >= v.y && v.x >= v.z {
+        0
+    } else if v.y >= v.z {
+        1
+    } else {
+        2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two unit triangles folded along the Y axis: one facing +Z, one facing +X.
+    fn fold() -> tobj::Mesh {
+        tobj::Mesh {
+            positions: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+            indices: vec![0, 1, 2, 0, 2, 3],
+            ..Default::default()
+        }
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(
+            actual.len(),
+            expected.len(),
+            "{:?} against {:?}",
+            actual,
+            expected
+        );
+        for (a, b) in actual.iter().zip(expected) {
+            assert!((a - b).abs() < 1e-6, "{:?} against {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn weld_merges_identical_vertices_and_drops_unused_ones() {
+        let mut mesh = tobj::Mesh {
+            // Each triangle has its own corners, and the last vertex is never used.
+            positions: vec![
+                0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, //
+                0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, //
+                5.0, 5.0, 5.0,
+            ],
+            normals: [0.0, 0.0, 1.0].repeat(7),
+            indices: (0..6).collect(),
+            ..Default::default()
+        };
+        let mut split = mesh.clone();
+        weld(&mut mesh);
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3]);
+        assert_eq!(mesh.positions, fold().positions);
+        assert_eq!(mesh.normals.len(), 12);
+
+        // A vertex whose normal differs stays apart from the one it shares a position with.
+        split.normals[12..15].copy_from_slice(&[1.0, 0.0, 0.0]);
+        weld(&mut split);
+        assert_eq!(split.indices, [0, 1, 2, 0, 3, 4]);
+        assert_eq!(split.positions.len(), 15);
+    }
+
+    #[test]
+    fn smooth_normals_are_shared_across_uv_seams() {
+        let mut mesh = fold();
+        // A second copy of the vertex on the fold, as a UV seam would leave.
+        mesh.positions.extend_from_slice(&[0.0, 1.0, 0.0]);
+        mesh.indices[4] = 4;
+        generate_normals(&mut mesh, NormalMode::Smooth);
+
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 4, 3]);
+        assert_close(
+            &mesh.normals,
+            &[
+                diagonal, 0.0, diagonal, //
+                0.0, 0.0, 1.0, //
+                diagonal, 0.0, diagonal, //
+                1.0, 0.0, 0.0, //
+                diagonal, 0.0, diagonal,
+            ],
+        );
+    }
+
+    #[test]
+    fn flat_normals_split_every_vertex() {
+        let mut mesh = fold();
+        generate_normals(&mut mesh, NormalMode::Flat);
+        assert_eq!(mesh.indices, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(mesh.positions.len(), 18);
+        assert_close(
+            &mesh.normals,
+            &[[0.0, 0.0, 1.0].repeat(3), [1.0, 0.0, 0.0].repeat(3)].concat(),
+        );
+    }
+
+    #[test]
+    fn planar_uvs_project_along_the_thinnest_axis() {
+        let mut mesh = tobj::Mesh {
+            positions: vec![0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 2.0, 1.0, 0.0, 0.0, 1.0, 0.0],
+            indices: vec![0, 1, 2, 0, 2, 3],
+            ..Default::default()
+        };
+        generate_uvs(&mut mesh, UvProjection::Planar);
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3]);
+        assert_close(&mesh.texcoords, &[0.0, 0.0, 1.0, 0.0, 1.0, 0.5, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn box_uvs_project_each_face_along_its_normal() {
+        let mut mesh = fold();
+        generate_uvs(&mut mesh, UvProjection::Box);
+        assert_eq!(mesh.indices, [0, 1, 2, 3, 4, 5]);
+        assert_close(
+            &mesh.texcoords,
+            &[
+                // The +Z face takes X and Y, the +X face Z and Y.
+                0.0, 0.0, 1.0, 0.0, 0.0, 1.0, //
+                0.0, 0.0, 0.0, 1.0, 1.0, 0.0,
+            ],
+        );
+    }
+
+    #[test]
+    fn prepare_only_fills_in_what_is_missing() {
+        let mut mesh = fold();
+        mesh.normals = [0.0, 1.0, 0.0].repeat(4);
+        prepare(&mut mesh, NormalMode::Flat, UvProjection::Planar);
+        assert_eq!(mesh.normals, [0.0, 1.0, 0.0].repeat(4));
+        assert_eq!(mesh.texcoords.len(), 8);
+
+        let mut mesh = fold();
+        prepare(&mut mesh, NormalMode::Flat, UvProjection::Planar);
+        assert_eq!(mesh.positions.len(), 18);
+    }
+
+    #[test]
+    fn bounds_sphere_is_tighter_than_the_box() {
+        let bounds = Bounds::from_points([
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [2.0, 1.0, 0.0],
+            [1.0, 2.0, 0.0],
+        ]);
+        assert_eq!(bounds.min, Point3::new(0.0, 0.0, 0.0));
+        assert_eq!(bounds.max, Point3::new(2.0, 2.0, 0.0));
+        assert_eq!(bounds.center, Point3::new(1.0, 1.0, 0.0));
+        assert_eq!(bounds.radius, 1.0);
+        assert_eq!(bounds.extent(), Vector3::new(2.0, 2.0, 0.0));
+        assert_eq!(
+            Bounds::from_points(Vec::<[f32; 3]>::new()),
+            Bounds::default()
+        );
+    }
+
+    #[test]
+    fn bounds_union_encloses_both() {
+        let a = Bounds::from_points([[0.0, 0.0, 0.0], [2.0, 0.0, 0.0]]);
+        let b = Bounds::from_points([[4.0, -1.0, 0.0], [6.0, 1.0, 0.0]]);
+        let union = a.union(&b);
+        assert_eq!(union.min, Point3::new(0.0, -1.0, 0.0));
+        assert_eq!(union.max, Point3::new(6.0, 1.0, 0.0));
+        assert_eq!(union.center, Point3::new(3.0, 0.0, 0.0));
+        assert_eq!(union.radius, 2.0 + 2.0_f32.sqrt());
+        assert_eq!(union, b.union(&a));
+        assert_eq!(a.union(&a), a);
+    }
+}
@@ -1,10 +1,20 @@
-use anyhow::*;
 use std::collections::HashMap;
 use std::iter::FromIterator;
+use std::sync::Arc;
 use std::{ops::Range, path::Path};
 
+use crate::asset_cache::{AssetCache, CacheKey, MeshData, pod_vec};
+use crate::asset_loader::PLACEHOLDER_NORMAL;
+use crate::baked::{self, BakedModel};
+use crate::error::{Error, Result};
 use crate::file_reader::FileReader;
-use crate::pipeline;
+use crate::gltf_import;
+use crate::mesh_import;
+use crate::mesh_processing::{self, Bounds, NormalMode, UvProjection};
+use crate::pipeline::{self, Bindable, Binder};
+use crate::profiler::{EncoderProfile, GpuProfiler};
+use crate::shader::{ShaderCache, ShaderDefines};
+use crate::stats::DrawStats;
 use crate::texture::Texture;
 use crate::vertex::Vertex;
 
@@ -38,6 +48,39 @@ impl Vertex for QuadVertex {
     }
 }
 
+/// The vertex buffer slot each vertex's [`VertexIds`] are bound to.
+pub const VERTEX_IDS_SLOT: u32 = 2;
+
+/// Which material and mesh a vertex belongs to. Bindless materials are looked up by the
+/// material, and the G-buffer pass writes the mesh into the picking ID target.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct VertexIds {
+    pub material: u32,
+    pub mesh: u32,
+}
+
+impl Vertex for VertexIds {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<VertexIds>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Uint32,
+                    offset: 0,
+                    shader_location: 13,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Uint32,
+                    offset: std::mem::size_of::<u32>() as wgpu::BufferAddress,
+                    shader_location: 14,
+                },
+            ],
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct ModelVertex {
@@ -46,7 +89,8 @@ pub struct ModelVertex {
     normal: [f32; 3],
     tangent: [f32; 3],
     bitangent: [f32; 3],
-    padding: [u32; 2],
+    colour: [u8; 4],
+    padding: u32,
 }
 
 impl Vertex for ModelVertex {
@@ -80,25 +124,440 @@ impl Vertex for ModelVertex {
                     offset: std::mem::size_of::<[f32; 11]>() as wgpu::BufferAddress,
                     shader_location: 4,
                 },
+                // Locations 5 to 11 are taken by the instance data.
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Unorm8x4,
+                    offset: std::mem::size_of::<[f32; 14]>() as wgpu::BufferAddress,
+                    shader_location: 12,
+                },
+            ],
+        }
+    }
+}
+
+/// A [`ModelVertex`] packed into half the size: octahedral normals and tangents, half
+/// float UVs and only the sign of the bitangent, which `shader.wgsl` rebuilds from the
+/// normal and tangent.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct QuantizedVertex {
+    position: [f32; 3],
+    tex_coords: [u16; 2],
+    normal: [i16; 2],
+    /// The octahedral tangent, then the bitangent's sign.
+    tangent: [i16; 4],
+    colour: [u8; 4],
+}
+
+impl QuantizedVertex {
+    pub fn from_vertex(vertex: &ModelVertex) -> Self {
+        use cgmath::{InnerSpace, Vector3};
+
+        let normal = Vector3::from(vertex.normal);
+        let tangent = Vector3::from(vertex.tangent);
+        let handedness = if normal.cross(tangent).dot(vertex.bitangent.into()) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        let [tangent_x, tangent_y] = octahedral_encode(tangent);
+        Self {
+            position: vertex.position,
+            tex_coords: vertex
+                .tex_coords
+                .map(|coord| half::f16::from_f32(coord).to_bits()),
+            normal: octahedral_encode(normal),
+            tangent: [tangent_x, tangent_y, snorm16(handedness), 0],
+            colour: vertex.colour,
+        }
+    }
+}
+
+impl Vertex for QuantizedVertex {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<QuantizedVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x3,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float16x2,
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Snorm16x2,
+                    offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Snorm16x4,
+                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                },
+                // Locations 5 to 11 are taken by the instance data.
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Unorm8x4,
+                    offset: std::mem::size_of::<[f32; 7]>() as wgpu::BufferAddress,
+                    shader_location: 12,
+                },
             ],
         }
     }
 }
 
+/// Maps a direction onto the unit octahedron and unfolds it into a square, the inverse
+/// of `octahedral_decode` in `common/octahedral.wgsl`.
+fn octahedral_encode(v: cgmath::Vector3<f32>) -> [i16; 2] {
+    let length = v.x.abs() + v.y.abs() + v.z.abs();
+    if length == 0.0 {
+        return [0, 0];
+    }
+    let v = v / length;
+    let sign = |value: f32| if value >= 0.0 { 1.0 } else { -1.0 };
+    let (x, y) = if v.z >= 0.0 {
+        (v.x, v.y)
+    } else {
+        ((1.0 - v.y.abs()) * sign(v.x), (1.0 - v.x.abs()) * sign(v.y))
+    };
+    [snorm16(x), snorm16(y)]
+}
+
+fn snorm16(value: f32) -> i16 {
+    (value.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
+}
+
+/// How mesh vertices are stored on the GPU. Both layouts are built from the same
+/// [`MeshData`], so caches and baked models don't depend on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VertexLayout {
+    /// [`ModelVertex`], 64 bytes per vertex.
+    #[default]
+    Full,
+    /// [`QuantizedVertex`], 32 bytes per vertex. Tangents are always generated on the CPU,
+    /// since the compute shader writes full vertices.
+    Quantized,
+}
+
+impl VertexLayout {
+    pub fn desc<'a>(self) -> wgpu::VertexBufferLayout<'a> {
+        match self {
+            Self::Full => ModelVertex::desc(),
+            Self::Quantized => QuantizedVertex::desc(),
+        }
+    }
+
+    /// The entry point of `shader.wgsl` that reads this layout.
+    /// The vertex shader for this layout, optionally the variant that also reads a
+    /// material id for bindless materials.
+    pub fn vertex_entry_point(self, bindless: bool) -> &'static str {
+        match (self, bindless) {
+            (Self::Full, false) => "vertex_main",
+            (Self::Quantized, false) => "vertex_quantized",
+            (Self::Full, true) => "vertex_bindless",
+            (Self::Quantized, true) => "vertex_quantized_bindless",
+        }
+    }
+}
+
+/// How a material's alpha, from its diffuse texture and vertex colours, is used.
+/// Variants are in draw order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum AlphaMode {
+    /// Alpha is ignored.
+    #[default]
+    Opaque,
+    /// Drawn with the opaque geometry, discarding fragments with alpha below the cutoff.
+    Mask,
+    /// Drawn after the opaque geometry has been lit, blended over it.
+    Blend,
+}
+
+/// A material's alpha settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaterialParams {
+    pub alpha_mode: AlphaMode,
+    /// The alpha below which [`AlphaMode::Mask`] discards.
+    pub alpha_cutoff: f32,
+    /// Multiplies alpha for [`AlphaMode::Blend`].
+    pub opacity: f32,
+}
+
+impl Default for MaterialParams {
+    fn default() -> Self {
+        Self {
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            opacity: 1.0,
+        }
+    }
+}
+
+impl MaterialParams {
+    /// Reads the alpha settings of an MTL material. A dissolve (`d`) below one blends the
+    /// whole material, and otherwise an alpha map (`map_d`) masks it. The alpha itself is
+    /// always read from the diffuse texture, which is where exporters usually put it.
+    pub fn from_mtl(material: &tobj::Material) -> Self {
+        let opacity = material.dissolve.unwrap_or(1.0).clamp(0.0, 1.0);
+        let alpha_mode = if opacity < 1.0 {
+            AlphaMode::Blend
+        } else if material.dissolve_texture.is_some() {
+            AlphaMode::Mask
+        } else {
+            AlphaMode::Opaque
+        };
+        Self {
+            alpha_mode,
+            opacity,
+            ..Self::default()
+        }
+    }
+
+    /// Reads the alpha settings of a glTF material. Its base colour factor's alpha
+    /// scales blended materials, as it does the texture's alpha.
+    pub fn from_gltf(material: &gltf::Material) -> Self {
+        Self {
+            alpha_mode: match material.alpha_mode() {
+                gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+                gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+                gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+            },
+            alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+            opacity: material.pbr_metallic_roughness().base_color_factor()[3].clamp(0.0, 1.0),
+        }
+    }
+
+    fn uniform(&self) -> MaterialUniform {
+        MaterialUniform {
+            opacity: self.opacity,
+            alpha_cutoff: self.alpha_cutoff,
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+/// `MaterialParams` in `shader.wgsl`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct MaterialUniform {
+    opacity: f32,
+    alpha_cutoff: f32,
+    _padding: [f32; 2],
+}
+
 pub struct Material {
-    #[allow(dead_code)]
     pub name: String,
     pub textures: HashMap<String, Texture>,
+    pub params: MaterialParams,
+    params_buffer: wgpu::Buffer,
     pub bind_group: wgpu::BindGroup,
 }
 
+impl Mesh {
+    /// Uploads `data` as the model's `index`th mesh.
+    pub fn from_data(
+        device: &wgpu::Device,
+        label: &str,
+        index: usize,
+        data: &MeshData,
+        vertex_layout: VertexLayout,
+        index_format: wgpu::IndexFormat,
+    ) -> Self {
+        let quantized: Vec<QuantizedVertex>;
+        let contents: &[u8] = match vertex_layout {
+            VertexLayout::Full => bytemuck::cast_slice(&data.vertices),
+            VertexLayout::Quantized => {
+                quantized = data
+                    .vertices
+                    .iter()
+                    .map(QuantizedVertex::from_vertex)
+                    .collect();
+                bytemuck::cast_slice(&quantized)
+            }
+        };
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Vertex Buffer", label)),
+            contents,
+            usage: wgpu::BufferUsages::VERTEX
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC,
+        });
+
+        let index_buffer = create_index_buffer(device, label, &data.indices, index_format);
+        let ids_buffer = create_ids_buffer(
+            device,
+            label,
+            data.vertices.len(),
+            VertexIds {
+                material: data.material as u32,
+                mesh: index as u32,
+            },
+        );
+
+        Self {
+            name: data.name.clone(),
+            vertex_buffer,
+            index_buffer,
+            ids_buffer,
+            index_format,
+            num_elements: data.indices.len() as u32,
+            material: data.material,
+            bounds: vertex_bounds(&data.vertices),
+        }
+    }
+
+    /// Uploads every mesh of a model. They share one index format, picked for the
+    /// largest, so a [`GpuScene`](crate::gpu_scene::GpuScene) can merge their buffers.
+    pub fn from_data_all(
+        device: &wgpu::Device,
+        label: &str,
+        data: &[MeshData],
+        vertex_layout: VertexLayout,
+    ) -> Vec<Self> {
+        let index_format = index_format(
+            data.iter()
+                .map(|mesh| mesh.vertices.len())
+                .max()
+                .unwrap_or(0),
+        );
+        data.iter()
+            .enumerate()
+            .map(|(index, mesh)| {
+                Self::from_data(device, label, index, mesh, vertex_layout, index_format)
+            })
+            .collect()
+    }
+}
+
+/// A material's textures and samplers, then its [`MaterialParams`].
+#[derive(Bindable)]
+#[bindable(fragment)]
+pub struct MaterialBinding {
+    #[texture]
+    diffuse: wgpu::TextureView,
+    #[sampler]
+    diffuse_sampler: wgpu::Sampler,
+    #[texture]
+    normal: wgpu::TextureView,
+    #[sampler]
+    normal_sampler: wgpu::Sampler,
+    #[uniform]
+    params: wgpu::Buffer,
+}
+
+impl Material {
+    pub fn new(
+        device: &wgpu::Device,
+        binder: &Binder<MaterialBinding>,
+        name: &str,
+        diffuse_texture: Texture,
+        normal_texture: Texture,
+        params: MaterialParams,
+    ) -> Self {
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Params Buffer", name)),
+            contents: bytemuck::bytes_of(&params.uniform()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = Self::bind(
+            device,
+            binder,
+            &diffuse_texture,
+            &normal_texture,
+            &params_buffer,
+        );
+        Self {
+            name: name.to_owned(),
+            textures: HashMap::from_iter([
+                ("diffuse".to_owned(), diffuse_texture),
+                ("normal".to_owned(), normal_texture),
+            ]),
+            params,
+            params_buffer,
+            bind_group,
+        }
+    }
+
+    /// A plain white material, for models that come without any.
+    pub fn untextured(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        binder: &Binder<MaterialBinding>,
+    ) -> Self {
+        Self::new(
+            device,
+            binder,
+            "Untextured",
+            Texture::placeholder(device, queue, [255, 255, 255, 255], false),
+            Texture::placeholder(device, queue, PLACEHOLDER_NORMAL, true),
+            MaterialParams::default(),
+        )
+    }
+
+    /// Replaces one of the material's textures, rebuilding its bind group.
+    pub fn set_texture(
+        &mut self,
+        device: &wgpu::Device,
+        binder: &Binder<MaterialBinding>,
+        kind: &str,
+        texture: Texture,
+    ) {
+        self.textures.insert(kind.to_owned(), texture);
+        self.bind_group = Self::bind(
+            device,
+            binder,
+            &self.textures["diffuse"],
+            &self.textures["normal"],
+            &self.params_buffer,
+        );
+    }
+
+    /// Updates the material's alpha settings. A change of [`AlphaMode`] moves the
+    /// material to another pass, so any [`GpuScene`](crate::gpu_scene::GpuScene) built
+    /// from its model has to be rebuilt.
+    pub fn set_params(&mut self, queue: &wgpu::Queue, params: MaterialParams) {
+        self.params = params;
+        queue.write_buffer(
+            &self.params_buffer,
+            0,
+            bytemuck::bytes_of(&params.uniform()),
+        );
+    }
+
+    fn bind(
+        device: &wgpu::Device,
+        binder: &Binder<MaterialBinding>,
+        diffuse: &Texture,
+        normal: &Texture,
+        params: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        let binding = MaterialBinding {
+            diffuse: diffuse.view.clone(),
+            diffuse_sampler: diffuse.sampler.clone(),
+            normal: normal.view.clone(),
+            normal_sampler: normal.sampler.clone(),
+            params: params.clone(),
+        };
+        binder.create_bind_group(&binding, device, None)
+    }
+}
+
 pub struct Mesh {
     #[allow(dead_code)]
     pub name: String,
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
+    pub index_format: wgpu::IndexFormat,
+    /// The [`VertexIds`] of every vertex, bound to [`VERTEX_IDS_SLOT`].
+    pub ids_buffer: wgpu::Buffer,
     pub num_elements: u32,
     pub material: usize,
+    /// Bounds of the mesh in model space, for culling and framing the camera.
+    pub bounds: Bounds,
 }
 
 #[repr(C)]
@@ -106,328 +565,630 @@ pub struct Mesh {
 struct ComputeInfo {
     num_vertices: u32,
     num_indices: u32,
+    row_width: u32,
+    padding: u32,
 }
 
+/// Must match the `@workgroup_size` of both entry points in `compute_bitangents.wgsl`.
+const TANGENT_WORKGROUP_SIZE: u32 = 64;
+
+#[derive(Bindable)]
+#[bindable(compute)]
 struct BitangentComputeBinding {
-    src_vertex_buffer: wgpu::Buffer,
-    dst_vertex_buffer: wgpu::Buffer,
+    #[storage]
+    vertex_buffer: wgpu::Buffer,
+    #[storage(read_only)]
     index_buffer: wgpu::Buffer,
+    #[storage]
+    tangent_sum_buffer: wgpu::Buffer,
+    #[uniform]
     info_buffer: wgpu::Buffer,
     compute_info: ComputeInfo,
 }
 
-impl pipeline::Bindable for BitangentComputeBinding {
-    fn layout_entries() -> Vec<wgpu::BindGroupLayoutEntry> {
-        vec![
-            wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Storage { read_only: true },
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            },
-            wgpu::BindGroupLayoutEntry {
-                binding: 1,
-                visibility: wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Storage { read_only: false },
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            },
-            wgpu::BindGroupLayoutEntry {
-                binding: 2,
-                visibility: wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Storage { read_only: true },
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            },
-            wgpu::BindGroupLayoutEntry {
-                binding: 3,
-                visibility: wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            },
-        ]
-    }
+/// How tangents and bitangents are generated for a mesh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TangentMethod {
+    /// Averages the tangents of every triangle using a vertex, in `compute_bitangents.wgsl`.
+    #[default]
+    Compute,
+    /// MikkTSpace on the CPU, which matches what most DCC tools bake normal maps against.
+    /// Vertices are split wherever the faces sharing them need different tangents.
+    MikkTSpace,
+}
 
-    fn bind_group_entries(&self) -> Vec<wgpu::BindGroupEntry<'_>> {
-        vec![
-            //Src Verts
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: self.src_vertex_buffer.as_entire_binding(),
-            },
-            //Dst Verts
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: self.dst_vertex_buffer.as_entire_binding(),
-            },
-            //Index Buffer
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: self.index_buffer.as_entire_binding(),
-            },
-            //Compute info buffer
-            wgpu::BindGroupEntry {
-                binding: 3,
-                resource: self.info_buffer.as_entire_binding(),
-            },
-        ]
-    }
+/// Per-load choices about how a model's meshes are processed and stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MeshOptions {
+    pub tangents: TangentMethod,
+    pub vertex_layout: VertexLayout,
+    /// How normals are generated for meshes whose file has none.
+    pub normals: NormalMode,
+    /// How UVs are projected for meshes whose file has none.
+    pub uvs: UvProjection,
 }
 
+/// The `tobj` triangulate and single index flags. These change the processed mesh, so
+/// they also form part of its cache key.
+const OBJ_LOAD_SETTINGS: [u8; 2] = [1, 1];
+
 pub struct Model {
     pub meshes: Vec<Mesh>,
     pub materials: Vec<Material>,
+    /// Layout shared by every mesh, which decides the pipelines the model is drawn with.
+    pub vertex_layout: VertexLayout,
+}
+
+impl Model {
+    /// Whether any mesh of the model uses a material with `alpha_mode`.
+    pub fn has_alpha_mode(&self, alpha_mode: AlphaMode) -> bool {
+        self.meshes
+            .iter()
+            .any(|mesh| self.materials[mesh.material].params.alpha_mode == alpha_mode)
+    }
+
+    /// What drawing each mesh whose material has `alpha_mode`, or every mesh, with
+    /// `instances` instances costs.
+    pub fn draw_stats(&self, alpha_mode: Option<AlphaMode>, instances: u32) -> DrawStats {
+        let mut stats = DrawStats::default();
+        for mesh in &self.meshes {
+            if alpha_mode.is_none_or(|mode| self.materials[mesh.material].params.alpha_mode == mode)
+            {
+                stats += DrawStats::draws(1, instances, mesh.num_elements as u64 / 3);
+            }
+        }
+        stats
+    }
+
+    /// Bounds enclosing every mesh of the model.
+    pub fn bounds(&self) -> Bounds {
+        self.meshes
+            .iter()
+            .map(|mesh| mesh.bounds)
+            .reduce(|a, b| a.union(&b))
+            .unwrap_or_default()
+    }
+}
+
+/// Where one of a material's textures comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextureSource {
+    /// An image, or a baked texture, read through the [`FileReader`].
+    Path(String),
+    /// An image stored inside the model file. The name is a path next to the model that
+    /// labels it and that it is baked to.
+    Embedded { name: String, bytes: Arc<[u8]> },
+    /// No texture, so a neutral placeholder is drawn instead.
+    None,
+}
+
+/// The textures referenced by a material, with paths resolved against the model's
+/// directory, and its alpha settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialSource {
+    pub name: String,
+    pub diffuse: TextureSource,
+    pub normal: TextureSource,
+    pub params: MaterialParams,
+}
+
+/// A parsed OBJ whose meshes and textures have not been uploaded yet.
+pub struct ObjScene {
+    pub path: String,
+    pub cache_key: CacheKey,
+    pub models: Vec<tobj::Model>,
+    pub materials: Vec<MaterialSource>,
+    /// Meshes read from a baked model, which already have their tangents, and the
+    /// method they were generated with.
+    pub baked: Option<(Vec<MeshData>, TangentMethod)>,
 }
 
 pub struct ModelLoader {
-    binder: pipeline::Binder<BitangentComputeBinding>,
-    pipeline: wgpu::ComputePipeline,
+    binder: Binder<BitangentComputeBinding>,
+    accumulate_pipeline: wgpu::ComputePipeline,
+    finalize_pipeline: wgpu::ComputePipeline,
+    profiler: GpuProfiler,
 }
 
 impl ModelLoader {
-    pub async fn new(device: &wgpu::Device) -> Self {
-        let binder = pipeline::Binder::new(device, Some("ModelLoader Binder"));
-
-        let shader_buffer = FileReader::read_file("shaders/compute_bitangents.wgsl").await;
-        let shader_str =
-            std::str::from_utf8(shader_buffer.as_slice()).expect("Failed to load shader");
+    pub async fn new(
+        device: &wgpu::Device,
+        shaders: &ShaderCache,
+        pipeline_cache: Option<&wgpu::PipelineCache>,
+        profiler: &GpuProfiler,
+    ) -> Result<Self> {
+        let binder = Binder::new(device, Some("ModelLoader Binder"));
 
-        let shader = wgpu::ShaderModuleDescriptor {
-            source: wgpu::ShaderSource::Wgsl(shader_str.into()),
-            label: Some("Bitangent Compute Shader Module"),
-        };
+        let shader = shaders
+            .load(
+                device,
+                "shaders/compute_bitangents.wgsl",
+                &ShaderDefines::new(),
+            )
+            .await?;
+        binder.check(&shader.reflection, 0)?;
 
-        let pipeline = pipeline::create_compute_pipeline(
+        let accumulate_pipeline = pipeline::create_compute_pipeline(
             device,
             &[Some(&binder.layout)],
-            shader,
-            Some("ModelLoader Compute Pipeline"),
-        );
+            &shader,
+            "accumulate",
+            pipeline_cache,
+            Some("ModelLoader Accumulate Pipeline"),
+        )
+        .await?;
+        let finalize_pipeline = pipeline::create_compute_pipeline(
+            device,
+            &[Some(&binder.layout)],
+            &shader,
+            "finalize",
+            pipeline_cache,
+            Some("ModelLoader Finalize Pipeline"),
+        )
+        .await?;
 
-        Self { binder, pipeline }
+        Ok(Self {
+            binder,
+            accumulate_pipeline,
+            finalize_pipeline,
+            profiler: profiler.clone(),
+        })
     }
 
+    /// Loads an OBJ along with every texture its materials reference.
     pub async fn load<P: AsRef<Path>>(
         &self,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        layout: &wgpu::BindGroupLayout,
+        binder: &Binder<MaterialBinding>,
         path: P,
+        options: MeshOptions,
     ) -> Result<Model> {
+        let scene = Self::parse(path, options).await?;
+
+        let mut materials =
+            futures::future::try_join_all(scene.materials.iter().map(|material| async move {
+                let (diffuse_texture, normal_texture) = futures::try_join!(
+                    Texture::load_source(device, queue, &material.diffuse, false),
+                    Texture::load_source(device, queue, &material.normal, true),
+                )?;
+                Ok::<_, Error>(Material::new(
+                    device,
+                    binder,
+                    &material.name,
+                    diffuse_texture,
+                    normal_texture,
+                    material.params,
+                ))
+            }))
+            .await?;
+
+        if materials.is_empty() {
+            materials.push(Material::untextured(device, queue, binder));
+        }
+        let meshes = self.build_meshes(device, queue, &scene, options)?;
+
+        Ok(Model {
+            meshes,
+            materials,
+            vertex_layout: options.vertex_layout,
+        })
+    }
+
+    /// Reads and parses a model without touching the GPU, preferring a baked `.bmdl`
+    /// next to the source file if one has been mounted. Baked models keep the normals and
+    /// UVs they were baked with, whatever `options` asks for.
+    pub async fn parse<P: AsRef<Path>>(path: P, options: MeshOptions) -> Result<ObjScene> {
         let path = path.as_ref();
-        let resource_base = path.parent().expect("Could not determine model base path");
-        let obj_data =
-            FileReader::read_file(path.to_str().expect("Could not convert model path to &str"))
-                .await;
-        let (obj_models, obj_materials) = tobj::futures::load_obj_buf(
+        let path_str = path_to_str(path)?;
+        let baked_path = baked::baked_path(path_str, baked::BAKED_MODEL_EXTENSION);
+        let Some(bytes) = FileReader::try_read_file(&baked_path).await? else {
+            return Self::parse_source(path, options).await;
+        };
+
+        let model =
+            BakedModel::decode(&bytes).ok_or(Error::InvalidBakedAsset { path: baked_path })?;
+        Ok(ObjScene {
+            path: path_str.to_owned(),
+            cache_key: CacheKey::new("baked", &bytes, &[]),
+            models: Vec::new(),
+            materials: model.materials,
+            baked: Some((model.meshes, model.tangents)),
+        })
+    }
+
+    /// Reads and parses an OBJ, glTF, PLY or STL file, picked by its extension.
+    pub async fn parse_source<P: AsRef<Path>>(path: P, options: MeshOptions) -> Result<ObjScene> {
+        let path = path.as_ref();
+        let path_str = path_to_str(path)?;
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        let load: fn(&str, &[u8]) -> Result<tobj::Model> = match extension.as_deref() {
+            Some("ply") => mesh_import::load_ply,
+            Some("stl") => mesh_import::load_stl,
+            Some("gltf") | Some("glb") => return Self::parse_gltf(path_str, options).await,
+            _ => return Self::parse_obj(path, options).await,
+        };
+
+        let bytes = FileReader::read_file(path_str).await?;
+        let mut model = load(path_str, &bytes)?;
+        mesh_processing::prepare(&mut model.mesh, options.normals, options.uvs);
+        Ok(ObjScene {
+            path: path_str.to_owned(),
+            cache_key: CacheKey::new("mesh", &bytes, &[]),
+            models: vec![model],
+            materials: Vec::new(),
+            baked: None,
+        })
+    }
+
+    /// Reads and parses a glTF or GLB and its buffers, without touching the GPU.
+    pub async fn parse_gltf(path: &str, options: MeshOptions) -> Result<ObjScene> {
+        let bytes = FileReader::read_file(path).await?;
+        let mut scene = gltf_import::load_gltf(path, &bytes).await?;
+        for model in &mut scene.models {
+            mesh_processing::prepare(&mut model.mesh, options.normals, options.uvs);
+        }
+        Ok(ObjScene {
+            path: path.to_owned(),
+            cache_key: scene.cache_key,
+            models: scene.models,
+            materials: scene.materials,
+            baked: None,
+        })
+    }
+
+    /// Reads and parses an OBJ and its MTL files, without touching the GPU.
+    pub async fn parse_obj<P: AsRef<Path>>(path: P, options: MeshOptions) -> Result<ObjScene> {
+        let path = path.as_ref();
+        let path_str = path_to_str(path)?;
+        let resource_base = path
+            .parent()
+            .ok_or_else(|| Error::InvalidPath(path.to_path_buf()))?;
+        let obj_data = FileReader::read_file(path_str).await?;
+        // tobj only lets the material loader report a bare `LoadError`, so keep
+        // hold of the real failure and surface it once parsing has finished.
+        let mtl_error = std::cell::RefCell::new(None);
+        let (mut obj_models, obj_materials) = tobj::futures::load_obj_buf(
             &mut obj_data.as_slice(),
             &tobj::LoadOptions {
-                triangulate: true,
-                single_index: true,
+                triangulate: OBJ_LOAD_SETTINGS[0] != 0,
+                single_index: OBJ_LOAD_SETTINGS[1] != 0,
                 ..Default::default()
             },
             async |path| {
-                let mtl_data = FileReader::read_file(
-                    resource_base
-                        .join(path)
-                        .to_str()
-                        .expect("Could not convert material path to &str"),
-                )
-                .await;
-                tobj::load_mtl_buf(&mut mtl_data.as_slice())
+                let mtl_path = resource_base.join(path);
+                let mtl_data = match mtl_path.to_str() {
+                    Some(mtl_path) => FileReader::read_file(mtl_path).await,
+                    None => Err(Error::InvalidPath(mtl_path.clone())),
+                };
+                match mtl_data {
+                    Ok(mtl_data) => tobj::load_mtl_buf(&mut mtl_data.as_slice()),
+                    Err(e) => {
+                        mtl_error.borrow_mut().get_or_insert(e);
+                        Err(tobj::LoadError::OpenFileFailed)
+                    }
+                }
             },
         )
-        .await?;
+        .await
+        .map_err(|source| Error::ObjParse {
+            path: path_str.to_owned(),
+            source,
+        })?;
+
+        if let Some(e) = mtl_error.into_inner() {
+            return Err(e);
+        }
+        for model in &mut obj_models {
+            mesh_processing::prepare(&mut model.mesh, options.normals, options.uvs);
+        }
+
+        let obj_materials = obj_materials.map_err(|source| Error::ObjParse {
+            path: path_str.to_owned(),
+            source,
+        })?;
 
-        let obj_materials = obj_materials?;
+        let materials = obj_materials
+            .into_iter()
+            .map(|mat| {
+                let diffuse_path =
+                    mat.diffuse_texture
+                        .as_ref()
+                        .ok_or_else(|| Error::MissingTexture {
+                            material: mat.name.clone(),
+                            kind: "diffuse",
+                        })?;
+                let normal_path =
+                    mat.normal_texture
+                        .as_ref()
+                        .ok_or_else(|| Error::MissingTexture {
+                            material: mat.name.clone(),
+                            kind: "normal",
+                        })?;
+                let resolve = |path: &String| -> Result<TextureSource> {
+                    Ok(TextureSource::Path(
+                        path_to_str(&resource_base.join(path))?.to_owned(),
+                    ))
+                };
+                Ok(MaterialSource {
+                    diffuse: resolve(diffuse_path)?,
+                    normal: resolve(normal_path)?,
+                    params: MaterialParams::from_mtl(&mat),
+                    name: mat.name,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
 
-        let mut materials = Vec::new();
+        Ok(ObjScene {
+            path: path_str.to_owned(),
+            cache_key: CacheKey::new("mesh", &obj_data, &OBJ_LOAD_SETTINGS),
+            models: obj_models,
+            materials,
+            baked: None,
+        })
+    }
 
-        for mat in obj_materials {
-            let diffuse_path = &mat.diffuse_texture;
-            let diffuse_path = diffuse_path
-                .clone()
-                .expect("Material has no diffuse texture");
-            let diffuse_texture = Texture::load(
+    /// Uploads the meshes of a parsed OBJ and generates their tangents and bitangents.
+    pub fn build_meshes(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &ObjScene,
+        options: MeshOptions,
+    ) -> Result<Vec<Mesh>> {
+        let MeshOptions {
+            tangents,
+            vertex_layout,
+            normals,
+            uvs,
+        } = options;
+        let cache = AssetCache::global();
+        // Normals and UVs are generated when the scene is parsed, but change the meshes
+        // just as much as the tangents do.
+        let cache_key = scene
+            .cache_key
+            .with_settings(&[tangents as u8, normals as u8, uvs as u8]);
+        if let Some((baked, baked_tangents)) = &scene.baked {
+            if *baked_tangents == tangents {
+                return Ok(Mesh::from_data_all(
+                    device,
+                    &scene.path,
+                    baked,
+                    vertex_layout,
+                ));
+            }
+            // Baking keeps the normals and UVs, so the tangents can be generated again.
+            // Vertices the bake split along tangent seams stay split.
+            log::info!(
+                "{} was baked with {:?} tangents, generating {:?} tangents instead",
+                scene.path,
+                baked_tangents,
+                tangents
+            );
+            let mesh_data: Vec<_> = baked
+                .iter()
+                .map(|mesh| {
+                    let (vertices, indices) =
+                        generate_tangents(&mesh.name, &mesh.vertices, &mesh.indices, tangents);
+                    MeshData {
+                        name: mesh.name.clone(),
+                        material: mesh.material,
+                        vertices,
+                        indices,
+                    }
+                })
+                .collect();
+            return Ok(Mesh::from_data_all(
                 device,
-                queue,
-                resource_base
-                    .join(diffuse_path.clone())
-                    .to_str()
-                    .unwrap_or_else(|| {
-                        panic!(
-                            "{}",
-                            ("Could not convert diffuse path to &str: ".to_owned() + &diffuse_path)
-                        )
-                    }),
-                false,
-            )
-            .await?;
+                &scene.path,
+                &mesh_data,
+                vertex_layout,
+            ));
+        }
+        if let Some(cached) = cache.load_meshes(cache_key) {
+            return Ok(Mesh::from_data_all(
+                device,
+                &scene.path,
+                &cached,
+                vertex_layout,
+            ));
+        }
 
-            let normal_path = &mat.normal_texture;
-            let normal_path = normal_path
-                .clone()
-                .expect("Material has no normal texture!");
-            let normal_texture = Texture::load(
+        if tangents == TangentMethod::MikkTSpace || vertex_layout == VertexLayout::Quantized {
+            let mesh_data: Vec<_> = scene
+                .models
+                .iter()
+                .map(|model| mesh_data_from_obj(model, tangents))
+                .collect();
+            if cache.is_enabled() {
+                cache.store_meshes(cache_key, &mesh_data);
+            }
+            return Ok(Mesh::from_data_all(
                 device,
-                queue,
-                resource_base
-                    .join(normal_path.clone())
-                    .to_str()
-                    .unwrap_or_else(|| {
-                        panic!(
-                            "{}",
-                            ("Could not convert normal path to &str: ".to_owned() + &normal_path)
-                        )
-                    }),
-                true,
-            )
-            .await?;
+                &scene.path,
+                &mesh_data,
+                vertex_layout,
+            ));
+        }
 
-            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: None,
-                layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: wgpu::BindingResource::TextureView(&normal_texture.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 3,
-                        resource: wgpu::BindingResource::Sampler(&normal_texture.sampler),
-                    },
-                ],
-            });
+        let mut meshes = Vec::new();
+        let mut mesh_data = Vec::new();
+        let mut computed = Vec::new();
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Tangent and Bitangent compute encoder"),
+        });
+        let mut profile = self.profiler.begin("Model Loader");
+        let index_format = index_format(
+            scene
+                .models
+                .iter()
+                .map(|model| model.mesh.positions.len() / 3)
+                .max()
+                .unwrap_or(0),
+        );
 
-            materials.push(Material {
-                name: mat.name,
-                textures: HashMap::from_iter([
-                    ("diffuse".to_owned(), diffuse_texture),
-                    ("normal".to_owned(), normal_texture),
-                ]),
-                bind_group,
-            })
+        for model in &scene.models {
+            let vertices = vertices_from_obj(&model.mesh);
+            let bounds = vertex_bounds(&vertices);
+            let binding = self.dispatch_tangents(
+                device,
+                &mut encoder,
+                &mut profile,
+                &scene.path,
+                &vertices,
+                &model.mesh.indices,
+            );
+
+            computed.push((model, binding, bounds));
         }
 
-        let mut meshes = Vec::new();
+        let timings = profile.resolve(&mut encoder);
+        let submission = queue.submit(std::iter::once(encoder.finish()));
+        timings.read_back();
+        device.poll(wgpu::PollType::Wait {
+            submission_index: Some(submission),
+            timeout: None,
+        })?;
 
-        for model in obj_models {
-            let mut vertices = Vec::with_capacity(model.mesh.positions.len() / 3);
-            for i in 0..model.mesh.positions.len() / 3 {
-                vertices.push(ModelVertex {
-                    position: [
-                        model.mesh.positions[i * 3],
-                        model.mesh.positions[i * 3 + 1],
-                        model.mesh.positions[i * 3 + 2],
-                    ],
-                    tex_coords: [model.mesh.texcoords[i * 2], model.mesh.texcoords[i * 2 + 1]],
-                    normal: [
-                        model.mesh.normals[i * 3],
-                        model.mesh.normals[i * 3 + 1],
-                        model.mesh.normals[i * 3 + 2],
-                    ],
-                    tangent: [0.0; 3],
-                    bitangent: [0.0; 3],
-                    padding: [0u32; 2],
+        for (model, binding, bounds) in computed {
+            if cache.is_enabled() {
+                let vertices = read_back_buffer(device, queue, &binding.vertex_buffer)?;
+                mesh_data.push(MeshData {
+                    name: model.name.clone(),
+                    material: model.mesh.material_id.unwrap_or(0),
+                    vertices,
+                    indices: model.mesh.indices.clone(),
                 });
             }
 
-            let indices = &model.mesh.indices;
+            // The compute pass reads 32-bit indices, so only keep its buffer for drawing
+            // when the model needs them.
+            let index_buffer = match index_format {
+                wgpu::IndexFormat::Uint32 => binding.index_buffer,
+                wgpu::IndexFormat::Uint16 => {
+                    create_index_buffer(device, &scene.path, &model.mesh.indices, index_format)
+                }
+            };
 
-            let src_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Compute Src Vertex Buffer", path)),
-                contents: bytemuck::cast_slice(&vertices),
-                usage: wgpu::BufferUsages::STORAGE,
+            let ids_buffer = create_ids_buffer(
+                device,
+                &scene.path,
+                binding.compute_info.num_vertices as usize,
+                VertexIds {
+                    material: model.mesh.material_id.unwrap_or(0) as u32,
+                    mesh: meshes.len() as u32,
+                },
+            );
+            meshes.push(Mesh {
+                name: model.name.clone(),
+                vertex_buffer: binding.vertex_buffer,
+                index_buffer,
+                index_format,
+                ids_buffer,
+                num_elements: binding.compute_info.num_indices,
+                material: model.mesh.material_id.unwrap_or(0),
+                bounds,
             });
+        }
 
-            let dst_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Compute Dst Vertex Buffer", path)),
-                contents: bytemuck::cast_slice(&vertices),
-                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
-            });
+        if cache.is_enabled() {
+            cache.store_meshes(cache_key, &mesh_data);
+        }
 
-            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Index Buffer", path)),
-                contents: bytemuck::cast_slice(&model.mesh.indices),
-                usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::STORAGE,
-            });
+        Ok(meshes)
+    }
 
-            let compute_info = ComputeInfo {
-                num_vertices: vertices.len() as _,
-                num_indices: indices.len() as _,
-            };
+    /// Uploads a mesh and records the `compute_bitangents.wgsl` passes that generate its
+    /// tangents and bitangents in place, returning the buffers they write.
+    fn dispatch_tangents(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        profile: &mut EncoderProfile,
+        label: &str,
+        vertices: &[ModelVertex],
+        indices: &[u32],
+    ) -> BitangentComputeBinding {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Vertex Buffer", label)),
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsages::VERTEX
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC,
+        });
 
-            let info_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Compute Info Buffer", path)),
-                contents: bytemuck::cast_slice(&[compute_info]),
-                usage: wgpu::BufferUsages::UNIFORM,
-            });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Index Buffer", label)),
+            contents: bytemuck::cast_slice(indices),
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::STORAGE,
+        });
 
-            let binding = BitangentComputeBinding {
-                src_vertex_buffer,
-                dst_vertex_buffer,
-                index_buffer,
-                info_buffer,
-                compute_info,
-            };
+        // wgpu zero initialises new buffers, which is where the sums need to start.
+        let tangent_sum_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{} Tangent Sum Buffer", label)),
+            size: (vertices.len().max(1) * 6 * std::mem::size_of::<i32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
 
-            let calc_bind_group = self.binder.create_bind_group(
-                &binding,
-                device,
-                Some("Bitangent Compute Binding Group"),
-            );
-            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Tangent and Bitangent compute encoder"),
-            });
-            {
-                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                    label: Some("Compute Pass"),
-                    timestamp_writes: None,
-                });
-                pass.set_pipeline(&self.pipeline);
-                pass.set_bind_group(0, &calc_bind_group, &[]);
-                pass.dispatch_workgroups(binding.compute_info.num_vertices, 1, 1);
-            }
-            queue.submit(std::iter::once(encoder.finish()));
-            if let Err(e) = device.poll(wgpu::PollType::Poll) {
-                return Err(anyhow!(
-                    "Error during compute bitangent calculation: {:?}",
-                    e
-                ));
-            }
+        let triangle_count = (indices.len() / 3) as u32;
+        let vertex_count = vertices.len() as u32;
+        let max_workgroups = device.limits().max_compute_workgroups_per_dimension;
+        let row_workgroups = triangle_count
+            .max(vertex_count)
+            .div_ceil(TANGENT_WORKGROUP_SIZE)
+            .clamp(1, max_workgroups);
 
-            meshes.push(Mesh {
-                name: model.name,
-                vertex_buffer: binding.dst_vertex_buffer,
-                index_buffer: binding.index_buffer,
-                num_elements: binding.compute_info.num_indices,
-                material: model.mesh.material_id.unwrap_or(0),
+        let compute_info = ComputeInfo {
+            num_vertices: vertex_count,
+            num_indices: indices.len() as _,
+            row_width: row_workgroups * TANGENT_WORKGROUP_SIZE,
+            padding: 0,
+        };
+
+        let info_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Compute Info Buffer", label)),
+            contents: bytemuck::cast_slice(&[compute_info]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let binding = BitangentComputeBinding {
+            vertex_buffer,
+            index_buffer,
+            tangent_sum_buffer,
+            info_buffer,
+            compute_info,
+        };
+
+        let calc_bind_group = self.binder.create_bind_group(
+            &binding,
+            device,
+            Some("Bitangent Compute Binding Group"),
+        );
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Tangent Compute Pass"),
+                timestamp_writes: profile.compute_pass("Tangent compute"),
             });
+            pass.set_bind_group(0, &calc_bind_group, &[]);
+            for (pipeline, invocations) in [
+                (&self.accumulate_pipeline, triangle_count),
+                (&self.finalize_pipeline, vertex_count),
+            ] {
+                let workgroups = invocations.div_ceil(TANGENT_WORKGROUP_SIZE);
+                pass.set_pipeline(pipeline);
+                pass.dispatch_workgroups(
+                    row_workgroups.min(workgroups),
+                    workgroups.div_ceil(row_workgroups),
+                    1,
+                );
+            }
         }
+        profile.end_pass();
 
-        Ok(Model { meshes, materials })
+        binding
     }
 
     pub fn create_screen_quad_mesh(device: &wgpu::Device) -> Mesh {
@@ -450,7 +1211,7 @@ impl ModelLoader {
             },
         ];
 
-        let quad_indices = [0u32, 1u32, 2u32, 0u32, 2u32, 3u32];
+        let quad_indices = [0u16, 1u16, 2u16, 0u16, 2u16, 3u16];
 
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Output Vertex Buffer"),
@@ -464,14 +1225,332 @@ impl ModelLoader {
             usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::STORAGE,
         });
 
+        let ids_buffer = create_ids_buffer(
+            device,
+            "Output Quad",
+            quad_verts.len(),
+            VertexIds {
+                material: 0,
+                mesh: 0,
+            },
+        );
+
         Mesh {
             name: String::from("Output Quad"),
             vertex_buffer,
             index_buffer,
+            index_format: wgpu::IndexFormat::Uint16,
+            ids_buffer,
             num_elements: quad_indices.len() as u32,
             material: 0,
+            bounds: Bounds::from_points(
+                quad_verts
+                    .iter()
+                    .map(|vertex| [vertex.position[0], vertex.position[1], 0.0]),
+            ),
+        }
+    }
+}
+
+/// 16-bit indices when they can address every vertex, halving the index buffer.
+fn index_format(vertex_count: usize) -> wgpu::IndexFormat {
+    if vertex_count <= u16::MAX as usize + 1 {
+        wgpu::IndexFormat::Uint16
+    } else {
+        wgpu::IndexFormat::Uint32
+    }
+}
+
+fn create_index_buffer(
+    device: &wgpu::Device,
+    label: &str,
+    indices: &[u32],
+    format: wgpu::IndexFormat,
+) -> wgpu::Buffer {
+    let narrowed: Vec<u16>;
+    let contents: &[u8] = match format {
+        wgpu::IndexFormat::Uint16 => {
+            narrowed = indices.iter().map(|&index| index as u16).collect();
+            bytemuck::cast_slice(&narrowed)
         }
+        wgpu::IndexFormat::Uint32 => bytemuck::cast_slice(indices),
+    };
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} Index Buffer", label)),
+        contents,
+        usage: wgpu::BufferUsages::INDEX
+            | wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_SRC,
+    })
+}
+
+/// A buffer repeating `ids` for each of a mesh's `vertex_count` vertices.
+fn create_ids_buffer(
+    device: &wgpu::Device,
+    label: &str,
+    vertex_count: usize,
+    ids: VertexIds,
+) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(&format!("{} Ids Buffer", label)),
+        contents: bytemuck::cast_slice(&vec![ids; vertex_count]),
+        usage: wgpu::BufferUsages::VERTEX,
+    })
+}
+
+fn vertex_bounds(vertices: &[ModelVertex]) -> Bounds {
+    Bounds::from_points(vertices.iter().map(|vertex| vertex.position))
+}
+
+/// Converts a `tobj` mesh loaded with `single_index` into vertices with empty tangents.
+/// Parsing already fills in missing UVs and normals, but anything still short is zeroed,
+/// and missing colours are white.
+pub(crate) fn vertices_from_obj(mesh: &tobj::Mesh) -> Vec<ModelVertex> {
+    let attribute = |values: &[f32], i: usize| values.get(i).copied().unwrap_or(0.0);
+    let mut vertices = Vec::with_capacity(mesh.positions.len() / 3);
+    for i in 0..mesh.positions.len() / 3 {
+        let colour = if mesh.vertex_color.is_empty() {
+            [255; 4]
+        } else {
+            let channel =
+                |c: usize| (attribute(&mesh.vertex_color, i * 3 + c) * 255.0).round() as u8;
+            [channel(0), channel(1), channel(2), 255]
+        };
+        vertices.push(ModelVertex {
+            position: [
+                mesh.positions[i * 3],
+                mesh.positions[i * 3 + 1],
+                mesh.positions[i * 3 + 2],
+            ],
+            tex_coords: [
+                attribute(&mesh.texcoords, i * 2),
+                attribute(&mesh.texcoords, i * 2 + 1),
+            ],
+            normal: [
+                attribute(&mesh.normals, i * 3),
+                attribute(&mesh.normals, i * 3 + 1),
+                attribute(&mesh.normals, i * 3 + 2),
+            ],
+            tangent: [0.0; 3],
+            bitangent: [0.0; 3],
+            colour,
+            padding: 0,
+        });
     }
+    vertices
+}
+
+/// Builds a mesh with tangents generated on the CPU. [`TangentMethod::Compute`] runs the
+/// maths of `compute_bitangents.wgsl` on the CPU rather than dispatching it.
+pub(crate) fn mesh_data_from_obj(model: &tobj::Model, tangents: TangentMethod) -> MeshData {
+    let (vertices, indices) = generate_tangents(
+        &model.name,
+        &vertices_from_obj(&model.mesh),
+        &model.mesh.indices,
+        tangents,
+    );
+    MeshData {
+        name: model.name.clone(),
+        material: model.mesh.material_id.unwrap_or(0),
+        vertices,
+        indices,
+    }
+}
+
+/// Replaces the tangents and bitangents of a mesh. MikkTSpace can split vertices, so
+/// this gives back new indices too.
+fn generate_tangents(
+    name: &str,
+    vertices: &[ModelVertex],
+    indices: &[u32],
+    tangents: TangentMethod,
+) -> (Vec<ModelVertex>, Vec<u32>) {
+    match tangents {
+        TangentMethod::Compute => {
+            let mut vertices = vertices.to_vec();
+            compute_tangents(&mut vertices, indices);
+            (vertices, indices.to_vec())
+        }
+        TangentMethod::MikkTSpace => generate_mikktspace(name, vertices, indices),
+    }
+}
+
+struct MikkTSpaceGeometry<'a> {
+    vertices: &'a [ModelVertex],
+    indices: &'a [u32],
+    corners: Vec<ModelVertex>,
+}
+
+impl MikkTSpaceGeometry<'_> {
+    fn vertex(&self, face: usize, vert: usize) -> &ModelVertex {
+        &self.vertices[self.indices[face * 3 + vert] as usize]
+    }
+}
+
+impl bevy_mikktspace::Geometry for MikkTSpaceGeometry<'_> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).position
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).normal
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.vertex(face, vert).tex_coords
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        use cgmath::Vector3;
+
+        let corner = &mut self.corners[face * 3 + vert];
+        let normal = Vector3::from(corner.normal);
+        let [x, y, z, sign] = tangent;
+        let tangent = Vector3::new(x, y, z);
+        corner.tangent = tangent.into();
+        corner.bitangent = (normal.cross(tangent) * sign).into();
+    }
+}
+
+/// Generates MikkTSpace tangents per triangle corner, then welds corners that ended
+/// up identical back into shared vertices.
+fn generate_mikktspace(
+    name: &str,
+    vertices: &[ModelVertex],
+    indices: &[u32],
+) -> (Vec<ModelVertex>, Vec<u32>) {
+    let mut geometry = MikkTSpaceGeometry {
+        vertices,
+        indices,
+        corners: indices
+            .iter()
+            .map(|&index| vertices[index as usize])
+            .collect(),
+    };
+    if !bevy_mikktspace::generate_tangents(&mut geometry) {
+        log::warn!("MikkTSpace could not generate tangents for {}", name);
+    }
+
+    let mut welded = HashMap::new();
+    let mut vertices = Vec::with_capacity(vertices.len());
+    let indices = geometry
+        .corners
+        .iter()
+        .map(|corner| {
+            let key: [u32; 16] = bytemuck::cast(*corner);
+            *welded.entry(key).or_insert_with(|| {
+                vertices.push(*corner);
+                vertices.len() as u32 - 1
+            })
+        })
+        .collect();
+    (vertices, indices)
+}
+
+/// The CPU equivalent of `compute_bitangents.wgsl`: each vertex sums the unit tangent
+/// and bitangent of the triangles that use it, then orthogonalises them against its
+/// normal. The shader sums in fixed point, so results can differ in the last few bits.
+pub(crate) fn compute_tangents(vertices: &mut [ModelVertex], indices: &[u32]) {
+    use cgmath::{InnerSpace, Vector2, Vector3, Zero};
+
+    fn safe_normalize(v: Vector3<f32>) -> Vector3<f32> {
+        let len = v.magnitude();
+        if len > 0.0 { v / len } else { Vector3::zero() }
+    }
+
+    let mut sums = vec![(Vector3::zero(), Vector3::zero()); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [vert0, vert1, vert2] = [0, 1, 2].map(|corner| vertices[triangle[corner] as usize]);
+
+        let delta_pos1 = Vector3::from(vert1.position) - Vector3::from(vert0.position);
+        let delta_pos2 = Vector3::from(vert2.position) - Vector3::from(vert0.position);
+
+        let delta_uv1 = Vector2::from(vert1.tex_coords) - Vector2::from(vert0.tex_coords);
+        let delta_uv2 = Vector2::from(vert2.tex_coords) - Vector2::from(vert0.tex_coords);
+
+        let det = delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x;
+        if det == 0.0 {
+            continue;
+        }
+        let r = 1.0 / det;
+        let tangent = safe_normalize((delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r);
+        let bitangent = safe_normalize((delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * r);
+
+        for (corner, &index) in triangle.iter().enumerate() {
+            if triangle[..corner].contains(&index) {
+                continue;
+            }
+            let sum = &mut sums[index as usize];
+            sum.0 += tangent;
+            sum.1 += bitangent;
+        }
+    }
+
+    for (vertex, (tangent_sum, bitangent_sum)) in vertices.iter_mut().zip(sums) {
+        let normal = safe_normalize(Vector3::from(vertex.normal));
+        let mut tangent = safe_normalize(tangent_sum - normal * normal.dot(tangent_sum));
+        if tangent.is_zero() {
+            // Meshes without UVs have no tangent to sum, so any frame around the normal will do.
+            let axis = if normal.x.abs() > 0.9 {
+                Vector3::unit_y()
+            } else {
+                Vector3::unit_x()
+            };
+            tangent = safe_normalize(normal.cross(axis));
+        }
+        let bitangent = bitangent_sum - normal * normal.dot(bitangent_sum);
+        let mut bitangent = safe_normalize(bitangent - tangent * tangent.dot(bitangent));
+        if bitangent.is_zero() {
+            bitangent = normal.cross(tangent);
+        }
+        vertex.tangent = tangent.into();
+        vertex.bitangent = bitangent.into();
+    }
+}
+
+/// Waits for the GPU to finish with `buffer` and copies its contents back to the CPU.
+fn read_back_buffer<T: bytemuck::Pod>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+) -> Result<Vec<T>> {
+    let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Read Back Staging Buffer"),
+        size: buffer.size(),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Read back encoder"),
+    });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, buffer.size());
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    staging_buffer.map_async(wgpu::MapMode::Read, .., move |result| {
+        let _ = sender.send(result);
+    });
+    device.poll(wgpu::PollType::wait_indefinitely())?;
+    // A wait poll runs the map callback before returning, so an empty channel means it never will.
+    receiver.try_recv().unwrap_or(Err(wgpu::BufferAsyncError))?;
+
+    let data = pod_vec(&staging_buffer.slice(..).get_mapped_range());
+    staging_buffer.unmap();
+    data.ok_or(Error::BufferMap(wgpu::BufferAsyncError))
+}
+
+fn path_to_str(path: &Path) -> Result<&str> {
+    path.to_str()
+        .ok_or_else(|| Error::InvalidPath(path.to_path_buf()))
 }
 
 pub trait DrawModel<'a, 'b>
@@ -502,6 +1581,7 @@ where
         uniforms: &'b wgpu::BindGroup,
         light: &'b wgpu::BindGroup,
     );
+    #[allow(dead_code)]
     fn draw_model_instanced(
         &mut self,
         model: &'b Model,
@@ -509,6 +1589,16 @@ where
         uniforms: &'b wgpu::BindGroup,
         light: &'b wgpu::BindGroup,
     );
+    /// Draws only the meshes whose material uses `alpha_mode`, which decides the
+    /// pipeline they need.
+    fn draw_model_alpha_instanced(
+        &mut self,
+        model: &'b Model,
+        alpha_mode: AlphaMode,
+        instances: Range<u32>,
+        uniforms: &'b wgpu::BindGroup,
+        light: &'b wgpu::BindGroup,
+    );
 }
 
 impl<'a, 'b> DrawModel<'a, 'b> for wgpu::RenderPass<'a>
@@ -523,7 +1613,7 @@ where
         light: &'b wgpu::BindGroup,
     ) {
         self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
         self.set_bind_group(0, &material.bind_group, &[]);
         self.set_bind_group(1, uniforms, &[]);
         self.set_bind_group(2, light, &[]);
@@ -539,7 +1629,8 @@ where
         light: &'b wgpu::BindGroup,
     ) {
         self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.set_vertex_buffer(VERTEX_IDS_SLOT, mesh.ids_buffer.slice(..));
+        self.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
         self.set_bind_group(0, &material.bind_group, &[]);
         self.set_bind_group(1, uniforms, &[]);
         self.set_bind_group(2, light, &[]);
@@ -567,6 +1658,22 @@ where
             self.draw_mesh_instanced(mesh, material, instances.clone(), uniforms, light);
         }
     }
+
+    fn draw_model_alpha_instanced(
+        &mut self,
+        model: &'b Model,
+        alpha_mode: AlphaMode,
+        instances: Range<u32>,
+        uniforms: &'b wgpu::BindGroup,
+        light: &'b wgpu::BindGroup,
+    ) {
+        for mesh in &model.meshes {
+            let material = &model.materials[mesh.material];
+            if material.params.alpha_mode == alpha_mode {
+                self.draw_mesh_instanced(mesh, material, instances.clone(), uniforms, light);
+            }
+        }
+    }
 }
 
 pub trait DrawLight<'a, 'b>
@@ -624,7 +1731,7 @@ where
         light: &'b wgpu::BindGroup,
     ) {
         self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
         self.set_bind_group(0, uniforms, &[]);
         self.set_bind_group(1, light, &[]);
         self.draw_indexed(0..mesh.num_elements, 0, instances);
@@ -651,3 +1758,153 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::asset_source::{AssetSource, DirectorySource};
+
+    /// The bundled resources and shaders sit at the workspace root, above the crate tests
+    /// run in.
+    fn workspace() -> Arc<dyn AssetSource> {
+        Arc::new(DirectorySource::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/.."
+        )))
+    }
+
+    /// The bundled cube, parsed and prepared the way [`ModelLoader::parse_obj`] does,
+    /// without its materials.
+    fn cube() -> Vec<tobj::Model> {
+        let obj = block_on(workspace().read("resources/cube/cube.obj"))
+            .unwrap()
+            .unwrap();
+        let (mut models, _) = tobj::load_obj_buf(
+            &mut obj.as_slice(),
+            &tobj::LoadOptions {
+                triangulate: OBJ_LOAD_SETTINGS[0] != 0,
+                single_index: OBJ_LOAD_SETTINGS[1] != 0,
+                ..Default::default()
+            },
+            |_| Ok(Default::default()),
+        )
+        .unwrap();
+        for model in &mut models {
+            mesh_processing::prepare(&mut model.mesh, NormalMode::Smooth, UvProjection::Box);
+        }
+        assert!(!models.is_empty());
+        models
+    }
+
+    /// A device on the default adapter, or `None` on machines without one.
+    fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        let instance = wgpu::Instance::default();
+        let adapter =
+            block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default())).ok()?;
+        block_on(adapter.request_device(&wgpu::DeviceDescriptor::default())).ok()
+    }
+
+    /// `compute_tangents` and the shader sum in different orders, and the shader in 16.16
+    /// fixed point, so they agree closely but not exactly.
+    const SHADER_DEVIATION: f32 = 1e-3;
+
+    #[test]
+    fn compute_tangents_matches_shader_on_cube() {
+        let Some((device, queue)) = device() else {
+            eprintln!("No adapter, skipping the tangent shader test");
+            return;
+        };
+        let shaders = ShaderCache::with_source(workspace());
+        let profiler = GpuProfiler::new(&device, &queue);
+        let loader = block_on(ModelLoader::new(&device, &shaders, None, &profiler)).unwrap();
+
+        for model in cube() {
+            let vertices = vertices_from_obj(&model.mesh);
+            let mut encoder = device.create_command_encoder(&Default::default());
+            let mut profile = profiler.begin("Tangent test");
+            let binding = loader.dispatch_tangents(
+                &device,
+                &mut encoder,
+                &mut profile,
+                &model.name,
+                &vertices,
+                &model.mesh.indices,
+            );
+            let timings = profile.resolve(&mut encoder);
+            queue.submit(std::iter::once(encoder.finish()));
+            timings.read_back();
+            let shader: Vec<ModelVertex> =
+                read_back_buffer(&device, &queue, &binding.vertex_buffer).unwrap();
+
+            let mut cpu = vertices.clone();
+            compute_tangents(&mut cpu, &model.mesh.indices);
+            assert_eq!(shader.len(), cpu.len());
+            for (vertex, (a, b)) in shader.iter().zip(&cpu).enumerate() {
+                for (name, a, b) in [
+                    ("tangent", a.tangent, b.tangent),
+                    ("bitangent", a.bitangent, b.bitangent),
+                ] {
+                    let deviation = (Vector3::from(a) - Vector3::from(b)).magnitude();
+                    assert!(
+                        deviation < SHADER_DEVIATION,
+                        "{} {} of vertex {}: shader {:?} against {:?}",
+                        model.name,
+                        name,
+                        vertex,
+                        a,
+                        b
+                    );
+                }
+            }
+        }
+    }
+
+    /// How far apart the two methods' unit vectors may be at any corner. `Compute` weights
+    /// every triangle at a vertex equally and MikkTSpace weights them by the angle at the
+    /// vertex, so they part most on the cube's smoothed bevels.
+    const MAX_DEVIATION: f32 = 0.25;
+    /// How far apart they may be on average, which the flat faces keep near zero.
+    const MEAN_DEVIATION: f32 = 0.01;
+
+    #[test]
+    fn mikktspace_matches_compute_on_cube() {
+        let mut deviations = Vec::new();
+        for model in &cube() {
+            let compute = mesh_data_from_obj(model, TangentMethod::Compute);
+            let mikktspace = mesh_data_from_obj(model, TangentMethod::MikkTSpace);
+            // MikkTSpace splits and welds vertices, but keeps every triangle's corners in
+            // order, so corners are compared rather than vertices.
+            assert_eq!(compute.indices.len(), mikktspace.indices.len());
+            for (corner, (&a, &b)) in compute.indices.iter().zip(&mikktspace.indices).enumerate() {
+                let (a, b) = (
+                    compute.vertices[a as usize],
+                    mikktspace.vertices[b as usize],
+                );
+                assert_eq!(a.position, b.position);
+                for (name, a, b) in [
+                    ("tangent", a.tangent, b.tangent),
+                    ("bitangent", a.bitangent, b.bitangent),
+                ] {
+                    let (a, b) = (Vector3::from(a), Vector3::from(b).normalize());
+                    let deviation = (a - b).magnitude();
+                    assert!(
+                        deviation < MAX_DEVIATION,
+                        "{} {} of corner {}: {:?} against {:?}",
+                        model.name,
+                        name,
+                        corner,
+                        a,
+                        b
+                    );
+                    deviations.push(deviation);
+                }
+            }
+        }
+        let mean = deviations.iter().sum::<f32>() / deviations.len() as f32;
+        assert!(mean < MEAN_DEVIATION, "mean deviation {}", mean);
+    }
+}
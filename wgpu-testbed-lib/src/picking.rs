@@ -0,0 +1,376 @@
+//! Mouse picking through an ID target.
+//!
+//! The G-buffer pass writes the instance and mesh drawn at each pixel into the two
+//! channels of an `Rg32Uint` target, through `pick_id` in `shader.wgsl`. A pick copies the
+//! ID under the cursor into a buffer along with the depth there, which `pick.wgsl` first copies into a one
+//! pixel target, as depth-stencil textures can't be copied into buffers. The buffer is
+//! mapped once the frame has been submitted, so picks arrive a frame or two after they
+//! were asked for, and [`Picker::poll`] hands them back with the world position
+//! reconstructed from the depth.
+//!
+//! The picked instance is drawn again at the end of the G-buffer pass, setting
+//! [`SELECTION_STENCIL`] wherever it is visible, and `selection.wgsl` outlines those pixels.
+//!
+//! Blended meshes are drawn after the G-buffer pass and write neither IDs nor depth, so
+//! they can't be picked. A click on one picks whatever opaque or masked mesh is behind it.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use cgmath::{EuclideanSpace, Matrix4, Point3, SquareMatrix, Vector4};
+
+use crate::instance::Instance;
+use crate::model::Mesh;
+use crate::pipeline::{Bindable, Binder, MARK_STENCIL};
+use crate::profiler::EncoderProfile;
+use crate::render_targets::RenderTargets;
+use crate::state::draw_screen_quad;
+
+/// The format of the ID target.
+pub const ID_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg32Uint;
+/// The format of the one pixel target the picked depth is copied into.
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+/// The stencil bit set over the selected instance, on top of the models' 64.
+pub const SELECTION_STENCIL: u32 = 128;
+/// Sets [`SELECTION_STENCIL`] and leaves the other bits alone.
+pub const SELECTION_STENCIL_STATE: wgpu::StencilState = wgpu::StencilState {
+    write_mask: SELECTION_STENCIL,
+    ..MARK_STENCIL
+};
+/// Where the depth follows the ID in the readback buffer.
+const DEPTH_OFFSET: u64 = 8;
+const READBACK_SIZE: u64 = 12;
+
+/// An ID as the target holds it: the instance plus one, then the mesh.
+pub type PickId = [u32; 2];
+
+/// The instance and mesh an ID was written for, or `None` where nothing was drawn.
+pub fn decode_id([instance, mesh]: PickId) -> Option<(usize, usize)> {
+    (instance != 0).then(|| ((instance - 1) as usize, mesh as usize))
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PickUniform {
+    pixel: [u32; 2],
+    _padding: [u32; 2],
+}
+
+/// The depth target, as `pick.wgsl` reads it.
+#[derive(Bindable)]
+#[bindable(fragment)]
+pub struct PickBinding {
+    #[texture(depth)]
+    pub depth: wgpu::TextureView,
+    /// A [`PickUniform`].
+    #[uniform]
+    pub params: wgpu::Buffer,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SelectionUniform {
+    colour: [f32; 4],
+    width: u32,
+    _padding: [u32; 3],
+}
+
+impl SelectionUniform {
+    /// The selection colour, in linear RGB.
+    const COLOUR: [f32; 4] = [1.0, 0.55, 0.0, 1.0];
+
+    /// An outline about two surface pixels wide at `render_scale`.
+    pub fn new(render_scale: f32) -> Self {
+        Self {
+            colour: Self::COLOUR,
+            width: (2.0 * render_scale).ceil().max(1.0) as u32,
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// The stencil, as `selection.wgsl` reads it.
+#[derive(Bindable)]
+#[bindable(fragment)]
+pub struct SelectionBinding {
+    #[texture(uint)]
+    pub stencil: wgpu::TextureView,
+    /// A [`SelectionUniform`].
+    #[uniform]
+    pub params: wgpu::Buffer,
+}
+
+/// What was under the cursor.
+#[derive(Debug, Clone, Copy)]
+pub struct Pick {
+    /// The instance's index in the grid, and the instance as it was when picked.
+    pub instance_index: usize,
+    pub instance: Instance,
+    /// The mesh's index in the model, and its material's.
+    pub mesh: usize,
+    pub material: usize,
+    /// Where the surface under the cursor is, in world space.
+    pub position: Point3<f32>,
+}
+
+/// Called with the pick once it is read back, or `None` if nothing was under the cursor.
+pub type PickCallback = Box<dyn FnOnce(Option<&Pick>)>;
+
+/// A pick that has been read back, before it is matched up with the scene.
+pub struct PickReadback {
+    pub id: PickId,
+    pub position: Point3<f32>,
+    pub callback: PickCallback,
+}
+
+impl PickReadback {
+    /// Finds the instance and mesh the ID names, if they are still there.
+    pub fn resolve(&self, instances: &[Instance], meshes: &[Mesh]) -> Option<Pick> {
+        let (instance_index, mesh) = decode_id(self.id)?;
+        Some(Pick {
+            instance_index,
+            instance: *instances.get(instance_index)?,
+            mesh,
+            material: meshes.get(mesh)?.material,
+            position: self.position,
+        })
+    }
+}
+
+struct PickRequest {
+    /// The pixel in the render targets.
+    pixel: [u32; 2],
+    callback: PickCallback,
+}
+
+/// A pick that has been encoded, waiting for its buffer to be mapped.
+struct InFlight {
+    callback: PickCallback,
+    /// Normalised device coordinates of the pixel's centre.
+    ndc: [f32; 2],
+    /// Clip space back to world space, as the picked frame saw it.
+    inverse_view_proj: Matrix4<f32>,
+}
+
+pub struct Picker {
+    binder: Binder<PickBinding>,
+    params: wgpu::Buffer,
+    depth: wgpu::TextureView,
+    depth_texture: wgpu::Texture,
+    requests: Vec<PickRequest>,
+    /// Buffers encoded this frame, to be mapped once it is submitted.
+    encoded: Vec<(u64, wgpu::Buffer)>,
+    in_flight: HashMap<u64, InFlight>,
+    /// The ID and depth of each mapped pick, by its key in `in_flight`.
+    arrived: Arc<Mutex<Vec<(u64, PickId, f32)>>>,
+    next_key: u64,
+}
+
+impl Picker {
+    pub fn new(device: &wgpu::Device, binder: &Binder<PickBinding>) -> Self {
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pick buffer"),
+            size: std::mem::size_of::<PickUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Pick Depth Surface"),
+            size: wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        Self {
+            binder: binder.clone(),
+            params,
+            depth: depth_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            depth_texture,
+            requests: Vec::new(),
+            encoded: Vec::new(),
+            in_flight: HashMap::new(),
+            arrived: Arc::new(Mutex::new(Vec::new())),
+            next_key: 0,
+        }
+    }
+
+    /// Picks whatever is drawn at `pixel` in the render targets next frame.
+    pub fn request(&mut self, pixel: [u32; 2], callback: PickCallback) {
+        self.requests.push(PickRequest { pixel, callback });
+    }
+
+    /// Copies the ID and depth under the oldest requested pick into a readback buffer,
+    /// once the G-buffer has been drawn. The one uniform buffer means only one pick can
+    /// be encoded a frame, so any others wait for the next.
+    #[allow(clippy::too_many_arguments)]
+    pub fn encode(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        profile: &mut EncoderProfile,
+        targets: &RenderTargets,
+        pipeline: &wgpu::RenderPipeline,
+        screen_quad: &Mesh,
+        view_proj: Matrix4<f32>,
+    ) {
+        if self.requests.is_empty() {
+            return;
+        }
+        let request = self.requests.remove(0);
+        let size = targets.ids.texture.size();
+        let pixel = [
+            request.pixel[0].min(size.width - 1),
+            request.pixel[1].min(size.height - 1),
+        ];
+        queue.write_buffer(
+            &self.params,
+            0,
+            bytemuck::bytes_of(&PickUniform {
+                pixel,
+                _padding: [0; 2],
+            }),
+        );
+        let bind_group = self.binder.create_bind_group(
+            &PickBinding {
+                depth: targets
+                    .depth
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor {
+                        aspect: wgpu::TextureAspect::DepthOnly,
+                        ..Default::default()
+                    }),
+                params: self.params.clone(),
+            },
+            device,
+            Some("Pick bind group"),
+        );
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Pick render pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.depth,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: profile.render_pass("Pick"),
+                occlusion_query_set: None,
+                multiview_mask: None,
+            });
+            draw_screen_quad(&mut render_pass, screen_quad, pipeline, &bind_group);
+        }
+        profile.end_pass();
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pick readback buffer"),
+            size: READBACK_SIZE,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut copy_texel = |texture, origin, offset| {
+            encoder.copy_texture_to_buffer(
+                wgpu::TexelCopyTextureInfo {
+                    texture,
+                    mip_level: 0,
+                    origin,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::TexelCopyBufferInfo {
+                    buffer: &buffer,
+                    layout: wgpu::TexelCopyBufferLayout {
+                        offset,
+                        bytes_per_row: None,
+                        rows_per_image: None,
+                    },
+                },
+                wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+            )
+        };
+        copy_texel(
+            &targets.ids.texture,
+            wgpu::Origin3d {
+                x: pixel[0],
+                y: pixel[1],
+                z: 0,
+            },
+            0,
+        );
+        copy_texel(&self.depth_texture, wgpu::Origin3d::ZERO, DEPTH_OFFSET);
+
+        let key = self.next_key;
+        self.next_key += 1;
+        let centre = |pixel: u32, size: u32| (pixel as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+        self.in_flight.insert(
+            key,
+            InFlight {
+                callback: request.callback,
+                ndc: [centre(pixel[0], size.width), -centre(pixel[1], size.height)],
+                inverse_view_proj: view_proj.invert().unwrap_or_else(Matrix4::identity),
+            },
+        );
+        self.encoded.push((key, buffer));
+    }
+
+    /// Maps the buffers encoded this frame. The encoder must already have been submitted.
+    pub fn read_back(&mut self) {
+        for (key, buffer) in self.encoded.drain(..) {
+            let arrived = self.arrived.clone();
+            buffer
+                .clone()
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    // A failed readback is reported as nothing having been picked.
+                    let (id, depth) = match result {
+                        Ok(()) => {
+                            let data = buffer.slice(..).get_mapped_range();
+                            let id: PickId = bytemuck::pod_read_unaligned(&data[..8]);
+                            let depth: f32 = bytemuck::pod_read_unaligned(&data[8..12]);
+                            drop(data);
+                            buffer.unmap();
+                            (id, depth)
+                        }
+                        Err(_) => ([0; 2], 1.0),
+                    };
+                    arrived.lock().unwrap().push((key, id, depth));
+                });
+        }
+    }
+
+    /// The picks read back since the last poll.
+    pub fn poll(&mut self) -> Vec<PickReadback> {
+        let arrived = std::mem::take(&mut *self.arrived.lock().unwrap());
+        arrived
+            .into_iter()
+            .filter_map(|(key, id, depth)| {
+                let InFlight {
+                    callback,
+                    ndc,
+                    inverse_view_proj,
+                } = self.in_flight.remove(&key)?;
+                let clip = inverse_view_proj * Vector4::new(ndc[0], ndc[1], depth, 1.0);
+                Some(PickReadback {
+                    id,
+                    position: Point3::from_vec(clip.truncate() / clip.w),
+                    callback,
+                })
+            })
+            .collect()
+    }
+}
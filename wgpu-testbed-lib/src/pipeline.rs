@@ -1,3 +1,14 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::error::{Error, Result};
+use crate::reflection::ShaderReflection;
+use crate::shader::Shader;
+
+/// Derives both methods from fields marked `#[uniform]`, `#[storage]`,
+/// `#[storage(read_only)]`, `#[texture]` or `#[sampler]`; see `wgpu-testbed-derive`.
+pub use wgpu_testbed_derive::Bindable;
+
 pub trait Bindable {
     fn layout_entries() -> Vec<wgpu::BindGroupLayoutEntry>;
     fn bind_group_entries(&self) -> Vec<wgpu::BindGroupEntry<'_>>;
@@ -8,6 +19,15 @@ pub struct Binder<T: Bindable> {
     _marker: std::marker::PhantomData<T>,
 }
 
+impl<T: Bindable> Clone for Binder<T> {
+    fn clone(&self) -> Self {
+        Self {
+            layout: self.layout.clone(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
 impl<T: Bindable> Binder<T> {
     pub fn new(device: &wgpu::Device, label: Option<&str>) -> Self {
         let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -32,101 +52,383 @@ impl<T: Bindable> Binder<T> {
             entries: &data.bind_group_entries(),
         })
     }
+
+    /// Checks the resources `reflection` declares in bind group `group` against this
+    /// layout, so a layout that drifts from its shader fails at startup with the name of
+    /// the binding instead of at pipeline creation.
+    pub fn check(&self, reflection: &ShaderReflection, group: u32) -> Result<()> {
+        reflection.check_bind_group(group, &T::layout_entries())
+    }
 }
 
-pub fn create_render_pipeline(
+/// A single uniform buffer, as used for the camera, light and loading screen bind groups.
+#[derive(Bindable)]
+#[bindable(vertex, fragment)]
+pub struct UniformBinding {
+    #[uniform]
+    pub buffer: wgpu::Buffer,
+}
+
+pub(crate) async fn validated<T>(
     device: &wgpu::Device,
-    layout: &wgpu::PipelineLayout,
-    depth_format: Option<wgpu::TextureFormat>,
-    vertex_layouts: &[wgpu::VertexBufferLayout],
-    shader: wgpu::ShaderModuleDescriptor,
-    targets: &[Option<wgpu::ColorTargetState>],
     label: Option<&str>,
-) -> wgpu::RenderPipeline {
-    let shader = device.create_shader_module(shader);
-
-    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label,
-        layout: Some(layout),
-        vertex: wgpu::VertexState {
-            module: &shader,
-            entry_point: Some("vertex_main"),
-            buffers: vertex_layouts,
-            compilation_options: wgpu::PipelineCompilationOptions {
-                ..Default::default()
-            },
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &shader,
-            entry_point: Some("fragment_main"),
-            targets,
-            compilation_options: wgpu::PipelineCompilationOptions {
-                ..Default::default()
-            },
+    create: impl FnOnce() -> T,
+) -> Result<T> {
+    let scope = device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let created = create();
+    match scope.pop().await {
+        Some(error) => Err(Error::ShaderValidation {
+            label: label.unwrap_or("unlabelled pipeline").to_owned(),
+            message: error.to_string(),
         }),
-        primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList,
-            strip_index_format: None,
-            front_face: wgpu::FrontFace::Ccw,
-            cull_mode: None,
-            polygon_mode: wgpu::PolygonMode::Fill,
-            unclipped_depth: false,
-            conservative: false,
-        },
-        depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+        None => Ok(created),
+    }
+}
+
+/// The vertex and fragment entry points a render pipeline takes from its shader.
+#[derive(Debug, Clone, Copy)]
+pub struct EntryPoints<'a> {
+    pub vertex: &'a str,
+    pub fragment: &'a str,
+}
+
+impl Default for EntryPoints<'_> {
+    fn default() -> Self {
+        Self {
+            vertex: "vertex_main",
+            fragment: "fragment_main",
+        }
+    }
+}
+
+/// The stencil state render pipelines get unless they set their own: front faces replace
+/// the stencil value with the pass's reference, and back faces never pass.
+pub const MARK_STENCIL: wgpu::StencilState = wgpu::StencilState {
+    front: wgpu::StencilFaceState {
+        compare: wgpu::CompareFunction::Always,
+        fail_op: wgpu::StencilOperation::Keep,
+        depth_fail_op: wgpu::StencilOperation::Keep,
+        pass_op: wgpu::StencilOperation::Replace,
+    },
+    back: wgpu::StencilFaceState {
+        compare: wgpu::CompareFunction::Never,
+        fail_op: wgpu::StencilOperation::Keep,
+        depth_fail_op: wgpu::StencilOperation::Keep,
+        pass_op: wgpu::StencilOperation::Keep,
+    },
+    read_mask: 0xFF,
+    write_mask: 0xFF,
+};
+
+/// Describes a render pipeline, one piece of state per setter. It starts as a filled,
+/// unculled triangle list with counter-clockwise front faces, no colour targets and no
+/// depth buffer; once a depth format is set, depth is written and tested with
+/// `LessEqual`, and the stencil follows [`MARK_STENCIL`].
+///
+/// ```ignore
+/// let desc = RenderPipelineDesc::new(&layout, &shader)
+///     .label("Outline pipeline")
+///     .vertex_layouts(&layouts)
+///     .target(format, Some(wgpu::BlendState::ALPHA_BLENDING))
+///     .cull_mode(Some(wgpu::Face::Back))
+///     .depth(Texture::DEPTH_FORMAT)
+///     .depth_write(false);
+/// ```
+#[derive(Clone)]
+pub struct RenderPipelineDesc<'a> {
+    label: Option<&'a str>,
+    layout: &'a wgpu::PipelineLayout,
+    shader: &'a Shader,
+    entry_points: EntryPoints<'a>,
+    vertex_layouts: &'a [wgpu::VertexBufferLayout<'a>],
+    targets: Vec<Option<wgpu::ColorTargetState>>,
+    primitive: wgpu::PrimitiveState,
+    depth_format: Option<wgpu::TextureFormat>,
+    depth_write: bool,
+    depth_compare: wgpu::CompareFunction,
+    stencil: wgpu::StencilState,
+    depth_bias: wgpu::DepthBiasState,
+}
+
+impl<'a> RenderPipelineDesc<'a> {
+    pub fn new(layout: &'a wgpu::PipelineLayout, shader: &'a Shader) -> Self {
+        Self {
+            label: None,
+            layout,
+            shader,
+            entry_points: EntryPoints::default(),
+            vertex_layouts: &[],
+            targets: Vec::new(),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_format: None,
+            depth_write: true,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: MARK_STENCIL,
+            depth_bias: wgpu::DepthBiasState::default(),
+        }
+    }
+
+    pub fn label(mut self, label: &'a str) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    pub fn entry_points(mut self, entry_points: EntryPoints<'a>) -> Self {
+        self.entry_points = entry_points;
+        self
+    }
+
+    pub fn vertex_layouts(mut self, vertex_layouts: &'a [wgpu::VertexBufferLayout<'a>]) -> Self {
+        self.vertex_layouts = vertex_layouts;
+        self
+    }
+
+    /// Adds a colour target of `format` that writes every channel, blended with `blend`
+    /// or replacing what is there if it is `None`.
+    pub fn target(self, format: wgpu::TextureFormat, blend: Option<wgpu::BlendState>) -> Self {
+        self.color_target(Some(wgpu::ColorTargetState {
+            format,
+            blend,
+            write_mask: wgpu::ColorWrites::ALL,
+        }))
+    }
+
+    /// Adds a colour target, or a gap in the targets if `target` is `None`.
+    pub fn color_target(mut self, target: Option<wgpu::ColorTargetState>) -> Self {
+        self.targets.push(target);
+        self
+    }
+
+    pub fn topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+        self.primitive.topology = topology;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: wgpu::FrontFace) -> Self {
+        self.primitive.front_face = front_face;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.primitive.cull_mode = cull_mode;
+        self
+    }
+
+    /// Anything other than `Fill` needs `Features::POLYGON_MODE_LINE` or
+    /// `Features::POLYGON_MODE_POINT` on the device.
+    pub fn polygon_mode(mut self, polygon_mode: wgpu::PolygonMode) -> Self {
+        self.primitive.polygon_mode = polygon_mode;
+        self
+    }
+
+    /// Tests and writes a depth buffer of `format`.
+    pub fn depth(mut self, format: wgpu::TextureFormat) -> Self {
+        self.depth_format = Some(format);
+        self
+    }
+
+    pub fn depth_write(mut self, enabled: bool) -> Self {
+        self.depth_write = enabled;
+        self
+    }
+
+    pub fn depth_compare(mut self, compare: wgpu::CompareFunction) -> Self {
+        self.depth_compare = compare;
+        self
+    }
+
+    pub fn stencil(mut self, stencil: wgpu::StencilState) -> Self {
+        self.stencil = stencil;
+        self
+    }
+
+    pub fn depth_bias(mut self, depth_bias: wgpu::DepthBiasState) -> Self {
+        self.depth_bias = depth_bias;
+        self
+    }
+
+    fn depth_stencil(&self) -> Option<wgpu::DepthStencilState> {
+        self.depth_format.map(|format| wgpu::DepthStencilState {
             format,
-            depth_write_enabled: Some(true),
-            depth_compare: Some(wgpu::CompareFunction::LessEqual),
-            stencil: wgpu::StencilState {
-                front: wgpu::StencilFaceState {
-                    compare: wgpu::CompareFunction::Always,
-                    fail_op: wgpu::StencilOperation::Keep,
-                    depth_fail_op: wgpu::StencilOperation::Keep,
-                    pass_op: wgpu::StencilOperation::Replace,
+            depth_write_enabled: Some(self.depth_write),
+            depth_compare: Some(self.depth_compare),
+            stencil: self.stencil.clone(),
+            bias: self.depth_bias,
+        })
+    }
+
+    fn key(&self) -> RenderPipelineKey {
+        RenderPipelineKey {
+            layout: self.layout.clone(),
+            module: self.shader.module.clone(),
+            entry_points: (
+                self.entry_points.vertex.to_owned(),
+                self.entry_points.fragment.to_owned(),
+            ),
+            vertex_layouts: self
+                .vertex_layouts
+                .iter()
+                .map(|layout| {
+                    (
+                        layout.array_stride,
+                        layout.step_mode,
+                        layout.attributes.to_vec(),
+                    )
+                })
+                .collect(),
+            targets: self.targets.clone(),
+            primitive: self.primitive,
+            depth_stencil: self.depth_stencil(),
+        }
+    }
+
+    /// Creates the pipeline, after checking the vertex layouts against the shader.
+    pub async fn build(
+        &self,
+        device: &wgpu::Device,
+        cache: Option<&wgpu::PipelineCache>,
+    ) -> Result<wgpu::RenderPipeline> {
+        self.shader
+            .reflection
+            .check_vertex_layouts(self.entry_points.vertex, self.vertex_layouts)?;
+
+        validated(device, self.label, || {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: self.label,
+                layout: Some(self.layout),
+                vertex: wgpu::VertexState {
+                    module: &self.shader.module,
+                    entry_point: Some(self.entry_points.vertex),
+                    buffers: self.vertex_layouts,
+                    compilation_options: wgpu::PipelineCompilationOptions {
+                        ..Default::default()
+                    },
                 },
-                back: wgpu::StencilFaceState {
-                    compare: wgpu::CompareFunction::Never,
-                    fail_op: wgpu::StencilOperation::Keep,
-                    depth_fail_op: wgpu::StencilOperation::Keep,
-                    pass_op: wgpu::StencilOperation::Keep,
+                fragment: Some(wgpu::FragmentState {
+                    module: &self.shader.module,
+                    entry_point: Some(self.entry_points.fragment),
+                    targets: &self.targets,
+                    compilation_options: wgpu::PipelineCompilationOptions {
+                        ..Default::default()
+                    },
+                }),
+                primitive: self.primitive,
+                depth_stencil: self.depth_stencil(),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
                 },
-                read_mask: 0xFF,
-                write_mask: 0xFF,
-            },
-            bias: wgpu::DepthBiasState::default(),
-        }),
-        multisample: wgpu::MultisampleState {
-            count: 1,
-            mask: !0,
-            alpha_to_coverage_enabled: false,
-        },
-        cache: None,
-        multiview_mask: None,
-    })
+                cache,
+                multiview_mask: None,
+            })
+        })
+        .await
+    }
+}
+
+/// Everything a [`RenderPipelineDesc`] sets except its label, owned so it can key a map.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct RenderPipelineKey {
+    layout: wgpu::PipelineLayout,
+    module: wgpu::ShaderModule,
+    entry_points: (String, String),
+    vertex_layouts: Vec<(
+        wgpu::BufferAddress,
+        wgpu::VertexStepMode,
+        Vec<wgpu::VertexAttribute>,
+    )>,
+    targets: Vec<Option<wgpu::ColorTargetState>>,
+    primitive: wgpu::PrimitiveState,
+    depth_stencil: Option<wgpu::DepthStencilState>,
+}
+
+/// Render pipelines by description, so asking twice for the same pipeline returns the one
+/// created the first time. New pipelines also go through the driver's pipeline cache, if
+/// there is one.
+#[derive(Clone, Default)]
+pub struct RenderPipelineCache {
+    driver_cache: Option<wgpu::PipelineCache>,
+    pipelines: Arc<Mutex<HashMap<RenderPipelineKey, wgpu::RenderPipeline>>>,
+}
+
+impl RenderPipelineCache {
+    pub fn new(driver_cache: Option<wgpu::PipelineCache>) -> Self {
+        Self {
+            driver_cache,
+            pipelines: Arc::default(),
+        }
+    }
+
+    pub fn driver_cache(&self) -> Option<&wgpu::PipelineCache> {
+        self.driver_cache.as_ref()
+    }
+
+    /// Returns the pipeline matching `desc`, creating it if there isn't one yet.
+    pub async fn get(
+        &self,
+        device: &wgpu::Device,
+        desc: &RenderPipelineDesc<'_>,
+    ) -> Result<wgpu::RenderPipeline> {
+        let key = desc.key();
+        if let Some(pipeline) = self.pipelines.lock().unwrap().get(&key) {
+            return Ok(pipeline.clone());
+        }
+
+        // Not locked while the pipeline compiles, so if two loads race for it the first
+        // one to finish is kept.
+        let pipeline = desc.build(device, self.driver_cache.as_ref()).await?;
+        Ok(self
+            .pipelines
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert(pipeline)
+            .clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.pipelines.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
-pub fn create_compute_pipeline(
+pub async fn create_compute_pipeline(
     device: &wgpu::Device,
     bind_group_layouts: &[Option<&wgpu::BindGroupLayout>],
-    shader: wgpu::ShaderModuleDescriptor,
+    shader: &Shader,
+    entry_point: &str,
+    cache: Option<&wgpu::PipelineCache>,
     label: Option<&str>,
-) -> wgpu::ComputePipeline {
-    let shader = device.create_shader_module(shader);
-
-    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label,
-        bind_group_layouts,
-        immediate_size: 0,
-    });
-
-    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-        label,
-        layout: Some(&layout),
-        module: &shader,
-        entry_point: Some("main"),
-        compilation_options: wgpu::PipelineCompilationOptions {
-            ..Default::default()
-        },
-        cache: None,
+) -> Result<wgpu::ComputePipeline> {
+    validated(device, label, || {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label,
+            bind_group_layouts,
+            immediate_size: 0,
+        });
+
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label,
+            layout: Some(&layout),
+            module: &shader.module,
+            entry_point: Some(entry_point),
+            compilation_options: wgpu::PipelineCompilationOptions {
+                ..Default::default()
+            },
+            cache,
+        })
     })
+    .await
 }
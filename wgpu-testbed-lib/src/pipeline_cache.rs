@@ -0,0 +1,72 @@
+//! The driver's pipeline cache, saved to the asset cache directory on exit and handed back
+//! to the driver on the next launch so it can skip recompiling unchanged pipelines.
+//!
+//! Only Vulkan supports pipeline caches, so everywhere else pipelines are created without
+//! one, as before.
+
+use log::info;
+
+use crate::asset_cache::{AssetCache, CacheKey};
+
+pub struct PersistentPipelineCache {
+    pub cache: wgpu::PipelineCache,
+    key: CacheKey,
+    /// The adapter and driver the cache was created for, saved alongside it so data from
+    /// another GPU or driver version is never handed to the driver.
+    adapter: String,
+}
+
+impl PersistentPipelineCache {
+    /// The device features needed for a pipeline cache on `adapter`, if it supports one.
+    pub fn required_features(adapter: &wgpu::Adapter) -> wgpu::Features {
+        adapter.features() & wgpu::Features::PIPELINE_CACHE
+    }
+
+    /// Creates a pipeline cache, seeded with the data saved by a previous run on the same
+    /// adapter and driver. Returns `None` if `device` can't cache pipelines.
+    pub fn new(adapter: &wgpu::Adapter, device: &wgpu::Device) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::PIPELINE_CACHE) {
+            return None;
+        }
+        let info = adapter.get_info();
+        let adapter = format!(
+            "{} {} ({}, {})",
+            wgpu::util::pipeline_cache_key(&info)?,
+            info.name,
+            info.driver,
+            info.driver_info
+        );
+        let key = CacheKey::new("pipeline", adapter.as_bytes(), &[]);
+
+        let data = AssetCache::global().load_pipeline_cache(key, &adapter);
+        info!(
+            "Pipeline cache: {} bytes saved for {}",
+            data.as_ref().map_or(0, Vec::len),
+            adapter
+        );
+        // SAFETY: the data was returned by `PipelineCache::get_data` for this adapter and
+        // driver, and its checksum still matches. With `fallback` set, data the driver
+        // rejects anyway gives an empty cache rather than an error.
+        let cache = unsafe {
+            device.create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+                label: Some("Pipeline cache"),
+                data: data.as_deref(),
+                fallback: true,
+            })
+        };
+
+        Some(Self {
+            cache,
+            key,
+            adapter,
+        })
+    }
+
+    /// Writes the cache's current contents to disk, replacing what was loaded.
+    pub fn save(&self) {
+        if let Some(data) = self.cache.get_data() {
+            info!("Saving {} bytes of pipeline cache", data.len());
+            AssetCache::global().store_pipeline_cache(self.key, &self.adapter, &data);
+        }
+    }
+}
@@ -0,0 +1,554 @@
+//! Timings of each render and compute pass.
+//!
+//! Where the device has `Features::TIMESTAMP_QUERY`, every pass writes a GPU timestamp as
+//! it starts and ends. Elsewhere the time spent encoding each pass is measured on the CPU
+//! instead, which shows where the frame's CPU time goes but nothing of the GPU's.
+//!
+//! Timestamps are resolved into a buffer at the end of each encoder and mapped once the
+//! encoder has been submitted, so they arrive a few frames after they were recorded.
+//! [`GpuProfiler::end_frame`] folds whatever has arrived into rolling averages, and a span
+//! of frames can be captured as Chrome trace event JSON, for `chrome://tracing` or
+//! Perfetto.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use web_time::Instant;
+
+/// The passes one encoder can time. Any after these go untimed.
+pub const MAX_PASSES: u32 = 32;
+/// Encoders whose timestamps can be waiting to be read back at once. Encoders started
+/// while every slot is waiting go untimed.
+const SLOTS: u32 = 8;
+/// How many frames each pass's average is taken over.
+const AVERAGE_FRAMES: usize = 60;
+
+/// When one pass started and ended, in nanoseconds on the profiler's clock.
+#[derive(Debug, Clone)]
+pub struct PassTiming {
+    pub label: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+impl PassTiming {
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64((self.end - self.start).max(0.0) * 1e-9)
+    }
+}
+
+/// The timed passes of one encoder, in the order they were recorded.
+#[derive(Debug, Clone)]
+pub struct EncoderTimings {
+    /// The frame that was being drawn when the encoder was started.
+    pub frame: u64,
+    pub encoder: String,
+    pub passes: Vec<PassTiming>,
+}
+
+/// The query set and, for each slot, the buffers its timestamps are resolved and read
+/// back through.
+struct Timestamps {
+    query_set: wgpu::QuerySet,
+    slots: Vec<(wgpu::Buffer, wgpu::Buffer)>,
+    /// Nanoseconds per timestamp tick.
+    period: f64,
+}
+
+struct Capture {
+    first_frame: u64,
+    frames: u64,
+    timings: Vec<EncoderTimings>,
+}
+
+#[derive(Default)]
+struct Shared {
+    frame: u64,
+    busy: Vec<bool>,
+    next_slot: usize,
+    /// Timings read back since the last [`GpuProfiler::end_frame`].
+    arrived: Vec<EncoderTimings>,
+    /// Each pass's total time in the most recent frames, in milliseconds.
+    history: HashMap<String, VecDeque<f64>>,
+    /// Pass labels in the order they were first seen.
+    order: Vec<String>,
+    capture: Option<Capture>,
+    trace: Option<String>,
+}
+
+/// Times passes across every encoder on one device. Clones share their results, so the
+/// background loader can time its compute passes alongside the frame's.
+#[derive(Clone)]
+pub struct GpuProfiler {
+    timestamps: Option<Arc<Timestamps>>,
+    shared: Arc<Mutex<Shared>>,
+    epoch: Instant,
+}
+
+impl GpuProfiler {
+    /// The device features needed for GPU timestamps on `adapter`, if it supports them.
+    pub fn required_features(adapter: &wgpu::Adapter) -> wgpu::Features {
+        adapter.features() & wgpu::Features::TIMESTAMP_QUERY
+    }
+
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let timestamps = device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+            .then(|| {
+                let size = (MAX_PASSES * 2) as u64 * wgpu::QUERY_SIZE as u64;
+                let slots = (0..SLOTS)
+                    .map(|slot| {
+                        let buffer = |kind, usage| {
+                            device.create_buffer(&wgpu::BufferDescriptor {
+                                label: Some(&format!("Timestamp {} buffer {}", kind, slot)),
+                                size,
+                                usage,
+                                mapped_at_creation: false,
+                            })
+                        };
+                        (
+                            buffer(
+                                "resolve",
+                                wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                            ),
+                            buffer(
+                                "readback",
+                                wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                            ),
+                        )
+                    })
+                    .collect();
+                Arc::new(Timestamps {
+                    query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                        label: Some("Pass timestamps"),
+                        ty: wgpu::QueryType::Timestamp,
+                        count: SLOTS * MAX_PASSES * 2,
+                    }),
+                    slots,
+                    period: queue.get_timestamp_period() as f64,
+                })
+            });
+
+        Self {
+            timestamps,
+            shared: Arc::new(Mutex::new(Shared {
+                busy: vec![false; SLOTS as usize],
+                ..Shared::default()
+            })),
+            epoch: Instant::now(),
+        }
+    }
+
+    /// Whether passes are timed on the GPU rather than the CPU.
+    pub fn has_timestamps(&self) -> bool {
+        self.timestamps.is_some()
+    }
+
+    /// Starts timing the passes of an encoder.
+    pub fn begin(&self, encoder: &str) -> EncoderProfile {
+        let mut shared = self.shared.lock().unwrap();
+        // With timestamps, the encoder needs a slot that isn't still being read back.
+        let slot = match self.timestamps {
+            Some(_) => {
+                let free = (0..SLOTS as usize)
+                    .map(|offset| (shared.next_slot + offset) % SLOTS as usize)
+                    .find(|&slot| !shared.busy[slot]);
+                if let Some(slot) = free {
+                    shared.busy[slot] = true;
+                    shared.next_slot = (slot + 1) % SLOTS as usize;
+                }
+                free
+            }
+            None => None,
+        };
+        EncoderProfile {
+            profiler: self.clone(),
+            encoder: encoder.to_owned(),
+            frame: shared.frame,
+            slot,
+            passes: Vec::new(),
+        }
+    }
+
+    /// Moves on to the next frame, adding the timings read back since the last call to
+    /// the averages and any capture in progress.
+    pub fn end_frame(&self) {
+        let mut shared = self.shared.lock().unwrap();
+        let arrived = std::mem::take(&mut shared.arrived);
+        for timings in &arrived {
+            let mut totals: Vec<(&str, f64)> = Vec::new();
+            for pass in &timings.passes {
+                let milliseconds = pass.duration().as_secs_f64() * 1e3;
+                match totals.iter_mut().find(|(label, _)| *label == pass.label) {
+                    Some((_, total)) => *total += milliseconds,
+                    None => totals.push((&pass.label, milliseconds)),
+                }
+            }
+            for (label, total) in totals {
+                if !shared.history.contains_key(label) {
+                    shared.order.push(label.to_owned());
+                }
+                let history = shared.history.entry(label.to_owned()).or_default();
+                history.push_back(total);
+                if history.len() > AVERAGE_FRAMES {
+                    history.pop_front();
+                }
+            }
+        }
+
+        let frame = shared.frame;
+        if let Some(capture) = &mut shared.capture {
+            let frames = capture.first_frame..capture.first_frame + capture.frames;
+            let last_frame = frames.end - 1;
+            capture.timings.extend(
+                arrived
+                    .iter()
+                    .filter(|timings| frames.contains(&timings.frame))
+                    .cloned(),
+            );
+            // Frames arrive in order, so once the last one is in, so is everything else.
+            // Give up waiting on timings that were never recorded after a while.
+            let complete = arrived.iter().any(|timings| timings.frame >= last_frame)
+                || frame > last_frame + SLOTS as u64;
+            if complete {
+                let capture = shared.capture.take().unwrap();
+                shared.trace = Some(chrome_trace(&capture.timings));
+            }
+        }
+        shared.frame += 1;
+    }
+
+    /// Each pass's average time per frame over the last few frames, in the order the
+    /// passes were first seen.
+    pub fn averages(&self) -> Vec<(String, Duration)> {
+        let shared = self.shared.lock().unwrap();
+        shared
+            .order
+            .iter()
+            .map(|label| {
+                let history = &shared.history[label];
+                let average = history.iter().sum::<f64>() / history.len().max(1) as f64;
+                (label.clone(), Duration::from_secs_f64(average * 1e-3))
+            })
+            .collect()
+    }
+
+    /// Starts recording the next `frames` frames for a Chrome trace, replacing any
+    /// capture in progress.
+    pub fn capture(&self, frames: u64) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.capture = Some(Capture {
+            first_frame: shared.frame,
+            frames: frames.max(1),
+            timings: Vec::new(),
+        });
+    }
+
+    /// The Chrome trace event JSON of the last finished capture, if it hasn't been taken.
+    pub fn take_trace(&self) -> Option<String> {
+        self.shared.lock().unwrap().trace.take()
+    }
+
+    fn now(&self) -> f64 {
+        self.epoch.elapsed().as_nanos() as f64
+    }
+}
+
+struct ProfiledPass {
+    label: String,
+    /// CPU times, used when there are no timestamps.
+    start: f64,
+    end: Option<f64>,
+}
+
+/// The passes timed in one encoder. Each pass is started with [`render_pass`] or
+/// [`compute_pass`], whose result goes in its descriptor, and ended with [`end_pass`]
+/// once its commands have been recorded.
+///
+/// [`render_pass`]: EncoderProfile::render_pass
+/// [`compute_pass`]: EncoderProfile::compute_pass
+/// [`end_pass`]: EncoderProfile::end_pass
+pub struct EncoderProfile {
+    profiler: GpuProfiler,
+    encoder: String,
+    frame: u64,
+    /// The slot whose queries and buffers this encoder uses, when it has one.
+    slot: Option<usize>,
+    passes: Vec<ProfiledPass>,
+}
+
+impl EncoderProfile {
+    /// Starts timing a pass, returning the first of its two timestamp queries if it gets
+    /// them.
+    fn start(&mut self, label: &str) -> Option<(&wgpu::QuerySet, u32)> {
+        self.end_pass();
+        let index = self.passes.len() as u32;
+        self.passes.push(ProfiledPass {
+            label: label.to_owned(),
+            start: self.profiler.now(),
+            end: None,
+        });
+        let timestamps = self.profiler.timestamps.as_ref()?;
+        let slot = self.slot? as u32;
+        (index < MAX_PASSES).then(|| (&timestamps.query_set, (slot * MAX_PASSES + index) * 2))
+    }
+
+    pub fn render_pass(&mut self, label: &str) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        self.start(label)
+            .map(|(query_set, query)| wgpu::RenderPassTimestampWrites {
+                query_set,
+                beginning_of_pass_write_index: Some(query),
+                end_of_pass_write_index: Some(query + 1),
+            })
+    }
+
+    pub fn compute_pass(&mut self, label: &str) -> Option<wgpu::ComputePassTimestampWrites<'_>> {
+        self.start(label)
+            .map(|(query_set, query)| wgpu::ComputePassTimestampWrites {
+                query_set,
+                beginning_of_pass_write_index: Some(query),
+                end_of_pass_write_index: Some(query + 1),
+            })
+    }
+
+    /// Stops the CPU timer of the last pass started. Passes left running are ended when
+    /// the next one starts.
+    pub fn end_pass(&mut self) {
+        if let Some(pass) = self.passes.last_mut().filter(|pass| pass.end.is_none()) {
+            pass.end = Some(self.profiler.now());
+        }
+    }
+
+    /// Copies the timestamps written so far into the slot's readback buffer, at the end
+    /// of `encoder`. Call [`ResolvedProfile::read_back`] once it has been submitted.
+    pub fn resolve(mut self, encoder: &mut wgpu::CommandEncoder) -> ResolvedProfile {
+        self.end_pass();
+        if let (Some(timestamps), Some(slot)) = (&self.profiler.timestamps, self.slot) {
+            let count = (self.passes.len() as u32).min(MAX_PASSES) * 2;
+            if count > 0 {
+                let (resolve_buffer, readback_buffer) = &timestamps.slots[slot];
+                let first = slot as u32 * MAX_PASSES * 2;
+                encoder.resolve_query_set(
+                    &timestamps.query_set,
+                    first..first + count,
+                    resolve_buffer,
+                    0,
+                );
+                encoder.copy_buffer_to_buffer(
+                    resolve_buffer,
+                    0,
+                    readback_buffer,
+                    0,
+                    count as u64 * wgpu::QUERY_SIZE as u64,
+                );
+            }
+        }
+        ResolvedProfile { profile: self }
+    }
+}
+
+impl Drop for EncoderProfile {
+    /// Frees the slot of a profile that is never read back, such as one dropped when its
+    /// encoder fails, so later encoders can still be timed.
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot {
+            if let Ok(mut shared) = self.profiler.shared.lock() {
+                shared.busy[slot] = false;
+            }
+        }
+    }
+}
+
+/// An [`EncoderProfile`] whose timestamps will be in its readback buffer once the
+/// encoder has run.
+pub struct ResolvedProfile {
+    profile: EncoderProfile,
+}
+
+impl ResolvedProfile {
+    /// Maps the readback buffer, handing the timings to the profiler when it is ready.
+    /// The encoder must already have been submitted.
+    pub fn read_back(mut self) {
+        let profiler = self.profile.profiler.clone();
+        let encoder = std::mem::take(&mut self.profile.encoder);
+        let frame = self.profile.frame;
+        // Taken so that dropping the profile leaves the slot busy until it's been mapped.
+        let slot = self.profile.slot.take();
+        let passes = std::mem::take(&mut self.profile.passes);
+        let Some(timestamps) = profiler.timestamps.clone() else {
+            let passes = timed(&passes, |_, pass| Some((pass.start, pass.end?)));
+            profiler
+                .shared
+                .lock()
+                .unwrap()
+                .arrived
+                .push(EncoderTimings {
+                    frame,
+                    encoder,
+                    passes,
+                });
+            return;
+        };
+        // CPU times can't be mixed with timestamps, so an encoder that got no slot goes
+        // untimed.
+        let Some(slot) = slot else {
+            return;
+        };
+
+        let count = passes.len().min(MAX_PASSES as usize);
+        let release = move |timings: Option<EncoderTimings>| {
+            let mut shared = profiler.shared.lock().unwrap();
+            shared.busy[slot] = false;
+            shared.arrived.extend(timings);
+        };
+        if count == 0 {
+            release(None);
+            return;
+        }
+
+        let buffer = timestamps.slots[slot].1.clone();
+        let size = (count * 2) as u64 * wgpu::QUERY_SIZE as u64;
+        buffer
+            .clone()
+            .slice(..size)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let timings = result.ok().map(|()| {
+                    let ticks: Vec<u64> =
+                        bytemuck::pod_collect_to_vec(&buffer.slice(..size).get_mapped_range());
+                    buffer.unmap();
+                    let passes = timed(&passes, |index, _| {
+                        let tick = |i: usize| ticks.get(i).map(|&t| t as f64 * timestamps.period);
+                        Some((tick(index * 2)?, tick(index * 2 + 1)?))
+                    });
+                    EncoderTimings {
+                        frame,
+                        encoder,
+                        passes,
+                    }
+                });
+                release(timings);
+            });
+    }
+}
+
+/// The passes `times` gives a start and end for, by their index.
+fn timed(
+    passes: &[ProfiledPass],
+    times: impl Fn(usize, &ProfiledPass) -> Option<(f64, f64)>,
+) -> Vec<PassTiming> {
+    passes
+        .iter()
+        .enumerate()
+        .filter_map(|(index, pass)| {
+            times(index, pass).map(|(start, end)| PassTiming {
+                label: pass.label.clone(),
+                start,
+                end,
+            })
+        })
+        .collect()
+}
+
+/// Writes `timings` as Chrome trace events: one complete event per pass, with a track for
+/// each encoder, starting from the earliest pass.
+fn chrome_trace(timings: &[EncoderTimings]) -> String {
+    let origin = timings
+        .iter()
+        .flat_map(|timings| &timings.passes)
+        .map(|pass| pass.start)
+        .fold(f64::INFINITY, f64::min);
+    let mut encoders: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+    for timings in timings {
+        let track = match encoders.iter().position(|&name| name == timings.encoder) {
+            Some(track) => track,
+            None => {
+                encoders.push(&timings.encoder);
+                encoders.len() - 1
+            }
+        };
+        for pass in &timings.passes {
+            events.push(format!(
+                r#"{{"name":{},"cat":"pass","ph":"X","pid":0,"tid":{},"ts":{:.3},"dur":{:.3},"args":{{"frame":{}}}}}"#,
+                json_string(&pass.label),
+                track,
+                (pass.start - origin) * 1e-3,
+                (pass.end - pass.start).max(0.0) * 1e-3,
+                timings.frame
+            ));
+        }
+    }
+    for (track, name) in encoders.iter().enumerate() {
+        events.push(format!(
+            r#"{{"name":"thread_name","ph":"M","pid":0,"tid":{},"args":{{"name":{}}}}}"#,
+            track,
+            json_string(name)
+        ));
+    }
+    format!(
+        "{{\"displayTimeUnit\":\"ms\",\"traceEvents\":[\n{}\n]}}\n",
+        events.join(",\n")
+    )
+}
+
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        let instance = wgpu::Instance::default();
+        let adapter =
+            block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default())).ok()?;
+        block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            required_features: GpuProfiler::required_features(&adapter),
+            ..Default::default()
+        }))
+        .ok()
+    }
+
+    #[test]
+    fn dropped_profiles_free_their_slots() {
+        let Some((device, queue)) = device() else {
+            eprintln!("No adapter, skipping the profiler slot test");
+            return;
+        };
+        let profiler = GpuProfiler::new(&device, &queue);
+        if !profiler.has_timestamps() {
+            eprintln!("No timestamp queries, skipping the profiler slot test");
+            return;
+        }
+
+        for _ in 0..SLOTS * 2 {
+            let profile = profiler.begin("dropped");
+            assert!(profile.slot.is_some());
+        }
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        for _ in 0..SLOTS * 2 {
+            let profile = profiler.begin("resolved");
+            assert!(profile.slot.is_some());
+            let _ = profile.resolve(&mut encoder);
+        }
+    }
+}
@@ -0,0 +1,779 @@
+//! Reflection of WGSL shaders with naga, used to check the layouts maintained by hand in
+//! Rust against what the shaders declare.
+//!
+//! Every [`Shader`](crate::shader::Shader) is reflected as it is compiled. Vertex buffer
+//! layouts are checked when a render pipeline is created, and bind group layouts through
+//! [`Binder::check`](crate::pipeline::Binder::check). Either way a mismatch comes back as
+//! an error naming the shader variable, instead of a wgpu validation panic about a
+//! pipeline. Shaders whose bind groups have no Rust layout can
+//! also have their pipeline layout generated outright.
+
+use std::num::NonZeroU32;
+
+use crate::error::{Error, Result};
+
+/// A vertex shader input bound to a location.
+#[derive(Debug, Clone)]
+pub struct VertexInput {
+    pub name: String,
+    pub location: u32,
+    pub scalar: naga::Scalar,
+    /// 1 for a scalar, or the size of a vector.
+    pub components: u32,
+}
+
+pub struct ShaderReflection {
+    label: String,
+    module: naga::Module,
+    info: naga::valid::ModuleInfo,
+}
+
+impl ShaderReflection {
+    pub fn new(label: &str, source: &str) -> Result<Self> {
+        Self::with_line_origins(label, source, |_| None)
+    }
+
+    /// Like [`new`](Self::new), for source put together from several files. `origin`
+    /// names where a line of `source` came from, and errors say it for the line they
+    /// point at.
+    pub fn with_line_origins(
+        label: &str,
+        source: &str,
+        origin: impl Fn(usize) -> Option<String>,
+    ) -> Result<Self> {
+        let invalid = |mut message: String, location: Option<naga::SourceLocation>| {
+            let line = location.map(|location| location.line_number as usize);
+            if let Some((line, origin)) = line.and_then(|line| Some((line, origin(line)?))) {
+                message = format!("{}\nline {} is {}", message.trim_end(), line, origin);
+            }
+            Error::ShaderValidation {
+                label: label.to_owned(),
+                message,
+            }
+        };
+        let module = naga::front::wgsl::parse_str(source)
+            .map_err(|e| invalid(e.emit_to_string(source), e.location(source)))?;
+        // Only usage information is needed here; wgpu checks the device's capabilities
+        // itself when the module is created.
+        let info = naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(&module)
+        .map_err(|e| invalid(e.emit_to_string(source), e.location(source)))?;
+        Ok(Self {
+            label: label.to_owned(),
+            module,
+            info,
+        })
+    }
+
+    /// The location-bound inputs of a vertex entry point, including those inside input
+    /// structs.
+    pub fn vertex_inputs(&self, entry_point: &str) -> Result<Vec<VertexInput>> {
+        let entry = self.entry_point(entry_point)?;
+        let mut inputs = Vec::new();
+        for argument in &entry.function.arguments {
+            match &self.module.types[argument.ty].inner {
+                naga::TypeInner::Struct { members, .. } => {
+                    for member in members {
+                        self.push_input(&mut inputs, &member.name, member.ty, &member.binding);
+                    }
+                }
+                _ => self.push_input(&mut inputs, &argument.name, argument.ty, &argument.binding),
+            }
+        }
+        Ok(inputs)
+    }
+
+    fn push_input(
+        &self,
+        inputs: &mut Vec<VertexInput>,
+        name: &Option<String>,
+        ty: naga::Handle<naga::Type>,
+        binding: &Option<naga::Binding>,
+    ) {
+        let location = match binding {
+            Some(naga::Binding::Location { location, .. }) => *location,
+            _ => return,
+        };
+        let inner = &self.module.types[ty].inner;
+        if let Some(scalar) = inner.scalar() {
+            inputs.push(VertexInput {
+                name: name
+                    .clone()
+                    .unwrap_or_else(|| format!("location {}", location)),
+                location,
+                scalar,
+                components: match inner {
+                    naga::TypeInner::Vector { size, .. } => *size as u32,
+                    _ => 1,
+                },
+            });
+        }
+    }
+
+    /// Checks that `layouts` feed every input of `entry_point` with an attribute of the
+    /// right type and size. Formats only made in twos and fours, like `Snorm16x4`, may
+    /// give a three component input one more than it reads.
+    pub fn check_vertex_layouts(
+        &self,
+        entry_point: &str,
+        layouts: &[wgpu::VertexBufferLayout<'_>],
+    ) -> Result<()> {
+        let mismatch = |message: String| Error::VertexLayoutMismatch {
+            label: self.label.clone(),
+            entry_point: entry_point.to_owned(),
+            message,
+        };
+
+        let mut attributes: Vec<(usize, &wgpu::VertexAttribute)> = Vec::new();
+        for (slot, layout) in layouts.iter().enumerate() {
+            for attribute in layout.attributes {
+                if let Some((other, _)) = attributes
+                    .iter()
+                    .find(|(_, other)| other.shader_location == attribute.shader_location)
+                {
+                    return Err(mismatch(format!(
+                        "location {} is given by both buffer {} and buffer {}",
+                        attribute.shader_location, other, slot
+                    )));
+                }
+                attributes.push((slot, attribute));
+            }
+        }
+
+        for input in self.vertex_inputs(entry_point)? {
+            let (slot, attribute) = attributes
+                .iter()
+                .find(|(_, attribute)| attribute.shader_location == input.location)
+                .ok_or_else(|| {
+                    mismatch(format!(
+                        "`{}` at location {} isn't in any vertex buffer layout",
+                        input.name, input.location
+                    ))
+                })?;
+            let provided = format_scalar(attribute.format);
+            if provided != input.scalar {
+                return Err(mismatch(format!(
+                    "`{}` at location {} is {:?} in the shader but buffer {} gives it as {:?}",
+                    input.name, input.location, input.scalar.kind, slot, attribute.format
+                )));
+            }
+            let components = format_components(attribute.format);
+            // Formats with 32-bit components come in every size, so only the packed
+            // ones may be padded.
+            let padded = input.components == 3
+                && components == 4
+                && attribute.format.size() / u64::from(components) < 4;
+            if components != input.components && !padded {
+                return Err(mismatch(format!(
+                    "`{}` at location {} has {} components in the shader but buffer {} gives \
+                     it {} as {:?}",
+                    input.name,
+                    input.location,
+                    input.components,
+                    slot,
+                    components,
+                    attribute.format
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks every resource declared in bind group `group` against `entries`.
+    pub fn check_bind_group(
+        &self,
+        group: u32,
+        entries: &[wgpu::BindGroupLayoutEntry],
+    ) -> Result<()> {
+        let mismatch = |message: String| Error::BindingMismatch {
+            label: self.label.clone(),
+            group,
+            message,
+        };
+
+        for (handle, variable) in self.group_variables(group) {
+            let binding = variable.binding.as_ref().unwrap().binding;
+            let name = variable.name.as_deref().unwrap_or("<unnamed>");
+            let entry = entries
+                .iter()
+                .find(|entry| entry.binding == binding)
+                .ok_or_else(|| {
+                    mismatch(format!(
+                        "`{}` uses binding {}, which the layout doesn't have",
+                        name, binding
+                    ))
+                })?;
+
+            let (declared, count) = self.binding_type(variable).ok_or_else(|| {
+                mismatch(format!(
+                    "`{}` at binding {} can't be checked against a layout",
+                    name, binding
+                ))
+            })?;
+            if !binding_matches(&declared, &entry.ty) || count.is_some() != entry.count.is_some() {
+                return Err(mismatch(format!(
+                    "`{}` at binding {} is a {} in the shader but a {} in the layout",
+                    name,
+                    binding,
+                    describe_binding(&declared, count),
+                    describe_binding(&entry.ty, entry.count)
+                )));
+            }
+
+            // Filtering an unfilterable texture is only caught by wgpu once a pipeline
+            // uses both, so look for samplers in this group that would.
+            let unfilterable = matches!(
+                entry.ty,
+                wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    ..
+                }
+            );
+            if !unfilterable {
+                continue;
+            }
+            for sampler in self.samplers_of(handle) {
+                let sampler = &self.module.global_variables[sampler];
+                let Some(sampler_binding) = sampler.binding.as_ref() else {
+                    continue;
+                };
+                let filtering = sampler_binding.group == group
+                    && entries.iter().any(|entry| {
+                        entry.binding == sampler_binding.binding
+                            && entry.ty
+                                == wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering)
+                    });
+                if filtering {
+                    return Err(mismatch(format!(
+                        "`{}` at binding {} is unfilterable in the layout but the shader \
+                         samples it with the filtering sampler `{}`",
+                        name,
+                        binding,
+                        sampler.name.as_deref().unwrap_or("<unnamed>")
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The binding type `variable` needs, with filterable textures, and its array size if
+    /// it is a binding array. `None` for resources this can't describe, like storage
+    /// textures.
+    fn binding_type(
+        &self,
+        variable: &naga::GlobalVariable,
+    ) -> Option<(wgpu::BindingType, Option<NonZeroU32>)> {
+        let (inner, count) = match &self.module.types[variable.ty].inner {
+            naga::TypeInner::BindingArray { base, size } => match size {
+                naga::ArraySize::Constant(size) => (&self.module.types[*base].inner, Some(*size)),
+                _ => return None,
+            },
+            inner => (inner, None),
+        };
+        let ty = match (variable.space, inner) {
+            (naga::AddressSpace::Uniform, _) => wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            (naga::AddressSpace::Storage { access }, _) => wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage {
+                    read_only: !access.contains(naga::StorageAccess::STORE),
+                },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            (
+                _,
+                naga::TypeInner::Image {
+                    dim,
+                    arrayed,
+                    class,
+                },
+            ) => {
+                let (sample_type, multisampled) = match *class {
+                    naga::ImageClass::Sampled { kind, multi } => (
+                        match kind {
+                            naga::ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+                            naga::ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+                            _ => wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        multi,
+                    ),
+                    naga::ImageClass::Depth { multi } => (wgpu::TextureSampleType::Depth, multi),
+                    _ => return None,
+                };
+                wgpu::BindingType::Texture {
+                    sample_type,
+                    view_dimension: view_dimension(*dim, *arrayed),
+                    multisampled,
+                }
+            }
+            (_, naga::TypeInner::Sampler { comparison }) => {
+                wgpu::BindingType::Sampler(if *comparison {
+                    wgpu::SamplerBindingType::Comparison
+                } else {
+                    wgpu::SamplerBindingType::Filtering
+                })
+            }
+            _ => return None,
+        };
+        Some((ty, count))
+    }
+
+    /// The samplers any entry point samples `image` with.
+    fn samplers_of(
+        &self,
+        image: naga::Handle<naga::GlobalVariable>,
+    ) -> Vec<naga::Handle<naga::GlobalVariable>> {
+        (0..self.module.entry_points.len())
+            .flat_map(|index| &self.info.get_entry_point(index).sampling_set)
+            .filter(|key| key.image == image)
+            .map(|key| key.sampler)
+            .collect()
+    }
+
+    /// Layout entries for bind group `group`, visible to the stages whose entry points use
+    /// each binding. Textures are assumed to be filterable.
+    pub fn layout_entries(&self, group: u32) -> Result<Vec<wgpu::BindGroupLayoutEntry>> {
+        self.group_variables(group)
+            .map(|(handle, variable)| {
+                let binding = variable.binding.as_ref().unwrap().binding;
+                let unsupported = || Error::BindingMismatch {
+                    label: self.label.clone(),
+                    group,
+                    message: format!(
+                        "`{}` at binding {} can't be reflected into a layout",
+                        variable.name.as_deref().unwrap_or("<unnamed>"),
+                        binding
+                    ),
+                };
+
+                let (ty, count) = self.binding_type(variable).ok_or_else(unsupported)?;
+                Ok(wgpu::BindGroupLayoutEntry {
+                    binding,
+                    visibility: self.visibility(handle),
+                    ty,
+                    count,
+                })
+            })
+            .collect()
+    }
+
+    /// Creates a layout for every bind group the shader declares, and a pipeline layout
+    /// over them. Groups the shader skips are left empty.
+    pub fn create_pipeline_layout(
+        &self,
+        device: &wgpu::Device,
+    ) -> Result<(wgpu::PipelineLayout, Vec<Option<wgpu::BindGroupLayout>>)> {
+        let group_count = self
+            .module
+            .global_variables
+            .iter()
+            .filter_map(|(_, variable)| variable.binding.as_ref())
+            .map(|binding| binding.group + 1)
+            .max()
+            .unwrap_or(0);
+
+        let bind_group_layouts = (0..group_count)
+            .map(|group| {
+                let entries = self.layout_entries(group)?;
+                Ok((!entries.is_empty()).then(|| {
+                    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                        label: Some(&format!("{} group {}", self.label, group)),
+                        entries: &entries,
+                    })
+                }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&self.label),
+            bind_group_layouts: &bind_group_layouts
+                .iter()
+                .map(Option::as_ref)
+                .collect::<Vec<_>>(),
+            immediate_size: 0,
+        });
+        Ok((pipeline_layout, bind_group_layouts))
+    }
+
+    fn entry_point(&self, name: &str) -> Result<&naga::EntryPoint> {
+        self.module
+            .entry_points
+            .iter()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| Error::ShaderValidation {
+                label: self.label.clone(),
+                message: format!("no entry point named `{}`", name),
+            })
+    }
+
+    fn group_variables(
+        &self,
+        group: u32,
+    ) -> impl Iterator<Item = (naga::Handle<naga::GlobalVariable>, &naga::GlobalVariable)> {
+        self.module
+            .global_variables
+            .iter()
+            .filter(move |(_, variable)| {
+                matches!(&variable.binding, Some(binding) if binding.group == group)
+            })
+    }
+
+    /// The stages of every entry point that uses a variable.
+    fn visibility(&self, variable: naga::Handle<naga::GlobalVariable>) -> wgpu::ShaderStages {
+        self.module
+            .entry_points
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !self.info.get_entry_point(*index)[variable].is_empty())
+            .fold(wgpu::ShaderStages::NONE, |stages, (_, entry)| {
+                stages
+                    | match entry.stage {
+                        naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+                        naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+                        naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+                        _ => wgpu::ShaderStages::NONE,
+                    }
+            })
+    }
+}
+
+/// Whether a layout entry of type `provided` can be bound where the shader declares
+/// `declared`, following wgpu's rules. Float textures of either filterability match, and
+/// shader samplers match a layout's filtering and non-filtering ones.
+fn binding_matches(declared: &wgpu::BindingType, provided: &wgpu::BindingType) -> bool {
+    use wgpu::{BindingType as B, SamplerBindingType as S, TextureSampleType as T};
+    match (declared, provided) {
+        (B::Buffer { ty: declared, .. }, B::Buffer { ty: provided, .. }) => declared == provided,
+        (
+            B::Texture {
+                sample_type: declared_sample,
+                view_dimension: declared_dimension,
+                multisampled: declared_multisampled,
+            },
+            B::Texture {
+                sample_type: provided_sample,
+                view_dimension: provided_dimension,
+                multisampled: provided_multisampled,
+            },
+        ) => {
+            let samples_match = matches!(
+                (declared_sample, provided_sample),
+                (T::Float { .. }, T::Float { .. })
+                    | (T::Sint, T::Sint)
+                    | (T::Uint, T::Uint)
+                    | (T::Depth, T::Depth)
+            );
+            samples_match
+                && declared_dimension == provided_dimension
+                && declared_multisampled == provided_multisampled
+        }
+        (B::Sampler(S::Comparison), B::Sampler(provided)) => *provided == S::Comparison,
+        (B::Sampler(_), B::Sampler(provided)) => *provided != S::Comparison,
+        _ => false,
+    }
+}
+
+/// A binding type in words, like "multisampled 2D depth texture".
+fn describe_binding(ty: &wgpu::BindingType, count: Option<NonZeroU32>) -> String {
+    let kind = match ty {
+        wgpu::BindingType::Buffer { ty, .. } => match ty {
+            wgpu::BufferBindingType::Uniform => "uniform buffer".to_owned(),
+            wgpu::BufferBindingType::Storage { read_only: false } => "storage buffer".to_owned(),
+            wgpu::BufferBindingType::Storage { read_only: true } => {
+                "read-only storage buffer".to_owned()
+            }
+        },
+        wgpu::BindingType::Texture {
+            sample_type,
+            view_dimension,
+            multisampled,
+        } => {
+            let sample_type = match sample_type {
+                wgpu::TextureSampleType::Float { filterable: true } => "float",
+                wgpu::TextureSampleType::Float { filterable: false } => "unfilterable float",
+                wgpu::TextureSampleType::Sint => "sint",
+                wgpu::TextureSampleType::Uint => "uint",
+                wgpu::TextureSampleType::Depth => "depth",
+            };
+            let dimension = match view_dimension {
+                wgpu::TextureViewDimension::D1 => "1D",
+                wgpu::TextureViewDimension::D2 => "2D",
+                wgpu::TextureViewDimension::D2Array => "2D array",
+                wgpu::TextureViewDimension::Cube => "cube",
+                wgpu::TextureViewDimension::CubeArray => "cube array",
+                wgpu::TextureViewDimension::D3 => "3D",
+            };
+            let multisampled = if *multisampled { "multisampled " } else { "" };
+            format!("{}{} {} texture", multisampled, dimension, sample_type)
+        }
+        wgpu::BindingType::StorageTexture { .. } => "storage texture".to_owned(),
+        wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering) => {
+            "filtering sampler".to_owned()
+        }
+        wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering) => {
+            "non-filtering sampler".to_owned()
+        }
+        wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison) => {
+            "comparison sampler".to_owned()
+        }
+        _ => "resource".to_owned(),
+    };
+    match count {
+        Some(count) => format!("{} array of {}", kind, count),
+        None => kind,
+    }
+}
+
+/// How many components a vertex format gives the shader.
+fn format_components(format: wgpu::VertexFormat) -> u32 {
+    use wgpu::VertexFormat as F;
+    match format {
+        F::Uint8x2
+        | F::Sint8x2
+        | F::Unorm8x2
+        | F::Snorm8x2
+        | F::Uint16x2
+        | F::Sint16x2
+        | F::Unorm16x2
+        | F::Snorm16x2
+        | F::Float16x2
+        | F::Float32x2
+        | F::Uint32x2
+        | F::Sint32x2
+        | F::Float64x2 => 2,
+        F::Float32x3 | F::Uint32x3 | F::Sint32x3 | F::Float64x3 => 3,
+        F::Uint8x4
+        | F::Sint8x4
+        | F::Unorm8x4
+        | F::Snorm8x4
+        | F::Uint16x4
+        | F::Sint16x4
+        | F::Unorm16x4
+        | F::Snorm16x4
+        | F::Float16x4
+        | F::Float32x4
+        | F::Uint32x4
+        | F::Sint32x4
+        | F::Float64x4
+        | F::Unorm10_10_10_2
+        | F::Unorm8x4Bgra => 4,
+        _ => 1,
+    }
+}
+
+/// The scalar type a vertex format arrives in the shader as.
+fn format_scalar(format: wgpu::VertexFormat) -> naga::Scalar {
+    use wgpu::VertexFormat as F;
+    match format {
+        F::Uint8 | F::Uint8x2 | F::Uint8x4 | F::Uint16 | F::Uint16x2 | F::Uint16x4 => {
+            naga::Scalar::U32
+        }
+        F::Uint32 | F::Uint32x2 | F::Uint32x3 | F::Uint32x4 => naga::Scalar::U32,
+        F::Sint8 | F::Sint8x2 | F::Sint8x4 | F::Sint16 | F::Sint16x2 | F::Sint16x4 => {
+            naga::Scalar::I32
+        }
+        F::Sint32 | F::Sint32x2 | F::Sint32x3 | F::Sint32x4 => naga::Scalar::I32,
+        F::Float64 | F::Float64x2 | F::Float64x3 | F::Float64x4 => naga::Scalar::F64,
+        // Normalised and half formats are converted to f32.
+        _ => naga::Scalar::F32,
+    }
+}
+
+fn view_dimension(dim: naga::ImageDimension, arrayed: bool) -> wgpu::TextureViewDimension {
+    match (dim, arrayed) {
+        (naga::ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+        (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+        (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+        (naga::ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+        (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+        (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXTURE_SHADER: &str = "
+        @group(0) @binding(0) var t_input: TEXTURE;
+        @group(0) @binding(1) var s_input: sampler;
+
+        @fragment
+        fn fragment_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+            return USE;
+        }
+    ";
+
+    fn texture_shader(texture: &str, usage: &str) -> ShaderReflection {
+        let source = TEXTURE_SHADER
+            .replace("TEXTURE", texture)
+            .replace("USE", usage);
+        ShaderReflection::new("test", &source).unwrap()
+    }
+
+    fn texture_entries(
+        sample_type: wgpu::TextureSampleType,
+        view_dimension: wgpu::TextureViewDimension,
+        multisampled: bool,
+        sampler: wgpu::SamplerBindingType,
+    ) -> [wgpu::BindGroupLayoutEntry; 2] {
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type,
+                    view_dimension,
+                    multisampled,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(sampler),
+                count: None,
+            },
+        ]
+    }
+
+    const FLOAT: wgpu::TextureSampleType = wgpu::TextureSampleType::Float { filterable: true };
+    const D2: wgpu::TextureViewDimension = wgpu::TextureViewDimension::D2;
+    const FILTERING: wgpu::SamplerBindingType = wgpu::SamplerBindingType::Filtering;
+    const SAMPLE: &str = "textureSample(t_input, s_input, position.xy)";
+
+    fn assert_mismatch(result: Result<()>, expected: &str) {
+        match result {
+            Err(Error::BindingMismatch { message, .. }) => {
+                assert!(message.contains(expected), "{}", message)
+            }
+            other => panic!("expected a binding mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn matching_texture_passes() {
+        let shader = texture_shader("texture_2d<f32>", SAMPLE);
+        shader
+            .check_bind_group(0, &texture_entries(FLOAT, D2, false, FILTERING))
+            .unwrap();
+    }
+
+    #[test]
+    fn rejects_wrong_sample_type() {
+        let shader = texture_shader(
+            "texture_2d<u32>",
+            "vec4<f32>(textureLoad(t_input, vec2<u32>(position.xy), 0))",
+        );
+        assert_mismatch(
+            shader.check_bind_group(0, &texture_entries(FLOAT, D2, false, FILTERING)),
+            "2D uint texture in the shader but a 2D float texture",
+        );
+    }
+
+    #[test]
+    fn rejects_depth_as_float() {
+        let shader = texture_shader(
+            "texture_depth_2d",
+            "vec4<f32>(textureLoad(t_input, vec2<u32>(position.xy), 0))",
+        );
+        assert_mismatch(
+            shader.check_bind_group(0, &texture_entries(FLOAT, D2, false, FILTERING)),
+            "2D depth texture in the shader but a 2D float texture",
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_view_dimension() {
+        let shader = texture_shader(
+            "texture_2d_array<f32>",
+            "textureSample(t_input, s_input, position.xy, 0)",
+        );
+        assert_mismatch(
+            shader.check_bind_group(0, &texture_entries(FLOAT, D2, false, FILTERING)),
+            "2D array float texture in the shader but a 2D float texture",
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_multisampling() {
+        let shader = texture_shader(
+            "texture_multisampled_2d<f32>",
+            "textureLoad(t_input, vec2<u32>(position.xy), 0)",
+        );
+        assert_mismatch(
+            shader.check_bind_group(0, &texture_entries(FLOAT, D2, false, FILTERING)),
+            "multisampled 2D float texture in the shader but a 2D float texture",
+        );
+    }
+
+    #[test]
+    fn rejects_filtering_unfilterable_texture() {
+        let shader = texture_shader("texture_2d<f32>", SAMPLE);
+        let unfilterable = wgpu::TextureSampleType::Float { filterable: false };
+        assert_mismatch(
+            shader.check_bind_group(0, &texture_entries(unfilterable, D2, false, FILTERING)),
+            "samples it with the filtering sampler `s_input`",
+        );
+        shader
+            .check_bind_group(
+                0,
+                &texture_entries(
+                    unfilterable,
+                    D2,
+                    false,
+                    wgpu::SamplerBindingType::NonFiltering,
+                ),
+            )
+            .unwrap();
+    }
+
+    const VERTEX_SHADER: &str = "
+        @vertex
+        fn vertex_main(@location(0) normal: vec3<f32>) -> @builtin(position) vec4<f32> {
+            return vec4<f32>(normal, 1.0);
+        }
+    ";
+
+    fn vertex_layout(attributes: &[wgpu::VertexAttribute]) -> wgpu::VertexBufferLayout<'_> {
+        wgpu::VertexBufferLayout {
+            array_stride: 16,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes,
+        }
+    }
+
+    #[test]
+    fn checks_vertex_component_count() {
+        let shader = ShaderReflection::new("test", VERTEX_SHADER).unwrap();
+        let check = |format| {
+            shader.check_vertex_layouts(
+                "vertex_main",
+                &[vertex_layout(&[wgpu::VertexAttribute {
+                    format,
+                    offset: 0,
+                    shader_location: 0,
+                }])],
+            )
+        };
+
+        check(wgpu::VertexFormat::Float32x3).unwrap();
+        // Packed formats have no three component size, so a fourth is padding.
+        check(wgpu::VertexFormat::Snorm16x4).unwrap();
+        for format in [wgpu::VertexFormat::Float32x2, wgpu::VertexFormat::Float32x4] {
+            match check(format) {
+                Err(Error::VertexLayoutMismatch { message, .. }) => {
+                    assert!(message.contains("has 3 components"), "{}", message)
+                }
+                other => panic!("expected a vertex layout mismatch, got {:?}", other),
+            }
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! The textures a frame is drawn through at the render scale, before it is scaled down
+//! to the surface.
+
+use wgpu::util::DeviceExt;
+
+use crate::debug_view::{DebugViewBinding, DebugViewUniform};
+use crate::picking::{self, SelectionBinding, SelectionUniform};
+use crate::pipeline::Bindable;
+use crate::state::SceneLayouts;
+use crate::texture::Texture;
+use crate::transparency::{self, OitBinding};
+
+/// The G-buffer textures, as the output pass reads them.
+#[derive(Bindable)]
+#[bindable(fragment)]
+pub struct GBufferBinding {
+    #[texture]
+    diffuse: wgpu::TextureView,
+    #[sampler]
+    diffuse_sampler: wgpu::Sampler,
+    #[texture]
+    specular: wgpu::TextureView,
+    #[sampler]
+    specular_sampler: wgpu::Sampler,
+}
+
+/// The lit scene, as the final pass samples it.
+#[derive(Bindable)]
+#[bindable(fragment)]
+pub struct SceneBinding {
+    #[texture]
+    scene: wgpu::TextureView,
+    #[sampler]
+    sampler: wgpu::Sampler,
+}
+
+pub struct RenderTargets {
+    /// The G-buffer: each fragment's colour, and the light reaching it.
+    pub diffuse: Texture,
+    pub specular: Texture,
+    /// The instance and mesh drawn at each pixel, for picking.
+    pub ids: Texture,
+    /// Depth and stencil, shared by every pass drawing meshes.
+    pub depth: Texture,
+    /// The G-buffer lit, with blended materials drawn over it.
+    pub scene: Texture,
+    /// Weighted blended transparency, resolved into `scene`.
+    pub accum: Texture,
+    pub revealage: Texture,
+    pub gbuffer_bind_group: wgpu::BindGroup,
+    pub scene_bind_group: wgpu::BindGroup,
+    pub oit_bind_group: wgpu::BindGroup,
+    /// The [`DebugViewUniform`] read through `debug_view_bind_group`.
+    pub debug_view_buffer: wgpu::Buffer,
+    pub debug_view_bind_group: wgpu::BindGroup,
+    /// The [`SelectionUniform`] read through `selection_bind_group`.
+    pub selection_buffer: wgpu::Buffer,
+    pub selection_bind_group: wgpu::BindGroup,
+}
+
+impl RenderTargets {
+    pub fn new(
+        device: &wgpu::Device,
+        surface_config: &wgpu::SurfaceConfiguration,
+        render_scale: f32,
+        layouts: &SceneLayouts,
+    ) -> Self {
+        let create = |format, label| {
+            Texture::create_render_texture(device, surface_config, render_scale, format, label)
+        };
+        let diffuse = create(surface_config.format, "Deferred Diffuse Surface");
+        let specular = create(surface_config.format, "Deferred Normal Surface");
+        let ids = create(picking::ID_FORMAT, "Picking ID Surface");
+        let depth =
+            Texture::create_depth_texture(device, surface_config, render_scale, "Depth Texture");
+        let scene = create(surface_config.format, "Scene Surface");
+        let accum = create(transparency::ACCUM_FORMAT, "OIT Accumulation Surface");
+        let revealage = create(transparency::REVEALAGE_FORMAT, "OIT Revealage Surface");
+
+        let gbuffer_bind_group = layouts.output.create_bind_group(
+            &GBufferBinding {
+                diffuse: diffuse.view.clone(),
+                diffuse_sampler: diffuse.sampler.clone(),
+                specular: specular.view.clone(),
+                specular_sampler: specular.sampler.clone(),
+            },
+            device,
+            Some("G-buffer bind group"),
+        );
+        let scene_bind_group = layouts.scene.create_bind_group(
+            &SceneBinding {
+                scene: scene.view.clone(),
+                sampler: scene.sampler.clone(),
+            },
+            device,
+            Some("Scene bind group"),
+        );
+        let oit_bind_group = layouts.oit.create_bind_group(
+            &OitBinding {
+                accum: accum.view.clone(),
+                revealage: revealage.view.clone(),
+            },
+            device,
+            Some("OIT bind group"),
+        );
+
+        // Depth and stencil can only be bound one aspect at a time.
+        let aspect_view = |aspect| {
+            depth.texture.create_view(&wgpu::TextureViewDescriptor {
+                aspect,
+                ..Default::default()
+            })
+        };
+        let debug_view_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Debug view buffer"),
+            contents: bytemuck::bytes_of(&DebugViewUniform::default()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let debug_view_bind_group = layouts.debug_view.create_bind_group(
+            &DebugViewBinding {
+                diffuse: diffuse.view.clone(),
+                specular: specular.view.clone(),
+                depth: aspect_view(wgpu::TextureAspect::DepthOnly),
+                stencil: aspect_view(wgpu::TextureAspect::StencilOnly),
+                overdraw: accum.view.clone(),
+                params: debug_view_buffer.clone(),
+            },
+            device,
+            Some("Debug view bind group"),
+        );
+
+        let selection_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Selection buffer"),
+            contents: bytemuck::bytes_of(&SelectionUniform::default()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let selection_bind_group = layouts.selection.create_bind_group(
+            &SelectionBinding {
+                stencil: aspect_view(wgpu::TextureAspect::StencilOnly),
+                params: selection_buffer.clone(),
+            },
+            device,
+            Some("Selection bind group"),
+        );
+
+        Self {
+            diffuse,
+            specular,
+            ids,
+            depth,
+            scene,
+            accum,
+            revealage,
+            gbuffer_bind_group,
+            scene_bind_group,
+            oit_bind_group,
+            debug_view_buffer,
+            debug_view_bind_group,
+            selection_buffer,
+            selection_bind_group,
+        }
+    }
+}
@@ -0,0 +1,499 @@
+//! Scenes saved as text, to be edited by hand and loaded back.
+//!
+//! Each line is a key followed by its values, separated by spaces. A `#` at the start of
+//! a line or after a space starts a comment. The model's path is quoted, with `\`
+//! escaping a `"` or `\` in it, so it can hold spaces and `#`s. Every key but `light` and
+//! `instance` appears once. Floats are written with as many digits as they need to read
+//! back exactly, so a loaded scene renders the same as the one saved.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+use cgmath::{Quaternion, Vector3};
+
+use crate::camera::Camera;
+use crate::debug_view::DebugView;
+use crate::error::{Error, Result};
+use crate::file_reader::FileReader;
+use crate::instance::Instance;
+use crate::light::Light;
+use crate::mesh_processing::{NormalMode, UvProjection};
+use crate::model::{MeshOptions, TangentMethod, VertexLayout};
+use crate::transparency::TransparencyMode;
+
+/// Where the scene is saved and loaded from, relative to the asset directory.
+pub const SCENE_FILE: &str = "scene.txt";
+
+/// Everything needed to draw a scene again.
+#[derive(Debug, Clone)]
+pub struct SceneFile {
+    /// The model drawn at every instance, and how its meshes are processed.
+    pub model: String,
+    pub mesh_options: MeshOptions,
+    /// The camera's `aspect` follows the window, so isn't saved.
+    pub camera: Camera,
+    pub camera_speed: f32,
+    pub background: wgpu::Color,
+    pub lights: Vec<Light>,
+    pub orbit_light: bool,
+    pub render_scale: f32,
+    pub transparency: TransparencyMode,
+    pub debug_view: DebugView,
+    pub gpu_driven: bool,
+    pub bindless: bool,
+    pub picking: bool,
+    pub instance_spacing: f32,
+    pub instances: Vec<Instance>,
+}
+
+impl SceneFile {
+    pub async fn load(path: &str) -> Result<Self> {
+        Self::parse(path, &FileReader::read_string(path).await?)
+    }
+
+    /// Reads a scene written by [`SceneFile::to_text`]. `path` is only used in errors.
+    pub fn parse(path: &str, text: &str) -> Result<Self> {
+        let mut keys: HashMap<&str, Fields> = HashMap::new();
+        let mut lights = Vec::new();
+        let mut instances = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let fields = Fields {
+                path,
+                line: index + 1,
+                // The model's path is the rest of the line, quoted or not.
+                values: if key == "model" {
+                    vec![rest.trim()]
+                } else {
+                    rest.split_whitespace().collect()
+                },
+            };
+            match key {
+                "light" => {
+                    let [x, y, z, r, g, b] = fields.parse()?;
+                    lights.push(Light {
+                        position: [x, y, z],
+                        _padding: 0.0,
+                        colour: [r, g, b],
+                        _padding2: 0.0,
+                    });
+                }
+                "instance" => {
+                    let [px, py, pz, rx, ry, rz, rw, sx, sy, sz] = fields.parse()?;
+                    instances.push(Instance {
+                        position: Vector3::new(px, py, pz),
+                        rotation: Quaternion::new(rw, rx, ry, rz),
+                        scale: Vector3::new(sx, sy, sz),
+                    });
+                }
+                _ => {
+                    if keys.contains_key(key) {
+                        return Err(fields.error(format!("`{}` is given more than once", key)));
+                    }
+                    keys.insert(key, fields);
+                }
+            }
+        }
+
+        let last_line = text.lines().count();
+        let mut take = |key: &str| {
+            keys.remove(key).ok_or_else(|| Error::SceneParse {
+                path: path.to_owned(),
+                line: last_line,
+                message: format!("`{}` is missing", key),
+            })
+        };
+        let model = take("model")?;
+        let model_path = unquote(model.values[0])
+            .ok_or_else(|| model.error("`model` has an unterminated quoted path".to_owned()))?;
+        if model_path.is_empty() {
+            return Err(model.error("`model` needs a path".to_owned()));
+        }
+        let [r, g, b, a] = take("background")?.parse()?;
+        let scene = Self {
+            model: model_path,
+            mesh_options: MeshOptions {
+                tangents: take("tangents")?
+                    .named(&[TangentMethod::Compute, TangentMethod::MikkTSpace])?,
+                vertex_layout: take("vertex_layout")?
+                    .named(&[VertexLayout::Full, VertexLayout::Quantized])?,
+                normals: take("normals")?.named(&[NormalMode::Smooth, NormalMode::Flat])?,
+                uvs: take("uvs")?.named(&[UvProjection::Planar, UvProjection::Box])?,
+            },
+            camera: Camera {
+                eye: take("camera_eye")?.parse::<f32, 3>()?.into(),
+                target: take("camera_target")?.parse::<f32, 3>()?.into(),
+                up: take("camera_up")?.parse::<f32, 3>()?.into(),
+                aspect: 1.0,
+                fov_y: take("camera_fov_y")?.one()?,
+                z_near: take("camera_z_near")?.one()?,
+                z_far: take("camera_z_far")?.one()?,
+            },
+            camera_speed: take("camera_speed")?.one()?,
+            background: wgpu::Color { r, g, b, a },
+            lights,
+            orbit_light: take("orbit_light")?.one()?,
+            render_scale: take("render_scale")?.one()?,
+            transparency: take("transparency")?
+                .named(&[TransparencyMode::Sorted, TransparencyMode::WeightedBlended])?,
+            debug_view: take("debug_view")?.named(&DebugView::ALL)?,
+            gpu_driven: take("gpu_driven")?.one()?,
+            bindless: take("bindless")?.one()?,
+            picking: take("picking")?.one()?,
+            instance_spacing: take("instance_spacing")?.one()?,
+            instances,
+        };
+
+        match keys.into_iter().min_by_key(|(_, fields)| fields.line) {
+            Some((key, fields)) => Err(fields.error(format!("unknown key `{}`", key))),
+            None => Ok(scene),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        self.write(&mut text).unwrap();
+        text
+    }
+
+    fn write(&self, out: &mut String) -> fmt::Result {
+        let camera = &self.camera;
+        let background = self.background;
+        writeln!(out, "# wgpu-testbed scene")?;
+        writeln!(out, "model {}", quote(&self.model))?;
+        writeln!(out, "tangents {:?}", self.mesh_options.tangents)?;
+        writeln!(out, "vertex_layout {:?}", self.mesh_options.vertex_layout)?;
+        writeln!(out, "normals {:?}", self.mesh_options.normals)?;
+        writeln!(out, "uvs {:?}", self.mesh_options.uvs)?;
+        writeln!(out)?;
+        writeln!(out, "camera_eye {}", Floats(&xyz(camera.eye)))?;
+        writeln!(out, "camera_target {}", Floats(&xyz(camera.target)))?;
+        writeln!(out, "camera_up {}", Floats(&xyz(camera.up)))?;
+        writeln!(out, "camera_fov_y {:?}", camera.fov_y)?;
+        writeln!(out, "camera_z_near {:?}", camera.z_near)?;
+        writeln!(out, "camera_z_far {:?}", camera.z_far)?;
+        writeln!(out, "camera_speed {:?}", self.camera_speed)?;
+        writeln!(out)?;
+        writeln!(
+            out,
+            "background {}",
+            Floats(&[background.r, background.g, background.b, background.a])
+        )?;
+        writeln!(out, "# position, colour")?;
+        for light in &self.lights {
+            writeln!(
+                out,
+                "light {} {}",
+                Floats(&light.position),
+                Floats(&light.colour)
+            )?;
+        }
+        writeln!(out, "orbit_light {}", self.orbit_light)?;
+        writeln!(out)?;
+        writeln!(out, "render_scale {:?}", self.render_scale)?;
+        writeln!(out, "transparency {:?}", self.transparency)?;
+        writeln!(out, "debug_view {:?}", self.debug_view)?;
+        writeln!(out, "gpu_driven {}", self.gpu_driven)?;
+        writeln!(out, "bindless {}", self.bindless)?;
+        writeln!(out, "picking {}", self.picking)?;
+        writeln!(out)?;
+        writeln!(out, "instance_spacing {:?}", self.instance_spacing)?;
+        writeln!(out, "# position, rotation as x y z w, scale")?;
+        for instance in &self.instances {
+            let rotation = instance.rotation;
+            writeln!(
+                out,
+                "instance {} {} {}",
+                Floats(&xyz(instance.position)),
+                Floats(&[rotation.v.x, rotation.v.y, rotation.v.z, rotation.s]),
+                Floats(&xyz(instance.scale)),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The values after a key, and where they came from for errors.
+struct Fields<'a> {
+    path: &'a str,
+    line: usize,
+    values: Vec<&'a str>,
+}
+
+impl Fields<'_> {
+    fn error(&self, message: String) -> Error {
+        Error::SceneParse {
+            path: self.path.to_owned(),
+            line: self.line,
+            message,
+        }
+    }
+
+    /// Exactly `N` values of type `T`.
+    fn parse<T: FromStr, const N: usize>(&self) -> Result<[T; N]> {
+        let values = self
+            .values
+            .iter()
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| self.error(format!("`{}` is not a valid value here", value)))
+            })
+            .collect::<Result<Vec<T>>>()?;
+        values.try_into().map_err(|values: Vec<T>| {
+            self.error(format!("expected {} values, found {}", N, values.len()))
+        })
+    }
+
+    fn one<T: FromStr>(&self) -> Result<T> {
+        let [value] = self.parse()?;
+        Ok(value)
+    }
+
+    /// One of `options`, by the name it is written with.
+    fn named<T: fmt::Debug + Copy>(&self, options: &[T]) -> Result<T> {
+        let [name] = self.parse::<String, 1>()?;
+        options
+            .iter()
+            .copied()
+            .find(|option| format!("{:?}", option) == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = options
+                    .iter()
+                    .map(|option| format!("{:?}", option))
+                    .collect();
+                self.error(format!(
+                    "expected one of {}, found `{}`",
+                    names.join(", "),
+                    name
+                ))
+            })
+    }
+}
+
+fn xyz(vector: impl Into<[f32; 3]>) -> [f32; 3] {
+    vector.into()
+}
+
+/// Values separated by spaces, each written to read back exactly.
+struct Floats<'a, T>(&'a [T]);
+
+impl<T: fmt::Debug> fmt::Display for Floats<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, value) in self.0.iter().enumerate() {
+            if index > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{:?}", value)?;
+        }
+        Ok(())
+    }
+}
+
+/// `path` in quotes, with `\` before any `"` or `\` in it.
+fn quote(path: &str) -> String {
+    let mut quoted = String::with_capacity(path.len() + 2);
+    quoted.push('"');
+    for c in path.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Reads a path written by [`quote`], or takes `value` as it is if it isn't quoted.
+/// Returns `None` if the quotes aren't closed at the end of `value`.
+fn unquote(value: &str) -> Option<String> {
+    let Some(quoted) = value.strip_prefix('"') else {
+        return Some(value.to_owned());
+    };
+    let mut path = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => path.push(chars.next()?),
+            '"' => return chars.as_str().is_empty().then_some(path),
+            c => path.push(c),
+        }
+    }
+    None
+}
+
+/// Cuts `line` at the first `#` outside quotes that starts it or follows whitespace.
+fn strip_comment(line: &str) -> &str {
+    let mut previous = ' ';
+    let mut quoted = false;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        if quoted {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                quoted = false;
+            }
+        } else if c == '"' {
+            quoted = true;
+        } else if c == '#' && previous.is_whitespace() {
+            return &line[..index];
+        }
+        previous = c;
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, InnerSpace, Rotation3};
+
+    use super::*;
+    use crate::instance::InstanceRaw;
+
+    fn instance_buffer(instances: &[Instance]) -> Vec<u8> {
+        let data: Vec<InstanceRaw> = instances.iter().map(Instance::to_raw).collect();
+        bytemuck::cast_slice(&data).to_vec()
+    }
+
+    fn scene() -> SceneFile {
+        let instances = (0..20)
+            .map(|i| {
+                let i = i as f32;
+                Instance {
+                    position: Vector3::new(i * 0.1, -i / 3.0, 1.0e-7 * i),
+                    rotation: Quaternion::from_axis_angle(
+                        Vector3::new(1.0, i, 0.3).normalize(),
+                        Deg(i * 17.3),
+                    ),
+                    scale: Vector3::new(1.0, 0.5 + i / 7.0, 2.0 / 3.0),
+                }
+            })
+            .collect();
+        SceneFile {
+            model: r#"resources/my model/model #2 "final" \.obj"#.to_owned(),
+            mesh_options: MeshOptions {
+                tangents: TangentMethod::MikkTSpace,
+                vertex_layout: VertexLayout::Quantized,
+                normals: NormalMode::Flat,
+                uvs: UvProjection::Planar,
+            },
+            camera: Camera {
+                eye: (0.0, 5.0, -10.0).into(),
+                target: (0.1, 0.2, 0.3).into(),
+                up: Vector3::unit_y(),
+                aspect: 1.0,
+                fov_y: 45.0,
+                z_near: 0.1,
+                z_far: 100.0,
+            },
+            camera_speed: 0.2,
+            background: wgpu::Color {
+                r: 0.1,
+                g: 0.2,
+                b: 1.0 / 3.0,
+                a: 1.0,
+            },
+            lights: vec![Light {
+                position: [2.0, 2.0, 2.0],
+                _padding: 0.0,
+                colour: [1.0, 0.9, 0.8],
+                _padding2: 0.0,
+            }],
+            orbit_light: false,
+            render_scale: 1.5,
+            transparency: TransparencyMode::WeightedBlended,
+            debug_view: DebugView::ALL[3],
+            gpu_driven: false,
+            bindless: true,
+            picking: true,
+            instance_spacing: 2.5,
+            instances,
+        }
+    }
+
+    #[test]
+    fn round_trip_keeps_instance_buffer() {
+        let scene = scene();
+
+        let text = scene.to_text();
+        let loaded = SceneFile::parse(SCENE_FILE, &text).unwrap();
+
+        assert_eq!(
+            instance_buffer(&loaded.instances),
+            instance_buffer(&scene.instances)
+        );
+        assert_eq!(loaded.model, scene.model);
+        assert_eq!(loaded.mesh_options, scene.mesh_options);
+        assert_eq!(loaded.lights[0].colour, scene.lights[0].colour);
+        assert_eq!(loaded.background, scene.background);
+        assert_eq!(loaded.debug_view, scene.debug_view);
+        assert_eq!(loaded.to_text(), text);
+    }
+
+    #[test]
+    fn reports_the_line_of_a_bad_value() {
+        let text = scene().to_text();
+        let mut lines: Vec<&str> = text.lines().collect();
+        let index = lines
+            .iter()
+            .position(|line| line.starts_with("render_scale "))
+            .unwrap();
+        lines[index] = "render_scale big";
+
+        match SceneFile::parse(SCENE_FILE, &lines.join("\n")) {
+            Err(Error::SceneParse { line, message, .. }) => {
+                assert_eq!(line, index + 1);
+                assert_eq!(message, "`big` is not a valid value here");
+            }
+            other => panic!("expected a parse error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn comments_follow_whitespace() {
+        assert_eq!(strip_comment("# a comment"), "");
+        assert_eq!(strip_comment("render_scale 2 # sharper"), "render_scale 2 ");
+        assert_eq!(strip_comment("model cube#2.obj"), "model cube#2.obj");
+        assert_eq!(
+            strip_comment(r#"model "a #2 \" #3" # path"#),
+            r#"model "a #2 \" #3" "#
+        );
+    }
+
+    #[test]
+    fn reads_quoted_and_bare_model_paths() {
+        let text = scene().to_text();
+        let with_model = |model: &str| {
+            text.lines()
+                .map(|line| {
+                    if line.starts_with("model ") {
+                        format!("model {}", model)
+                    } else {
+                        line.to_owned()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+
+        let bare = SceneFile::parse(SCENE_FILE, &with_model("my model/cube#2.obj # bare"));
+        assert_eq!(bare.unwrap().model, "my model/cube#2.obj");
+        let quoted = SceneFile::parse(SCENE_FILE, &with_model(r#""a \"b\" #c""#));
+        assert_eq!(quoted.unwrap().model, r#"a "b" #c"#);
+        match SceneFile::parse(SCENE_FILE, &with_model(r#""open #2.obj"#)) {
+            Err(Error::SceneParse { message, .. }) => {
+                assert_eq!(message, "`model` has an unterminated quoted path");
+            }
+            other => panic!("expected a parse error, got {:?}", other.map(|_| ())),
+        }
+    }
+}
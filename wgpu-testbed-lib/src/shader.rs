@@ -0,0 +1,624 @@
+//! WGSL preprocessing, and a cache of compiled shader modules per permutation.
+//!
+//! Shaders can use a small set of line directives before they reach naga:
+//!
+//! - `#include "path"` pastes in another file, resolved relative to the including one.
+//!   Each file is included at most once per shader, so shared structs can be included
+//!   from several places. Files are only read once an include is reached, so an include
+//!   in code that is compiled out doesn't need to exist.
+//! - `#define NAME` and `#define NAME value` set a flag or a value, and `#undef NAME`
+//!   clears it. Defined values replace whole identifiers in the code that follows.
+//! - `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif` compile code in or out.
+//!
+//! Defines can also be passed in with [`ShaderDefines`], and each distinct set compiles
+//! to its own module, so features can be toggled without copies of the shader. Errors
+//! from naga name the file and line that the offending line was expanded from.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use crate::asset_source::{AssetSource, normalize_path};
+use crate::error::{Error, Result};
+use crate::file_reader::FileReader;
+use crate::pipeline;
+use crate::reflection::ShaderReflection;
+
+/// The defines a shader is compiled with. Flags are defines with an empty value.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ShaderDefines(BTreeMap<String, String>);
+
+impl ShaderDefines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(self, name: &str) -> Self {
+        self.with_value(name, "")
+    }
+
+    pub fn with_value(mut self, name: &str, value: &str) -> Self {
+        self.set(name, value);
+        self
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.0.insert(name.to_owned(), value.to_owned());
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.0.remove(name);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    /// A readable form of the permutation, e.g. `BINDLESS, MAX_LIGHTS=4`.
+    pub fn key(&self) -> String {
+        self.0
+            .iter()
+            .map(|(name, value)| {
+                if value.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}={}", name, value)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// A compiled shader permutation, with its reflection for checking layouts against.
+pub struct Shader {
+    pub label: String,
+    pub module: wgpu::ShaderModule,
+    pub reflection: ShaderReflection,
+}
+
+#[derive(Default)]
+struct Cache {
+    sources: HashMap<String, String>,
+    shaders: HashMap<(String, ShaderDefines), Arc<Shader>>,
+}
+
+/// Shader sources and compiled permutations, shared between everything that builds
+/// pipelines on one device.
+#[derive(Clone, Default)]
+pub struct ShaderCache {
+    cache: Arc<Mutex<Cache>>,
+    /// Where shader files are read from, instead of through the [`FileReader`] mounts.
+    source: Option<Arc<dyn AssetSource>>,
+}
+
+impl ShaderCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A cache that reads shaders and their includes from `source` alone.
+    pub fn with_source(source: Arc<dyn AssetSource>) -> Self {
+        Self {
+            source: Some(source),
+            ..Self::default()
+        }
+    }
+
+    /// Compiles `path` with `defines`, or returns the module compiled for them before.
+    pub async fn load(
+        &self,
+        device: &wgpu::Device,
+        path: &str,
+        defines: &ShaderDefines,
+    ) -> Result<Arc<Shader>> {
+        let path = normalize_path(path);
+        let key = (path.clone(), defines.clone());
+        if let Some(shader) = self.cache.lock().unwrap().shaders.get(&key) {
+            return Ok(shader.clone());
+        }
+
+        let preprocessed = self.preprocess(&path, defines).await?;
+        let source = preprocessed.source.as_str();
+        let label = if defines.key().is_empty() {
+            path.clone()
+        } else {
+            format!("{} [{}]", path, defines.key())
+        };
+        let reflection = ShaderReflection::with_line_origins(&label, source, |line| {
+            let (path, line) = preprocessed.origin(line)?;
+            Some(format!("{}:{}", path, line))
+        })?;
+        let module = pipeline::validated(device, Some(&label), || {
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(&label),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            })
+        })
+        .await?;
+
+        let shader = Arc::new(Shader {
+            label,
+            module,
+            reflection,
+        });
+        self.cache
+            .lock()
+            .unwrap()
+            .shaders
+            .insert(key, shader.clone());
+        Ok(shader)
+    }
+
+    /// Preprocesses `path` with `defines`, reading it and the includes that are compiled
+    /// in as they are reached.
+    pub async fn preprocess(
+        &self,
+        path: &str,
+        defines: &ShaderDefines,
+    ) -> Result<PreprocessedShader> {
+        let path = normalize_path(path);
+        loop {
+            let expansion = {
+                let cache = self.cache.lock().unwrap();
+                try_preprocess(&path, &cache.sources, defines)?
+            };
+            match expansion {
+                Expansion::Done(shader) => return Ok(shader),
+                Expansion::Unread(path) => {
+                    let source = self.read(&path).await?;
+                    self.cache.lock().unwrap().sources.insert(path, source);
+                }
+            }
+        }
+    }
+
+    async fn read(&self, path: &str) -> Result<String> {
+        let Some(source) = &self.source else {
+            return FileReader::read_string(path).await;
+        };
+        let bytes = source.read(path).await?.ok_or_else(|| Error::Io {
+            path: path.to_owned(),
+            source: std::io::ErrorKind::NotFound.into(),
+        })?;
+        String::from_utf8(bytes).map_err(|e| Error::InvalidUtf8 {
+            path: path.to_owned(),
+            source: e.utf8_error(),
+        })
+    }
+}
+
+enum Directive<'a> {
+    Include(&'a str),
+    Define(&'a str, &'a str),
+    Undef(&'a str),
+    IfDef(&'a str),
+    IfNDef(&'a str),
+    Else,
+    EndIf,
+}
+
+fn parse_directive<'a>(
+    path: &str,
+    line_number: usize,
+    line: &'a str,
+) -> Result<Option<Directive<'a>>> {
+    let error = |message: String| Error::ShaderPreprocess {
+        path: path.to_owned(),
+        line: line_number + 1,
+        message,
+    };
+    let Some(directive) = line.trim().strip_prefix('#') else {
+        return Ok(None);
+    };
+    let (name, rest) = directive
+        .split_once(char::is_whitespace)
+        .map_or((directive, ""), |(name, rest)| (name, rest.trim()));
+    let identifier = || {
+        if is_identifier(rest) {
+            Ok(rest)
+        } else {
+            Err(error(format!("`#{}` needs a name", name)))
+        }
+    };
+
+    Ok(Some(match name {
+        "include" => Directive::Include(
+            rest.strip_prefix('"')
+                .and_then(|rest| rest.strip_suffix('"'))
+                .ok_or_else(|| error("`#include` needs a quoted path".to_owned()))?,
+        ),
+        "define" => {
+            let (define, value) = rest
+                .split_once(char::is_whitespace)
+                .map_or((rest, ""), |(define, value)| (define, value.trim()));
+            if !is_identifier(define) {
+                return Err(error("`#define` needs a name".to_owned()));
+            }
+            Directive::Define(define, value)
+        }
+        "undef" => Directive::Undef(identifier()?),
+        "ifdef" => Directive::IfDef(identifier()?),
+        "ifndef" => Directive::IfNDef(identifier()?),
+        "else" => Directive::Else,
+        "endif" => Directive::EndIf,
+        _ => return Err(error(format!("unknown directive `#{}`", name))),
+    }))
+}
+
+fn resolve_include(path: &str, include: &str) -> String {
+    let directory = path.rsplit_once('/').map_or("", |(directory, _)| directory);
+    normalize_path(&format!("{}/{}", directory, include))
+}
+
+/// WGSL with its directives expanded, and where each of its lines came from.
+pub struct PreprocessedShader {
+    pub source: String,
+    origins: Vec<(Arc<str>, usize)>,
+}
+
+impl PreprocessedShader {
+    /// The file and line number that `line` of the source was expanded from, all
+    /// counted from 1.
+    pub fn origin(&self, line: usize) -> Option<(&str, usize)> {
+        let (path, line) = self.origins.get(line.checked_sub(1)?)?;
+        Some((path, *line))
+    }
+
+    fn end_line(&mut self, path: &Arc<str>, line_number: usize) {
+        self.source.push('\n');
+        self.origins.push((path.clone(), line_number + 1));
+    }
+}
+
+/// Expands the directives in `path`, reading it and its includes from `sources`.
+pub fn preprocess(
+    path: &str,
+    sources: &HashMap<String, String>,
+    defines: &ShaderDefines,
+) -> Result<PreprocessedShader> {
+    match try_preprocess(path, sources, defines)? {
+        Expansion::Done(shader) => Ok(shader),
+        Expansion::Unread(path) => Err(Error::Io {
+            path,
+            source: std::io::ErrorKind::NotFound.into(),
+        }),
+    }
+}
+
+enum Expansion {
+    Done(PreprocessedShader),
+    /// A file that is compiled in isn't in the sources yet.
+    Unread(String),
+}
+
+fn try_preprocess(
+    path: &str,
+    sources: &HashMap<String, String>,
+    defines: &ShaderDefines,
+) -> Result<Expansion> {
+    let mut defines = defines.0.clone();
+    let mut included = HashSet::new();
+    let mut output = PreprocessedShader {
+        source: String::new(),
+        origins: Vec::new(),
+    };
+    Ok(
+        match expand(path, sources, &mut defines, &mut included, &mut output)? {
+            Some(unread) => Expansion::Unread(unread),
+            None => Expansion::Done(output),
+        },
+    )
+}
+
+struct Condition {
+    /// Whether the enclosing block is being compiled.
+    parent_active: bool,
+    /// Whether this branch of the block is.
+    active: bool,
+    seen_else: bool,
+}
+
+/// Expands `path` into `output`, stopping at the first file that hasn't been read and
+/// returning its path.
+fn expand(
+    path: &str,
+    sources: &HashMap<String, String>,
+    defines: &mut BTreeMap<String, String>,
+    included: &mut HashSet<String>,
+    output: &mut PreprocessedShader,
+) -> Result<Option<String>> {
+    let Some(source) = sources.get(path) else {
+        return Ok(Some(path.to_owned()));
+    };
+    if !included.insert(path.to_owned()) {
+        return Ok(None);
+    }
+    let file: Arc<str> = path.into();
+
+    let mut conditions: Vec<Condition> = Vec::new();
+    let mut last_line = 0;
+    for (line_number, line) in source.lines().enumerate() {
+        last_line = line_number;
+        let error = |message: &str| Error::ShaderPreprocess {
+            path: path.to_owned(),
+            line: line_number + 1,
+            message: message.to_owned(),
+        };
+        let active = conditions.last().is_none_or(|condition| condition.active);
+
+        let directive = match parse_directive(path, line_number, line)? {
+            Some(directive) => directive,
+            None => {
+                if active {
+                    substitute(line, defines, &mut output.source);
+                }
+                output.end_line(&file, line_number);
+                continue;
+            }
+        };
+        match directive {
+            Directive::IfDef(name) | Directive::IfNDef(name) => {
+                let defined = defines.contains_key(name);
+                let wanted = matches!(directive, Directive::IfDef(_));
+                conditions.push(Condition {
+                    parent_active: active,
+                    active: active && defined == wanted,
+                    seen_else: false,
+                });
+            }
+            Directive::Else => {
+                let condition = conditions
+                    .last_mut()
+                    .ok_or_else(|| error("`#else` without `#ifdef`"))?;
+                if condition.seen_else {
+                    return Err(error("second `#else` in one block"));
+                }
+                condition.seen_else = true;
+                condition.active = condition.parent_active && !condition.active;
+            }
+            Directive::EndIf => {
+                conditions
+                    .pop()
+                    .ok_or_else(|| error("`#endif` without `#ifdef`"))?;
+            }
+            _ if !active => {}
+            Directive::Include(include) => {
+                let include = resolve_include(path, include);
+                if let Some(unread) = expand(&include, sources, defines, included, output)? {
+                    return Ok(Some(unread));
+                }
+            }
+            Directive::Define(name, value) => {
+                defines.insert(name.to_owned(), value.to_owned());
+            }
+            Directive::Undef(name) => {
+                defines.remove(name);
+            }
+        }
+        // Directives leave a blank line, after the included file for an `#include`, so
+        // every line of the output has one line of a file it came from.
+        output.end_line(&file, line_number);
+    }
+
+    if !conditions.is_empty() {
+        return Err(Error::ShaderPreprocess {
+            path: path.to_owned(),
+            line: last_line + 1,
+            message: "`#ifdef` without `#endif`".to_owned(),
+        });
+    }
+    Ok(None)
+}
+
+/// Appends `line` to `output`, replacing identifiers that have a defined value.
+fn substitute(line: &str, defines: &BTreeMap<String, String>, output: &mut String) {
+    let mut rest = line;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        let (before, from_start) = rest.split_at(start);
+        output.push_str(before);
+        let end = from_start
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(from_start.len());
+        let (word, after) = from_start.split_at(end);
+        match defines.get(word) {
+            Some(value) if !value.is_empty() => output.push_str(value),
+            _ => output.push_str(word),
+        }
+        rest = after;
+    }
+    output.push_str(rest);
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset_source::MemorySource;
+
+    fn run(files: &[(&str, &str)], defines: &ShaderDefines) -> Result<PreprocessedShader> {
+        let sources = files
+            .iter()
+            .map(|(path, source)| (path.to_string(), source.to_string()))
+            .collect();
+        preprocess("main.wgsl", &sources, defines)
+    }
+
+    /// The lines of `main.wgsl` that are compiled in, without the blank ones.
+    fn code(source: &str, defines: &ShaderDefines) -> Vec<String> {
+        let shader = run(&[("main.wgsl", source)], defines).unwrap();
+        shader
+            .source
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_owned)
+            .collect()
+    }
+
+    fn assert_preprocess_error(source: &str, line: usize, message: &str) {
+        match run(&[("main.wgsl", source)], &ShaderDefines::new()) {
+            Err(Error::ShaderPreprocess {
+                path,
+                line: error_line,
+                message: error_message,
+            }) => {
+                assert_eq!(path, "main.wgsl");
+                assert_eq!(error_line, line);
+                assert_eq!(error_message, message);
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("preprocessed {:?}", source),
+        }
+    }
+
+    #[test]
+    fn nested_ifdefs_follow_their_branches() {
+        let source = "\
+            #ifdef A\n\
+            a\n\
+            #ifdef B\n\
+            a_b\n\
+            #else\n\
+            a_not_b\n\
+            #endif\n\
+            #else\n\
+            not_a\n\
+            #ifndef B\n\
+            not_a_not_b\n\
+            #endif\n\
+            #endif\n";
+        let defines = ShaderDefines::new();
+        assert_eq!(code(source, &defines), ["not_a", "not_a_not_b"]);
+        assert_eq!(code(source, &defines.clone().with("B")), ["not_a"]);
+        assert_eq!(code(source, &defines.clone().with("A")), ["a", "a_not_b"]);
+        assert_eq!(code(source, &defines.with("A").with("B")), ["a", "a_b"]);
+    }
+
+    #[test]
+    fn undef_clears_defines() {
+        let source =
+            "#define A\n#undef A\n#undef B\n#ifdef A\na\n#endif\n#ifndef B\nno_b\n#endif\n";
+        assert_eq!(code(source, &ShaderDefines::new().with("B")), ["no_b"]);
+    }
+
+    #[test]
+    fn includes_each_file_once() {
+        let files = [
+            (
+                "main.wgsl",
+                "#include \"common/a.wgsl\"\n#include \"common/b.wgsl\"\nmain\n",
+            ),
+            ("common/a.wgsl", "struct A\n"),
+            ("common/b.wgsl", "#include \"a.wgsl\"\nstruct B\n"),
+        ];
+        let shader = run(&files, &ShaderDefines::new()).unwrap();
+        let lines: Vec<_> = shader.source.lines().filter(|l| !l.is_empty()).collect();
+        assert_eq!(lines, ["struct A", "struct B", "main"]);
+    }
+
+    #[test]
+    fn substitutes_defined_values() {
+        let source = "\
+            #define COUNT 4\n\
+            #define FLAG\n\
+            array<f32, COUNT> COUNTER FLAG SCALE*x_SCALE\n";
+        let defines = ShaderDefines::new().with_value("SCALE", "2.0");
+        assert_eq!(
+            code(source, &defines),
+            ["array<f32, 4> COUNTER FLAG 2.0*x_SCALE"]
+        );
+    }
+
+    #[test]
+    fn rejects_unmatched_else_and_endif() {
+        assert_preprocess_error("a\n#else\n", 2, "`#else` without `#ifdef`");
+        assert_preprocess_error("#endif\n", 1, "`#endif` without `#ifdef`");
+    }
+
+    #[test]
+    fn rejects_unterminated_ifdef() {
+        assert_preprocess_error("#ifdef A\na\nb\n", 3, "`#ifdef` without `#endif`");
+    }
+
+    #[test]
+    fn rejects_second_else() {
+        assert_preprocess_error(
+            "#ifdef A\n#else\n#else\n#endif\n",
+            3,
+            "second `#else` in one block",
+        );
+    }
+
+    #[test]
+    fn includes_compiled_out_are_not_read() {
+        let mut source = MemorySource::new();
+        source.insert(
+            "shaders/main.wgsl",
+            b"#ifdef EXTRA\n#include \"missing.wgsl\"\n#endif\nmain\n".to_vec(),
+        );
+        let cache = ShaderCache::with_source(Arc::new(source));
+
+        let shader = futures::executor::block_on(
+            cache.preprocess("shaders/main.wgsl", &ShaderDefines::new()),
+        )
+        .unwrap();
+        assert!(shader.source.contains("main"));
+
+        let defines = ShaderDefines::new().with("EXTRA");
+        match futures::executor::block_on(cache.preprocess("shaders/main.wgsl", &defines)) {
+            Err(Error::Io { path, .. }) => assert_eq!(path, "shaders/missing.wgsl"),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("preprocessed a missing include"),
+        }
+    }
+
+    #[test]
+    fn maps_lines_back_to_their_files() {
+        let files = [
+            ("main.wgsl", "a\n#include \"b.wgsl\"\nc\n"),
+            ("b.wgsl", "b1\nb2\n"),
+        ];
+        let shader = run(&files, &ShaderDefines::new()).unwrap();
+        let origins: Vec<_> = (1..=5).map(|line| shader.origin(line).unwrap()).collect();
+        assert_eq!(
+            origins,
+            [
+                ("main.wgsl", 1),
+                ("b.wgsl", 1),
+                ("b.wgsl", 2),
+                ("main.wgsl", 2),
+                ("main.wgsl", 3),
+            ]
+        );
+        assert_eq!(shader.origin(0), None);
+        assert_eq!(shader.origin(6), None);
+    }
+
+    #[test]
+    fn validation_errors_name_the_included_line() {
+        let files = [
+            (
+                "main.wgsl",
+                "#include \"types.wgsl\"\nfn f() -> Missing {}\n",
+            ),
+            ("types.wgsl", "struct A {\n    x: f32,\n}\n"),
+        ];
+        let shader = run(&files, &ShaderDefines::new()).unwrap();
+        let result = ShaderReflection::with_line_origins("test", &shader.source, |line| {
+            let (path, line) = shader.origin(line)?;
+            Some(format!("{}:{}", path, line))
+        });
+        match result {
+            Err(Error::ShaderValidation { message, .. }) => {
+                assert!(message.ends_with("line 5 is main.wgsl:2"), "{}", message);
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("validated an unknown type"),
+        }
+    }
+}
@@ -1,28 +1,56 @@
-use std::collections::HashMap;
-use std::iter::FromIterator;
+use std::future::Future;
 use std::sync::Arc;
 #[cfg(not(target_arch = "wasm32"))]
 use std::sync::mpsc;
 
+use crate::asset_loader::{self, AssetLoader, LoadEvent};
+use crate::baked::{self, BAKED_ARCHIVE};
+use crate::bindless::{self, BindlessLayout, BindlessMaterials};
 use crate::camera::CameraController;
+use crate::debug_ui::{self, DebugUi, DrawUi, UiTextureBinding};
+use crate::debug_view::{
+    self, DebugMeshBinding, DebugMeshes, DebugView, DebugViewBinding, DebugViewUniform,
+    DrawDebugMeshes,
+};
+use crate::error::{Error, Result};
 use crate::file_reader::FileReader;
+use crate::gizmo::{Edit, Gizmo, GizmoMode, GizmoView, History};
 use crate::instance::InstanceRaw;
-use crate::pipeline::{self, create_render_pipeline};
+use crate::picking::{
+    self, Pick, PickBinding, PickCallback, Picker, SelectionBinding, SelectionUniform,
+};
+use crate::pipeline::{
+    Bindable, Binder, EntryPoints, RenderPipelineCache, RenderPipelineDesc, UniformBinding,
+};
+use crate::pipeline_cache::PersistentPipelineCache;
+use crate::profiler::{EncoderProfile, GpuProfiler};
+use crate::render_targets::{GBufferBinding, RenderTargets, SceneBinding};
+use crate::scene_file::{SCENE_FILE, SceneFile};
+use crate::shader::{Shader, ShaderCache, ShaderDefines};
+use crate::stats::{DrawStats, FrameInfo, StatsOverlay};
+use crate::text::{DrawText, TextBinding, TextInstance, TextRenderer};
+use crate::transparency::{self, OitBinding, TransparencyMode};
 use crate::uniform::Uniforms;
 use crate::{instance::Instance, light::Light};
 use cgmath::*;
+use futures::channel::oneshot;
 
 use log::info;
 use wgpu::util::DeviceExt;
 use wgpu::{
-    ExperimentalFeatures, InstanceDescriptor, PowerPreference, SamplerBindingType,
-    TexelCopyBufferInfo, TexelCopyTextureInfo,
+    ExperimentalFeatures, InstanceDescriptor, PowerPreference, TexelCopyBufferInfo,
+    TexelCopyTextureInfo,
 };
-use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::event::{ElementState, MouseButton};
+use winit::keyboard::{KeyCode, ModifiersState, PhysicalKey};
 use winit::{event::WindowEvent, window::Window};
 
 use crate::camera::Camera;
-use crate::model::{self, DrawLight, Material, Mesh, ModelLoader, QuadVertex};
+use crate::gpu_scene::{DrawGpuScene, DrawMode, GpuScene};
+use crate::model::{
+    AlphaMode, DrawLight, MaterialBinding, MaterialParams, Mesh, MeshOptions, ModelLoader,
+    QuadVertex, VertexIds, VertexLayout,
+};
 use crate::model::{DrawModel, Model};
 use crate::texture::{self, Texture};
 use crate::vertex::Vertex;
@@ -43,7 +71,11 @@ const INSTANCE_DISPLACEMENT: cgmath::Vector3<f32> = cgmath::Vector3::new(
     INSTANCES_PER_ROW as f32 * 0.5,
 );
 
-const RENDER_SCALE: f32 = 2.0;
+const DEFAULT_RENDER_SCALE: f32 = 2.0;
+const DEFAULT_INSTANCE_SPACING: f32 = 3.0;
+const DEFAULT_MODEL: &str = "resources/cube/cube.obj";
+/// How many frames a trace captured with P covers.
+const TRACE_FRAMES: u64 = 120;
 
 pub struct MappedTextureView {
     pub data: Vec<u8>,
@@ -55,31 +87,753 @@ pub struct MappedTextureView {
     pub name: String,
 }
 
+/// The bind group layouts shared between the renderer and the background asset loader.
+#[derive(Clone)]
+pub struct SceneLayouts {
+    pub texture: Binder<MaterialBinding>,
+    pub uniform: Binder<UniformBinding>,
+    pub light: Binder<UniformBinding>,
+    pub output: Binder<GBufferBinding>,
+    pub scene: Binder<SceneBinding>,
+    pub oit: Binder<OitBinding>,
+    pub debug_view: Binder<DebugViewBinding>,
+    pub debug_mesh: Binder<DebugMeshBinding>,
+    pub text: Binder<TextBinding>,
+    pub ui_texture: Binder<UiTextureBinding>,
+    pub pick: Binder<PickBinding>,
+    pub selection: Binder<SelectionBinding>,
+    /// Present when the device can index material textures from one bind group.
+    pub bindless: Option<BindlessLayout>,
+}
+
+/// A pipeline built once for each vertex layout.
+struct LayoutPipelines {
+    full: wgpu::RenderPipeline,
+    quantized: wgpu::RenderPipeline,
+}
+
+impl LayoutPipelines {
+    async fn new<F, Fut>(create: F) -> Result<Self>
+    where
+        F: Fn(VertexLayout) -> Fut,
+        Fut: Future<Output = Result<wgpu::RenderPipeline>>,
+    {
+        let (full, quantized) =
+            futures::try_join!(create(VertexLayout::Full), create(VertexLayout::Quantized))?;
+        Ok(Self { full, quantized })
+    }
+
+    fn get(&self, vertex_layout: VertexLayout) -> &wgpu::RenderPipeline {
+        match vertex_layout {
+            VertexLayout::Full => &self.full,
+            VertexLayout::Quantized => &self.quantized,
+        }
+    }
+}
+
+pub struct ScenePipelines {
+    deferred: LayoutPipelines,
+    /// The deferred pipelines for [`AlphaMode::Mask`] materials.
+    deferred_masked: LayoutPipelines,
+    /// The deferred pipelines for bindless materials.
+    deferred_bindless: Option<LayoutPipelines>,
+    /// The selected instance's opaque and masked materials again, marking it in the
+    /// stencil.
+    selection_stencil: LayoutPipelines,
+    selection_stencil_masked: LayoutPipelines,
+    light: LayoutPipelines,
+    /// [`AlphaMode::Blend`] materials, blended over the scene.
+    transparent: LayoutPipelines,
+    /// [`AlphaMode::Blend`] materials, accumulated into the weighted blended targets.
+    transparent_oit: LayoutPipelines,
+    output: wgpu::RenderPipeline,
+    oit_composite: wgpu::RenderPipeline,
+    blit: wgpu::RenderPipeline,
+    /// [`DebugView::UvChecker`] and [`DebugView::MipLevel`], into the G-buffer.
+    uv_checker: LayoutPipelines,
+    mip_level: LayoutPipelines,
+    /// Every mesh, counted into the accumulation target for [`DebugView::Overdraw`].
+    overdraw: LayoutPipelines,
+    /// Triangle edges in a line polygon mode, when the device has one.
+    wireframe: Option<LayoutPipelines>,
+    /// Triangle edges from the meshes' storage buffers, for devices without one.
+    wireframe_fallback: wgpu::RenderPipeline,
+    vectors: wgpu::RenderPipeline,
+    debug_view: wgpu::RenderPipeline,
+    /// Glyphs and rectangles for the stats overlay, blended over the frame.
+    text: wgpu::RenderPipeline,
+    /// The debug UI's meshes, blended over the frame.
+    ui: wgpu::RenderPipeline,
+    /// The depth under a picked pixel, into a one pixel target.
+    pick_depth: wgpu::RenderPipeline,
+    /// The selected instance's highlight, blended over the scene.
+    selection: wgpu::RenderPipeline,
+}
+
+impl ScenePipelines {
+    pub const SHADER_COUNT: usize = 15;
+
+    pub async fn load(
+        device: &wgpu::Device,
+        layouts: &SceneLayouts,
+        shaders: &ShaderCache,
+        pipelines: &RenderPipelineCache,
+        surface_format: wgpu::TextureFormat,
+        on_shader_loaded: impl Fn(),
+    ) -> Result<Self> {
+        let on_shader_loaded = &on_shader_loaded;
+        let load_shader = |path: &'static str, defines: ShaderDefines| async move {
+            let shader = shaders.load(device, path, &defines).await;
+            on_shader_loaded();
+            shader
+        };
+        let deferred_defines = ShaderDefines::new().with("NORMAL_MAPPING");
+        let load_bindless = async {
+            match layouts.bindless {
+                Some(_) => Ok(Some(
+                    load_shader("shaders/bindless.wgsl", deferred_defines.clone()).await?,
+                )),
+                None => {
+                    on_shader_loaded();
+                    Ok(None)
+                }
+            }
+        };
+        let (
+            deferred_shader,
+            masked_shader,
+            bindless_shader,
+            light_shader,
+            output_shader,
+            transparent_shader,
+            composite_shader,
+            blit_shader,
+            debug_material_shader,
+            debug_geometry_shader,
+            debug_view_shader,
+            text_shader,
+            ui_shader,
+            pick_shader,
+            selection_shader,
+        ) = futures::try_join!(
+            load_shader("shaders/shader.wgsl", deferred_defines.clone()),
+            load_shader(
+                "shaders/shader.wgsl",
+                deferred_defines.clone().with("ALPHA_MASK")
+            ),
+            load_bindless,
+            load_shader("shaders/light.wgsl", ShaderDefines::new()),
+            load_shader("shaders/draw_deferred.wgsl", ShaderDefines::new()),
+            load_shader("shaders/transparent.wgsl", deferred_defines.clone()),
+            load_shader("shaders/oit_composite.wgsl", ShaderDefines::new()),
+            load_shader("shaders/blit.wgsl", ShaderDefines::new()),
+            load_shader("shaders/debug_material.wgsl", ShaderDefines::new()),
+            load_shader("shaders/debug_geometry.wgsl", ShaderDefines::new()),
+            load_shader("shaders/debug_view.wgsl", ShaderDefines::new()),
+            load_shader("shaders/text.wgsl", ShaderDefines::new()),
+            load_shader("shaders/ui.wgsl", ShaderDefines::new()),
+            load_shader("shaders/pick.wgsl", ShaderDefines::new()),
+            load_shader("shaders/selection.wgsl", ShaderDefines::new()),
+        )?;
+
+        for shader in [
+            &deferred_shader,
+            &masked_shader,
+            &transparent_shader,
+            &debug_material_shader,
+        ] {
+            layouts.texture.check(&shader.reflection, 0)?;
+            layouts.uniform.check(&shader.reflection, 1)?;
+            layouts.light.check(&shader.reflection, 2)?;
+        }
+        layouts.uniform.check(&light_shader.reflection, 0)?;
+        layouts.light.check(&light_shader.reflection, 1)?;
+        layouts.output.check(&output_shader.reflection, 0)?;
+        layouts.oit.check(&composite_shader.reflection, 0)?;
+        layouts.scene.check(&blit_shader.reflection, 0)?;
+        layouts
+            .debug_mesh
+            .check(&debug_geometry_shader.reflection, 0)?;
+        layouts
+            .uniform
+            .check(&debug_geometry_shader.reflection, 1)?;
+        layouts.debug_view.check(&debug_view_shader.reflection, 0)?;
+        layouts.text.check(&text_shader.reflection, 0)?;
+        layouts.uniform.check(&ui_shader.reflection, 0)?;
+        layouts.ui_texture.check(&ui_shader.reflection, 1)?;
+        layouts.pick.check(&pick_shader.reflection, 0)?;
+        layouts.selection.check(&selection_shader.reflection, 0)?;
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[
+                    Some(&layouts.texture.layout),
+                    Some(&layouts.uniform.layout),
+                    Some(&layouts.light.layout),
+                ],
+                immediate_size: 0,
+            });
+        let create_mesh = |shader, pass| {
+            create_mesh_pipelines(
+                device,
+                pipelines,
+                &render_pipeline_layout,
+                shader,
+                surface_format,
+                pass,
+            )
+        };
+
+        info!("Creating deferred render pipelines");
+        let (deferred, deferred_masked, selection_stencil, selection_stencil_masked) = futures::try_join!(
+            create_mesh(&deferred_shader, MeshPass::Deferred),
+            create_mesh(&masked_shader, MeshPass::Masked),
+            create_mesh(&deferred_shader, MeshPass::SelectionStencil),
+            create_mesh(&masked_shader, MeshPass::SelectionStencil),
+        )?;
+
+        let deferred_bindless = match (&layouts.bindless, &bindless_shader) {
+            (Some(bindless), Some(shader)) => {
+                info!("Creating bindless deferred render pipelines");
+                // Material textures come from group 3, so group 0 is left empty.
+                let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Bindless Render Pipeline Layout"),
+                    bind_group_layouts: &[
+                        None,
+                        Some(&layouts.uniform.layout),
+                        Some(&layouts.light.layout),
+                        Some(&bindless.layout),
+                    ],
+                    immediate_size: 0,
+                });
+                Some(
+                    create_mesh_pipelines(
+                        device,
+                        pipelines,
+                        &layout,
+                        shader,
+                        surface_format,
+                        MeshPass::Bindless,
+                    )
+                    .await?,
+                )
+            }
+            _ => None,
+        };
+
+        info!("Creating transparent render pipelines");
+        let (transparent, transparent_oit) = futures::try_join!(
+            create_mesh(&transparent_shader, MeshPass::Transparent),
+            create_mesh(&transparent_shader, MeshPass::TransparentOit),
+        )?;
+
+        info!("Creating light render pipelines");
+        let light_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Light pipeline layout desc"),
+                bind_group_layouts: &[Some(&layouts.uniform.layout), Some(&layouts.light.layout)],
+                immediate_size: 0,
+            });
+        // The light shader only reads positions, which both layouts start with.
+        let light = LayoutPipelines::new(|vertex_layout: VertexLayout| {
+            let (light_pipeline_layout, light_shader) = (&light_pipeline_layout, &light_shader);
+            async move {
+                let vertex_layouts = [vertex_layout.desc()];
+                let desc = RenderPipelineDesc::new(light_pipeline_layout, light_shader)
+                    .label(match vertex_layout {
+                        VertexLayout::Full => "Light render pipeline",
+                        VertexLayout::Quantized => "Quantized light render pipeline",
+                    })
+                    .vertex_layouts(&vertex_layouts)
+                    .target(surface_format, Some(wgpu::BlendState::REPLACE))
+                    .target(surface_format, Some(wgpu::BlendState::REPLACE))
+                    .target(picking::ID_FORMAT, None)
+                    .depth(texture::Texture::DEPTH_FORMAT);
+                pipelines.get(device, &desc).await
+            }
+        })
+        .await?;
+
+        info!("Creating debug render pipelines");
+        let (uv_checker, mip_level, overdraw) = futures::try_join!(
+            create_mesh(&debug_material_shader, MeshPass::UvChecker),
+            create_mesh(&debug_material_shader, MeshPass::MipLevel),
+            create_mesh(&debug_material_shader, MeshPass::Overdraw),
+        )?;
+        let wireframe = if device
+            .features()
+            .contains(wgpu::Features::POLYGON_MODE_LINE)
+        {
+            Some(create_mesh(&debug_material_shader, MeshPass::Wireframe).await?)
+        } else {
+            None
+        };
+
+        let debug_geometry_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Debug geometry pipeline layout"),
+                bind_group_layouts: &[
+                    Some(&layouts.debug_mesh.layout),
+                    Some(&layouts.uniform.layout),
+                ],
+                immediate_size: 0,
+            });
+        // Vertices come from storage buffers, so only the instances are vertex input.
+        let instance_layouts = [InstanceRaw::desc()];
+        let debug_geometry = |label, vertex, fragment, topology, blend| {
+            let desc = RenderPipelineDesc::new(&debug_geometry_layout, &debug_geometry_shader)
+                .label(label)
+                .entry_points(EntryPoints { vertex, fragment })
+                .vertex_layouts(&instance_layouts)
+                .topology(topology)
+                .target(surface_format, Some(blend))
+                .depth(texture::Texture::DEPTH_FORMAT)
+                .depth_write(false)
+                .stencil(wgpu::StencilState::default());
+            async move { pipelines.get(device, &desc).await }
+        };
+        let (wireframe_fallback, vectors) = futures::try_join!(
+            debug_geometry(
+                "Wireframe Fallback Pipeline",
+                "vertex_wireframe",
+                "fragment_wireframe",
+                wgpu::PrimitiveTopology::TriangleList,
+                wgpu::BlendState::ALPHA_BLENDING,
+            ),
+            debug_geometry(
+                "Vector Lines Pipeline",
+                "vertex_vectors",
+                "fragment_vectors",
+                wgpu::PrimitiveTopology::LineList,
+                wgpu::BlendState::REPLACE,
+            ),
+        )?;
+
+        info!("Creating screen render pipelines");
+        let (output, oit_composite, blit, debug_view, selection) = futures::try_join!(
+            create_screen_pipeline(
+                device,
+                pipelines,
+                "Output Pipeline",
+                &output_shader,
+                &layouts.output.layout,
+                surface_format,
+                wgpu::BlendState::REPLACE,
+            ),
+            create_screen_pipeline(
+                device,
+                pipelines,
+                "OIT Composite Pipeline",
+                &composite_shader,
+                &layouts.oit.layout,
+                surface_format,
+                wgpu::BlendState::ALPHA_BLENDING,
+            ),
+            create_screen_pipeline(
+                device,
+                pipelines,
+                "Blit Pipeline",
+                &blit_shader,
+                &layouts.scene.layout,
+                surface_format,
+                wgpu::BlendState::REPLACE,
+            ),
+            create_screen_pipeline(
+                device,
+                pipelines,
+                "Debug View Pipeline",
+                &debug_view_shader,
+                &layouts.debug_view.layout,
+                surface_format,
+                wgpu::BlendState::REPLACE,
+            ),
+            create_screen_pipeline(
+                device,
+                pipelines,
+                "Selection Pipeline",
+                &selection_shader,
+                &layouts.selection.layout,
+                surface_format,
+                wgpu::BlendState::ALPHA_BLENDING,
+            ),
+        )?;
+
+        // Float targets can't be blended, so the depth is written as it is.
+        let pick_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pick pipeline layout"),
+            bind_group_layouts: &[Some(&layouts.pick.layout)],
+            immediate_size: 0,
+        });
+        let screen_vertex_layouts = [QuadVertex::desc()];
+        let pick_depth = pipelines
+            .get(
+                device,
+                &RenderPipelineDesc::new(&pick_layout, &pick_shader)
+                    .label("Pick Depth Pipeline")
+                    .vertex_layouts(&screen_vertex_layouts)
+                    .target(picking::DEPTH_FORMAT, None),
+            )
+            .await?;
+
+        let text_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Text pipeline layout"),
+            bind_group_layouts: &[Some(&layouts.text.layout)],
+            immediate_size: 0,
+        });
+        let text_vertex_layouts = [QuadVertex::desc(), TextInstance::desc()];
+        let text = pipelines
+            .get(
+                device,
+                &RenderPipelineDesc::new(&text_layout, &text_shader)
+                    .label("Text Pipeline")
+                    .vertex_layouts(&text_vertex_layouts)
+                    .target(surface_format, Some(wgpu::BlendState::ALPHA_BLENDING)),
+            )
+            .await?;
+
+        let ui_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Debug UI pipeline layout"),
+            bind_group_layouts: &[
+                Some(&layouts.uniform.layout),
+                Some(&layouts.ui_texture.layout),
+            ],
+            immediate_size: 0,
+        });
+        let ui_vertex_layouts = [debug_ui::vertex_desc()];
+        // egui blends in gamma space, so sRGB targets get their colours linearised.
+        let ui_entry_points = EntryPoints {
+            fragment: if surface_format.is_srgb() {
+                "fragment_linear"
+            } else {
+                "fragment_gamma"
+            },
+            ..Default::default()
+        };
+        let ui = pipelines
+            .get(
+                device,
+                &RenderPipelineDesc::new(&ui_layout, &ui_shader)
+                    .label("Debug UI Pipeline")
+                    .entry_points(ui_entry_points)
+                    .vertex_layouts(&ui_vertex_layouts)
+                    .target(
+                        surface_format,
+                        Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    ),
+            )
+            .await?;
+
+        Ok(Self {
+            deferred,
+            deferred_masked,
+            deferred_bindless,
+            selection_stencil,
+            selection_stencil_masked,
+            light,
+            transparent,
+            transparent_oit,
+            output,
+            oit_composite,
+            blit,
+            uv_checker,
+            mip_level,
+            overdraw,
+            wireframe,
+            wireframe_fallback,
+            vectors,
+            debug_view,
+            text,
+            ui,
+            pick_depth,
+            selection,
+        })
+    }
+}
+
+/// The passes meshes are drawn in, each with its own fragment shader and targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MeshPass {
+    /// Opaque materials, into the G-buffer.
+    Deferred,
+    /// [`AlphaMode::Mask`] materials, into the G-buffer.
+    Masked,
+    /// Opaque materials from the arrays in `bindless.wgsl`, into the G-buffer.
+    Bindless,
+    /// The selected instance, setting [`picking::SELECTION_STENCIL`] where it is
+    /// visible and writing no colour.
+    SelectionStencil,
+    /// [`AlphaMode::Blend`] materials, blended over the lit scene.
+    Transparent,
+    /// [`AlphaMode::Blend`] materials, into the weighted blended targets.
+    TransparentOit,
+    /// Every material as a UV checkerboard, into the G-buffer.
+    UvChecker,
+    /// Every material as a heatmap of its mip level, into the G-buffer.
+    MipLevel,
+    /// A count of every fragment, into the accumulation target with no depth test.
+    Overdraw,
+    /// Triangle edges in a line polygon mode, over the lit scene.
+    Wireframe,
+}
+
+/// Creates the pipelines drawing meshes of both vertex layouts in `pass`.
+async fn create_mesh_pipelines(
+    device: &wgpu::Device,
+    pipelines: &RenderPipelineCache,
+    layout: &wgpu::PipelineLayout,
+    shader: &Shader,
+    surface_format: wgpu::TextureFormat,
+    pass: MeshPass,
+) -> Result<LayoutPipelines> {
+    LayoutPipelines::new(|vertex_layout| {
+        create_mesh_pipeline(
+            device,
+            pipelines,
+            layout,
+            shader,
+            surface_format,
+            vertex_layout,
+            pass,
+        )
+    })
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn create_mesh_pipeline(
+    device: &wgpu::Device,
+    pipelines: &RenderPipelineCache,
+    layout: &wgpu::PipelineLayout,
+    shader: &Shader,
+    surface_format: wgpu::TextureFormat,
+    vertex_layout: VertexLayout,
+    pass: MeshPass,
+) -> Result<wgpu::RenderPipeline> {
+    let bindless = pass == MeshPass::Bindless;
+    let vertex_layouts = [vertex_layout.desc(), InstanceRaw::desc(), VertexIds::desc()];
+    let (pass_name, fragment) = match pass {
+        MeshPass::Deferred => ("", "fragment_main"),
+        MeshPass::Masked => ("Masked ", "fragment_main"),
+        MeshPass::Bindless => ("Bindless ", "fragment_bindless"),
+        MeshPass::SelectionStencil => ("Selection Stencil ", "fragment_main"),
+        MeshPass::Transparent => ("Transparent ", "fragment_blend"),
+        MeshPass::TransparentOit => ("OIT ", "fragment_oit"),
+        MeshPass::UvChecker => ("UV Checker ", "fragment_uv_checker"),
+        MeshPass::MipLevel => ("Mip Level ", "fragment_mip_level"),
+        MeshPass::Overdraw => ("Overdraw ", "fragment_overdraw"),
+        MeshPass::Wireframe => ("Wireframe ", "fragment_wireframe"),
+    };
+    let label = format!(
+        "{}{}Render Pipeline",
+        pass_name,
+        match vertex_layout {
+            VertexLayout::Full => "",
+            VertexLayout::Quantized => "Quantized ",
+        }
+    );
+
+    let desc = RenderPipelineDesc::new(layout, shader)
+        .label(&label)
+        .entry_points(EntryPoints {
+            vertex: vertex_layout.vertex_entry_point(bindless),
+            fragment,
+        })
+        .vertex_layouts(&vertex_layouts);
+    let desc = match pass {
+        MeshPass::Deferred
+        | MeshPass::Masked
+        | MeshPass::Bindless
+        | MeshPass::UvChecker
+        | MeshPass::MipLevel => desc
+            .target(surface_format, Some(wgpu::BlendState::REPLACE))
+            .target(surface_format, Some(wgpu::BlendState::REPLACE))
+            .target(picking::ID_FORMAT, None),
+        MeshPass::SelectionStencil => {
+            let unwritten = |format| {
+                Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::empty(),
+                })
+            };
+            desc.color_target(unwritten(surface_format))
+                .color_target(unwritten(surface_format))
+                .color_target(unwritten(picking::ID_FORMAT))
+        }
+        MeshPass::Transparent => {
+            desc.target(surface_format, Some(wgpu::BlendState::ALPHA_BLENDING))
+        }
+        MeshPass::TransparentOit => desc
+            .target(transparency::ACCUM_FORMAT, Some(transparency::ACCUM_BLEND))
+            .target(
+                transparency::REVEALAGE_FORMAT,
+                Some(transparency::REVEALAGE_BLEND),
+            ),
+        MeshPass::Overdraw => {
+            desc.target(transparency::ACCUM_FORMAT, Some(transparency::ACCUM_BLEND))
+        }
+        MeshPass::Wireframe => desc
+            .target(surface_format, Some(wgpu::BlendState::REPLACE))
+            .polygon_mode(wgpu::PolygonMode::Line),
+    };
+    // Blended materials and overlays are tested against the opaque depth but leave it
+    // and the stencil alone, the selection only adds its stencil bit, and overdraw counts
+    // hidden fragments too.
+    let desc = match pass {
+        MeshPass::Deferred
+        | MeshPass::Masked
+        | MeshPass::Bindless
+        | MeshPass::UvChecker
+        | MeshPass::MipLevel => desc.depth(texture::Texture::DEPTH_FORMAT),
+        MeshPass::SelectionStencil => desc
+            .depth(texture::Texture::DEPTH_FORMAT)
+            .depth_write(false)
+            .stencil(picking::SELECTION_STENCIL_STATE),
+        MeshPass::Transparent | MeshPass::TransparentOit | MeshPass::Wireframe => desc
+            .depth(texture::Texture::DEPTH_FORMAT)
+            .depth_write(false)
+            .stencil(wgpu::StencilState::default()),
+        MeshPass::Overdraw => desc,
+    };
+    pipelines.get(device, &desc).await
+}
+
+/// Edits a vector's components side by side.
+fn drag_xyz(ui: &mut egui::Ui, values: &mut [f32; 3]) {
+    ui.horizontal(|ui| {
+        for value in values {
+            ui.add(egui::DragValue::new(value).speed(0.05));
+        }
+    });
+}
+
+/// A square grid of instances `spacing` apart, each tilted away from the centre.
+fn create_instances(spacing: f32) -> Vec<Instance> {
+    (0..INSTANCES_PER_ROW)
+        .flat_map(|z| {
+            (0..INSTANCES_PER_ROW).map(move |x| {
+                let x = spacing * (x as f32 - INSTANCES_PER_ROW as f32 / 2.0);
+                let z = spacing * (z as f32 - INSTANCES_PER_ROW as f32 / 2.0);
+
+                let position = cgmath::Vector3 { x, y: 0.0, z } - INSTANCE_DISPLACEMENT;
+
+                let rotation = if position.is_zero() {
+                    cgmath::Quaternion::from_axis_angle(cgmath::Vector3::unit_z(), cgmath::Deg(0.0))
+                } else {
+                    cgmath::Quaternion::from_axis_angle(position.normalize(), cgmath::Deg(45.0))
+                };
+
+                Instance {
+                    position,
+                    rotation,
+                    scale: cgmath::Vector3::new(1.0, 1.0, 1.0),
+                }
+            })
+        })
+        .collect()
+}
+
+/// Creates a pipeline drawing the screen quad with `group` bound at group 0.
+async fn create_screen_pipeline(
+    device: &wgpu::Device,
+    pipelines: &RenderPipelineCache,
+    label: &str,
+    shader: &Shader,
+    group: &wgpu::BindGroupLayout,
+    surface_format: wgpu::TextureFormat,
+    blend: wgpu::BlendState,
+) -> Result<wgpu::RenderPipeline> {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts: &[Some(group)],
+        immediate_size: 0,
+    });
+    let vertex_layouts = [QuadVertex::desc()];
+    let desc = RenderPipelineDesc::new(&layout, shader)
+        .label(label)
+        .vertex_layouts(&vertex_layouts)
+        .target(surface_format, Some(blend));
+    pipelines.get(device, &desc).await
+}
+
 pub struct State<'a> {
+    window: Arc<Window>,
     surface: wgpu::Surface<'a>,
     device: wgpu::Device,
     queue: wgpu::Queue,
     surface_config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
     bg_color: wgpu::Color,
-    deferred_render_pipeline: wgpu::RenderPipeline,
+    layouts: SceneLayouts,
+    pipelines: Option<ScenePipelines>,
     camera: Camera,
     uniforms: Uniforms,
     uniform_buffer: wgpu::Buffer,
     uniform_bind_group: wgpu::BindGroup,
     camera_controller: CameraController,
     instances: Vec<Instance>,
+    /// The distance between neighbouring instances in the grid.
+    instance_spacing: f32,
     instance_buffer: wgpu::Buffer,
-    depth_texture: Texture,
-    obj_model: Model,
+    /// The instances sorted back to front, for blended materials.
+    sorted_instance_buffer: wgpu::Buffer,
+    /// How blended materials are drawn. Toggled with O.
+    transparency: TransparencyMode,
+    /// What the frame shows in place of, or over, the lit scene. Cycled with V.
+    debug_view: DebugView,
+    /// Where `obj_model` is loaded from, and how.
+    model_path: String,
+    mesh_options: MeshOptions,
+    obj_model: Option<Model>,
+    gpu_scene: Option<GpuScene>,
+    draw_mode: DrawMode,
+    /// Whether the model is drawn from its [`GpuScene`] or mesh by mesh. Toggled with G.
+    gpu_driven: bool,
+    /// Every material's textures in one bind group, when the device supports it.
+    bindless_materials: Option<BindlessMaterials>,
+    /// Whether GPU-driven drawing uses `bindless_materials`. Toggled with B.
+    bindless: bool,
+    /// The model's meshes, bound for the wireframe and vector line debug views.
+    debug_meshes: Option<DebugMeshes>,
+    pending_textures: Vec<(usize, &'static str, Texture)>,
+    asset_loader: AssetLoader,
+    shaders: ShaderCache,
+    /// A scene being read from disk, to replace this one once it has been.
+    scene_load: Option<oneshot::Receiver<Result<SceneFile>>>,
+    /// Present when the device can cache compiled pipelines between runs.
+    pipeline_cache: Option<PersistentPipelineCache>,
+    render_pipelines: RenderPipelineCache,
+    loading_pipeline: wgpu::RenderPipeline,
+    loading_buffer: wgpu::Buffer,
+    loading_bind_group: wgpu::BindGroup,
     screen_quad: Mesh,
-    render_material: Material,
+    targets: RenderTargets,
+    /// The size of `targets` relative to the surface.
+    render_scale: f32,
     light: Light,
+    /// Whether the light circles the origin each frame.
+    orbit_light: bool,
     light_buffer: wgpu::Buffer,
     light_bind_group: wgpu::BindGroup,
-    light_render_pipeline: wgpu::RenderPipeline,
-    output_render_pipeline: wgpu::RenderPipeline,
     capture_next_frame: bool,
+    profiler: GpuProfiler,
+    traces_saved: u32,
+    adapter_info: wgpu::AdapterInfo,
+    stats: StatsOverlay,
+    text: TextRenderer,
+    ui: DebugUi,
+    /// Whether the G-buffer keeps its ID target and clicks pick instances.
+    picking: bool,
+    picker: Picker,
+    /// The instance last picked, highlighted over the scene.
+    selected: Option<usize>,
+    /// Where the cursor is in the window, if it is over it.
+    cursor: Option<winit::dpi::PhysicalPosition<f64>>,
+    modifiers: ModifiersState,
+    /// Edits the selected instance while the debug UI is shown.
+    gizmo: Gizmo,
+    history: History,
     #[cfg(not(target_arch = "wasm32"))]
     data_export: mpsc::Sender<MappedTextureView>,
 }
@@ -87,9 +841,12 @@ pub struct State<'a> {
 impl<'a> State<'a> {
     pub async fn new(
         window: Arc<Window>,
-        #[cfg(not(target_arch = "wasm32"))]
-        data_export: mpsc::Sender<MappedTextureView>,
-    ) -> Self {
+        #[cfg(not(target_arch = "wasm32"))] data_export: mpsc::Sender<MappedTextureView>,
+    ) -> Result<Self> {
+        if baked::use_baked_archive() && FileReader::mount_archive("", BAKED_ARCHIVE).await? {
+            info!("Mounted baked assets from {}", BAKED_ARCHIVE);
+        }
+
         let size = window.inner_size();
 
         #[cfg(target_arch = "wasm32")]
@@ -119,9 +876,7 @@ impl<'a> State<'a> {
             display: None,
         };
         let instance = wgpu::Instance::new(instance_desc);
-        let surface = instance
-            .create_surface(window.clone())
-            .expect("Expected surface from window");
+        let surface = instance.create_surface(window.clone())?;
 
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
@@ -129,23 +884,39 @@ impl<'a> State<'a> {
                 power_preference: PowerPreference::HighPerformance,
                 force_fallback_adapter: false,
             })
-            .await
-            .expect("Could not create adapter instance!");
-
+            .await?;
+
+        let bindless_capacity = bindless::capacity(&adapter);
+        let (required_features, required_limits) = match bindless_capacity {
+            Some(capacity) => (
+                bindless::required_features(),
+                bindless::required_limits(capacity, wgpu::Limits::default()),
+            ),
+            None => (wgpu::Features::empty(), wgpu::Limits::default()),
+        };
         let (device, queue) = adapter
             .request_device(&wgpu::DeviceDescriptor {
                 label: None,
-                required_features: wgpu::Features::empty(),
-                required_limits: wgpu::Limits::default(),
+                required_features: required_features
+                    | PersistentPipelineCache::required_features(&adapter)
+                    | debug_view::optional_features(&adapter)
+                    | GpuProfiler::required_features(&adapter),
+                required_limits,
                 memory_hints: wgpu::MemoryHints::Performance,
                 experimental_features: ExperimentalFeatures::disabled(),
                 trace: wgpu::Trace::Off,
             })
-            .await
-            .expect("Could not get device from adapter!");
+            .await?;
 
-        info!("Adapter info: {:?}", adapter.get_info());
+        let adapter_info = adapter.get_info();
+        info!("Adapter info: {:?}", adapter_info);
         info!("Device info: {:?}", device);
+        let draw_mode = DrawMode::supported(&adapter);
+        info!("GPU-driven draw mode: {:?}", draw_mode);
+        info!("Bindless material capacity: {:?}", bindless_capacity);
+        let pipeline_cache = PersistentPipelineCache::new(&adapter, &device);
+        let profiler = GpuProfiler::new(&device, &queue);
+        info!("GPU timestamps: {}", profiler.has_timestamps());
 
         info!("Supported surface formats:");
         let capabilities = surface.get_capabilities(&adapter);
@@ -170,44 +941,7 @@ impl<'a> State<'a> {
         surface.configure(&device, &surface_config);
 
         info!("Surface configured: {:?}", surface_config);
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("Texture bind group layout"),
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 3,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-            });
+        let texture_binder = Binder::new(&device, Some("Texture bind group layout"));
 
         let camera = Camera {
             eye: (0.0, 1.0, 2.0).into(),
@@ -230,29 +964,14 @@ impl<'a> State<'a> {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
-        let uniform_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("Uniform Bind Group Layout"),
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-            });
-
-        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Uniform Bind Group"),
-            layout: &uniform_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniform_buffer.as_entire_binding(),
-            }],
-        });
+        let uniform_binder = Binder::new(&device, Some("Uniform Bind Group Layout"));
+        let uniform_bind_group = uniform_binder.create_bind_group(
+            &UniformBinding {
+                buffer: uniform_buffer.clone(),
+            },
+            &device,
+            Some("Uniform Bind Group"),
+        );
 
         // Cornflour blue, because I 'member XNA
         let bg_color = rgb_to_normalized(0, 0, 0);
@@ -270,325 +989,174 @@ impl<'a> State<'a> {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
-        let light_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-                label: None,
-            });
-
-        let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &light_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: light_buffer.as_entire_binding(),
-            }],
-            label: None,
-        });
-
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[
-                    Some(&texture_bind_group_layout),
-                    Some(&uniform_bind_group_layout),
-                    Some(&light_bind_group_layout),
-                ],
-                immediate_size: 0,
-            });
-
-        let model_loader = ModelLoader::new(&device).await;
-
-        let obj_model = model_loader
-            .load(
-                &device,
-                &queue,
-                &texture_bind_group_layout,
-                "resources/cube/cube.obj",
-            )
-            .await
-            .unwrap();
-
-        const SPACE_BETWEEN: f32 = 3.0;
-        let instances = (0..INSTANCES_PER_ROW)
-            .flat_map(|z| {
-                (0..INSTANCES_PER_ROW).map(move |x| {
-                    let x = SPACE_BETWEEN * (x as f32 - INSTANCES_PER_ROW as f32 / 2.0);
-                    let z = SPACE_BETWEEN * (z as f32 - INSTANCES_PER_ROW as f32 / 2.0);
-
-                    let position = cgmath::Vector3 { x, y: 0.0, z } - INSTANCE_DISPLACEMENT;
-
-                    let rotation = if position.is_zero() {
-                        cgmath::Quaternion::from_axis_angle(
-                            cgmath::Vector3::unit_z(),
-                            cgmath::Deg(0.0),
-                        )
-                    } else {
-                        cgmath::Quaternion::from_axis_angle(position.normalize(), cgmath::Deg(45.0))
-                    };
-
-                    Instance { position, rotation }
-                })
-            })
-            .collect::<Vec<_>>();
+        let light_binder = Binder::new(&device, Some("Light bind group layout"));
+        let light_bind_group = light_binder.create_bind_group(
+            &UniformBinding {
+                buffer: light_buffer.clone(),
+            },
+            &device,
+            Some("Light bind group"),
+        );
 
+        let instances = create_instances(DEFAULT_INSTANCE_SPACING);
         let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
 
         info!("Creating instance buffer");
         let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Instance Buffer"),
             contents: bytemuck::cast_slice(instance_data.as_slice()),
-            usage: wgpu::BufferUsages::VERTEX,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+        let sorted_instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sorted Instance Buffer"),
+            size: instance_buffer.size(),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
         });
 
-        let depth_texture =
-            Texture::create_depth_texture(&device, &surface_config, RENDER_SCALE, "Depth Texture");
-        let shader_buffer = FileReader::read_file("shaders/shader.wgsl").await;
-        let shader_str =
-            std::str::from_utf8(shader_buffer.as_slice()).expect("Failed to load shader");
-
-        info!("Creating deferred render pipeline");
-        let deferred_render_pipeline = {
-            let shader = wgpu::ShaderModuleDescriptor {
-                label: Some("Normal Shader"),
-                source: wgpu::ShaderSource::Wgsl(shader_str.into()),
-            };
-
-            pipeline::create_render_pipeline(
-                &device,
-                &render_pipeline_layout,
-                Some(texture::Texture::DEPTH_FORMAT),
-                &[model::ModelVertex::desc(), InstanceRaw::desc()],
-                shader,
-                &[
-                    Some(wgpu::ColorTargetState {
-                        format: surface_config.format,
-                        blend: Some(wgpu::BlendState {
-                            color: wgpu::BlendComponent::REPLACE,
-                            alpha: wgpu::BlendComponent::REPLACE,
-                        }),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    }),
-                    Some(wgpu::ColorTargetState {
-                        format: surface_config.format,
-                        blend: Some(wgpu::BlendState {
-                            color: wgpu::BlendComponent::REPLACE,
-                            alpha: wgpu::BlendComponent::REPLACE,
-                        }),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    }),
-                ],
-                Some("Render Pipeline"),
-            )
-        };
-
-        info!("Creating light render pipeline");
-        let light_render_pipeline = {
-            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Light pipeline layout desc"),
-                bind_group_layouts: &[
-                    Some(&uniform_bind_group_layout),
-                    Some(&light_bind_group_layout),
-                ],
-                immediate_size: 0,
-            });
-            let shader_buffer = FileReader::read_file("shaders/light.wgsl").await;
-            let shader_str =
-                std::str::from_utf8(shader_buffer.as_slice()).expect("Failed to load shader");
-
-            let shader = wgpu::ShaderModuleDescriptor {
-                label: Some("Light Shader"),
-                source: wgpu::ShaderSource::Wgsl(shader_str.into()),
-            };
+        let screen_quad = ModelLoader::create_screen_quad_mesh(&device);
 
-            pipeline::create_render_pipeline(
-                &device,
-                &layout,
-                Some(texture::Texture::DEPTH_FORMAT),
-                &[model::ModelVertex::desc()],
-                shader,
-                &[
-                    Some(wgpu::ColorTargetState {
-                        format: surface_config.format,
-                        blend: Some(wgpu::BlendState {
-                            color: wgpu::BlendComponent::REPLACE,
-                            alpha: wgpu::BlendComponent::REPLACE,
-                        }),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    }),
-                    Some(wgpu::ColorTargetState {
-                        format: surface_config.format,
-                        blend: Some(wgpu::BlendState {
-                            color: wgpu::BlendComponent::REPLACE,
-                            alpha: wgpu::BlendComponent::REPLACE,
-                        }),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    }),
-                ],
-                Some("Light render pipeline"),
-            )
+        let layouts = SceneLayouts {
+            texture: texture_binder,
+            uniform: uniform_binder,
+            light: light_binder,
+            output: Binder::new(&device, Some("Output bind group layout")),
+            scene: Binder::new(&device, Some("Scene bind group layout")),
+            oit: Binder::new(&device, Some("OIT bind group layout")),
+            debug_view: Binder::new(&device, Some("Debug view bind group layout")),
+            debug_mesh: Binder::new(&device, Some("Debug mesh bind group layout")),
+            text: Binder::new(&device, Some("Text bind group layout")),
+            ui_texture: Binder::new(&device, Some("Debug UI texture bind group layout")),
+            pick: Binder::new(&device, Some("Pick bind group layout")),
+            selection: Binder::new(&device, Some("Selection bind group layout")),
+            bindless: bindless_capacity.map(|capacity| BindlessLayout::new(&device, capacity)),
         };
+        let targets = RenderTargets::new(&device, &surface_config, DEFAULT_RENDER_SCALE, &layouts);
+        let text = TextRenderer::new(&device, &queue, &layouts.text);
+        let ui = DebugUi::new(&device, &window, &layouts.uniform, &layouts.ui_texture);
+        let picker = Picker::new(&device, &layouts.pick);
+
+        // The loading screen is the only thing loaded up front, everything else
+        // streams in from the background while it is displayed.
+        info!("Creating loading render pipeline");
+        let loading_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Loading buffer"),
+            contents: bytemuck::cast_slice(&[0.0f32; 4]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
 
-        let output_bindgroup_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 3,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-                label: None,
-            });
-
-        let shader_buffer = FileReader::read_file("shaders/draw_deferred.wgsl").await;
-        let shader_str =
-            std::str::from_utf8(shader_buffer.as_slice()).expect("Failed to load shader");
-        info!("Creating output render pipeline");
-
-        let output_render_pipeline = {
-            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Deferred pipeline layout desc"),
-                bind_group_layouts: &[Some(&output_bindgroup_layout)],
-                immediate_size: 0,
-            });
-
-            let shader = wgpu::ShaderModuleDescriptor {
-                label: Some("Output Shader"),
-                source: wgpu::ShaderSource::Wgsl(shader_str.into()),
-            };
+        // Nothing else shares the loading screen's bind group, so its layout is taken
+        // straight from the shader.
+        let shaders = ShaderCache::new();
+        let loading_shader = shaders
+            .load(&device, "shaders/loading.wgsl", &ShaderDefines::new())
+            .await?;
+        let (loading_pipeline_layout, loading_group_layouts) =
+            loading_shader.reflection.create_pipeline_layout(&device)?;
+        let loading_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Loading bind group"),
+            layout: loading_group_layouts[0].as_ref().unwrap(),
+            entries: &UniformBinding {
+                buffer: loading_buffer.clone(),
+            }
+            .bind_group_entries(),
+        });
 
-            create_render_pipeline(
+        let render_pipelines =
+            RenderPipelineCache::new(pipeline_cache.as_ref().map(|cache| cache.cache.clone()));
+        let loading_vertex_layouts = [QuadVertex::desc()];
+        let loading_pipeline = render_pipelines
+            .get(
                 &device,
-                &layout,
-                None,
-                &[QuadVertex::desc()],
-                shader,
-                &[Some(wgpu::ColorTargetState {
-                    format: surface_config.format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::REPLACE,
-                        alpha: wgpu::BlendComponent::REPLACE,
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                Some("Output Pipeline"),
+                &RenderPipelineDesc::new(&loading_pipeline_layout, &loading_shader)
+                    .label("Loading Pipeline")
+                    .vertex_layouts(&loading_vertex_layouts)
+                    .target(surface_config.format, Some(wgpu::BlendState::REPLACE)),
             )
-        };
+            .await?;
 
-        let diffuse_texture = Texture::create_render_texture(
+        let asset_loader = AssetLoader::start(
             &device,
-            &surface_config,
-            RENDER_SCALE,
-            "Deferred Diffuse Surface",
+            &queue,
+            &layouts,
+            &shaders,
+            &render_pipelines,
+            &profiler,
+            surface_config.format,
+            DEFAULT_MODEL,
+            MeshOptions::default(),
         );
 
-        let specular_texture = Texture::create_render_texture(
-            &device,
-            &surface_config,
-            RENDER_SCALE,
-            "Deferred Normal Surface",
-        );
-
-        let screen_quad = ModelLoader::create_screen_quad_mesh(&device);
-
-        let render_material = {
-            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: None,
-                layout: &output_bindgroup_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: wgpu::BindingResource::TextureView(&specular_texture.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 3,
-                        resource: wgpu::BindingResource::Sampler(&specular_texture.sampler),
-                    },
-                ],
-            });
-
-            Material {
-                name: String::from("Output Quad Textures"),
-                textures: HashMap::from_iter([
-                    ("ss_diffuse".to_owned(), diffuse_texture),
-                    ("ss_specular".to_owned(), specular_texture),
-                ]),
-                bind_group,
-            }
-        };
-
         info!("State ctor completed");
-        Self {
+        Ok(Self {
+            window,
             surface,
             device,
             queue,
             surface_config,
             size,
             bg_color,
-            deferred_render_pipeline,
+            layouts,
+            pipelines: None,
             camera,
             uniforms,
             uniform_buffer,
             uniform_bind_group,
             camera_controller,
             instances,
+            instance_spacing: DEFAULT_INSTANCE_SPACING,
             instance_buffer,
-            depth_texture,
-            obj_model,
+            sorted_instance_buffer,
+            transparency: TransparencyMode::default(),
+            debug_view: DebugView::default(),
+            model_path: DEFAULT_MODEL.to_owned(),
+            mesh_options: MeshOptions::default(),
+            obj_model: None,
+            gpu_scene: None,
+            draw_mode,
+            gpu_driven: true,
+            bindless_materials: None,
+            bindless: true,
+            debug_meshes: None,
+            pending_textures: Vec::new(),
+            asset_loader,
+            shaders,
+            scene_load: None,
+            pipeline_cache,
+            render_pipelines,
+            loading_pipeline,
+            loading_buffer,
+            loading_bind_group,
             light,
+            orbit_light: true,
             light_buffer,
             light_bind_group,
-            light_render_pipeline,
-            output_render_pipeline,
             screen_quad,
-            render_material,
+            targets,
+            render_scale: DEFAULT_RENDER_SCALE,
             capture_next_frame: false,
+            profiler,
+            traces_saved: 0,
+            adapter_info,
+            stats: StatsOverlay::default(),
+            text,
+            ui,
+            picking: true,
+            picker,
+            selected: None,
+            cursor: None,
+            modifiers: ModifiersState::default(),
+            gizmo: Gizmo::default(),
+            history: History::default(),
             #[cfg(not(target_arch = "wasm32"))]
             data_export,
+        })
+    }
+
+    /// Writes the compiled pipelines to disk for the next launch, if there is a cache.
+    pub fn save_pipeline_cache(&self) {
+        if let Some(cache) = &self.pipeline_cache {
+            cache.save();
         }
     }
 
@@ -597,86 +1165,781 @@ impl<'a> State<'a> {
         self.surface_config.width = self.size.width;
         self.surface_config.height = self.size.height;
         self.surface.configure(&self.device, &self.surface_config);
-        self.depth_texture = Texture::create_depth_texture(
-            &self.device,
-            &self.surface_config,
-            RENDER_SCALE,
-            "Depth Texture",
-        );
         self.camera.aspect = self.surface_config.width as f32 / self.surface_config.height as f32;
 
-        let diffuse_texture = Texture::create_render_texture(
+        self.targets = RenderTargets::new(
             &self.device,
             &self.surface_config,
-            RENDER_SCALE,
-            "Deferred Surface",
+            self.render_scale,
+            &self.layouts,
         );
+    }
 
-        let screen_normal_texture = Texture::create_render_texture(
+    /// Draws the scene at `scale` times the surface's size, recreating the targets.
+    pub fn set_render_scale(&mut self, scale: f32) {
+        self.render_scale = scale;
+        self.targets = RenderTargets::new(
             &self.device,
             &self.surface_config,
-            RENDER_SCALE,
-            "Deferred Normal Surface",
+            self.render_scale,
+            &self.layouts,
+        );
+    }
+
+    /// Lays the instances out `spacing` apart, keeping their count. Any edits to them
+    /// are lost, and can't be undone.
+    pub fn set_instance_spacing(&mut self, spacing: f32) {
+        self.instance_spacing = spacing;
+        self.set_instances(create_instances(spacing));
+    }
+
+    /// Replaces every instance, forgetting the edits made to the old ones. The instance
+    /// buffers are only recreated if the count changes.
+    fn set_instances(&mut self, instances: Vec<Instance>) {
+        self.gizmo.release();
+        self.history.clear();
+        self.selected = self.selected.filter(|&index| index < instances.len());
+        let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
+        if instances.len() == self.instances.len() {
+            self.queue.write_buffer(
+                &self.instance_buffer,
+                0,
+                bytemuck::cast_slice(instance_data.as_slice()),
+            );
+        } else {
+            self.instance_buffer =
+                self.device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("Instance Buffer"),
+                        contents: bytemuck::cast_slice(instance_data.as_slice()),
+                        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    });
+            self.sorted_instance_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Sorted Instance Buffer"),
+                size: self.instance_buffer.size(),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+            if let Some(gpu_scene) = self.gpu_scene.as_mut() {
+                gpu_scene.set_instance_count(&self.queue, instances.len() as u32);
+            }
+        }
+        self.instances = instances;
+    }
+
+    /// The scene as it is now, to save.
+    pub fn scene(&self) -> SceneFile {
+        SceneFile {
+            model: self.model_path.clone(),
+            mesh_options: self.mesh_options,
+            camera: self.camera,
+            camera_speed: self.camera_controller.speed,
+            background: self.bg_color,
+            lights: vec![self.light],
+            orbit_light: self.orbit_light,
+            render_scale: self.render_scale,
+            transparency: self.transparency,
+            debug_view: self.debug_view,
+            gpu_driven: self.gpu_driven,
+            bindless: self.bindless,
+            picking: self.picking,
+            instance_spacing: self.instance_spacing,
+            instances: self.instances.clone(),
+        }
+    }
+
+    /// Replaces the scene with `scene`, loading its model in the background if it isn't
+    /// the one already drawn. Only the first light is drawn, and a scene without one is
+    /// unlit.
+    pub fn load_scene(&mut self, scene: SceneFile) {
+        if scene.model != self.model_path || scene.mesh_options != self.mesh_options {
+            info!("Loading model {}", scene.model);
+            // Replacing the loader drops whatever it was still loading, so the pipelines
+            // start again if they hadn't arrived.
+            self.asset_loader = if self.pipelines.is_some() {
+                AssetLoader::start_model(
+                    &self.device,
+                    &self.queue,
+                    &self.layouts,
+                    &self.shaders,
+                    &self.render_pipelines,
+                    &self.profiler,
+                    &scene.model,
+                    scene.mesh_options,
+                )
+            } else {
+                AssetLoader::start(
+                    &self.device,
+                    &self.queue,
+                    &self.layouts,
+                    &self.shaders,
+                    &self.render_pipelines,
+                    &self.profiler,
+                    self.surface_config.format,
+                    &scene.model,
+                    scene.mesh_options,
+                )
+            };
+            self.obj_model = None;
+            self.gpu_scene = None;
+            self.debug_meshes = None;
+            self.bindless_materials = None;
+            self.pending_textures.clear();
+            self.model_path = scene.model;
+            self.mesh_options = scene.mesh_options;
+        }
+
+        self.camera = Camera {
+            aspect: self.camera.aspect,
+            ..scene.camera
+        };
+        self.camera_controller.speed = scene.camera_speed;
+        self.bg_color = scene.background;
+        if scene.lights.len() > 1 {
+            info!("Drawing the first of {} lights", scene.lights.len());
+        }
+        self.light = scene.lights.first().copied().unwrap_or(Light {
+            position: [0.0; 3],
+            _padding: 0.0,
+            colour: [0.0; 3],
+            _padding2: 0.0,
+        });
+        self.orbit_light = scene.orbit_light;
+        if scene.render_scale != self.render_scale {
+            self.set_render_scale(scene.render_scale);
+        }
+        self.set_transparency_mode(scene.transparency);
+        self.set_debug_view(scene.debug_view);
+        self.gpu_driven = scene.gpu_driven;
+        self.bindless = scene.bindless;
+        self.set_picking(scene.picking);
+        self.instance_spacing = scene.instance_spacing;
+        self.set_instances(scene.instances);
+    }
+
+    /// Reads the scene at `path` in the background, loading it once it has been read.
+    pub fn open_scene(&mut self, path: &str) {
+        let (sender, receiver) = oneshot::channel();
+        let path = path.to_owned();
+        asset_loader::spawn_task(move || async move {
+            let _ = sender.send(SceneFile::load(&path).await);
+        });
+        self.scene_load = Some(receiver);
+    }
+
+    /// Writes the scene to `path` in the asset directory, where [`State::open_scene`]
+    /// reads it back from.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_scene(&self, path: &str) -> Result<()> {
+        FileReader::write_file(path, self.scene().to_text().as_bytes())
+    }
+
+    /// Downloads the scene, named after `path`. Pages can't write beside themselves, so
+    /// [`State::open_scene`] only fetches it once it has been put there by hand.
+    #[cfg(target_arch = "wasm32")]
+    pub fn save_scene(&self, path: &str) -> Result<()> {
+        let text = self.scene().to_text();
+        let name = path.rsplit('/').next().unwrap_or(path);
+        trigger_browser_download(text.as_bytes(), name, "text/plain").map_err(|e| Error::Io {
+            path: path.to_owned(),
+            source: std::io::Error::other(format!("Download failed: {:?}", e)),
+        })
+    }
+
+    /// Saves the scene, logging whether it worked.
+    fn save_scene_file(&self) {
+        match self.save_scene(SCENE_FILE) {
+            Ok(()) => info!("Saved {}", SCENE_FILE),
+            Err(e) => log::error!("{}", e),
+        }
+    }
+
+    /// Handles `event` if the debug UI, camera or a shortcut wants it. The debug UI sees
+    /// events first, so typing into it or dragging over it leaves the camera alone.
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        // A drag started on the gizmo follows the cursor wherever it goes, over the debug
+        // UI's windows too.
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = Some(*position);
+                if self.gizmo.is_dragging() {
+                    self.drag_gizmo(*position);
+                    return true;
+                }
+            }
+            WindowEvent::CursorLeft { .. } => self.cursor = None,
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = modifiers.state(),
+            WindowEvent::MouseInput {
+                state: ElementState::Released,
+                button: MouseButton::Left,
+                ..
+            } if self.gizmo.is_dragging() => {
+                self.release_gizmo();
+                return true;
+            }
+            _ => {}
+        }
+        (self.ui.visible && self.ui.on_window_event(&self.window, event))
+            || self.camera_controller.process_inputs(event)
+            || self.process_inputs(event)
+    }
+
+    fn process_inputs(&mut self, event: &WindowEvent) -> bool {
+        if let WindowEvent::MouseInput {
+            state: ElementState::Pressed,
+            button: MouseButton::Left,
+            ..
+        } = event
+        {
+            if let Some(cursor) = self.cursor {
+                if self.grab_gizmo(cursor) {
+                    return true;
+                }
+                if self.picking {
+                    self.pick(cursor, Box::new(log_pick));
+                    return true;
+                }
+            }
+        }
+        if let WindowEvent::KeyboardInput { event: key, .. } = event {
+            if let PhysicalKey::Code(code) = key.physical_key {
+                if code == KeyCode::Backspace && key.state.is_pressed() {
+                    self.capture_next_frame = true;
+                    return true;
+                }
+                if code == KeyCode::KeyG && key.state.is_pressed() {
+                    self.gpu_driven = !self.gpu_driven;
+                    info!("GPU-driven drawing: {}", self.gpu_driven);
+                    return true;
+                }
+                if code == KeyCode::KeyB && key.state.is_pressed() {
+                    self.bindless = !self.bindless;
+                    info!("Bindless materials: {}", self.bindless);
+                    return true;
+                }
+                if code == KeyCode::KeyO && key.state.is_pressed() {
+                    self.set_transparency_mode(self.transparency.toggled());
+                    return true;
+                }
+                if code == KeyCode::KeyV && key.state.is_pressed() {
+                    self.set_debug_view(self.debug_view.next());
+                    return true;
+                }
+                if code == KeyCode::F1 && key.state.is_pressed() {
+                    self.stats.visible = !self.stats.visible;
+                    return true;
+                }
+                if code == KeyCode::F2 && key.state.is_pressed() {
+                    self.ui.visible = !self.ui.visible;
+                    return true;
+                }
+                if code == KeyCode::KeyP && key.state.is_pressed() {
+                    self.capture_trace(TRACE_FRAMES);
+                    return true;
+                }
+                if let Some(mode) = match code {
+                    KeyCode::Digit1 => Some(GizmoMode::Translate),
+                    KeyCode::Digit2 => Some(GizmoMode::Rotate),
+                    KeyCode::Digit3 => Some(GizmoMode::Scale),
+                    _ => None,
+                } {
+                    if key.state.is_pressed() {
+                        self.gizmo.mode = mode;
+                    }
+                    return true;
+                }
+                if self.modifiers.control_key() && key.state.is_pressed() {
+                    if code == KeyCode::KeyS {
+                        self.save_scene_file();
+                        return true;
+                    }
+                    if code == KeyCode::KeyO {
+                        self.open_scene(SCENE_FILE);
+                        return true;
+                    }
+                    let redo = code == KeyCode::KeyY
+                        || (code == KeyCode::KeyZ && self.modifiers.shift_key());
+                    if redo {
+                        self.redo();
+                        return true;
+                    }
+                    if code == KeyCode::KeyZ {
+                        self.undo();
+                        return true;
+                    }
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Reads back what is drawn at `position` in the window, calling `callback` with it
+    /// a frame or two later. Whatever is picked becomes the selection.
+    pub fn pick(&mut self, position: winit::dpi::PhysicalPosition<f64>, callback: PickCallback) {
+        let size = self.targets.ids.texture.size();
+        let scale = |position: f64, surface: u32, target: u32| {
+            (position * target as f64 / surface.max(1) as f64) as u32
+        };
+        self.picker.request(
+            [
+                scale(position.x, self.surface_config.width, size.width),
+                scale(position.y, self.surface_config.height, size.height),
+            ],
+            callback,
         );
+    }
+
+    /// The index of the selected instance, if any.
+    pub fn selection(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// Replaces the instance at `index` and uploads it, as an edit that can be undone.
+    pub fn edit_instance(&mut self, index: usize, instance: Instance) {
+        if let Some(&before) = self.instances.get(index) {
+            self.set_instance(index, instance);
+            self.history.push(Edit {
+                index,
+                before,
+                after: instance,
+            });
+        }
+    }
+
+    /// Reverts the last edit, selecting the instance it changed.
+    pub fn undo(&mut self) {
+        if let Some(edit) = self.history.undo() {
+            self.set_instance(edit.index, edit.before);
+            self.selected = Some(edit.index);
+        }
+    }
+
+    /// Applies the last edit undone again, selecting the instance it changed.
+    pub fn redo(&mut self) {
+        if let Some(edit) = self.history.redo() {
+            self.set_instance(edit.index, edit.after);
+            self.selected = Some(edit.index);
+        }
+    }
+
+    /// Replaces one instance, writing just its part of the instance buffer.
+    fn set_instance(&mut self, index: usize, instance: Instance) {
+        self.instances[index] = instance;
+        self.queue.write_buffer(
+            &self.instance_buffer,
+            (index * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+            bytemuck::bytes_of(&instance.to_raw()),
+        );
+    }
+
+    /// The view the gizmo is drawn and dragged in.
+    fn gizmo_view(&self) -> GizmoView {
+        GizmoView::new(
+            &self.camera,
+            [self.surface_config.width, self.surface_config.height],
+        )
+    }
+
+    /// Grabs a handle of the selected instance's gizmo at `cursor`, if one is there.
+    fn grab_gizmo(&mut self, cursor: winit::dpi::PhysicalPosition<f64>) -> bool {
+        let Some(instance) = self
+            .selected
+            .filter(|_| self.ui.visible)
+            .and_then(|index| self.instances.get(index).copied())
+        else {
+            return false;
+        };
+        let view = self.gizmo_view();
+        self.gizmo
+            .grab(&view, &instance, [cursor.x as f32, cursor.y as f32])
+    }
+
+    fn drag_gizmo(&mut self, cursor: winit::dpi::PhysicalPosition<f64>) {
+        let view = self.gizmo_view();
+        let instance = self.gizmo.drag(&view, [cursor.x as f32, cursor.y as f32]);
+        if let (Some(index), Some(instance)) = (self.selected, instance) {
+            self.set_instance(index, instance);
+        }
+    }
+
+    /// Ends a drag, recording it as one edit.
+    fn release_gizmo(&mut self) {
+        let (Some(before), Some(index)) = (self.gizmo.release(), self.selected) else {
+            return;
+        };
+        self.history.push(Edit {
+            index,
+            before,
+            after: self.instances[index],
+        });
+    }
+
+    /// Turns picking on or off. Without it, the G-buffer's IDs aren't kept and nothing
+    /// is selected.
+    pub fn set_picking(&mut self, picking: bool) {
+        self.picking = picking;
+        if !picking {
+            self.release_gizmo();
+            self.selected = None;
+        }
+    }
+
+    pub fn set_transparency_mode(&mut self, mode: TransparencyMode) {
+        self.transparency = mode;
+        info!("Transparency: {:?}", mode);
+    }
+
+    pub fn set_debug_view(&mut self, view: DebugView) {
+        self.debug_view = view;
+        info!("Debug view: {:?}", view);
+    }
+
+    /// Each pass's average time per frame over the last few frames, measured on the GPU
+    /// where the device supports timestamps and on the CPU otherwise.
+    pub fn pass_timings(&self) -> Vec<(String, std::time::Duration)> {
+        self.profiler.averages()
+    }
+
+    /// Records the passes of the next `frames` frames, then saves them as a Chrome trace.
+    pub fn capture_trace(&mut self, frames: u64) {
+        self.profiler.capture(frames);
+        info!("Capturing a trace of {} frames", frames);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save_trace(&mut self, trace: String) {
+        let filename = format!("trace_{:04}.json", self.traces_saved);
+        self.traces_saved += 1;
+        match std::fs::write(&filename, trace) {
+            Ok(()) => println!("Saved {}", filename),
+            Err(e) => eprintln!("Failed to save {}: {}", filename, e),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn save_trace(&mut self, trace: String) {
+        let filename = format!("trace_{:04}.json", self.traces_saved);
+        self.traces_saved += 1;
+        if let Err(e) = trigger_browser_download(trace.as_bytes(), &filename, "application/json") {
+            web_sys::console::error_1(&format!("Trace download failed: {e:?}").into());
+        }
+    }
+
+    /// Changes the alpha settings of one of the model's materials, regrouping the GPU
+    /// scene's draws if it moves to another pass.
+    pub fn set_material_params(&mut self, material: usize, params: MaterialParams) {
+        let Some(model) = self.obj_model.as_mut() else {
+            return;
+        };
+        let Some(material) = model.materials.get_mut(material) else {
+            return;
+        };
+        let regroup = material.params.alpha_mode != params.alpha_mode;
+        material.set_params(&self.queue, params);
+        if regroup {
+            self.gpu_scene = Some(GpuScene::new(
+                &self.device,
+                &self.queue,
+                model,
+                self.instances.len() as u32,
+            ));
+        }
+    }
+
+    /// Lays out the debug UI, editing the renderer's settings in place.
+    fn show_ui(&mut self, context: &egui::Context) {
+        egui::Window::new("Renderer")
+            .anchor(egui::Align2::RIGHT_TOP, [-8.0, 8.0])
+            .default_width(280.0)
+            .show(context, |ui| {
+                ui.collapsing("Camera", |ui| self.camera_ui(ui));
+                ui.collapsing("Light", |ui| self.light_ui(ui));
+                ui.collapsing("Materials", |ui| self.materials_ui(ui));
+                ui.collapsing("Render settings", |ui| self.render_settings_ui(ui));
+                ui.collapsing("Selection", |ui| self.selection_ui(ui));
+                ui.collapsing("Debug views", |ui| self.debug_view_ui(ui));
+                ui.horizontal(|ui| {
+                    if ui.button("Save scene").clicked() {
+                        self.save_scene_file();
+                    }
+                    if ui.button("Load scene").clicked() {
+                        self.open_scene(SCENE_FILE);
+                    }
+                });
+            });
+
+        // Behind the windows, over the scene.
+        if let Some(instance) = self.selected.and_then(|index| self.instances.get(index)) {
+            let painter = context.layer_painter(egui::LayerId::background());
+            self.gizmo.paint(
+                &painter,
+                &self.gizmo_view(),
+                instance,
+                context.pixels_per_point(),
+            );
+        }
+    }
+
+    fn camera_ui(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("camera").num_columns(2).show(ui, |ui| {
+            ui.label("Eye");
+            drag_xyz(ui, self.camera.eye.as_mut());
+            ui.end_row();
+            ui.label("Target");
+            drag_xyz(ui, self.camera.target.as_mut());
+            ui.end_row();
+            ui.label("Speed");
+            ui.add(
+                egui::DragValue::new(&mut self.camera_controller.speed)
+                    .speed(0.01)
+                    .range(0.0..=10.0),
+            );
+            ui.end_row();
+            ui.label("Field of view");
+            ui.add(egui::Slider::new(&mut self.camera.fov_y, 10.0..=120.0).suffix("°"));
+            ui.end_row();
+            ui.label("Near");
+            ui.add(
+                egui::DragValue::new(&mut self.camera.z_near)
+                    .speed(0.01)
+                    .range(0.001..=self.camera.z_far),
+            );
+            ui.end_row();
+            ui.label("Far");
+            ui.add(
+                egui::DragValue::new(&mut self.camera.z_far).range(self.camera.z_near..=10000.0),
+            );
+            ui.end_row();
+        });
+    }
+
+    fn light_ui(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("light").num_columns(2).show(ui, |ui| {
+            ui.label("Position");
+            drag_xyz(ui, &mut self.light.position);
+            ui.end_row();
+            ui.label("Colour");
+            ui.color_edit_button_rgb(&mut self.light.colour);
+            ui.end_row();
+            ui.label("Orbit");
+            ui.checkbox(&mut self.orbit_light, "");
+            ui.end_row();
+        });
+    }
+
+    fn materials_ui(&mut self, ui: &mut egui::Ui) {
+        let Some(model) = &self.obj_model else {
+            ui.label("Loading…");
+            return;
+        };
+        let mut changed = None;
+        egui::ScrollArea::vertical()
+            .max_height(240.0)
+            .show(ui, |ui| {
+                for (index, material) in model.materials.iter().enumerate() {
+                    let mut params = material.params;
+                    ui.push_id(index, |ui| {
+                        ui.strong(&material.name);
+                        egui::ComboBox::from_label("Alpha mode")
+                            .selected_text(format!("{:?}", params.alpha_mode))
+                            .show_ui(ui, |ui| {
+                                for mode in [AlphaMode::Opaque, AlphaMode::Mask, AlphaMode::Blend] {
+                                    ui.selectable_value(
+                                        &mut params.alpha_mode,
+                                        mode,
+                                        format!("{:?}", mode),
+                                    );
+                                }
+                            });
+                        ui.add(
+                            egui::Slider::new(&mut params.alpha_cutoff, 0.0..=1.0)
+                                .text("Alpha cutoff"),
+                        );
+                        ui.add(egui::Slider::new(&mut params.opacity, 0.0..=1.0).text("Opacity"));
+                    });
+                    if params != material.params {
+                        changed = Some((index, params));
+                    }
+                }
+            });
+        if let Some((material, params)) = changed {
+            self.set_material_params(material, params);
+        }
+    }
+
+    fn render_settings_ui(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("render_settings")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Background");
+                let mut bg_color = [
+                    self.bg_color.r as f32,
+                    self.bg_color.g as f32,
+                    self.bg_color.b as f32,
+                ];
+                if ui.color_edit_button_rgb(&mut bg_color).changed() {
+                    let [r, g, b] = bg_color.map(f64::from);
+                    self.bg_color = wgpu::Color { r, g, b, a: 1.0 };
+                }
+                ui.end_row();
 
-        self.render_material = {
-            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: None,
-                layout: &self.output_render_pipeline.get_bind_group_layout(0),
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: wgpu::BindingResource::TextureView(&screen_normal_texture.view),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 3,
-                        resource: wgpu::BindingResource::Sampler(&screen_normal_texture.sampler),
-                    },
-                ],
+                ui.label("Render scale");
+                let mut render_scale = self.render_scale;
+                if ui
+                    .add(egui::Slider::new(&mut render_scale, 0.25..=3.0))
+                    .changed()
+                {
+                    self.set_render_scale(render_scale);
+                }
+                ui.end_row();
+
+                ui.label("Instance spacing");
+                let mut spacing = self.instance_spacing;
+                if ui
+                    .add(egui::Slider::new(&mut spacing, 0.5..=10.0))
+                    .changed()
+                {
+                    self.set_instance_spacing(spacing);
+                }
+                ui.end_row();
+
+                ui.label("Transparency");
+                let mut transparency = self.transparency;
+                egui::ComboBox::from_id_salt("transparency")
+                    .selected_text(format!("{:?}", transparency))
+                    .show_ui(ui, |ui| {
+                        for mode in [TransparencyMode::Sorted, TransparencyMode::WeightedBlended] {
+                            ui.selectable_value(&mut transparency, mode, format!("{:?}", mode));
+                        }
+                    });
+                if transparency != self.transparency {
+                    self.set_transparency_mode(transparency);
+                }
+                ui.end_row();
+
+                ui.label("GPU-driven");
+                ui.checkbox(&mut self.gpu_driven, "");
+                ui.end_row();
+                ui.label("Bindless");
+                ui.add_enabled(
+                    self.bindless_materials.is_some(),
+                    egui::Checkbox::without_text(&mut self.bindless),
+                );
+                ui.end_row();
+                ui.label("Stats overlay");
+                ui.checkbox(&mut self.stats.visible, "");
+                ui.end_row();
             });
+    }
 
-            Material {
-                name: String::from("Output Quad Textures"),
-                textures: HashMap::from_iter([
-                    ("ss_diffuse".to_owned(), diffuse_texture),
-                    ("ss_specular".to_owned(), screen_normal_texture),
-                ]),
-                bind_group,
+    fn selection_ui(&mut self, ui: &mut egui::Ui) {
+        let mut picking = self.picking;
+        if ui.checkbox(&mut picking, "Pick with the mouse").changed() {
+            self.set_picking(picking);
+        }
+        match self
+            .selected
+            .and_then(|index| Some((index, self.instances.get(index)?)))
+        {
+            Some((index, instance)) => {
+                let position = instance.position;
+                ui.label(format!(
+                    "Instance {} at ({:.2}, {:.2}, {:.2})",
+                    index, position.x, position.y, position.z
+                ));
             }
-        };
+            None => {
+                ui.label("Nothing selected");
+            }
+        }
+        ui.horizontal(|ui| {
+            for (&mode, key) in GizmoMode::ALL.iter().zip(1..) {
+                ui.selectable_value(&mut self.gizmo.mode, mode, format!("{:?} ({})", mode, key));
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(self.history.can_undo(), egui::Button::new("Undo"))
+                .clicked()
+            {
+                self.undo();
+            }
+            if ui
+                .add_enabled(self.history.can_redo(), egui::Button::new("Redo"))
+                .clicked()
+            {
+                self.redo();
+            }
+        });
     }
 
-    pub fn input(&mut self, event: &WindowEvent) -> bool {
-        self.camera_controller.process_inputs(event) || self.process_inputs(event)
+    fn debug_view_ui(&mut self, ui: &mut egui::Ui) {
+        let mut debug_view = self.debug_view;
+        egui::ComboBox::from_label("View")
+            .selected_text(format!("{:?}", debug_view))
+            .show_ui(ui, |ui| {
+                for view in DebugView::ALL {
+                    ui.selectable_value(&mut debug_view, view, format!("{:?}", view));
+                }
+            });
+        if debug_view != self.debug_view {
+            self.set_debug_view(debug_view);
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Capture frame").clicked() {
+                self.capture_next_frame = true;
+            }
+            if ui.button("Capture trace").clicked() {
+                self.capture_trace(TRACE_FRAMES);
+            }
+        });
     }
 
-    fn process_inputs(&mut self, event: &WindowEvent) -> bool {
-        if let WindowEvent::KeyboardInput { event: key, .. } = event {
-            if let PhysicalKey::Code(code) = key.physical_key {
-                if code == KeyCode::Backspace && key.state.is_pressed() {
-                    self.capture_next_frame = true;
-                    return true;
-                }
+    pub fn update(&mut self) -> Result<()> {
+        if let Some(result) = self
+            .scene_load
+            .as_mut()
+            .and_then(|receiver| receiver.try_recv().ok().flatten())
+        {
+            self.scene_load = None;
+            match result {
+                Ok(scene) => self.load_scene(scene),
+                Err(e) => log::error!("{}", e),
             }
         }
 
-        false
-    }
+        self.receive_assets()?;
+
+        for readback in self.picker.poll() {
+            let pick = self
+                .obj_model
+                .as_ref()
+                .and_then(|model| readback.resolve(&self.instances, &model.meshes));
+            self.selected = pick.map(|pick| pick.instance_index);
+            (readback.callback)(pick.as_ref());
+        }
 
-    pub fn update(&mut self) {
-        let old_position: cgmath::Vector3<_> = self.light.position.into();
-        self.light.position =
-            (cgmath::Quaternion::from_axis_angle((0.0, 1.0, 0.0).into(), cgmath::Deg(1.0))
-                * old_position)
-                .into();
+        if self.ui.visible {
+            let input = self.ui.take_input(&self.window);
+            let context = self.ui.context().clone();
+            let output = context.run(input, |context| self.show_ui(context));
+            self.ui.finish(
+                &self.window,
+                &self.device,
+                &self.queue,
+                [self.surface_config.width, self.surface_config.height],
+                output,
+            );
+        }
+
+        if self.orbit_light {
+            let old_position: cgmath::Vector3<_> = self.light.position.into();
+            self.light.position =
+                (cgmath::Quaternion::from_axis_angle((0.0, 1.0, 0.0).into(), cgmath::Deg(1.0))
+                    * old_position)
+                    .into();
+        }
         self.queue
             .write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[self.light]));
 
@@ -687,30 +1950,106 @@ impl<'a> State<'a> {
             0,
             bytemuck::cast_slice(&[self.uniforms]),
         );
+
+        Ok(())
+    }
+
+    fn receive_assets(&mut self) -> Result<()> {
+        while let Some(event) = self.asset_loader.poll() {
+            match event {
+                LoadEvent::Pipelines(pipelines) => {
+                    info!("Scene pipelines loaded");
+                    self.pipelines = Some(pipelines);
+                }
+                LoadEvent::Model(model) => {
+                    info!("Model loaded");
+                    let gpu_scene = GpuScene::new(
+                        &self.device,
+                        &self.queue,
+                        &model,
+                        self.instances.len() as u32,
+                    );
+                    info!("Merged {} draws into one GPU scene", gpu_scene.draw_count());
+                    self.gpu_scene = Some(gpu_scene);
+                    self.debug_meshes = Some(DebugMeshes::new(
+                        &self.device,
+                        &self.layouts.debug_mesh,
+                        &model,
+                    ));
+                    self.obj_model = Some(model);
+                    self.rebind_bindless_materials();
+                }
+                LoadEvent::Texture {
+                    material,
+                    kind,
+                    texture,
+                } => self.pending_textures.push((material, kind, texture)),
+                LoadEvent::Failed(e) => return Err(e),
+            }
+        }
+
+        // Textures can finish before the meshes that use them, so hold on to them
+        // until the model they belong to has arrived.
+        if let Some(model) = self.obj_model.as_mut() {
+            if !self.pending_textures.is_empty() {
+                for (material, kind, texture) in self.pending_textures.drain(..) {
+                    if let Some(material) = model.materials.get_mut(material) {
+                        material.set_texture(&self.device, &self.layouts.texture, kind, texture);
+                    }
+                }
+                self.rebind_bindless_materials();
+            }
+        }
+
+        self.queue.write_buffer(
+            &self.loading_buffer,
+            0,
+            bytemuck::cast_slice(&[self.asset_loader.progress().fraction(), 0.0, 0.0, 0.0]),
+        );
+
+        Ok(())
+    }
+
+    /// Rebuilds the bindless material bind group from the model's current textures.
+    fn rebind_bindless_materials(&mut self) {
+        self.bindless_materials = match (&self.layouts.bindless, &self.obj_model) {
+            (Some(layout), Some(model)) => {
+                let materials = BindlessMaterials::new(&self.device, layout, &model.materials);
+                if materials.is_none() {
+                    info!(
+                        "{} materials don't fit in a bindless bind group of {}",
+                        model.materials.len(),
+                        layout.capacity
+                    );
+                }
+                materials
+            }
+            _ => None,
+        };
     }
 
-    pub fn render(&mut self) {
+    pub fn render(&mut self) -> Result<()> {
         let frame = match self.surface.get_current_texture() {
             wgpu::CurrentSurfaceTexture::Success(surface_texture) => surface_texture,
             wgpu::CurrentSurfaceTexture::Lost
             | wgpu::CurrentSurfaceTexture::Outdated
             | wgpu::CurrentSurfaceTexture::Suboptimal(_) => {
                 self.resize(self.size);
-                return;
+                return Ok(());
             }
             wgpu::CurrentSurfaceTexture::Timeout | wgpu::CurrentSurfaceTexture::Occluded => {
-                return;
-            }
-            wgpu::CurrentSurfaceTexture::Validation => {
-                panic!("Validation error when acquiring next surface texture!")
+                return Ok(());
             }
+            wgpu::CurrentSurfaceTexture::Validation => return Err(Error::SurfaceValidation),
         };
+        self.stats.frame();
 
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
+        let mut profile = self.profiler.begin("Render Encoder");
 
         let frame_view = frame.texture.create_view(&wgpu::TextureViewDescriptor {
             label: Some("Render Texture View"),
@@ -723,7 +2062,7 @@ impl<'a> State<'a> {
                 label: Some("Frame render pass"),
                 color_attachments: &[
                     Some(wgpu::RenderPassColorAttachment {
-                        view: &self.render_material.textures["ss_diffuse"].view,
+                        view: &self.targets.diffuse.view,
                         resolve_target: None,
                         ops: wgpu::Operations {
                             load: wgpu::LoadOp::Clear(self.bg_color),
@@ -732,7 +2071,7 @@ impl<'a> State<'a> {
                         depth_slice: None,
                     }),
                     Some(wgpu::RenderPassColorAttachment {
-                        view: &self.render_material.textures["ss_specular"].view,
+                        view: &self.targets.specular.view,
                         resolve_target: None,
                         ops: wgpu::Operations {
                             load: wgpu::LoadOp::Clear(self.bg_color),
@@ -740,9 +2079,23 @@ impl<'a> State<'a> {
                         },
                         depth_slice: None,
                     }),
+                    // The pipelines always write IDs, but they are only kept for picking.
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: &self.targets.ids.view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: if self.picking {
+                                wgpu::StoreOp::Store
+                            } else {
+                                wgpu::StoreOp::Discard
+                            },
+                        },
+                        depth_slice: None,
+                    }),
                 ],
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture.view,
+                    view: &self.targets.depth.view,
                     depth_ops: Some(wgpu::Operations {
                         load: wgpu::LoadOp::Clear(1.0),
                         store: wgpu::StoreOp::Store,
@@ -752,39 +2105,171 @@ impl<'a> State<'a> {
                         store: wgpu::StoreOp::Store,
                     }),
                 }),
-                timestamp_writes: None,
+                timestamp_writes: profile.render_pass("G-buffer"),
                 occlusion_query_set: None,
                 multiview_mask: None,
             });
-            render_pass.set_stencil_reference(32);
-            render_pass.set_pipeline(&self.light_render_pipeline);
-            render_pass.draw_light_model(
-                &self.obj_model,
-                &self.uniform_bind_group,
-                &self.light_bind_group,
-            );
 
-            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-            render_pass.set_stencil_reference(64);
-            render_pass.set_pipeline(&self.deferred_render_pipeline);
-            render_pass.draw_model_instanced(
-                &self.obj_model,
-                0..self.instances.len() as u32,
-                &self.uniform_bind_group,
-                &self.light_bind_group,
+            if let (Some(pipelines), Some(obj_model)) = (&self.pipelines, &self.obj_model) {
+                let vertex_layout = obj_model.vertex_layout;
+                render_pass.set_stencil_reference(32);
+                render_pass.set_pipeline(pipelines.light.get(vertex_layout));
+                render_pass.draw_light_model(
+                    obj_model,
+                    &self.uniform_bind_group,
+                    &self.light_bind_group,
+                );
+
+                render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+                render_pass.set_stencil_reference(64);
+                let debug_materials = match self.debug_view {
+                    DebugView::UvChecker => Some(&pipelines.uv_checker),
+                    DebugView::MipLevel => Some(&pipelines.mip_level),
+                    _ => None,
+                };
+                match (&self.gpu_scene, debug_materials) {
+                    (_, Some(debug_materials)) => {
+                        // Blended materials are drawn here too, as nothing is lit for
+                        // them to blend over.
+                        render_pass.set_pipeline(debug_materials.get(vertex_layout));
+                        self.draw_all_alpha_modes(&mut render_pass, obj_model);
+                    }
+                    (Some(gpu_scene), None) if self.gpu_driven => {
+                        // Bindless materials can't be masked, so only opaque ones use them.
+                        let bindless = self
+                            .bindless_materials
+                            .as_ref()
+                            .filter(|_| self.bindless)
+                            .zip(pipelines.deferred_bindless.as_ref());
+                        render_pass.set_pipeline(match bindless {
+                            Some((_, pipelines)) => pipelines.get(vertex_layout),
+                            None => pipelines.deferred.get(vertex_layout),
+                        });
+                        render_pass.draw_gpu_scene(
+                            gpu_scene,
+                            obj_model,
+                            self.draw_mode,
+                            AlphaMode::Opaque,
+                            &self.uniform_bind_group,
+                            &self.light_bind_group,
+                            bindless.map(|(materials, _)| &materials.bind_group),
+                        );
+                        render_pass.set_pipeline(pipelines.deferred_masked.get(vertex_layout));
+                        render_pass.draw_gpu_scene(
+                            gpu_scene,
+                            obj_model,
+                            self.draw_mode,
+                            AlphaMode::Mask,
+                            &self.uniform_bind_group,
+                            &self.light_bind_group,
+                            None,
+                        );
+                    }
+                    _ => {
+                        for (alpha_mode, pipeline) in [
+                            (AlphaMode::Opaque, &pipelines.deferred),
+                            (AlphaMode::Mask, &pipelines.deferred_masked),
+                        ] {
+                            render_pass.set_pipeline(pipeline.get(vertex_layout));
+                            self.draw_alpha_mode(&mut render_pass, obj_model, alpha_mode);
+                        }
+                    }
+                }
+
+                if let Some(selected) = self.selected.filter(|&i| i < self.instances.len()) {
+                    let selected = selected as u32;
+                    render_pass.set_stencil_reference(picking::SELECTION_STENCIL);
+                    for (alpha_mode, pipeline) in [
+                        (AlphaMode::Opaque, &pipelines.selection_stencil),
+                        (AlphaMode::Mask, &pipelines.selection_stencil_masked),
+                    ] {
+                        render_pass.set_pipeline(pipeline.get(vertex_layout));
+                        render_pass.draw_model_alpha_instanced(
+                            obj_model,
+                            alpha_mode,
+                            selected..selected + 1,
+                            &self.uniform_bind_group,
+                            &self.light_bind_group,
+                        );
+                    }
+                }
+            }
+        }
+        profile.end_pass();
+
+        if let Some(pipelines) = self.pipelines.as_ref().filter(|_| self.picking) {
+            self.picker.encode(
+                &self.device,
+                &self.queue,
+                &mut encoder,
+                &mut profile,
+                &self.targets,
+                &pipelines.pick_depth,
+                &self.screen_quad,
+                self.camera.build_view_projection_matrix(),
             );
         }
 
         if self.capture_next_frame {
-            let diffuse_texture = &self.render_material.textures["ss_diffuse"].texture;
-            self.read_texture_to_cpu(diffuse_texture, "ss_diffuse");
-
-            let specular_texture = &self.render_material.textures["ss_specular"].texture;
-            self.read_texture_to_cpu(specular_texture, "ss_specular");
+            self.read_texture_to_cpu(&self.targets.diffuse.texture, "ss_diffuse");
+            self.read_texture_to_cpu(&self.targets.specular.texture, "ss_specular");
 
             self.capture_next_frame = false;
         }
 
+        if self.debug_view == DebugView::Overdraw {
+            self.render_overdraw(&mut encoder, &mut profile);
+        }
+
+        if let Some(pipelines) = &self.pipelines {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Output render pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.targets.scene.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.bg_color),
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: profile.render_pass("Output"),
+                occlusion_query_set: None,
+                multiview_mask: None,
+            });
+            match self.debug_view.screen_view() {
+                Some(view) => {
+                    self.queue.write_buffer(
+                        &self.targets.debug_view_buffer,
+                        0,
+                        bytemuck::bytes_of(&DebugViewUniform::new(view, &self.camera)),
+                    );
+                    draw_screen_quad(
+                        &mut render_pass,
+                        &self.screen_quad,
+                        &pipelines.debug_view,
+                        &self.targets.debug_view_bind_group,
+                    );
+                }
+                None => draw_screen_quad(
+                    &mut render_pass,
+                    &self.screen_quad,
+                    &pipelines.output,
+                    &self.targets.gbuffer_bind_group,
+                ),
+            }
+            profile.end_pass();
+        }
+
+        if self.debug_view.draws_transparency() {
+            self.render_transparent(&mut encoder, &mut profile);
+        }
+        self.render_debug_overlay(&mut encoder, &mut profile);
+        if self.selected.is_some() {
+            self.render_selection(&mut encoder, &mut profile);
+        }
+
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Post render pass"),
@@ -798,24 +2283,443 @@ impl<'a> State<'a> {
                     depth_slice: None,
                 })],
                 depth_stencil_attachment: None,
-                timestamp_writes: None,
+                timestamp_writes: profile.render_pass("Post"),
                 occlusion_query_set: None,
                 multiview_mask: None,
             });
 
-            render_pass.set_pipeline(&self.output_render_pipeline);
-            render_pass.set_bind_group(0, &self.render_material.bind_group, &[]);
+            if let Some(pipelines) = &self.pipelines {
+                draw_screen_quad(
+                    &mut render_pass,
+                    &self.screen_quad,
+                    &pipelines.blit,
+                    &self.targets.scene_bind_group,
+                );
+            }
+
+            if !self.asset_loader.progress().is_done() {
+                draw_screen_quad(
+                    &mut render_pass,
+                    &self.screen_quad,
+                    &self.loading_pipeline,
+                    &self.loading_bind_group,
+                );
+            }
+            profile.end_pass();
+        }
 
-            render_pass.set_vertex_buffer(0, self.screen_quad.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(
-                self.screen_quad.index_buffer.slice(..),
-                wgpu::IndexFormat::Uint32,
+        if let Some(pipelines) = self.pipelines.as_ref().filter(|_| self.stats.visible) {
+            let info = FrameInfo {
+                draws: self.draw_stats(),
+                passes: &self.profiler.averages(),
+                gpu_timestamps: self.profiler.has_timestamps(),
+                adapter: &self.adapter_info,
+                render_scale: self.render_scale,
+                render_size: {
+                    let size = self.targets.scene.texture.size();
+                    [size.width, size.height]
+                },
+                camera_position: self.camera.eye,
+            };
+            self.stats.draw(&mut self.text, &info);
+            self.text.prepare(
+                &self.device,
+                &self.queue,
+                [self.surface_config.width, self.surface_config.height],
             );
-            render_pass.draw_indexed(0..self.screen_quad.num_elements, 0, 0..1);
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Overlay render pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &frame_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: profile.render_pass("Overlay"),
+                occlusion_query_set: None,
+                multiview_mask: None,
+            });
+            render_pass.set_pipeline(&pipelines.text);
+            render_pass.draw_text(&self.text, &self.screen_quad);
+            profile.end_pass();
+        }
+
+        if let Some(pipelines) = self.pipelines.as_ref().filter(|_| self.ui.visible) {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Debug UI render pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &frame_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: profile.render_pass("Debug UI"),
+                occlusion_query_set: None,
+                multiview_mask: None,
+            });
+            render_pass.set_pipeline(&pipelines.ui);
+            render_pass.draw_ui(&self.ui);
+            profile.end_pass();
         }
 
+        let timings = profile.resolve(&mut encoder);
         self.queue.submit(std::iter::once(encoder.finish()));
+        timings.read_back();
+        self.picker.read_back();
         frame.present();
+
+        // Hands over any timestamps that have been read back since the last frame.
+        self.device.poll(wgpu::PollType::Poll)?;
+        self.profiler.end_frame();
+        if let Some(trace) = self.profiler.take_trace() {
+            self.save_trace(trace);
+        }
+        Ok(())
+    }
+
+    /// What the frame's passes draw, following the same choices as [`State::render`].
+    fn draw_stats(&self) -> DrawStats {
+        let screen_quad = |calls| DrawStats::draws(calls, 1, 2);
+        let mut stats = DrawStats::default();
+        if !self.asset_loader.progress().is_done() {
+            stats += screen_quad(1);
+        }
+        let Some(pipelines) = &self.pipelines else {
+            return stats;
+        };
+        // The output and post passes.
+        stats += screen_quad(2);
+        let Some(obj_model) = &self.obj_model else {
+            return stats;
+        };
+
+        let instances = self.instances.len() as u32;
+        let alpha_mode_stats = |alpha_mode, bindless| match &self.gpu_scene {
+            Some(gpu_scene) if self.gpu_driven => {
+                gpu_scene.draw_stats(self.draw_mode, alpha_mode, bindless)
+            }
+            _ => obj_model.draw_stats(Some(alpha_mode), instances),
+        };
+        let all_alpha_modes = || {
+            let mut stats = DrawStats::default();
+            for alpha_mode in [AlphaMode::Opaque, AlphaMode::Mask, AlphaMode::Blend] {
+                stats += alpha_mode_stats(alpha_mode, false);
+            }
+            stats
+        };
+
+        // The light and the G-buffer.
+        stats += obj_model.draw_stats(None, 1);
+        match self.debug_view {
+            DebugView::UvChecker | DebugView::MipLevel => stats += all_alpha_modes(),
+            _ => {
+                let bindless = self.gpu_driven
+                    && self.gpu_scene.is_some()
+                    && self.bindless
+                    && self.bindless_materials.is_some()
+                    && pipelines.deferred_bindless.is_some();
+                stats += alpha_mode_stats(AlphaMode::Opaque, bindless);
+                stats += alpha_mode_stats(AlphaMode::Mask, false);
+            }
+        }
+
+        if self.debug_view == DebugView::Overdraw {
+            stats += all_alpha_modes();
+        }
+        if self.debug_view.draws_transparency() && obj_model.has_alpha_mode(AlphaMode::Blend) {
+            stats += alpha_mode_stats(AlphaMode::Blend, false);
+            if self.transparency == TransparencyMode::WeightedBlended {
+                stats += screen_quad(1);
+            }
+        }
+        let meshes = obj_model.meshes.len() as u32;
+        match (self.debug_view, &pipelines.wireframe) {
+            (DebugView::Wireframe, Some(_)) => stats += all_alpha_modes(),
+            (DebugView::Wireframe, None) => stats += obj_model.draw_stats(None, instances),
+            (DebugView::Vectors, _) => stats += DrawStats::draws(meshes, instances, 0),
+            _ => {}
+        }
+        if self.selected.is_some() {
+            // The selected instance again, marking the stencil, and the outline.
+            stats += obj_model.draw_stats(Some(AlphaMode::Opaque), 1);
+            stats += obj_model.draw_stats(Some(AlphaMode::Mask), 1);
+            stats += screen_quad(1);
+        }
+        stats
+    }
+
+    /// Draws the model's blended materials over the lit scene, either sorted back to front
+    /// or through weighted blended transparency.
+    fn render_transparent(&self, encoder: &mut wgpu::CommandEncoder, profile: &mut EncoderProfile) {
+        let (Some(pipelines), Some(obj_model)) = (&self.pipelines, &self.obj_model) else {
+            return;
+        };
+        if !obj_model.has_alpha_mode(AlphaMode::Blend) {
+            return;
+        }
+
+        let attachment = |view, load| {
+            Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load,
+                    store: wgpu::StoreOp::Store,
+                },
+                depth_slice: None,
+            })
+        };
+        let (pipeline, instance_buffer, color_attachments) = match self.transparency {
+            TransparencyMode::Sorted => {
+                let sorted = transparency::sort_back_to_front(&self.instances, self.camera.eye);
+                self.queue.write_buffer(
+                    &self.sorted_instance_buffer,
+                    0,
+                    bytemuck::cast_slice(&sorted),
+                );
+                (
+                    &pipelines.transparent,
+                    &self.sorted_instance_buffer,
+                    vec![attachment(&self.targets.scene.view, wgpu::LoadOp::Load)],
+                )
+            }
+            TransparencyMode::WeightedBlended => (
+                &pipelines.transparent_oit,
+                &self.instance_buffer,
+                vec![
+                    attachment(
+                        &self.targets.accum.view,
+                        wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    ),
+                    attachment(
+                        &self.targets.revealage.view,
+                        wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                    ),
+                ],
+            ),
+        };
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Transparent render pass"),
+                color_attachments: &color_attachments,
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.targets.depth.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    }),
+                }),
+                timestamp_writes: profile.render_pass("Transparent"),
+                occlusion_query_set: None,
+                multiview_mask: None,
+            });
+
+            render_pass.set_pipeline(pipeline.get(obj_model.vertex_layout));
+            render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+            self.draw_alpha_mode(&mut render_pass, obj_model, AlphaMode::Blend);
+        }
+        profile.end_pass();
+
+        if self.transparency == TransparencyMode::WeightedBlended {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("OIT composite render pass"),
+                color_attachments: &[attachment(&self.targets.scene.view, wgpu::LoadOp::Load)],
+                depth_stencil_attachment: None,
+                timestamp_writes: profile.render_pass("OIT composite"),
+                occlusion_query_set: None,
+                multiview_mask: None,
+            });
+            draw_screen_quad(
+                &mut render_pass,
+                &self.screen_quad,
+                &pipelines.oit_composite,
+                &self.targets.oit_bind_group,
+            );
+            profile.end_pass();
+        }
+    }
+
+    /// Counts the fragments every mesh draws at each pixel into the accumulation target,
+    /// hidden or not, for [`DebugView::Overdraw`].
+    fn render_overdraw(&self, encoder: &mut wgpu::CommandEncoder, profile: &mut EncoderProfile) {
+        let (Some(pipelines), Some(obj_model)) = (&self.pipelines, &self.obj_model) else {
+            return;
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Overdraw render pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.targets.accum.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: profile.render_pass("Overdraw"),
+            occlusion_query_set: None,
+            multiview_mask: None,
+        });
+        render_pass.set_pipeline(pipelines.overdraw.get(obj_model.vertex_layout));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        self.draw_all_alpha_modes(&mut render_pass, obj_model);
+        profile.end_pass();
+    }
+
+    /// Draws the wireframe or vector lines over the scene, if the debug view is one of
+    /// them, tested against the depth of the opaque meshes.
+    fn render_debug_overlay(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        profile: &mut EncoderProfile,
+    ) {
+        let (Some(pipelines), Some(obj_model), Some(debug_meshes)) =
+            (&self.pipelines, &self.obj_model, &self.debug_meshes)
+        else {
+            return;
+        };
+        if !matches!(self.debug_view, DebugView::Wireframe | DebugView::Vectors) {
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Debug overlay render pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.targets.scene.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.targets.depth.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+            }),
+            timestamp_writes: profile.render_pass("Debug overlay"),
+            occlusion_query_set: None,
+            multiview_mask: None,
+        });
+
+        let instances = 0..self.instances.len() as u32;
+        match (self.debug_view, &pipelines.wireframe) {
+            (DebugView::Wireframe, Some(wireframe)) => {
+                render_pass.set_pipeline(wireframe.get(obj_model.vertex_layout));
+                render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+                self.draw_all_alpha_modes(&mut render_pass, obj_model);
+            }
+            (DebugView::Wireframe, None) => {
+                render_pass.set_pipeline(&pipelines.wireframe_fallback);
+                render_pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+                render_pass.draw_debug_wireframe(debug_meshes, instances, &self.uniform_bind_group);
+            }
+            _ => {
+                render_pass.set_pipeline(&pipelines.vectors);
+                render_pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+                render_pass.draw_debug_vectors(debug_meshes, instances, &self.uniform_bind_group);
+            }
+        }
+        profile.end_pass();
+    }
+
+    /// Draws the model's meshes whose material has `alpha_mode` with the pipeline already
+    /// set, from the GPU scene when GPU-driven drawing is on. The instance buffer must
+    /// already be bound to slot 1.
+    /// Tints and outlines the selected instance over the scene, where the G-buffer pass
+    /// marked it in the stencil.
+    fn render_selection(&self, encoder: &mut wgpu::CommandEncoder, profile: &mut EncoderProfile) {
+        let Some(pipelines) = &self.pipelines else {
+            return;
+        };
+        self.queue.write_buffer(
+            &self.targets.selection_buffer,
+            0,
+            bytemuck::bytes_of(&SelectionUniform::new(self.render_scale)),
+        );
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Selection render pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.targets.scene.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+                depth_slice: None,
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: profile.render_pass("Selection"),
+            occlusion_query_set: None,
+            multiview_mask: None,
+        });
+        draw_screen_quad(
+            &mut render_pass,
+            &self.screen_quad,
+            &pipelines.selection,
+            &self.targets.selection_bind_group,
+        );
+        profile.end_pass();
+    }
+
+    fn draw_alpha_mode<'pass>(
+        &'pass self,
+        render_pass: &mut wgpu::RenderPass<'pass>,
+        obj_model: &'pass Model,
+        alpha_mode: AlphaMode,
+    ) {
+        match &self.gpu_scene {
+            Some(gpu_scene) if self.gpu_driven => render_pass.draw_gpu_scene(
+                gpu_scene,
+                obj_model,
+                self.draw_mode,
+                alpha_mode,
+                &self.uniform_bind_group,
+                &self.light_bind_group,
+                None,
+            ),
+            _ => render_pass.draw_model_alpha_instanced(
+                obj_model,
+                alpha_mode,
+                0..self.instances.len() as u32,
+                &self.uniform_bind_group,
+                &self.light_bind_group,
+            ),
+        }
+    }
+
+    /// Draws every mesh of the model, whatever its material's alpha mode, with the
+    /// pipeline already set.
+    fn draw_all_alpha_modes<'pass>(
+        &'pass self,
+        render_pass: &mut wgpu::RenderPass<'pass>,
+        obj_model: &'pass Model,
+    ) {
+        for alpha_mode in [AlphaMode::Opaque, AlphaMode::Mask, AlphaMode::Blend] {
+            self.draw_alpha_mode(render_pass, obj_model, alpha_mode);
+        }
     }
 
     #[cfg(not(target_arch = "wasm32"))]
@@ -983,7 +2887,9 @@ impl<'a> State<'a> {
                 }
 
                 // Trigger a browser download
-                if let Err(e) = trigger_browser_download(&png_bytes, &format!("{name}.png")) {
+                if let Err(e) =
+                    trigger_browser_download(&png_bytes, &format!("{name}.png"), "image/png")
+                {
                     web_sys::console::error_1(
                         &format!("Screenshot download failed: {e:?}").into(),
                     );
@@ -993,8 +2899,36 @@ impl<'a> State<'a> {
     }
 }
 
+/// Logs what a click picked.
+fn log_pick(pick: Option<&Pick>) {
+    match pick {
+        Some(pick) => info!(
+            "Picked instance {} (mesh {}, material {}) at {:?}",
+            pick.instance_index, pick.mesh, pick.material, pick.position
+        ),
+        None => info!("Picked nothing"),
+    }
+}
+
+pub(crate) fn draw_screen_quad(
+    render_pass: &mut wgpu::RenderPass<'_>,
+    screen_quad: &Mesh,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+) {
+    render_pass.set_pipeline(pipeline);
+    render_pass.set_bind_group(0, bind_group, &[]);
+    render_pass.set_vertex_buffer(0, screen_quad.vertex_buffer.slice(..));
+    render_pass.set_index_buffer(screen_quad.index_buffer.slice(..), screen_quad.index_format);
+    render_pass.draw_indexed(0..screen_quad.num_elements, 0, 0..1);
+}
+
 #[cfg(target_arch = "wasm32")]
-fn trigger_browser_download(data: &[u8], filename: &str) -> Result<(), wasm_bindgen::JsValue> {
+fn trigger_browser_download(
+    data: &[u8],
+    filename: &str,
+    mime_type: &str,
+) -> std::result::Result<(), wasm_bindgen::JsValue> {
     use js_sys::{Array, Uint8Array};
     use wasm_bindgen::JsCast;
     use web_sys::{Blob, BlobPropertyBag, HtmlAnchorElement, Url};
@@ -1004,7 +2938,7 @@ fn trigger_browser_download(data: &[u8], filename: &str) -> Result<(), wasm_bind
     array.push(&uint8_array.buffer());
 
     let options = BlobPropertyBag::new();
-    options.set_type("image/png");
+    options.set_type(mime_type);
 
     let blob = Blob::new_with_u8_array_sequence_and_options(&array, &options)?;
     let url = Url::create_object_url_with_blob(&blob)?;
@@ -0,0 +1,198 @@
+//! The stats overlay, toggled with F1: frame rate and a graph of recent frame times, what
+//! the frame drew, the time each pass took, the adapter, the render scale and the camera
+//! position, laid out with [`TextRenderer`] in the top left corner of the window.
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::time::Duration;
+
+use web_time::Instant;
+
+use crate::text::{CELL_HEIGHT, TextRenderer};
+
+/// How many frames the frame time graph and frame rate cover.
+const GRAPH_FRAMES: usize = 120;
+/// Frame times at the top of the graph, and past it.
+const GRAPH_MAX: Duration = Duration::from_micros(33_333);
+const GRAPH_HEIGHT: f32 = 48.0;
+/// Each font pixel is this many screen pixels across.
+const TEXT_SCALE: u32 = 2;
+const MARGIN: f32 = 8.0;
+const PADDING: f32 = 6.0;
+
+const TEXT_COLOUR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const BACKGROUND_COLOUR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const FAST_COLOUR: [f32; 4] = [0.2, 0.9, 0.3, 1.0];
+const SLOW_COLOUR: [f32; 4] = [1.0, 0.8, 0.1, 1.0];
+const STALL_COLOUR: [f32; 4] = [1.0, 0.25, 0.2, 1.0];
+const TARGET_COLOUR: [f32; 4] = [1.0, 1.0, 1.0, 0.35];
+
+/// What a frame asked the GPU to draw.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DrawStats {
+    pub draw_calls: u32,
+    /// Instances drawn, summed over every draw call.
+    pub instances: u64,
+    pub triangles: u64,
+}
+
+impl DrawStats {
+    /// `calls` draws of `instances` instances, each of `triangles` triangles.
+    pub fn draws(calls: u32, instances: u32, triangles: u64) -> Self {
+        Self {
+            draw_calls: calls,
+            instances: calls as u64 * instances as u64,
+            triangles: calls as u64 * instances as u64 * triangles,
+        }
+    }
+}
+
+impl std::ops::AddAssign for DrawStats {
+    fn add_assign(&mut self, other: Self) {
+        self.draw_calls += other.draw_calls;
+        self.instances += other.instances;
+        self.triangles += other.triangles;
+    }
+}
+
+/// Everything besides frame times the overlay shows, gathered by the renderer.
+pub struct FrameInfo<'a> {
+    pub draws: DrawStats,
+    /// Each pass and its average time.
+    pub passes: &'a [(String, Duration)],
+    pub gpu_timestamps: bool,
+    pub adapter: &'a wgpu::AdapterInfo,
+    pub render_scale: f32,
+    pub render_size: [u32; 2],
+    pub camera_position: cgmath::Point3<f32>,
+}
+
+#[derive(Default)]
+pub struct StatsOverlay {
+    pub visible: bool,
+    frame_times: VecDeque<Duration>,
+    last_frame: Option<Instant>,
+}
+
+impl StatsOverlay {
+    /// Records the time since the last frame. Call once a frame, shown or not, so the
+    /// graph is full when the overlay is turned on.
+    pub fn frame(&mut self) {
+        let now = Instant::now();
+        if let Some(last_frame) = self.last_frame {
+            if self.frame_times.len() == GRAPH_FRAMES {
+                self.frame_times.pop_front();
+            }
+            self.frame_times.push_back(now - last_frame);
+        }
+        self.last_frame = Some(now);
+    }
+
+    /// The average frame time over the graph.
+    pub fn frame_time(&self) -> Duration {
+        let total: Duration = self.frame_times.iter().sum();
+        total / self.frame_times.len().max(1) as u32
+    }
+
+    /// Lays the overlay out into `text`.
+    pub fn draw(&self, text: &mut TextRenderer, info: &FrameInfo) {
+        let frame_time = self.frame_time().as_secs_f64();
+        let above_graph = format!(
+            "{:.1} FPS  {:.2} ms",
+            1.0 / frame_time.max(1e-6),
+            frame_time * 1e3
+        );
+
+        let mut lines = String::new();
+        let _ = writeln!(lines, "Draw calls {}", info.draws.draw_calls);
+        let _ = writeln!(lines, "Instances  {}", info.draws.instances);
+        let _ = writeln!(lines, "Triangles  {}", info.draws.triangles);
+        let _ = writeln!(
+            lines,
+            "Passes ({})",
+            if info.gpu_timestamps { "GPU" } else { "CPU" }
+        );
+        let label_width = info
+            .passes
+            .iter()
+            .map(|(label, _)| label.len())
+            .max()
+            .unwrap_or(0);
+        for (label, time) in info.passes {
+            let _ = writeln!(
+                lines,
+                "  {:width$} {:6.3} ms",
+                label,
+                time.as_secs_f64() * 1e3,
+                width = label_width
+            );
+        }
+        let _ = writeln!(
+            lines,
+            "{} ({})",
+            info.adapter.name,
+            info.adapter.backend.to_str()
+        );
+        let _ = writeln!(
+            lines,
+            "Render scale {:.2} ({}x{})",
+            info.render_scale, info.render_size[0], info.render_size[1]
+        );
+        let position = info.camera_position;
+        let _ = writeln!(
+            lines,
+            "Camera {:.2}, {:.2}, {:.2}",
+            position.x, position.y, position.z
+        );
+        let below_graph = lines;
+
+        let graph_width = (GRAPH_FRAMES as u32 * TEXT_SCALE) as f32;
+        let [above_width, above_height] = TextRenderer::measure(&above_graph, TEXT_SCALE);
+        let [below_width, below_height] = TextRenderer::measure(&below_graph, TEXT_SCALE);
+        let spacing = (CELL_HEIGHT * TEXT_SCALE / 2) as f32;
+        let width = above_width.max(below_width).max(graph_width);
+        let height = above_height + GRAPH_HEIGHT + below_height + spacing * 2.0;
+
+        let origin = [MARGIN + PADDING, MARGIN + PADDING];
+        text.rect(
+            [MARGIN, MARGIN],
+            [width + PADDING * 2.0, height + PADDING * 2.0],
+            BACKGROUND_COLOUR,
+        );
+        text.text(origin, TEXT_SCALE, TEXT_COLOUR, &above_graph);
+
+        let graph_top = origin[1] + above_height + spacing;
+        let bar_width = graph_width / GRAPH_FRAMES as f32;
+        let graph_bottom = graph_top + GRAPH_HEIGHT;
+        for (index, time) in self.frame_times.iter().enumerate() {
+            let fraction = (time.as_secs_f32() / GRAPH_MAX.as_secs_f32()).min(1.0);
+            let colour = match fraction {
+                f if f <= 0.5 => FAST_COLOUR,
+                f if f < 1.0 => SLOW_COLOUR,
+                _ => STALL_COLOUR,
+            };
+            let bar_height = (fraction * GRAPH_HEIGHT).max(1.0);
+            text.rect(
+                [
+                    origin[0] + index as f32 * bar_width,
+                    graph_bottom - bar_height,
+                ],
+                [bar_width, bar_height],
+                colour,
+            );
+        }
+        // A line at 60 FPS, halfway up.
+        text.rect(
+            [origin[0], graph_top + GRAPH_HEIGHT * 0.5],
+            [graph_width, 1.0],
+            TARGET_COLOUR,
+        );
+
+        text.text(
+            [origin[0], graph_bottom + spacing],
+            TEXT_SCALE,
+            TEXT_COLOUR,
+            &below_graph,
+        );
+    }
+}
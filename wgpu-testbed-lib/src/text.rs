@@ -0,0 +1,375 @@
+//! Screen-space text and rectangles, for overlays.
+//!
+//! Glyphs come from a built-in 5×7 pixel font covering printable ASCII, packed into a
+//! small single-channel atlas. Every glyph and rectangle is an instance of the screen quad
+//! placed in pixels, and glyphs are scaled by whole pixels so they stay sharp. Rectangles
+//! fill themselves from a solid cell after the last glyph, so backgrounds, graphs and
+//! text all go through `text.wgsl` in a single draw.
+
+use wgpu::util::DeviceExt;
+
+use crate::model::Mesh;
+use crate::pipeline::{Bindable, Binder};
+
+/// The size of a glyph in the font, in pixels.
+pub const GLYPH_WIDTH: u32 = 5;
+pub const GLYPH_HEIGHT: u32 = 7;
+/// The space each glyph takes up in the atlas and in a line of text, leaving a pixel
+/// between neighbouring glyphs and lines.
+pub const CELL_WIDTH: u32 = GLYPH_WIDTH + 1;
+pub const CELL_HEIGHT: u32 = GLYPH_HEIGHT + 1;
+const ATLAS_COLUMNS: u32 = 16;
+const FIRST_CHAR: char = ' ';
+/// The atlas cell after the last glyph, which is filled for rectangles.
+const SOLID_CELL: u32 = GLYPHS.len() as u32;
+
+/// Each row of each glyph from `' '` to `'~'`, top to bottom, with the leftmost pixel in
+/// the highest of the five bits.
+#[rustfmt::skip]
+const GLYPHS: [[u8; GLYPH_HEIGHT as usize]; 95] = [
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000], // space
+    [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100], // '!'
+    [0b01010, 0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000], // '"'
+    [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010], // '#'
+    [0b00100, 0b01111, 0b10100, 0b01110, 0b00101, 0b11110, 0b00100], // '$'
+    [0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011], // '%'
+    [0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101], // '&'
+    [0b00100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000], // '\''
+    [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010], // '('
+    [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000], // ')'
+    [0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000], // '*'
+    [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000], // '+'
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000], // ','
+    [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000], // '-'
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100], // '.'
+    [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000], // '/'
+    [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110], // '0'
+    [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // '1'
+    [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111], // '2'
+    [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110], // '3'
+    [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010], // '4'
+    [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110], // '5'
+    [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110], // '6'
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000], // '7'
+    [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110], // '8'
+    [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100], // '9'
+    [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000], // ':'
+    [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b00100, 0b01000], // ';'
+    [0b00010, 0b00100, 0b01000, 0b10000, 0b01000, 0b00100, 0b00010], // '<'
+    [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000], // '='
+    [0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000], // '>'
+    [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100], // '?'
+    [0b01110, 0b10001, 0b00001, 0b01101, 0b10101, 0b10101, 0b01110], // '@'
+    [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001], // 'A'
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110], // 'B'
+    [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110], // 'C'
+    [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100], // 'D'
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111], // 'E'
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000], // 'F'
+    [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111], // 'G'
+    [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001], // 'H'
+    [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // 'I'
+    [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100], // 'J'
+    [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001], // 'K'
+    [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111], // 'L'
+    [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001], // 'M'
+    [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001], // 'N'
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110], // 'O'
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000], // 'P'
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101], // 'Q'
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001], // 'R'
+    [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110], // 'S'
+    [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100], // 'T'
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110], // 'U'
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100], // 'V'
+    [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010], // 'W'
+    [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001], // 'X'
+    [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100], // 'Y'
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111], // 'Z'
+    [0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110], // '['
+    [0b00000, 0b10000, 0b01000, 0b00100, 0b00010, 0b00001, 0b00000], // '\\'
+    [0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110], // ']'
+    [0b00100, 0b01010, 0b10001, 0b00000, 0b00000, 0b00000, 0b00000], // '^'
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111], // '_'
+    [0b01000, 0b00100, 0b00010, 0b00000, 0b00000, 0b00000, 0b00000], // '`'
+    [0b00000, 0b00000, 0b01110, 0b00001, 0b01111, 0b10001, 0b01111], // 'a'
+    [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b11110], // 'b'
+    [0b00000, 0b00000, 0b01110, 0b10000, 0b10000, 0b10001, 0b01110], // 'c'
+    [0b00001, 0b00001, 0b01101, 0b10011, 0b10001, 0b10001, 0b01111], // 'd'
+    [0b00000, 0b00000, 0b01110, 0b10001, 0b11111, 0b10000, 0b01110], // 'e'
+    [0b00110, 0b01001, 0b01000, 0b11100, 0b01000, 0b01000, 0b01000], // 'f'
+    [0b00000, 0b01111, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110], // 'g'
+    [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001], // 'h'
+    [0b00100, 0b00000, 0b01100, 0b00100, 0b00100, 0b00100, 0b01110], // 'i'
+    [0b00010, 0b00000, 0b00110, 0b00010, 0b00010, 0b10010, 0b01100], // 'j'
+    [0b10000, 0b10000, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010], // 'k'
+    [0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // 'l'
+    [0b00000, 0b00000, 0b11010, 0b10101, 0b10101, 0b10001, 0b10001], // 'm'
+    [0b00000, 0b00000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001], // 'n'
+    [0b00000, 0b00000, 0b01110, 0b10001, 0b10001, 0b10001, 0b01110], // 'o'
+    [0b00000, 0b00000, 0b11110, 0b10001, 0b11110, 0b10000, 0b10000], // 'p'
+    [0b00000, 0b00000, 0b01101, 0b10011, 0b01111, 0b00001, 0b00001], // 'q'
+    [0b00000, 0b00000, 0b10110, 0b11001, 0b10000, 0b10000, 0b10000], // 'r'
+    [0b00000, 0b00000, 0b01110, 0b10000, 0b01110, 0b00001, 0b11110], // 's'
+    [0b01000, 0b01000, 0b11100, 0b01000, 0b01000, 0b01001, 0b00110], // 't'
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b10011, 0b01101], // 'u'
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100], // 'v'
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10101, 0b10101, 0b01010], // 'w'
+    [0b00000, 0b00000, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001], // 'x'
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110], // 'y'
+    [0b00000, 0b00000, 0b11111, 0b00010, 0b00100, 0b01000, 0b11111], // 'z'
+    [0b00010, 0b00100, 0b00100, 0b01000, 0b00100, 0b00100, 0b00010], // '{'
+    [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100], // '|'
+    [0b01000, 0b00100, 0b00100, 0b00010, 0b00100, 0b00100, 0b01000], // '}'
+    [0b00000, 0b00000, 0b01000, 0b10101, 0b00010, 0b00000, 0b00000], // '~'
+];
+
+/// A glyph or rectangle, in pixels from the top left corner of the target.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TextInstance {
+    /// The position and size of the rectangle.
+    rect: [f32; 4],
+    /// The top left texel of the atlas cell filling it.
+    cell: [f32; 2],
+    colour: [f32; 4],
+}
+
+impl TextInstance {
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<TextInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: 0,
+                    shader_location: 2,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                },
+            ],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct TextUniform {
+    screen_size: [f32; 2],
+    _padding: [f32; 2],
+}
+
+#[derive(Bindable)]
+#[bindable(vertex, fragment)]
+pub struct TextBinding {
+    #[texture]
+    pub atlas: wgpu::TextureView,
+    /// A [`TextUniform`].
+    #[uniform]
+    pub screen: wgpu::Buffer,
+}
+
+/// Collects glyphs and rectangles each frame, then draws them in the order they were
+/// added with [`DrawText::draw_text`].
+pub struct TextRenderer {
+    instances: Vec<TextInstance>,
+    instance_buffer: wgpu::Buffer,
+    /// How many instances the last [`TextRenderer::prepare`] uploaded.
+    prepared: u32,
+    screen_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl TextRenderer {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, binder: &Binder<TextBinding>) -> Self {
+        let width = ATLAS_COLUMNS * CELL_WIDTH;
+        let rows = (GLYPHS.len() as u32 + 1).div_ceil(ATLAS_COLUMNS);
+        let height = rows * CELL_HEIGHT;
+        let mut texels = vec![0u8; (width * height) as usize];
+        let mut fill = |cell: u32, x: u32, y: u32| {
+            let (column, row) = (cell % ATLAS_COLUMNS, cell / ATLAS_COLUMNS);
+            texels[((row * CELL_HEIGHT + y) * width + column * CELL_WIDTH + x) as usize] = 255;
+        };
+        for (cell, glyph) in GLYPHS.iter().enumerate() {
+            for (y, bits) in glyph.iter().enumerate() {
+                for x in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - x)) != 0 {
+                        fill(cell as u32, x, y as u32);
+                    }
+                }
+            }
+        }
+        for y in 0..CELL_HEIGHT {
+            for x in 0..CELL_WIDTH {
+                fill(SOLID_CELL, x, y);
+            }
+        }
+
+        let atlas = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Font Atlas"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            &texels,
+        );
+        let screen_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Text Screen Buffer"),
+            contents: bytemuck::bytes_of(&TextUniform::default()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = binder.create_bind_group(
+            &TextBinding {
+                atlas: atlas.create_view(&wgpu::TextureViewDescriptor::default()),
+                screen: screen_buffer.clone(),
+            },
+            device,
+            Some("Text Bind Group"),
+        );
+
+        Self {
+            instances: Vec::new(),
+            instance_buffer: Self::create_instance_buffer(device, 256),
+            prepared: 0,
+            screen_buffer,
+            bind_group,
+        }
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Text Instance Buffer"),
+            size: (capacity * std::mem::size_of::<TextInstance>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// The width and height in pixels of `text` drawn at `scale`.
+    pub fn measure(text: &str, scale: u32) -> [f32; 2] {
+        let columns = text.lines().map(|line| line.chars().count()).max();
+        let lines = text.lines().count();
+        [
+            (columns.unwrap_or(0) as u32 * CELL_WIDTH * scale) as f32,
+            (lines as u32 * CELL_HEIGHT * scale) as f32,
+        ]
+    }
+
+    /// Adds `text` with its top left corner at `position`, each font pixel `scale` pixels
+    /// across. Characters outside printable ASCII are drawn as `?`.
+    pub fn text(&mut self, position: [f32; 2], scale: u32, colour: [f32; 4], text: &str) {
+        let (cell_width, cell_height) = ((CELL_WIDTH * scale) as f32, (CELL_HEIGHT * scale) as f32);
+        for (row, line) in text.lines().enumerate() {
+            for (column, c) in line.chars().enumerate() {
+                if c == ' ' {
+                    continue;
+                }
+                let glyph = match c {
+                    ' '..='~' => c as u32 - FIRST_CHAR as u32,
+                    _ => '?' as u32 - FIRST_CHAR as u32,
+                };
+                self.push(
+                    [
+                        position[0] + column as f32 * cell_width,
+                        position[1] + row as f32 * cell_height,
+                        cell_width,
+                        cell_height,
+                    ],
+                    glyph,
+                    colour,
+                );
+            }
+        }
+    }
+
+    /// Adds a filled rectangle with its top left corner at `position`.
+    pub fn rect(&mut self, position: [f32; 2], size: [f32; 2], colour: [f32; 4]) {
+        self.push(
+            [position[0], position[1], size[0], size[1]],
+            SOLID_CELL,
+            colour,
+        );
+    }
+
+    fn push(&mut self, rect: [f32; 4], cell: u32, colour: [f32; 4]) {
+        self.instances.push(TextInstance {
+            rect,
+            cell: [
+                ((cell % ATLAS_COLUMNS) * CELL_WIDTH) as f32,
+                ((cell / ATLAS_COLUMNS) * CELL_HEIGHT) as f32,
+            ],
+            colour,
+        });
+    }
+
+    /// Uploads everything added since the last call for drawing into a target of
+    /// `screen_size` pixels, and starts collecting afresh.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, screen_size: [u32; 2]) {
+        let capacity = self.instance_buffer.size() as usize / std::mem::size_of::<TextInstance>();
+        if self.instances.len() > capacity {
+            self.instance_buffer =
+                Self::create_instance_buffer(device, self.instances.len().next_power_of_two());
+        }
+        queue.write_buffer(
+            &self.instance_buffer,
+            0,
+            bytemuck::cast_slice(&self.instances),
+        );
+        queue.write_buffer(
+            &self.screen_buffer,
+            0,
+            bytemuck::bytes_of(&TextUniform {
+                screen_size: [screen_size[0] as f32, screen_size[1] as f32],
+                _padding: [0.0; 2],
+            }),
+        );
+        self.prepared = self.instances.len() as u32;
+        self.instances.clear();
+    }
+}
+
+pub trait DrawText<'a, 'b>
+where
+    'b: 'a,
+{
+    /// Draws what `text` last prepared over the screen quad, with the text pipeline
+    /// already set.
+    fn draw_text(&mut self, text: &'b TextRenderer, screen_quad: &'b Mesh);
+}
+
+impl<'a, 'b> DrawText<'a, 'b> for wgpu::RenderPass<'a>
+where
+    'b: 'a,
+{
+    fn draw_text(&mut self, text: &'b TextRenderer, screen_quad: &'b Mesh) {
+        if text.prepared == 0 {
+            return;
+        }
+        self.set_bind_group(0, &text.bind_group, &[]);
+        self.set_vertex_buffer(0, screen_quad.vertex_buffer.slice(..));
+        self.set_vertex_buffer(1, text.instance_buffer.slice(..));
+        self.set_index_buffer(screen_quad.index_buffer.slice(..), screen_quad.index_format);
+        self.draw_indexed(0..screen_quad.num_elements, 0, 0..text.prepared);
+    }
+}
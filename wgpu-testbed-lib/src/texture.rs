@@ -1,8 +1,13 @@
-use anyhow::*;
 use image::GenericImageView;
 
+use crate::asset_cache::{AssetCache, CacheKey, TextureData};
+use crate::asset_loader::PLACEHOLDER_NORMAL;
+use crate::baked::{BAKED_TEXTURE_EXTENSION, BakedTexture};
+use crate::error::{Error, Result};
 use crate::file_reader::FileReader;
+use crate::model::TextureSource;
 
+#[derive(Clone)]
 pub struct Texture {
     #[allow(dead_code)]
     pub texture: wgpu::Texture,
@@ -61,10 +66,12 @@ impl Texture {
         }
     }
 
+    /// A texture of `format` to render into, `render_scale` times the size of the surface.
     pub fn create_render_texture(
         device: &wgpu::Device,
         surface_config: &wgpu::SurfaceConfiguration,
         render_scale: f32,
+        format: wgpu::TextureFormat,
         label: &str,
     ) -> Self {
         let size = wgpu::Extent3d {
@@ -79,11 +86,11 @@ impl Texture {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: surface_config.format,
+            format,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT
                 | wgpu::TextureUsages::TEXTURE_BINDING
                 | wgpu::TextureUsages::COPY_SRC,
-            view_formats: &[surface_config.format],
+            view_formats: &[format],
         };
 
         let texture = device.create_texture(&desc);
@@ -107,16 +114,42 @@ impl Texture {
         }
     }
 
-    #[allow(dead_code)]
-    pub fn from_bytes(
+    /// A single texel texture standing in for one that is still loading.
+    pub fn placeholder(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        bytes: &[u8],
-        label: &str,
+        colour: [u8; 4],
+        is_normal_map: bool,
+    ) -> Self {
+        let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            1,
+            1,
+            image::Rgba(colour),
+        ));
+        Self::from_image(device, queue, &img, Some("Placeholder"), is_normal_map)
+    }
+
+    /// Loads a material texture, or its placeholder if the material has none.
+    pub async fn load_source(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        source: &TextureSource,
         is_normal_map: bool,
     ) -> Result<Self> {
-        let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label), is_normal_map)
+        match source {
+            TextureSource::Path(path) => Self::load(device, queue, path, is_normal_map).await,
+            TextureSource::Embedded { name, bytes } => {
+                Self::from_bytes(device, queue, bytes, name, is_normal_map)
+            }
+            TextureSource::None => {
+                let colour = if is_normal_map {
+                    PLACEHOLDER_NORMAL
+                } else {
+                    [255; 4]
+                };
+                Ok(Self::placeholder(device, queue, colour, is_normal_map))
+            }
+        }
     }
 
     pub async fn load(
@@ -124,16 +157,81 @@ impl Texture {
         queue: &wgpu::Queue,
         path: &str,
         is_normal_map: bool,
+    ) -> Result<Self> {
+        let img_buffer = FileReader::read_file(path).await?;
+        Self::from_bytes(device, queue, &img_buffer, path, is_normal_map)
+    }
+
+    /// Creates a texture from the bytes of an image or baked texture, going through the
+    /// asset cache, which keeps the decoded levels. `path` picks the format and labels the texture.
+    pub fn from_bytes(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img_buffer: &[u8],
+        path: &str,
+        is_normal_map: bool,
     ) -> Result<Self> {
         let label = Some(path);
+        let cache = AssetCache::global();
+        let key = CacheKey::new("texture", img_buffer, &[is_normal_map as u8]);
+        if let Some(data) = cache.load_texture(key) {
+            return Ok(Self::from_data(device, queue, &data, label, is_normal_map));
+        }
 
-        let img_buffer = FileReader::read_file(path).await;
-        let img = if path.contains(".tga") {
-            image::load_from_memory_with_format(&img_buffer, image::ImageFormat::Tga)?
+        let data = if path.ends_with(&format!(".{}", BAKED_TEXTURE_EXTENSION)) {
+            BakedTexture::decode(img_buffer)
+                .ok_or_else(|| Error::InvalidBakedAsset {
+                    path: path.to_owned(),
+                })?
+                .decompress(path)?
         } else {
-            image::load_from_memory(&img_buffer)?
+            Self::decode(path, img_buffer)?
         };
-        Self::from_image(device, queue, &img, label, is_normal_map)
+        cache.store_texture(key, &data);
+        Ok(Self::from_data(device, queue, &data, label, is_normal_map))
+    }
+
+    /// Decodes an image file and generates its mip chain, without touching the GPU.
+    pub fn decode(path: &str, bytes: &[u8]) -> Result<TextureData> {
+        let img = if path.contains(".tga") {
+            image::load_from_memory_with_format(bytes, image::ImageFormat::Tga)
+        } else {
+            image::load_from_memory(bytes)
+        }
+        .map_err(|source| Error::Decode {
+            path: path.to_owned(),
+            source,
+        })?;
+        Ok(Self::generate_mips(&img))
+    }
+
+    /// Decodes an image to RGBA8 and box filters it down to a full mip chain.
+    pub fn generate_mips(img: &image::DynamicImage) -> TextureData {
+        let (width, height) = img.dimensions();
+        let mut level = img.to_rgba8();
+        let mut levels = Vec::new();
+        loop {
+            let (level_width, level_height) = level.dimensions();
+            let next = (level_width > 1 || level_height > 1).then(|| {
+                image::imageops::resize(
+                    &level,
+                    (level_width / 2).max(1),
+                    (level_height / 2).max(1),
+                    image::imageops::FilterType::Triangle,
+                )
+            });
+            levels.push(level.into_raw());
+            match next {
+                Some(next) => level = next,
+                None => break,
+            }
+        }
+
+        TextureData {
+            width,
+            height,
+            levels,
+        }
     }
 
     pub fn from_image(
@@ -142,21 +240,28 @@ impl Texture {
         img: &image::DynamicImage,
         label: Option<&str>,
         is_normal_map: bool,
-    ) -> Result<Self> {
-        let rgba = img.to_rgba8();
-
-        let dimensions = img.dimensions();
+    ) -> Self {
+        let data = Self::generate_mips(img);
+        Self::from_data(device, queue, &data, label, is_normal_map)
+    }
 
+    pub fn from_data(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        data: &TextureData,
+        label: Option<&str>,
+        is_normal_map: bool,
+    ) -> Self {
         let texture_size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
+            width: data.width,
+            height: data.height,
             depth_or_array_layers: 1,
         };
 
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label,
             size: texture_size,
-            mip_level_count: 1,
+            mip_level_count: data.levels.len() as u32,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: if is_normal_map {
@@ -168,21 +273,25 @@ impl Texture {
             view_formats: &[wgpu::TextureFormat::Rgba8UnormSrgb],
         });
 
-        queue.write_texture(
-            wgpu::TexelCopyTextureInfo {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &rgba,
-            wgpu::TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            texture_size,
-        );
+        for (mip_level, level) in data.levels.iter().enumerate() {
+            let mip_size =
+                texture_size.mip_level_size(mip_level as u32, wgpu::TextureDimension::D2);
+            queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &texture,
+                    mip_level: mip_level as u32,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                level,
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * mip_size.width),
+                    rows_per_image: Some(mip_size.height),
+                },
+                mip_size,
+            );
+        }
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
 
@@ -195,10 +304,10 @@ impl Texture {
             mipmap_filter: wgpu::MipmapFilterMode::Nearest,
             ..Default::default()
         });
-        Ok(Texture {
+        Texture {
             texture,
             view,
             sampler,
-        })
+        }
     }
 }
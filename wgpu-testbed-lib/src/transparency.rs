@@ -0,0 +1,86 @@
+//! Drawing materials with [`AlphaMode::Blend`](crate::model::AlphaMode::Blend) over the
+//! lit scene, once the deferred passes are done.
+//!
+//! By default blended meshes are drawn forward with their instances sorted back to front
+//! each frame. Sorting is per mesh, so where two blended meshes overlap one can still be
+//! drawn over the other in the wrong order. Weighted blended order-independent
+//! transparency needs no sorting at all, but only approximates the result where several
+//! layers overlap. Either way blended meshes write no pick IDs, so they can't be picked.
+
+use cgmath::{EuclideanSpace, MetricSpace, Point3};
+
+use crate::instance::{Instance, InstanceRaw};
+use crate::pipeline::Bindable;
+
+/// How blended materials are drawn. Toggled with O.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TransparencyMode {
+    /// Blended over the scene in `transparent.wgsl`, farthest instance first.
+    #[default]
+    Sorted,
+    /// Accumulated in any order and resolved in `oit_composite.wgsl`.
+    WeightedBlended,
+}
+
+impl TransparencyMode {
+    pub fn toggled(self) -> Self {
+        match self {
+            Self::Sorted => Self::WeightedBlended,
+            Self::WeightedBlended => Self::Sorted,
+        }
+    }
+}
+
+/// Weighted colour and alpha, summed over every transparent fragment.
+pub const ACCUM_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// How much of the scene shows through, the product of one minus each alpha.
+pub const REVEALAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+pub const ACCUM_BLEND: wgpu::BlendState = wgpu::BlendState {
+    color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+    alpha: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+};
+
+pub const REVEALAGE_BLEND: wgpu::BlendState = wgpu::BlendState {
+    color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::Zero,
+        dst_factor: wgpu::BlendFactor::OneMinusSrc,
+        operation: wgpu::BlendOperation::Add,
+    },
+    alpha: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::Zero,
+        dst_factor: wgpu::BlendFactor::OneMinusSrc,
+        operation: wgpu::BlendOperation::Add,
+    },
+};
+
+/// The weighted blended targets, as `oit_composite.wgsl` reads them.
+#[derive(Bindable)]
+#[bindable(fragment)]
+pub struct OitBinding {
+    #[texture]
+    pub accum: wgpu::TextureView,
+    #[texture]
+    pub revealage: wgpu::TextureView,
+}
+
+/// The instances ordered from farthest from `eye` to nearest, for drawing blended meshes.
+pub fn sort_back_to_front(instances: &[Instance], eye: Point3<f32>) -> Vec<InstanceRaw> {
+    let mut order: Vec<(f32, &Instance)> = instances
+        .iter()
+        .map(|instance| (eye.distance2(Point3::from_vec(instance.position)), instance))
+        .collect();
+    order.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    order
+        .into_iter()
+        .map(|(_, instance)| instance.to_raw())
+        .collect()
+}
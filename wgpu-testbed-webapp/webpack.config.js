@@ -15,13 +15,10 @@ module.exports = {
     new CopyWebpackPlugin({
       patterns: [
         "index.html",
+        // Built by wgpu-testbed-bake in build.ps1, and holds both resources and shaders.
         {
-          from: "../resources",
-          to: "resources",
-        },
-        {
-          from: "../shaders",
-          to: "shaders",
+          from: "../baked.zip",
+          to: "baked.zip",
         },
       ],
     }),